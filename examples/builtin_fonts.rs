@@ -16,14 +16,14 @@ fn main() {
     let font = doc.embed(&font).unwrap();
 
     let mut page = PdfPage::new(Mm(500.0), Mm(300.0));
-    let font = page.register(&font);
+    let font = page.register(&font).unwrap();
 
     {
         let mut layer = PdfLayer::new("Layer 1");
 
         let text = "Lorem ipsum";
 
-        layer.use_text(text, 48.0, Mm(10.0), Mm(200.0), &font);
+        layer.use_text(text, 48.0, Mm(10.0), Mm(200.0), &font).unwrap();
 
         page.add_layer(layer);
     }
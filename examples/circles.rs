@@ -15,13 +15,7 @@ fn main() {
     let offset_x = Pt(10.0);
     let offset_y = Pt(50.0);
 
-    let line = Line {
-        points: calculate_points_for_circle(radius, offset_x, offset_y),
-        is_closed: true,
-        has_fill: true,
-        has_stroke: true,
-        is_clipping_path: false,
-    };
+    let line = Line::new(calculate_points_for_circle(radius, offset_x, offset_y), true, true, true);
 
     layer1.add_shape(line);
 
@@ -30,13 +24,8 @@ fn main() {
     let offset_x_rect = Pt(20.0);
     let offset_y_rect = Pt(5.0);
 
-    let line = Line {
-        points: calculate_points_for_rect(scale_x_rect, scale_y_rect, offset_x_rect, offset_y_rect),
-        is_closed: true,
-        has_fill: true,
-        has_stroke: true,
-        is_clipping_path: false,
-    };
+    let points = calculate_points_for_rect(scale_x_rect, scale_y_rect, offset_x_rect, offset_y_rect);
+    let line = Line::new(points, true, true, true);
 
     layer1.add_shape(line);
     page1.add_layer(layer1);
@@ -15,10 +15,10 @@ fn main() {
         std::io::Cursor::new(include_bytes!("../assets/fonts/RobotoMedium.ttf").as_ref());
     let font = Font::ExternalFont(ExternalFont::new(&mut font_reader).unwrap());
     let font = doc.embed(&font).unwrap();
-    let font = page.register(&font);
+    let font = page.register(&font).unwrap();
 
     // `use_text` is a wrapper around making a simple string
-    layer.use_text(text, 48.0, Mm(10.0), Mm(200.0), &font);
+    layer.use_text(text, 48.0, Mm(10.0), Mm(200.0), &font).unwrap();
 
     // text fill color = blue
     let blue = Rgb::new(13.0 / 256.0, 71.0 / 256.0, 161.0 / 256.0, None);
@@ -41,9 +41,9 @@ fn main() {
         layer.set_character_spacing(10.0);
 
         // write two lines (one line break)
-        layer.write_text(text, &font);
+        layer.write_text(text, &font).unwrap();
         layer.add_line_break();
-        layer.write_text(text2, &font);
+        layer.write_text(text2, &font).unwrap();
         layer.add_line_break();
 
         layer.set_text_rendering_mode(TextRenderingMode::FillStroke);
@@ -51,11 +51,11 @@ fn main() {
         layer.set_text_matrix(TextMatrix::Rotate(10.0));
 
         // write one line, but write text2 in superscript
-        layer.write_text(text, &font);
+        layer.write_text(text, &font).unwrap();
         layer.set_line_offset(10.0);
         layer.set_text_rendering_mode(TextRenderingMode::Stroke);
         layer.set_font(&font, 18.0);
-        layer.write_text(text2, &font);
+        layer.write_text(text2, &font).unwrap();
     }
     layer.end_text_section();
 
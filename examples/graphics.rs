@@ -22,13 +22,7 @@ fn main() {
     ];
 
     // Is the shape stroked? Is the shape closed? Is the shape filled?
-    let line1 = Line {
-        points: points1,
-        is_closed: true,
-        has_fill: true,
-        has_stroke: true,
-        is_clipping_path: false,
-    };
+    let line1 = Line::new(points1, true, true, true);
 
     // Triangle shape
     // Note: Line is invisible by default, the previous method of
@@ -64,7 +58,7 @@ fn main() {
         .with_overprint_stroke(true)
         .build();
     let gs = doc.embed(&gs).unwrap();
-    let gs = page1.register(&gs);
+    let gs = page1.register(&gs).unwrap();
 
     layer1.set_graphics_state(&gs);
     layer1.set_line_dash_pattern(dash_pattern);
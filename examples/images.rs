@@ -20,7 +20,7 @@ fn main() {
     let decoder = BmpDecoder::new(&mut reader).unwrap();
     let image = Image::try_from(decoder).unwrap();
     let image = doc.embed(&image).unwrap();
-    let image = page.register(&image);
+    let image = page.register(&image).unwrap();
 
     // layer,
     layer.use_image(&image, None, None, None, None, None, None);
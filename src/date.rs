@@ -1,62 +1,481 @@
-/// wasm32-unknown-unknown polyfill
+//! Date handling: `OffsetDateTime` / `UtcOffset` (with a polyfill for wasm32-unknown-unknown)
+//! and conversion from / to the date formats used by PDF and XMP
 
 #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-pub use self::js_sys_date::OffsetDateTime;
+pub use self::js_sys_date::{OffsetDateTime, UtcOffset};
 #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
-pub use time::OffsetDateTime;
+pub use time::{OffsetDateTime, UtcOffset};
+
+use {Error, PdfError};
+
+/// Formats a date as a PDF date string, such as `D:20170505150224+02'00'`
+pub fn to_pdf_date(date: &OffsetDateTime) -> String {
+    let (sign, hours, minutes) = split_offset(date.offset().as_seconds());
+    format!(
+        "D:{:04}{:02}{:02}{:02}{:02}{:02}{}{:02}'{:02}'",
+        date.year(),
+        date.month(),
+        date.day(),
+        date.hour(),
+        date.minute(),
+        date.second(),
+        sign,
+        hours,
+        minutes,
+    )
+}
+
+/// Formats a date as an ISO 8601 date as used by XMP, such as `2017-05-05T15:02:24+02:00`
+pub fn to_xmp_date(date: &OffsetDateTime) -> String {
+    let (sign, hours, minutes) = split_offset(date.offset().as_seconds());
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+        date.year(),
+        date.month(),
+        date.day(),
+        date.hour(),
+        date.minute(),
+        date.second(),
+        sign,
+        hours,
+        minutes,
+    )
+}
+
+/// Parses a PDF date string (`D:YYYYMMDDHHmmSSOHH'mm'`). Everything after the
+/// year is optional, a missing UTC offset is treated as UTC. Fails with
+/// `PdfError::InvalidDate` if anything follows the offset.
+pub fn parse_pdf_date(date: &str) -> Result<OffsetDateTime, Error> {
+    let date = date.trim();
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let mut reader = DateReader::new(date);
+
+    let year = reader.number(4)?.ok_or(PdfError::InvalidDate)? as i32;
+    let month = reader.number(2)?.unwrap_or(1);
+    let day = reader.number(2)?.unwrap_or(1);
+    let hour = reader.number(2)?.unwrap_or(0);
+    let minute = reader.number(2)?.unwrap_or(0);
+    let second = reader.number(2)?.unwrap_or(0);
+
+    let offset = match reader.next() {
+        None => 0,
+        // some writers add a zero offset after the `Z` (`Z00'00'`)
+        Some('Z') => match (reader.number(2)?, reader.skip('\'').number(2)?) {
+            (None, None) | (Some(0), None) | (Some(0), Some(0)) => 0,
+            _ => return Err(PdfError::InvalidDate.into()),
+        },
+        Some(sign @ '+') | Some(sign @ '-') => {
+            let hours = reader.number(2)?.ok_or(PdfError::InvalidDate)? as i32;
+            reader.skip('\'');
+            let minutes = reader.number(2)?.unwrap_or(0) as i32;
+            utc_offset(sign, hours, minutes)?
+        }
+        Some(_) => return Err(PdfError::InvalidDate.into()),
+    };
+
+    // the trailing apostrophe is optional
+    reader.skip('\'').end()?;
+
+    from_components(year, month, day, hour, minute, second, offset)
+}
+
+/// Parses an ISO 8601 date as used by XMP (`YYYY-MM-DDThh:mm:ss.sTZD`).
+/// Everything after the year is optional, a missing time zone is treated as UTC.
+/// Fails with `PdfError::InvalidDate` if anything follows the time zone.
+pub fn parse_xmp_date(date: &str) -> Result<OffsetDateTime, Error> {
+    let mut reader = DateReader::new(date.trim());
+
+    let year = reader.number(4)?.ok_or(PdfError::InvalidDate)? as i32;
+    let month = reader.skip('-').number(2)?.unwrap_or(1);
+    let day = reader.skip('-').number(2)?.unwrap_or(1);
+    let hour = reader.skip('T').number(2)?.unwrap_or(0);
+    let minute = reader.skip(':').number(2)?.unwrap_or(0);
+    let second = reader.skip(':').number(2)?.unwrap_or(0);
+
+    // fractions of a second are not stored
+    if reader.peek() == Some('.') {
+        reader.next();
+        while reader.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+            reader.next();
+        }
+    }
+
+    let offset = match reader.next() {
+        None | Some('Z') => 0,
+        Some(sign @ '+') | Some(sign @ '-') => {
+            let hours = reader.number(2)?.ok_or(PdfError::InvalidDate)? as i32;
+            let minutes = reader.skip(':').number(2)?.unwrap_or(0) as i32;
+            utc_offset(sign, hours, minutes)?
+        }
+        Some(_) => return Err(PdfError::InvalidDate.into()),
+    };
+
+    reader.end()?;
+
+    from_components(year, month, day, hour, minute, second, offset)
+}
+
+// returns the offset in seconds, the hours and minutes have to be in range
+fn utc_offset(sign: char, hours: i32, minutes: i32) -> Result<i32, Error> {
+    if hours >= 24 || minutes >= 60 {
+        return Err(PdfError::InvalidDate.into());
+    }
+    let offset = hours * 3600 + minutes * 60;
+    Ok(if sign == '-' { -offset } else { offset })
+}
+
+// returns the sign, hours and minutes of an offset in seconds
+fn split_offset(offset: i32) -> (char, i32, i32) {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    (sign, offset / 3600, (offset % 3600) / 60)
+}
+
+fn from_components(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    offset: i32,
+) -> Result<OffsetDateTime, Error> {
+    let is_valid = (1..=12).contains(&month)
+        && day >= 1
+        && day <= days_in_month(year, month)
+        && hour < 24
+        && minute < 60
+        && second < 60
+        && offset.abs() < 24 * 3600;
+
+    if !is_valid {
+        return Err(PdfError::InvalidDate.into());
+    }
+
+    let local_timestamp = days_from_civil(year, month, day) * 86_400
+        + i64::from(hour) * 3600
+        + i64::from(minute) * 60
+        + i64::from(second);
+
+    Ok(OffsetDateTime::from_unix_timestamp(local_timestamp - i64::from(offset))
+        .to_offset(UtcOffset::seconds(offset)))
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+// days since 1970-01-01 in the proleptic Gregorian calendar
+// (algorithm by Howard Hinnant, http://howardhinnant.github.io/date_algorithms.html)
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - if month <= 2 { 1 } else { 0 };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+struct DateReader<'a> {
+    chars: ::std::iter::Peekable<::std::str::Chars<'a>>,
+}
+
+impl<'a> DateReader<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        self.chars.next()
+    }
+
+    // skips the separator, if it is the next character
+    fn skip(&mut self, separator: char) -> &mut Self {
+        if self.peek() == Some(separator) {
+            self.chars.next();
+        }
+        self
+    }
+
+    // fails if there is any input left
+    fn end(&mut self) -> Result<(), Error> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(PdfError::InvalidDate.into()),
+        }
+    }
+
+    // reads a number with exactly `digits` digits, returns `None` at the end of the input
+    fn number(&mut self, digits: usize) -> Result<Option<u32>, Error> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {}
+            _ => return Ok(None),
+        }
+
+        let mut number = 0;
+        for _ in 0..digits {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(10))
+                .ok_or(PdfError::InvalidDate)?;
+            number = number * 10 + digit;
+        }
+
+        Ok(Some(number))
+    }
+}
 
 #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
 mod js_sys_date {
     use js_sys::Date;
-    #[derive(Debug, Clone)]
-    pub struct OffsetDateTime(Date);
+
+    /// An offset from UTC, same API as `time::UtcOffset`
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub struct UtcOffset {
+        seconds: i32,
+    }
+
+    impl UtcOffset {
+        /// A `UtcOffset` that is UTC
+        pub const UTC: Self = Self::seconds(0);
+
+        #[inline(always)]
+        pub const fn hours(hours: i8) -> Self {
+            Self::seconds(hours as i32 * 3600)
+        }
+
+        #[inline(always)]
+        pub const fn minutes(minutes: i16) -> Self {
+            Self::seconds(minutes as i32 * 60)
+        }
+
+        #[inline(always)]
+        pub const fn seconds(seconds: i32) -> Self {
+            Self { seconds }
+        }
+
+        #[inline(always)]
+        pub const fn as_seconds(self) -> i32 {
+            self.seconds
+        }
+
+        #[inline(always)]
+        pub const fn as_minutes(self) -> i16 {
+            (self.seconds / 60) as i16
+        }
+
+        #[inline(always)]
+        pub const fn as_hours(self) -> i8 {
+            (self.seconds / 3600) as i8
+        }
+
+        /// Local offset of the browser / JS runtime
+        #[inline(always)]
+        pub fn current_local_offset() -> Self {
+            // getTimezoneOffset() is in minutes and positive west of UTC
+            Self::minutes(-Date::new_0().get_timezone_offset() as i16)
+        }
+    }
+
+    /// A date with a UTC offset, with second precision.
+    /// Same API as the (commonly used parts of) `time::OffsetDateTime`
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub struct OffsetDateTime {
+        timestamp: i64,
+        offset: UtcOffset,
+    }
+
     impl OffsetDateTime {
         #[inline(always)]
         pub fn now_utc() -> Self {
-            let date = Date::new_0();
-            OffsetDateTime(date)
+            Self::from_unix_timestamp((Date::now() / 1000.0) as i64)
         }
 
         #[inline(always)]
         pub fn now() -> Self {
-            let date = Date::new_0();
-            OffsetDateTime(date)
+            Self::now_utc()
         }
 
         #[inline(always)]
+        pub fn now_local() -> Self {
+            Self::now_utc().to_offset(UtcOffset::current_local_offset())
+        }
+
+        #[inline(always)]
+        pub fn unix_epoch() -> Self {
+            Self::from_unix_timestamp(0)
+        }
+
+        #[inline(always)]
+        pub fn from_unix_timestamp(timestamp: i64) -> Self {
+            Self {
+                timestamp,
+                offset: UtcOffset::UTC,
+            }
+        }
+
+        #[inline(always)]
+        pub fn unix_timestamp(&self) -> i64 {
+            self.timestamp
+        }
+
+        #[inline(always)]
+        pub fn offset(&self) -> UtcOffset {
+            self.offset
+        }
+
+        #[inline(always)]
+        pub fn to_offset(&self, offset: UtcOffset) -> Self {
+            Self {
+                timestamp: self.timestamp,
+                offset,
+            }
+        }
+
+        /// Supports the `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%z` and `%%` specifiers
         pub fn format(&self, format: impl ToString) -> String {
-            // TODO
-            "".into()
+            let format = format.to_string();
+            let mut out = String::new();
+            let mut chars = format.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    out.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('Y') => out.push_str(&format!("{:04}", self.year())),
+                    Some('m') => out.push_str(&format!("{:02}", self.month())),
+                    Some('d') => out.push_str(&format!("{:02}", self.day())),
+                    Some('H') => out.push_str(&format!("{:02}", self.hour())),
+                    Some('M') => out.push_str(&format!("{:02}", self.minute())),
+                    Some('S') => out.push_str(&format!("{:02}", self.second())),
+                    Some('z') => {
+                        let offset = self.offset.as_seconds();
+                        let sign = if offset < 0 { '-' } else { '+' };
+                        let offset = offset.abs();
+                        out.push_str(&format!("{}{:02}{:02}", sign, offset / 3600, (offset % 3600) / 60));
+                    }
+                    Some(other) => {
+                        out.push('%');
+                        out.push(other);
+                    }
+                    None => out.push('%'),
+                }
+            }
+            out
         }
 
         #[inline(always)]
-        pub fn year(&self) -> u32 {
-            self.0.get_full_year()
+        pub fn year(&self) -> i32 {
+            self.civil_date().0
         }
 
         #[inline(always)]
-        pub fn month(&self) -> u32 {
-            self.0.get_month() + 1u32
+        pub fn month(&self) -> u8 {
+            self.civil_date().1
         }
 
         #[inline(always)]
-        pub fn day(&self) -> u32 {
-            self.0.get_date()
+        pub fn day(&self) -> u8 {
+            self.civil_date().2
         }
 
         #[inline(always)]
-        pub fn hour(&self) -> u32 {
-            self.0.get_hours()
+        pub fn hour(&self) -> u8 {
+            (self.local_seconds_of_day() / 3600) as u8
         }
 
         #[inline(always)]
-        pub fn minute(&self) -> u32 {
-            self.0.get_minutes()
+        pub fn minute(&self) -> u8 {
+            (self.local_seconds_of_day() % 3600 / 60) as u8
         }
 
         #[inline(always)]
-        pub fn second(&self) -> u32 {
-            self.0.get_seconds()
+        pub fn second(&self) -> u8 {
+            (self.local_seconds_of_day() % 60) as u8
+        }
+
+        fn local_timestamp(&self) -> i64 {
+            self.timestamp + i64::from(self.offset.as_seconds())
         }
+
+        fn local_seconds_of_day(&self) -> i64 {
+            self.local_timestamp().rem_euclid(86_400)
+        }
+
+        // (year, month, day) in the stored offset, inverse of `days_from_civil`
+        fn civil_date(&self) -> (i32, u8, u8) {
+            let days = self.local_timestamp().div_euclid(86_400) + 719_468;
+            let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+            let day_of_era = days - era * 146_097;
+            let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+                - day_of_era / 146_096)
+                / 365;
+            let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+            let mp = (5 * day_of_year + 2) / 153;
+            let day = day_of_year - (153 * mp + 2) / 5 + 1;
+            let month = if mp < 10 { mp + 3 } else { mp - 9 };
+            let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+            (year as i32, month as u8, day as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pdf_date_roundtrip() {
+        let date = parse_pdf_date("D:20170505150224+02'00'").unwrap();
+        assert_eq!(date.offset().as_seconds(), 7200);
+        assert_eq!(date.hour(), 15);
+        assert_eq!(date.unix_timestamp(), 1_493_989_344);
+        assert_eq!(to_pdf_date(&date), "D:20170505150224+02'00'");
+        assert_eq!(to_xmp_date(&date), "2017-05-05T15:02:24+02:00");
+        assert_eq!(parse_xmp_date(&to_xmp_date(&date)).unwrap(), date);
+
+        let date = parse_pdf_date("D:19991231235959-05'30").unwrap();
+        assert_eq!(to_pdf_date(&date), "D:19991231235959-05'30'");
+
+        let date = parse_pdf_date("D:2020").unwrap();
+        assert_eq!(to_pdf_date(&date), "D:20200101000000+00'00'");
+
+        assert!(parse_pdf_date("D:20170230").is_err());
+        assert!(parse_pdf_date("hello").is_err());
+        assert!(parse_xmp_date("2017-05-05T15:02:24.123Z").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_offsets() {
+        assert!(parse_pdf_date("D:20170505150224+23'59'").is_ok());
+        assert!(parse_pdf_date("D:20170505150224+02'60'").is_err());
+        assert!(parse_pdf_date("D:20170505150224-24'00'").is_err());
+        assert!(parse_pdf_date("D:20170505150224+99'99'").is_err());
+
+        assert!(parse_xmp_date("2017-05-05T15:02:24-23:59").is_ok());
+        assert!(parse_xmp_date("2017-05-05T15:02:24+02:60").is_err());
+        assert!(parse_xmp_date("2017-05-05T15:02:24+24:00").is_err());
+
+        // nothing may follow the offset
+        assert!(parse_pdf_date("D:20200101120000+02'00'garbage").is_err());
+        assert!(parse_pdf_date("D:20200101120000Zxyz").is_err());
+        assert!(parse_pdf_date("D:20200101120000+02'00").is_ok());
+        assert!(parse_pdf_date("D:20200101120000Z00'00'").is_ok());
+        assert!(parse_pdf_date("D:20200101120000Z02'00'").is_err());
+        assert!(parse_xmp_date("2020-01-01T00:00:00Zxyz").is_err());
+        assert!(parse_xmp_date("2020-01-01T00:00:00+02:00 ").is_ok());
     }
 }
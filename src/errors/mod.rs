@@ -3,6 +3,7 @@
 use std::error::Error as IError;
 use std::io::Error as IoError;
 use rusttype::Error as RusttypeError;
+use lopdf::Error as LopdfError;
 use std::fmt;
 
 /// error_chain and failure are certainly nice, but completely overengineered
@@ -30,6 +31,8 @@ pub enum Error {
     Io(IoError),
     /// External: rusttype::Error
     Rusttype(RusttypeError),
+    /// External: lopdf::Error
+    Lopdf(LopdfError),
     /// PDF error
     Pdf(PdfError),
     /// Indexing error (please report if this happens, shouldn't happen)
@@ -39,11 +42,55 @@ pub enum Error {
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum PdfError {
     FontFaceError,
+    /// XMP metadata could not be parsed
+    InvalidXmp,
+    /// Invalid PDF or XMP date string
+    InvalidDate,
+    /// Image data could not be read or is not supported
+    InvalidImage,
+    /// The image filter can't be used for this image (for example `CCITTFaxDecode`
+    /// for an image that is not a 1-bit image)
+    UnsupportedFilter,
+    /// A resource could not be registered on the page, because the resource
+    /// dictionary of the page has an unexpected structure or two different
+    /// resources have the same name
+    InvalidResource,
+    /// The font data could not be parsed
+    FontParsing,
+    /// The document uses a feature that is not allowed by its `PdfConformance`
+    ConformanceViolation,
+    /// The image can't be written as an inline image, because it is too large or
+    /// uses features that need resources (soft masks, ICC profiles, JPX or JBIG2 data)
+    InvalidInlineImage,
+    /// The number of color values doesn't match the number of components of the color space
+    InvalidColor,
+    /// The ICC profile could not be parsed or uses an unsupported color space or transform
+    InvalidIccProfile,
+    /// The parameters of a function object don't match (e.g. the number of samples, bounds
+    /// or outputs), or the function has the wrong number of inputs or outputs for its use
+    InvalidFunction,
+    /// The key of a custom Info dictionary entry is not a valid XMP property name
+    InvalidInfoKey,
 }
 
 impl fmt::Display for PdfError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Invalid or corrupt font face")
+        use self::PdfError::*;
+        write!(f, "{}", match *self {
+            FontFaceError => "Invalid or corrupt font face",
+            InvalidXmp => "Invalid or corrupt XMP metadata",
+            InvalidDate => "Invalid date string",
+            InvalidImage => "Invalid or unsupported image data",
+            UnsupportedFilter => "Filter is not supported for this image",
+            InvalidResource => "Invalid resource dictionary",
+            FontParsing => "Font could not be parsed",
+            ConformanceViolation => "Document violates the selected PDF conformance",
+            InvalidInlineImage => "Image can't be written as an inline image",
+            InvalidColor => "Color doesn't match its color space",
+            InvalidIccProfile => "Invalid or unsupported ICC profile",
+            InvalidFunction => "Invalid function parameters",
+            InvalidInfoKey => "Invalid custom document information key",
+        })
     }
 }
 
@@ -71,6 +118,7 @@ impl IError for IndexError {}
 
 impl_from!(IoError, Error::Io);
 impl_from!(RusttypeError, Error::Rusttype);
+impl_from!(LopdfError, Error::Lopdf);
 impl_from!(PdfError, Error::Pdf);
 impl_from!(IndexError, Error::Index);
 
@@ -80,6 +128,7 @@ impl fmt::Display for Error {
         match *self {
             Io(ref e) => write!(f, "{}", e),
             Rusttype(ref e) => write!(f, "{}", e),
+            Lopdf(ref e) => write!(f, "{}", e),
             Pdf(ref e) => write!(f, "{}", e),
             Index(ref e) => write!(f, "{}", e),
         }
@@ -67,13 +67,7 @@
 //!                    (Point::new(Mm(300.0), Mm(100.0)), false)];
 //!
 //! // Is the shape stroked? Is the shape closed? Is the shape filled?
-//! let line1 = Line {
-//!     points: points1,
-//!     is_closed: true,
-//!     has_fill: true,
-//!     has_stroke: true,
-//!     is_clipping_path: false,
-//! };
+//! let line1 = Line::new(points1, true, true, true);
 //!
 //! // Triangle shape
 //! // Note: Line is invisible by default, the previous method of
@@ -354,6 +348,7 @@ pub extern crate log;
 
 #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
 extern crate js_sys;
+extern crate flate2;
 extern crate lopdf;
 extern crate rusttype;
 extern crate time;
@@ -384,38 +379,60 @@ pub use self::types::pdf_page::PdfPage;
 pub use self::types::pdf_resources::{Embeddable, Embedded, Registered};
 
 pub use self::types::plugins::misc::document_info::DocumentInfo;
+pub use self::types::plugins::misc::embedded_file::{AFRelationship, EmbeddedFile};
+pub use self::types::plugins::xmp::xmp_extension::{XmpExtensionProperty, XmpExtensionSchema};
 pub use self::types::plugins::xmp::xmp_metadata::XmpMetadata;
+pub use self::types::plugins::xmp::xmp_packet::{XmpPacket, XmpSchema, XmpValue};
+pub use self::types::plugins::xmp::xmp_schemas::{
+    AdobePdf, DublinCore, PdfAIdentification, PdfXIdentification, XmpBasic, XmpMediaManagement,
+};
+pub use self::types::plugins::interactive::annotation::{
+    FileAttachmentAnnotation, FileAttachmentIcon,
+};
 
 /// Stub module for 3D content in a PDF
 pub use self::types::plugins::graphics::two_dimensional::font::{
     BuiltinFont, ExternalFont, Font, TextRenderingMode,
 };
-pub use self::types::plugins::graphics::two_dimensional::image::Image;
+pub use self::types::plugins::graphics::two_dimensional::image::{
+    Image, ImageAlignment, ImageFit, ImagePlacement,
+};
 pub use self::types::plugins::graphics::two_dimensional::line::Line;
+pub use self::types::plugins::graphics::two_dimensional::path::{FillRule, Path, PathSegment};
 pub use self::types::plugins::graphics::two_dimensional::point::Point;
 
+pub use self::types::plugins::graphics::ccitt::CcittFaxDecodeParms;
 pub use self::types::plugins::graphics::color::{
-    Cmyk, Color, ColorBits, ColorSpace, Greyscale, PdfColor, Rgb, SpotColor,
+    CalGray, CalGrayColorSpace, CalRgb, CalRgbColorSpace, Cmyk, Color, ColorBits, ColorSpace,
+    Greyscale, Lab, LabColorSpace, PdfColor, Rgb, SpotColor, D50_WHITE_POINT,
 };
+pub use self::types::plugins::graphics::color_conversion::ColorConverter;
+pub use self::types::plugins::graphics::color_space::{Colorant, ImageColorSpace};
 pub use self::types::plugins::graphics::ctm::{CurTransMat, TextMatrix};
 pub use self::types::plugins::graphics::extgstate::{
-    BlackGenerationExtraFunction, BlackGenerationFunction, BlendMode,
+    BlackGenerationExtraFunction, BlackGenerationFunction, BlendMode, DashPattern,
     ExtendedGraphicsState, ExtendedGraphicsStateBuilder, HalftoneType, LineCapStyle,
     LineDashPattern, LineJoinStyle, NonSeperableBlendMode, OverprintMode,
     RenderingIntent, SeperableBlendMode, SoftMask, SoftMaskFunction, SpotFunction,
     TransferExtraFunction, TransferFunction, UnderColorRemovalExtraFunction,
     UnderColorRemovalFunction,
 };
+pub use self::types::plugins::graphics::flate::{FlateDecodeParms, ImageCompression, PngPredictor};
+pub use self::types::plugins::graphics::function::{
+    ExponentialFunction, Function, PostScriptFunction, SampledFunction, StitchingFunction,
+};
 pub use self::types::plugins::graphics::icc_profile::{
     IccProfile, IccProfileList, IccProfileRef, IccProfileType,
 };
+pub use self::types::plugins::graphics::image_policy::{ImagePolicy, ImagePolicyReport, ResampleFilter};
+pub use self::types::plugins::graphics::jbig2::Jbig2Globals;
 pub use self::types::plugins::graphics::ocg::{OCGList, OCGRef};
 pub use self::types::plugins::graphics::pattern::{Pattern, PatternList, PatternRef};
 pub use self::types::plugins::graphics::pdf_resources::PdfResources;
 pub use self::types::plugins::graphics::xobject::{
-    FormType, FormXObject, FormXObjectRef, GroupXObject, GroupXObjectType, ImageFilter,
-    ImageXObject, OCGIntent, OptionalContentGroup, PostScriptXObject, ReferenceXObject,
-    SMask, XObject,
+    FormType, FormXObject, FormXObjectRef, GroupXObject, GroupXObjectType, ImageDecodeParms,
+    ImageFilter, ImageMasking, ImageXObject, OCGIntent, OptionalContentGroup, PostScriptXObject,
+    ReferenceXObject, SMask, XObject, INLINE_IMAGE_MAX_SIZE,
 };
 
 /// Stub module for interactive (JavaScript) content, embedded in PDF files
@@ -111,6 +111,10 @@ pub struct CustomPdfConformance {
     ///
     /// Default: __true__
     pub allows_pdf_layers: bool,
+    /// Does this standard allow embedded files (attachments)?
+    ///
+    /// Default: __true__
+    pub allows_embedded_files: bool,
 }
 
 impl Default for CustomPdfConformance {
@@ -126,6 +130,7 @@ impl Default for CustomPdfConformance {
             allows_default_fonts: false,
             requires_icc_profile: true,
             allows_pdf_layers: true,
+            allows_embedded_files: true,
         }
     }
 }
@@ -209,10 +214,70 @@ impl PdfConformance {
         }
     }
 
+    /// Is this conformance level one of the PDF/A (archiving) standards?
+    pub fn is_pdf_a(&self) -> bool {
+        self.get_pdfa_part().is_some()
+    }
+
+    /// Is this conformance level one of the PDF/X (printing) standards?
+    pub fn is_pdf_x(&self) -> bool {
+        matches!(
+            *self,
+            PdfConformance::X1A_2001_PDF_1_3
+                | PdfConformance::X3_2002_PDF_1_3
+                | PdfConformance::X1A_2003_PDF_1_4
+                | PdfConformance::X3_2003_PDF_1_4
+                | PdfConformance::X4_2010_PDF_1_4
+                | PdfConformance::X4P_2010_PDF_1_6
+                | PdfConformance::X5G_2010_PDF_1_6
+                | PdfConformance::X5PG_2010_PDF_1_6
+                | PdfConformance::X5N_2010_PDF_1_6
+        )
+    }
+
+    /// Returns the PDF/A part (1, 2 or 3) and the conformance level ("A", "B" or "U"),
+    /// as they have to be written into the `pdfaid` XMP schema
+    pub fn get_pdfa_part(&self) -> Option<(u8, &'static str)> {
+        match *self {
+            PdfConformance::A1B_2005_PDF_1_4 => Some((1, "B")),
+            PdfConformance::A1A_2005_PDF_1_4 => Some((1, "A")),
+            PdfConformance::A2_2011_PDF_1_7 => Some((2, "B")),
+            PdfConformance::A2A_2011_PDF_1_7 => Some((2, "A")),
+            PdfConformance::A2B_2011_PDF_1_7 => Some((2, "B")),
+            PdfConformance::A2U_2011_PDF_1_7 => Some((2, "U")),
+            PdfConformance::A3_2012_PDF_1_7 => Some((3, "B")),
+            _ => None,
+        }
+    }
+
+    /// Detects if the PDF has embedded files (attachments), but the
+    /// conformance to the given PDF standard does not allow it.
+    ///
+    /// PDF/A-1 and the older PDF/X standards forbid embedded files, PDF/A-2 only
+    /// allows embedding other PDF/A files, PDF/A-3 allows arbitrary files (for
+    /// example the XML invoice of a ZUGFeRD / Factur-X document).
+    #[cfg_attr(feature = "clippy", allow(match_same_arms))]
+    pub fn is_embedded_files_allowed(&self) -> bool {
+        match *self {
+            PdfConformance::A1B_2005_PDF_1_4 => false,
+            PdfConformance::A1A_2005_PDF_1_4 => false,
+            PdfConformance::X1A_2001_PDF_1_3 => false,
+            PdfConformance::X3_2002_PDF_1_3 => false,
+            PdfConformance::X1A_2003_PDF_1_4 => false,
+            PdfConformance::X3_2003_PDF_1_4 => false,
+            PdfConformance::Custom(ref c) => c.allows_embedded_files,
+            _ => true,
+        }
+    }
+
     /// Detects if the PDF must have XMP metadata
     /// if it has to conform to the given PDF Standard
     #[cfg_attr(feature = "clippy", allow(match_same_arms))]
     pub fn must_have_xmp_metadata(&self) -> bool {
+        if self.is_pdf_a() {
+            return true;
+        }
+
         match *self {
             PdfConformance::X1A_2001_PDF_1_3 => true,
             PdfConformance::X3_2002_PDF_1_3 => true,
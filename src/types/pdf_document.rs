@@ -1,14 +1,19 @@
 //! A `PDFDocument` represents the whole content of the file
 
+use std::collections::HashMap;
 use std::io::BufWriter;
 use std::io::Write;
-use utils::random_character_string_32;
+use utils::{md5, random_character_string_32, to_pdf_text_string};
+use date::to_pdf_date;
 
 use crate::OffsetDateTime;
 use lopdf;
 
+use super::plugins::misc::document_info::{is_reserved_info_key, is_valid_custom_info_key};
+use super::plugins::graphics::image_policy::{apply_image_policy, effective_dpi, ProcessedImage};
 use {
-    Embeddable, Embedded, Error, IccProfileList, PdfConformance, PdfMetadata, PdfPage,
+    EmbeddedFile, Embeddable, Embedded, Error, IccProfile, IccProfileList, IccProfileRef,
+    ImagePolicy, ImagePolicyReport, IndexError, PdfConformance, PdfError, PdfMetadata, PdfPage,
 };
 
 /// PDF document
@@ -21,8 +26,22 @@ pub struct PdfDocument {
     pub(super) icc_profiles: IccProfileList,
     /// Inner PDF document
     pub(super) inner_doc: lopdf::Document,
+    /// Files attached to the document: file name and file specification
+    pub(super) attached_files: Vec<(String, lopdf::ObjectId)>,
     /// Document ID. Must be changed if the document is loaded / parsed from a file
     pub document_id: String,
+    /// Whether the document is saved in reproducible mode, see `set_reproducible`
+    pub(super) reproducible: bool,
+    /// Dates that were set explicitly and are kept in reproducible mode
+    pub(super) explicit_dates: ExplicitDates,
+    /// Whether identical resources are only embedded once, see `set_resource_deduplication`
+    pub(super) deduplicate_resources: bool,
+    /// Embedded resources by content hash: object ID and the range of objects they consist of
+    pub(super) embedded_resources: HashMap<[u8; 16], EmbeddedObjects>,
+    /// Downsampling and recompression of images, see `set_image_policy`
+    pub(super) image_policy: Option<ImagePolicy>,
+    /// Images that the image policy changed, with their original streams
+    pub(super) processed_images: HashMap<lopdf::ObjectId, ProcessedImage>,
     /// Metadata for this document
     pub metadata: PdfMetadata,
 }
@@ -34,6 +53,13 @@ impl PdfDocument {
             pages: Vec::new(),
             document_id: random_character_string_32(),
             icc_profiles: IccProfileList::new(),
+            attached_files: Vec::new(),
+            reproducible: false,
+            explicit_dates: ExplicitDates::default(),
+            deduplicate_resources: true,
+            embedded_resources: HashMap::new(),
+            image_policy: None,
+            processed_images: HashMap::new(),
             inner_doc: lopdf::Document::with_version("1.3"),
             metadata: PdfMetadata::new(
                 document_title,
@@ -57,6 +83,78 @@ impl PdfDocument {
         self.metadata.document_title = new_title.into();
     }
 
+    /// Sets the author of the document
+    #[inline]
+    pub fn set_author<S>(&mut self, author: S)
+    where
+        S: Into<String>,
+    {
+        self.metadata.author = Some(author.into());
+    }
+
+    /// Sets the subject (short description) of the document
+    #[inline]
+    pub fn set_subject<S>(&mut self, subject: S)
+    where
+        S: Into<String>,
+    {
+        self.metadata.subject = Some(subject.into());
+    }
+
+    /// Sets the keywords of the document
+    #[inline]
+    pub fn set_keywords<I, S>(&mut self, keywords: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.metadata.keywords = keywords.into_iter().map(|k| k.into()).collect();
+    }
+
+    /// Sets the name of the application that created the original document
+    #[inline]
+    pub fn set_creator<S>(&mut self, creator: S)
+    where
+        S: Into<String>,
+    {
+        self.metadata.creator = Some(creator.into());
+    }
+
+    /// Sets the name of the application that converted the document to PDF
+    #[inline]
+    pub fn set_producer<S>(&mut self, producer: S)
+    where
+        S: Into<String>,
+    {
+        self.metadata.producer = Some(producer.into());
+    }
+
+    /// Adds a custom entry to the Info dictionary (and the XMP metadata).
+    /// Replaces an existing custom entry with the same key. The standard keys
+    /// (`Title`, `Author`, `Producer`, `CreationDate`, ...) are set with their
+    /// own setters, custom entries with these keys are ignored. The key is also the name
+    /// of the XMP property, keys that aren't XML names (e.g. with spaces) return
+    /// `PdfError::InvalidInfoKey`.
+    pub fn set_custom_info<S, T>(&mut self, key: S, value: T) -> ::std::result::Result<(), Error>
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        let key = key.into();
+        if is_reserved_info_key(&key) {
+            return Ok(());
+        }
+        if !is_valid_custom_info_key(&key) {
+            return Err(PdfError::InvalidInfoKey.into());
+        }
+        let value = value.into();
+        match self.metadata.custom_info.iter_mut().find(|e| e.0 == key) {
+            Some(entry) => entry.1 = value,
+            None => self.metadata.custom_info.push((key, value)),
+        }
+        Ok(())
+    }
+
     /// Set the trapping of the document
     #[inline]
     pub fn set_trapping(&mut self, trapping: bool) {
@@ -88,6 +186,7 @@ impl PdfDocument {
     #[inline]
     pub fn set_creation_date(&mut self, creation_date: OffsetDateTime) {
         self.metadata.creation_date = creation_date;
+        self.explicit_dates.creation = true;
     }
 
     /// Sets the modification date on the document. Intended to be used when
@@ -95,6 +194,44 @@ impl PdfDocument {
     #[inline]
     pub fn set_mod_date(&mut self, mod_date: OffsetDateTime) {
         self.metadata.modification_date = mod_date;
+        self.explicit_dates.modification = true;
+    }
+
+    /// Sets the date of the last metadata change
+    #[inline]
+    pub fn set_metadata_date(&mut self, metadata_date: OffsetDateTime) {
+        self.metadata.metadata_date = metadata_date;
+        self.explicit_dates.metadata = true;
+    }
+
+    /// Enables the reproducible mode: saving the same document twice produces
+    /// byte-identical files, e.g. for golden-file tests or content-addressed caches.
+    ///
+    /// - the document and instance IDs are derived from a hash of the file content
+    /// - the creation, modification and metadata dates that were not set with
+    ///   `set_creation_date`, `set_mod_date` or `set_metadata_date` are set to
+    ///   `SOURCE_DATE_EPOCH` (if the environment variable is set) or to the unix epoch
+    ///   when saving. Saving fails with `PdfError::InvalidDate` if `SOURCE_DATE_EPOCH`
+    ///   is not a timestamp between 1970 and 9999
+    /// - streams are always compressed, independent of the build profile
+    #[inline]
+    pub fn set_reproducible(&mut self, reproducible: bool) {
+        self.reproducible = reproducible;
+    }
+
+    /// Sets the policy for the resolution and compression of the images, which is applied when
+    /// the document is saved. Call `apply_image_policy` before saving to get a report
+    #[inline]
+    pub fn set_image_policy(&mut self, policy: ImagePolicy) {
+        self.image_policy = Some(policy);
+    }
+
+    /// Adds an ICC profile to the document, returns the reference for the `icc_profile` of
+    /// `Rgb`, `Cmyk` and `Greyscale` colors. Every profile is embedded once, adding the same
+    /// profile again returns the same reference
+    #[inline]
+    pub fn add_icc_profile(&mut self, profile: IccProfile) -> IccProfileRef {
+        self.icc_profiles.add_profile(profile)
     }
 
     // ----- ADD FUNCTIONS
@@ -105,10 +242,76 @@ impl PdfDocument {
         self.pages.push(page);
     }
 
-    /// Embed a resource
+    /// Enables or disables the deduplication of embedded resources (enabled by default).
+    /// If a resource is embedded that produces exactly the same objects as a resource that was
+    /// embedded before (for example the same logo on every page), the existing objects are
+    /// reused instead of writing them again
     #[inline]
-    pub fn embed<T: Embeddable>(&mut self, resource: T) -> lopdf::Result<Embedded<T>> {
-        super::pdf_resources::embed(&mut self.inner_doc, resource)
+    pub fn set_resource_deduplication(&mut self, deduplicate: bool) {
+        self.deduplicate_resources = deduplicate;
+    }
+
+    /// Embed a resource. Identical resources are only embedded once, see `set_resource_deduplication`
+    pub fn embed<T: Embeddable>(&mut self, resource: T) -> ::std::result::Result<Embedded<T>, Error> {
+        let max_id = self.inner_doc.max_id;
+        let first_id = max_id + 1;
+        let mut embedded = super::pdf_resources::embed(&mut self.inner_doc, resource)?;
+        let last_id = self.inner_doc.max_id;
+
+        // resources that didn't add objects (or reference older objects) can't be deduplicated
+        let root_id = embedded.object_id;
+        if !self.deduplicate_resources || root_id.0 < first_id || root_id.0 > last_id {
+            return Ok(embedded);
+        }
+
+        let added = EmbeddedObjects {
+            object_id: root_id,
+            first_id,
+            last_id,
+        };
+        let content = added.canonical_content(&self.inner_doc);
+        let hash = md5(&content);
+
+        match self.embedded_resources.get(&hash) {
+            Some(existing) if existing.canonical_content(&self.inner_doc) == content => {
+                // remove the new objects, the IDs are reused by the next resource
+                let created: Vec<lopdf::ObjectId> =
+                    self.inner_doc.objects.range((first_id, 0)..).map(|(id, _)| *id).collect();
+                for id in created {
+                    self.inner_doc.objects.remove(&id);
+                }
+                self.inner_doc.max_id = max_id;
+                embedded.object_id = existing.object_id;
+            }
+            Some(_) => {}
+            None => {
+                self.embedded_resources.insert(hash, added);
+            }
+        }
+
+        Ok(embedded)
+    }
+
+    /// Embeds a file and attaches it to the document: the file is listed in the
+    /// `/EmbeddedFiles` name tree (the attachment panel of PDF viewers) and in the
+    /// associated files (`/AF`) of the document, as required for PDF/A-3 documents
+    /// such as ZUGFeRD / Factur-X invoices.
+    ///
+    /// The returned file can additionally be shown on a page with `PdfPage::add_file_attachment`.
+    /// Saving fails with `PdfError::ConformanceViolation` if the conformance of the
+    /// document doesn't allow the file (see `check_for_errors`). Different files with the
+    /// same name are listed as "name (2)", "name (3)", ... in the name tree.
+    pub fn attach_file(
+        &mut self,
+        file: EmbeddedFile,
+    ) -> ::std::result::Result<Embedded<EmbeddedFile>, Error> {
+        let embedded = self.embed(file)?;
+        // attaching the same file again returns the deduplicated file specification
+        if !self.attached_files.iter().any(|(_, id)| *id == embedded.object_id) {
+            self.attached_files
+                .push((embedded.file_name.clone(), embedded.object_id));
+        }
+        Ok(embedded)
     }
 
     // ----- GET FUNCTIONS
@@ -122,17 +325,74 @@ impl PdfDocument {
 
     // --- MISC FUNCTIONS
 
-    /// Checks for invalid settings in the document
+    /// Checks for invalid settings in the document. Returns a `PdfError::ConformanceViolation`
+    /// if the document uses features that its conformance does not allow (currently only
+    /// embedded files are checked: PDF/A-2 only allows `application/pdf` files, PDF/A-3
+    /// requires a MIME type). Is called before the document is saved.
     pub fn check_for_errors(&self) -> ::std::result::Result<(), Error> {
-        // TODO
-        #[cfg(feature = "logging")]
-        {
-            warn!("Checking PDFs for errors is currently not supported!");
+        let conformance = &self.metadata.conformance;
+        let mime_types = embedded_file_mime_types(&self.inner_doc);
+
+        if !mime_types.is_empty() && !conformance.is_embedded_files_allowed() {
+            return Err(PdfError::ConformanceViolation.into());
+        }
+
+        let valid_mime_types = match conformance.get_pdfa_part() {
+            Some((2, _)) => mime_types.iter().all(|m| *m == Some(&b"application/pdf"[..])),
+            Some((3, _)) => mime_types.iter().all(Option::is_some),
+            _ => true,
+        };
+        if !valid_mime_types {
+            return Err(PdfError::ConformanceViolation.into());
         }
 
         Ok(())
     }
 
+    /// Downsamples and recompresses the images that are placed on the pages of the document,
+    /// according to the image policy (see `set_image_policy`). Processed images are kept, unless
+    /// the image was placed larger in the meantime: then it is processed again from the original
+    /// data. Needs the `image` feature, otherwise nothing is changed
+    pub fn apply_image_policy(&mut self) -> ::std::result::Result<ImagePolicyReport, Error> {
+        let policy = match self.image_policy {
+            Some(policy) => policy,
+            None => return Ok(ImagePolicyReport::default()),
+        };
+
+        let placements: Vec<_> = self
+            .pages
+            .iter()
+            .flat_map(|page| page.image_placements())
+            .collect();
+
+        // the resolution is computed from the original images
+        let mut processed = Vec::new();
+        for (id, image) in &self.processed_images {
+            if let Some(object) = self.inner_doc.objects.get_mut(id) {
+                let original = lopdf::Object::Stream(image.original.clone());
+                processed.push((*id, ::std::mem::replace(object, original)));
+            }
+        }
+
+        let mut dpi = effective_dpi(&self.inner_doc, &placements);
+
+        // images that aren't placed at a lower resolution than before stay processed
+        for (id, object) in processed {
+            let needs_update = match (self.processed_images.get(&id), dpi.get(&id)) {
+                (Some(image), Some(image_dpi)) => *image_dpi < image.dpi,
+                _ => false,
+            };
+            if needs_update {
+                self.processed_images.remove(&id);
+            } else {
+                self.inner_doc.objects.insert(id, object);
+                dpi.remove(&id);
+            }
+        }
+
+        apply_image_policy(&mut self.inner_doc, &dpi, &policy, &mut self.processed_images)
+    }
+
     /// Tries to match the document to the given conformance.
     /// Errors only on an unrecoverable error.
     pub fn repair_errors(
@@ -150,9 +410,38 @@ impl PdfDocument {
 
     /// Save PDF Document, writing the contents to the target
     pub fn save<W: Write>(
-        self,
+        mut self,
         target: &mut BufWriter<W>,
     ) -> ::std::result::Result<(), Error> {
+        self.check_for_errors()?;
+        self.apply_image_policy()?;
+
+        let instance_id = if self.reproducible {
+            let source_date_epoch = ::std::env::var("SOURCE_DATE_EPOCH").ok();
+            let date = reproducible_date(source_date_epoch.as_deref())?;
+            if !self.explicit_dates.creation {
+                self.metadata.creation_date = date;
+            }
+            if !self.explicit_dates.modification {
+                self.metadata.modification_date = date;
+            }
+            if !self.explicit_dates.metadata {
+                self.metadata.metadata_date = date;
+            }
+            // replaced by the content hash in into_lopdf_document
+            String::new()
+        } else {
+            random_character_string_32()
+        };
+
+        let mut doc = self.into_lopdf_document(instance_id)?;
+        doc.save_to(target)?;
+
+        Ok(())
+    }
+
+    /// Builds the final lopdf document (catalog, pages, metadata, trailer)
+    fn into_lopdf_document(mut self, mut instance_id: String) -> ::std::result::Result<lopdf::Document, Error> {
         use lopdf::Object::*;
         use lopdf::StringFormat::Literal;
         use lopdf::{Dictionary as LoDictionary, Object as LoObject};
@@ -160,10 +449,25 @@ impl PdfDocument {
 
         let mut doc = self.inner_doc;
 
-        // todo: remove unwrap, handle error
+        // embedded files without a modification date get the one of the document
+        let modification_date = to_pdf_date(&self.metadata.modification_date);
+        set_default_mod_dates(&mut doc, &modification_date);
+
         let pages_id = doc.new_object_id();
 
+        // in reproducible mode, the IDs are derived from the content, so they
+        // are left empty until all other objects are added
+        let reproducible_metadata = if self.reproducible {
+            self.document_id.clear();
+            self.metadata.xmp_metadata.document_id.clear();
+            self.metadata.xmp_metadata.instance_id = Some(::std::string::String::new());
+            Some(self.metadata.clone())
+        } else {
+            None
+        };
+
         // extra pdf infos
+        let is_pdf_a = self.metadata.conformance.is_pdf_a();
         let (xmp_metadata, document_info, icc_profile) = self.metadata.into_obj();
 
         let xmp_metadata_id = xmp_metadata.map(|m| doc.add_object(m.clone()));
@@ -176,8 +480,14 @@ impl PdfDocument {
         let icc_profile_str = "Coated FOGRA39 (ISO 12647-2:2004)";
         let icc_profile_short = "FOGRA39";
 
+        let output_intent_subtype = if is_pdf_a {
+            "GTS_PDFA1"
+        } else {
+            "GTS_PDFX"
+        };
+
         let mut output_intents = LoDictionary::from_iter(vec![
-            ("S", Name("GTS_PDFX".into())),
+            ("S", Name(output_intent_subtype.into())),
             ("OutputCondition", String(icc_profile_descr.into(), Literal)),
             ("Type", Name("OutputIntent".into())),
             (
@@ -209,6 +519,37 @@ impl PdfDocument {
             catalog.set("Metadata", Reference(metadata_id));
         }
 
+        // ----- EMBEDDED FILES
+
+        if !self.attached_files.is_empty() {
+            // the keys of a name tree have to be unique and sorted
+            let mut sorted_files = unique_file_names(&self.attached_files);
+            sorted_files.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let names = sorted_files
+                .into_iter()
+                .flat_map(|(name, id)| vec![String(name.into_bytes(), Literal), Reference(id)])
+                .collect();
+
+            catalog.set(
+                "Names",
+                Dictionary(LoDictionary::from_iter(vec![(
+                    "EmbeddedFiles",
+                    Dictionary(LoDictionary::from_iter(vec![("Names", Array(names))])),
+                )])),
+            );
+
+            catalog.set(
+                "AF",
+                Array(
+                    self.attached_files
+                        .iter()
+                        .map(|&(_, id)| Reference(id))
+                        .collect(),
+                ),
+            );
+        }
+
         let mut pages = LoDictionary::from_iter(vec![
             ("Type", "Pages".into()),
             ("Count", Integer(self.pages.len() as i64)),
@@ -258,7 +599,7 @@ impl PdfDocument {
                             Reference(doc.add_object(Dictionary(
                                 LoDictionary::from_iter(vec![
                                     ("Type", Name("OCG".into())),
-                                    ("Name", String(layer_name.into(), Literal)),
+                                    ("Name", to_pdf_text_string(&layer_name)),
                                     ("Intent", Reference(intent_arr_ref)),
                                     ("Usage", Reference(usage_ocg_dict_ref)),
                                 ]),
@@ -297,7 +638,13 @@ impl PdfDocument {
 
         // ----- PAGE CONTENT
 
-        for (idx, page) in self.pages.into_iter().enumerate() {
+        // only the profiles that are used by a page are embedded
+        let used_icc = self.pages.iter().flat_map(|page| page.icc_color_space_names()).collect();
+        let icc_color_spaces = self.icc_profiles.embed_color_spaces(&mut doc, &used_icc)?;
+
+        for (idx, mut page) in self.pages.into_iter().enumerate() {
+            page.add_color_spaces(&mut doc, &icc_color_spaces)?;
+
             let mut p = LoDictionary::from_iter(vec![
                 ("Type", "Page".into()),
                 ("Rotate", Integer(0)),
@@ -319,10 +666,27 @@ impl PdfDocument {
                 ("Parent", Reference(pages_id)),
             ]);
 
+            let annotations: Vec<LoObject> = ::std::mem::take(&mut page.annotations)
+                .into_iter()
+                .map(|(annotation, file_spec)| Reference(annotation.into_obj(&mut doc, file_spec)))
+                .collect();
+
+            if !annotations.is_empty() {
+                p.set("Annots", Array(annotations));
+            }
+
+            if !page.associated_files.is_empty() {
+                let files = page.associated_files.iter().map(|id| Reference(*id)).collect();
+                p.set("AF", Array(files));
+            }
+
             // this will collect the resources needed for rendering this page
-            let layers_temp = ocg_list.iter().find(|e| e.0 == idx).unwrap();
+            let layers_temp = ocg_list
+                .iter()
+                .find(|e| e.0 == idx)
+                .ok_or(IndexError::PdfPageIndexError)?;
             let (resources_page, layer_streams) =
-                page.collect_resources_and_streams(&layers_temp.1);
+                page.collect_resources_and_streams(&layers_temp.1)?;
 
             if resources_page.len() > 0 {
                 let resources_page_id = doc.add_object(Dictionary(resources_page));
@@ -352,10 +716,23 @@ impl PdfDocument {
 
         // save inner document
         let catalog_id = doc.add_object(catalog);
-        let instance_id = random_character_string_32();
 
         doc.trailer.set("Root", Reference(catalog_id));
         doc.trailer.set("Info", Reference(document_info_id));
+
+        if let Some(mut metadata) = reproducible_metadata {
+            let content_id = content_hash_id(&doc);
+            if let Some(xmp_metadata_id) = xmp_metadata_id {
+                metadata.xmp_metadata.document_id = content_id.clone();
+                metadata.xmp_metadata.instance_id = Some(content_id.clone());
+                if let (Some(xmp_metadata), _, _) = metadata.into_obj() {
+                    doc.objects.insert(xmp_metadata_id, xmp_metadata);
+                }
+            }
+            self.document_id = content_id.clone();
+            instance_id = content_id;
+        }
+
         doc.trailer.set(
             "ID",
             Array(vec![
@@ -364,11 +741,17 @@ impl PdfDocument {
             ]),
         );
 
-        // does nothing in debug mode, optimized in release mode
-        Self::optimize(&mut doc);
-        doc.save_to(target)?;
+        if self.reproducible {
+            // same output in debug and release mode
+            doc.prune_objects();
+            doc.delete_zero_length_streams();
+            doc.compress();
+        } else {
+            // does nothing in debug mode, optimized in release mode
+            Self::optimize(&mut doc);
+        }
 
-        Ok(())
+        Ok(doc)
     }
 
     #[cfg(any(debug_assertions, feature = "less-optimization"))]
@@ -383,3 +766,528 @@ impl PdfDocument {
         doc.compress();
     }
 }
+
+/// Creation, modification and metadata dates that were set explicitly
+#[derive(Debug, Default, Copy, Clone)]
+pub(super) struct ExplicitDates {
+    creation: bool,
+    modification: bool,
+    metadata: bool,
+}
+
+/// Latest timestamp that can be written as a PDF date (9999-12-31T23:59:59Z)
+const MAX_PDF_TIMESTAMP: i64 = 253_402_300_799;
+
+/// Date used in reproducible mode: `SOURCE_DATE_EPOCH` if set, otherwise the unix epoch
+/// MIME types (`/Subtype`) of the embedded file streams of the document
+fn embedded_file_mime_types(doc: &lopdf::Document) -> Vec<Option<&[u8]>> {
+    doc.objects
+        .values()
+        .filter_map(|o| o.as_stream().ok())
+        .filter(|s| s.dict.get(b"Type").and_then(|t| t.as_name()).ok() == Some(&b"EmbeddedFile"[..]))
+        .map(|s| s.dict.get(b"Subtype").and_then(|t| t.as_name()).ok())
+        .collect()
+}
+
+/// Sets the `/ModDate` of embedded file streams that don't have one (required for PDF/A-3)
+fn set_default_mod_dates(doc: &mut lopdf::Document, modification_date: &str) {
+    use lopdf::Object::*;
+    use lopdf::StringFormat::Literal;
+
+    for object in doc.objects.values_mut() {
+        let stream = match object.as_stream_mut() {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if stream.dict.get(b"Type").and_then(|t| t.as_name()).ok() != Some(&b"EmbeddedFile"[..]) {
+            continue;
+        }
+        if let Ok(params) = stream.dict.get_mut(b"Params").and_then(|p| p.as_dict_mut()) {
+            if params.get(b"ModDate").is_err() {
+                params.set("ModDate", String(modification_date.as_bytes().to_vec(), Literal));
+            }
+        }
+    }
+}
+
+/// Makes the names of the attached files unique for the `/EmbeddedFiles` name tree,
+/// by appending " (2)", " (3)", ... to the names that are used more than once
+fn unique_file_names(files: &[(String, lopdf::ObjectId)]) -> Vec<(String, lopdf::ObjectId)> {
+    let mut unique: Vec<(String, lopdf::ObjectId)> = Vec::with_capacity(files.len());
+    for (name, id) in files {
+        let mut key = name.clone();
+        let mut suffix = 2;
+        while unique.iter().any(|(k, _)| *k == key) || (key != *name && files.iter().any(|(n, _)| *n == key)) {
+            key = format!("{} ({})", name, suffix);
+            suffix += 1;
+        }
+        unique.push((key, *id));
+    }
+    unique
+}
+
+fn reproducible_date(source_date_epoch: Option<&str>) -> ::std::result::Result<OffsetDateTime, Error> {
+    let timestamp = match source_date_epoch {
+        Some(value) => value.trim().parse::<i64>().map_err(|_| PdfError::InvalidDate)?,
+        None => 0,
+    };
+    if !(0..=MAX_PDF_TIMESTAMP).contains(&timestamp) {
+        return Err(PdfError::InvalidDate.into());
+    }
+    Ok(OffsetDateTime::from_unix_timestamp(timestamp))
+}
+
+/// Hash of all objects of the document (with empty IDs), used as the
+/// document and instance ID in reproducible mode
+fn content_hash_id(doc: &lopdf::Document) -> String {
+    // an empty object range, all references are written as absolute object numbers
+    let absolute = EmbeddedObjects { object_id: (0, 0), first_id: 1, last_id: 0 };
+    let mut content = Vec::new();
+    for (id, object) in &doc.objects {
+        write_canonical_id(*id, &absolute, &mut content);
+        write_canonical(object, &absolute, &mut content);
+    }
+    md5(&content).iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Objects that were added to the document by embedding one resource
+#[derive(Debug, Copy, Clone)]
+pub(super) struct EmbeddedObjects {
+    /// Object ID of the resource
+    object_id: lopdf::ObjectId,
+    /// First and last object number of the added objects
+    first_id: u32,
+    last_id: u32,
+}
+
+impl EmbeddedObjects {
+    /// Serializes the objects, with the references between them relative to the
+    /// first object, so that identical resources have the same content
+    fn canonical_content(&self, doc: &lopdf::Document) -> Vec<u8> {
+        let mut content = Vec::new();
+        write_canonical_id(self.object_id, self, &mut content);
+        for id in self.first_id..=self.last_id {
+            match doc.objects.get(&(id, 0)) {
+                Some(object) => write_canonical(object, self, &mut content),
+                None => content.push(b'-'),
+            }
+        }
+        content
+    }
+}
+
+fn write_canonical_id(id: lopdf::ObjectId, objects: &EmbeddedObjects, out: &mut Vec<u8>) {
+    if id.0 >= objects.first_id && id.0 <= objects.last_id {
+        out.push(b'r');
+        out.extend_from_slice(&(id.0 - objects.first_id).to_be_bytes());
+    } else {
+        out.push(b'R');
+        out.extend_from_slice(&id.0.to_be_bytes());
+    }
+    out.extend_from_slice(&id.1.to_be_bytes());
+}
+
+// lossless serialization (tag, length-prefixed values) that is only used for comparing objects
+fn write_canonical(object: &lopdf::Object, objects: &EmbeddedObjects, out: &mut Vec<u8>) {
+    use lopdf::Object::*;
+
+    let write_bytes = |bytes: &[u8], out: &mut Vec<u8>| {
+        out.extend_from_slice(&(bytes.len() as u64).to_be_bytes());
+        out.extend_from_slice(bytes);
+    };
+
+    let write_dict = |dict: &lopdf::Dictionary, out: &mut Vec<u8>| {
+        out.push(b'd');
+        out.extend_from_slice(&(dict.len() as u64).to_be_bytes());
+        for (key, value) in dict {
+            write_bytes(key, out);
+            write_canonical(value, objects, out);
+        }
+    };
+
+    match object {
+        Null => out.push(b'n'),
+        Boolean(value) => out.extend_from_slice(&[b'b', *value as u8]),
+        Integer(value) => {
+            out.push(b'i');
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        Real(value) => {
+            out.push(b'f');
+            out.extend_from_slice(&value.to_bits().to_be_bytes());
+        }
+        Name(name) => {
+            out.push(b'/');
+            write_bytes(name, out);
+        }
+        String(text, format) => {
+            out.push(match format {
+                lopdf::StringFormat::Literal => b's',
+                lopdf::StringFormat::Hexadecimal => b'h',
+            });
+            write_bytes(text, out);
+        }
+        Array(array) => {
+            out.push(b'a');
+            out.extend_from_slice(&(array.len() as u64).to_be_bytes());
+            for item in array {
+                write_canonical(item, objects, out);
+            }
+        }
+        Dictionary(dict) => write_dict(dict, out),
+        Stream(stream) => {
+            out.push(b'S');
+            out.push(stream.allows_compression as u8);
+            write_dict(&stream.dict, out);
+            write_bytes(&stream.content, out);
+        }
+        Reference(id) => write_canonical_id(*id, objects, out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Mm, PdfLayer};
+
+    fn reproducible_document(title: &str) -> Vec<u8> {
+        let mut doc = PdfDocument::new(title);
+        doc.set_reproducible(true);
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        page.add_layer(PdfLayer::new("Layer 1"));
+        doc.add_page(page);
+
+        let mut target = BufWriter::new(Vec::new());
+        doc.save(&mut target).unwrap();
+        target.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_reproducible_save() {
+        let first = reproducible_document("reproducible");
+        let second = reproducible_document("reproducible");
+        let other = reproducible_document("other");
+        assert!(first == second);
+        assert!(first != other);
+    }
+
+    #[test]
+    fn test_reproducible_ids_and_dates() {
+        let creation_date = OffsetDateTime::from_unix_timestamp(1_500_000_000);
+        let mut doc = PdfDocument::new("reproducible");
+        doc.set_creation_date(creation_date);
+        doc.set_reproducible(true);
+
+        let mut target = BufWriter::new(Vec::new());
+        doc.clone().save(&mut target).unwrap();
+        let bytes = target.into_inner().unwrap();
+        let content = ::std::string::String::from_utf8_lossy(&bytes);
+        assert!(content.contains(&::date::to_pdf_date(&creation_date)));
+
+        let lopdf_doc = doc.into_lopdf_document(::std::string::String::new()).unwrap();
+        let ids = lopdf_doc.trailer.get(b"ID").unwrap().as_array().unwrap();
+        let document_id = ids[0].as_str().unwrap();
+        assert_eq!(document_id.len(), 32);
+        assert_eq!(ids[1].as_str().unwrap(), document_id);
+
+        assert_eq!(reproducible_date(None).unwrap().unix_timestamp(), 0);
+        assert_eq!(reproducible_date(Some(" 1500000000\n")).unwrap(), creation_date);
+        assert!(reproducible_date(Some("-1")).is_err());
+        assert!(reproducible_date(Some("99999999999999")).is_err());
+        assert!(reproducible_date(Some("yesterday")).is_err());
+    }
+
+    #[test]
+    fn test_custom_info_reserved_keys() {
+        let mut doc = PdfDocument::new("title");
+        doc.set_producer("printpdf");
+        doc.set_custom_info("Producer", "someone else").unwrap();
+        doc.set_custom_info("CreationDate", "D:19700101000000Z").unwrap();
+        doc.set_custom_info("Invoice", "1234").unwrap();
+        assert_eq!(doc.metadata.custom_info, vec![("Invoice".to_string(), "1234".to_string())]);
+
+        // keys that aren't XML names are rejected
+        match doc.set_custom_info("Invoice Number", "1234") {
+            Err(Error::Pdf(PdfError::InvalidInfoKey)) => {}
+            _ => panic!("expected an invalid key"),
+        }
+        assert!(doc.set_custom_info("1st", "1234").is_err());
+
+        // entries pushed directly into the metadata are skipped on save
+        doc.metadata.custom_info.push(("Title".into(), "other title".into()));
+        doc.metadata.custom_info.push(("Invoice Number".into(), "1234".into()));
+        let creation_date = ::date::to_pdf_date(&doc.metadata.creation_date);
+
+        let lopdf_doc = doc.into_lopdf_document(String::new()).unwrap();
+        let info_id = lopdf_doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = lopdf_doc.get_dictionary(info_id).unwrap();
+        assert_eq!(info.get(b"Producer").unwrap().as_str().unwrap(), b"printpdf");
+        assert_eq!(info.get(b"Title").unwrap().as_str().unwrap(), b"title");
+        assert_eq!(info.get(b"CreationDate").unwrap().as_str().unwrap(), creation_date.as_bytes());
+        assert_eq!(info.get(b"Invoice").unwrap().as_str().unwrap(), b"1234");
+        assert!(info.get(b"Invoice Number").is_err());
+    }
+
+    #[test]
+    fn test_attached_file_conformance_violation() {
+        let mut doc = PdfDocument::new("attachments");
+        doc.set_conformance(PdfConformance::A1B_2005_PDF_1_4);
+        doc.attach_file(EmbeddedFile::new("invoice.xml", b"<invoice/>".to_vec())).unwrap();
+
+        match doc.save(&mut BufWriter::new(Vec::new())) {
+            Err(Error::Pdf(PdfError::ConformanceViolation)) => {}
+            _ => panic!("expected a conformance violation"),
+        }
+
+        // PDF/A-2 only allows PDF files, PDF/A-3 requires a MIME type
+        let attachment = |conformance: PdfConformance, file: EmbeddedFile| {
+            let mut doc = PdfDocument::new("attachments");
+            doc.set_conformance(conformance);
+            doc.attach_file(file).unwrap();
+            doc.check_for_errors()
+        };
+        let xml = || EmbeddedFile::new("invoice.xml", b"<invoice/>".to_vec()).with_mime_type("text/xml");
+        let pdf = EmbeddedFile::new("invoice.pdf", b"%PDF-1.7".to_vec()).with_mime_type("application/pdf");
+        assert!(attachment(PdfConformance::A2B_2011_PDF_1_7, xml()).is_err());
+        assert!(attachment(PdfConformance::A2B_2011_PDF_1_7, pdf).is_ok());
+        assert!(attachment(PdfConformance::A3_2012_PDF_1_7, xml()).is_ok());
+        let no_mime_type = EmbeddedFile::new("invoice.xml", b"<invoice/>".to_vec());
+        assert!(attachment(PdfConformance::A3_2012_PDF_1_7, no_mime_type).is_err());
+    }
+
+    #[test]
+    fn test_attached_files_in_saved_document() {
+        use {AFRelationship, FileAttachmentAnnotation};
+
+        let mut doc = PdfDocument::new("attachments");
+        let file = EmbeddedFile::new("invoice.xml", b"<invoice/>".to_vec())
+            .with_relationship(AFRelationship::Data);
+        let file = doc.attach_file(file).unwrap();
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        page.add_layer(PdfLayer::new("Layer 1"));
+        let annotation = FileAttachmentAnnotation::new(Mm(10.0), Mm(10.0), Mm(5.0), Mm(5.0)).with_contents("Invoice");
+        page.add_file_attachment(annotation, &file);
+        doc.add_page(page);
+        // a different file with the same name
+        doc.attach_file(EmbeddedFile::new("invoice.xml", b"<invoice>2</invoice>".to_vec())).unwrap();
+        let modification_date = to_pdf_date(&doc.metadata.modification_date);
+
+        let saved = doc.into_lopdf_document(String::new()).unwrap();
+
+        let catalog = saved.catalog().unwrap();
+        let names = catalog.get(b"Names").unwrap().as_dict().unwrap();
+        let embedded_files = names.get(b"EmbeddedFiles").unwrap().as_dict().unwrap();
+        let tree = embedded_files.get(b"Names").unwrap().as_array().unwrap();
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree[0].as_str().unwrap(), b"invoice.xml");
+        assert_eq!(tree[2].as_str().unwrap(), b"invoice.xml (2)");
+        let file_spec_id = tree[1].as_reference().unwrap();
+
+        let document_files = catalog.get(b"AF").unwrap().as_array().unwrap();
+        assert_eq!(document_files.len(), 2);
+        assert_eq!(document_files[0].as_reference().unwrap(), file_spec_id);
+
+        let file_spec = saved.get_dictionary(file_spec_id).unwrap();
+        assert_eq!(file_spec.get(b"AFRelationship").unwrap().as_name_str().unwrap(), "Data");
+        let ef = file_spec.get(b"EF").unwrap().as_dict().unwrap();
+        let stream = saved.get_object(ef.get(b"F").unwrap().as_reference().unwrap()).unwrap().as_stream().unwrap();
+        assert_eq!(stream.content, b"<invoice/>".to_vec());
+        let params = stream.dict.get(b"Params").unwrap().as_dict().unwrap();
+        assert_eq!(params.get(b"ModDate").unwrap().as_str().unwrap(), modification_date.as_bytes());
+
+        let page_id = *saved.get_pages().values().next().unwrap();
+        let page = saved.get_dictionary(page_id).unwrap();
+        let page_files = page.get(b"AF").unwrap().as_array().unwrap();
+        assert_eq!(page_files[0].as_reference().unwrap(), file_spec_id);
+        let annotations = page.get(b"Annots").unwrap().as_array().unwrap();
+        let annotation = match annotations[0] {
+            lopdf::Object::Reference(id) => saved.get_dictionary(id).unwrap(),
+            ref object => object.as_dict().unwrap(),
+        };
+        assert_eq!(annotation.get(b"Subtype").unwrap().as_name_str().unwrap(), "FileAttachment");
+        assert_eq!(annotation.get(b"FS").unwrap().as_reference().unwrap(), file_spec_id);
+    }
+
+    #[test]
+    fn test_resource_deduplication() {
+        use {ColorBits, ColorSpace, ImageXObject, Px, SMask};
+
+        let image = || {
+            let data = vec![0; 6];
+            let mut image = ImageXObject::new(Px(2), Px(1), ColorSpace::Rgb, ColorBits::Bit8, true, None, None, data);
+            image.soft_mask = Some(SMask {
+                width: Px(2),
+                height: Px(1),
+                interpolate: true,
+                bits_per_component: ColorBits::Bit8,
+                matte: Vec::new(),
+                image_data: vec![0, 255],
+                image_filter: None,
+                decode_parms: None,
+            });
+            image
+        };
+
+        let mut doc = PdfDocument::new("deduplication");
+        let first = doc.embed(image()).unwrap();
+        let objects = doc.inner_doc.objects.len();
+        let max_id = doc.inner_doc.max_id;
+        let second = doc.embed(image()).unwrap();
+        assert_eq!(first.object_id, second.object_id);
+        assert_eq!(doc.inner_doc.objects.len(), objects);
+        assert_eq!(doc.inner_doc.max_id, max_id);
+
+        let mut other = image();
+        other.image_data = vec![255; 6];
+        let third = doc.embed(other).unwrap();
+        assert_ne!(first.object_id, third.object_id);
+        assert_eq!(doc.inner_doc.objects.len(), 2 * objects);
+
+        doc.set_resource_deduplication(false);
+        let fourth = doc.embed(image()).unwrap();
+        assert_ne!(first.object_id, fourth.object_id);
+
+        // attaching the same file twice lists it once
+        doc.set_resource_deduplication(true);
+        let file = || EmbeddedFile::new("data.csv", b"a,b".to_vec());
+        let attached = doc.attach_file(file()).unwrap();
+        assert_eq!(doc.attach_file(file()).unwrap().object_id, attached.object_id);
+        assert_eq!(doc.attached_files.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_image_policy_placed_larger() {
+        use {ColorBits, ColorSpace, Image, ImageFit, ImagePlacement, ImageXObject, Px};
+
+        let mut doc = PdfDocument::new("image policy");
+        doc.set_image_policy(ImagePolicy::downsample(150.0, 100.0));
+        let data = (0..100 * 100u32).map(|i| (i % 251) as u8).collect();
+        let image = ImageXObject::new(Px(100), Px(100), ColorSpace::Greyscale, ColorBits::Bit8, false, None, None, data);
+        let image = doc.embed(::std::rc::Rc::new(Image::from(image))).unwrap();
+
+        let place = |doc: &mut PdfDocument, size: Mm| {
+            let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+            let registered = page.register(&image).unwrap();
+            let mut layer = PdfLayer::new("Layer 1");
+            let placement = ImagePlacement::in_rect(Mm(0.0), Mm(0.0), size, size).with_fit(ImageFit::Stretch);
+            layer.place_image(&registered, &placement);
+            page.add_layer(layer);
+            doc.add_page(page);
+        };
+        let width = |doc: &PdfDocument| {
+            let stream = doc.inner_doc.get_object(image.object_id).and_then(|o| o.as_stream()).unwrap();
+            stream.dict.get(b"Width").and_then(|o| o.as_i64()).unwrap()
+        };
+
+        // 100 px in a quarter inch: 400 DPI, downsampled to 100 DPI
+        place(&mut doc, Mm(6.35));
+        assert_eq!(doc.apply_image_policy().unwrap().images_processed, 1);
+        assert_eq!(width(&doc), 25);
+
+        // placed in half an inch: processed again from the original data
+        place(&mut doc, Mm(12.7));
+        assert_eq!(doc.apply_image_policy().unwrap().images_processed, 1);
+        assert_eq!(width(&doc), 50);
+
+        assert_eq!(doc.apply_image_policy().unwrap().images_processed, 0);
+        assert_eq!(width(&doc), 50);
+    }
+
+    #[test]
+    fn test_icc_based_colors() {
+        use lopdf::Object;
+        use {Color, IccProfileType, Rgb};
+
+        let mut doc = PdfDocument::new("icc colors");
+        let profile = IccProfile::new(vec![0; 128], IccProfileType::Rgb).with_range(true);
+        let icc = doc.add_icc_profile(profile.clone());
+        assert_eq!(doc.add_icc_profile(profile), icc);
+        // not used by any color, so it isn't embedded
+        doc.add_icc_profile(IccProfile::new(vec![1; 128], IccProfileType::Cmyk));
+        let mismatched = doc.clone();
+
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        let mut layer = PdfLayer::new("Layer 1");
+        layer.set_fill_color(Color::Rgb(Rgb::new(1.0, 0.5, 0.0, Some(icc.clone()))));
+        layer.set_outline_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        let operators: Vec<&str> = layer.operations.iter().map(|op| op.operator.as_str()).collect();
+        assert_eq!(operators, ["cs", "scn", "RG"]);
+        page.add_layer(layer);
+        doc.add_page(page);
+
+        let lopdf_doc = doc.into_lopdf_document(String::new()).unwrap();
+        let color_space = lopdf_doc
+            .objects
+            .values()
+            .filter_map(|o| o.as_dict().ok())
+            .filter_map(|d| d.get(b"ColorSpace").and_then(Object::as_dict).ok())
+            .find_map(|d| d.get(b"ICC0").ok())
+            .and_then(|o| o.as_array().ok())
+            .expect("ICC color space resource");
+        let profile = lopdf_doc.get_object(color_space[1].as_reference().unwrap()).unwrap();
+        let profile = profile.as_stream().unwrap();
+        assert_eq!(profile.dict.get(b"N").and_then(Object::as_i64).unwrap(), 3);
+        assert_eq!(profile.dict.get(b"Range").and_then(Object::as_array).unwrap().len(), 6);
+        let mut streams = lopdf_doc.objects.values().filter_map(|o| o.as_stream().ok());
+        assert!(!streams.any(|s| s.content == vec![1; 128]));
+
+        // a greyscale color in the RGB profile
+        let mut doc = mismatched;
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        let mut layer = PdfLayer::new("Layer 1");
+        layer.set_outline_color(Color::Greyscale(::Greyscale::new(0.5, Some(icc))));
+        page.add_layer(layer);
+        doc.add_page(page);
+        match doc.into_lopdf_document(String::new()) {
+            Err(Error::Pdf(PdfError::InvalidColor)) => {}
+            _ => panic!("expected an invalid color"),
+        }
+    }
+
+    #[test]
+    fn test_cie_colors() {
+        use {Color, ImageColorSpace, Lab, LabColorSpace};
+
+        let mut doc = PdfDocument::new("lab colors");
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        let mut layer = PdfLayer::new("Layer 1");
+        layer.set_fill_color(Color::Lab(Lab::new(50.0, 20.0, -30.0)));
+        layer.set_outline_color(Color::Lab(Lab::new(20.0, 0.0, 0.0)));
+        let name = layer.operations[0].operands[0].as_name().unwrap().to_vec();
+        assert_eq!(layer.operations[2].operands[0].as_name().unwrap(), &name[..]);
+        page.add_layer(layer);
+        doc.add_page(page);
+
+        let lopdf_doc = doc.into_lopdf_document(String::new()).unwrap();
+        let color_spaces = lopdf_doc
+            .objects
+            .values()
+            .filter_map(|o| o.as_dict().ok())
+            .find_map(|d| d.get(b"ColorSpace").and_then(|o| o.as_dict()).ok())
+            .expect("color space resources");
+        assert_eq!(color_spaces.len(), 1);
+        let lab = color_spaces.get(&name).and_then(|o| o.as_array()).unwrap();
+        assert_eq!(lab[0].as_name().unwrap(), b"Lab");
+
+        // the name only depends on the parameters
+        let negative_zero = LabColorSpace { black_point: [-0.0; 3], ..LabColorSpace::default() };
+        let lab = Color::Lab(Lab::new(0.0, 0.0, 0.0).with_color_space(negative_zero));
+        let (same_name, _) = lab.cie_color_space().unwrap();
+        assert_eq!(same_name.as_bytes(), &name[..]);
+
+        // different parameters with the same name are rejected
+        let mut doc = PdfDocument::new("lab colors");
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        let mut layer = PdfLayer::new("Layer 1");
+        let wide = LabColorSpace { range: [-128.0, 127.0, -128.0, 127.0], ..LabColorSpace::default() };
+        layer.color_spaces.push((same_name.clone(), ImageColorSpace::Lab(LabColorSpace::default())));
+        layer.color_spaces.push((same_name, ImageColorSpace::Lab(wide)));
+        page.add_layer(layer);
+        doc.add_page(page);
+        match doc.into_lopdf_document(String::new()) {
+            Err(Error::Pdf(PdfError::InvalidResource)) => {}
+            _ => panic!("expected a color space name clash"),
+        }
+    }
+}
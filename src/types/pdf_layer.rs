@@ -2,14 +2,20 @@
 
 use lopdf;
 use std::borrow::Borrow;
+use std::convert::TryFrom;
 use types::RegisteredXObject;
 
-use glob_defines::OP_PATH_STATE_SET_LINE_WIDTH;
+use glob_defines::{
+    OP_COLOR_SET_FILL_COLOR_ICC, OP_COLOR_SET_FILL_CS, OP_COLOR_SET_STROKE_COLOR_ICC,
+    OP_COLOR_SET_STROKE_CS, OP_PATH_CONST_CLIP_NZ, OP_PATH_CONST_RECT, OP_PATH_PAINT_END,
+    OP_PATH_STATE_SET_LINE_WIDTH,
+};
 use lopdf::content::Operation;
+use super::plugins::graphics::xobject::OP_INLINE_IMAGE;
 use {
-    Color, CurTransMat, ExtendedGraphicsState, Font, Image, Line, LineCapStyle,
-    LineDashPattern, LineJoinStyle, Mm, PdfColor, Pt, Registered, TextMatrix,
-    TextRenderingMode,
+    Color, CurTransMat, DashPattern, Error, ExtendedGraphicsState, Font, Image, ImageColorSpace,
+    ImagePlacement, ImageXObject, Line, LineCapStyle, LineJoinStyle, Mm, Path, PdfColor, Pt, Px,
+    PdfError, Registered, TextMatrix, TextRenderingMode,
 };
 
 /// One layer of PDF data
@@ -19,6 +25,8 @@ pub struct PdfLayer {
     pub(crate) name: String,
     /// Stream objects in this layer. Usually, one layer == one stream
     pub(super) operations: Vec<Operation>,
+    /// CIE-based color spaces of the colors in this layer, by resource name
+    pub(super) color_spaces: Vec<(String, ImageColorSpace)>,
 }
 
 impl PdfLayer {
@@ -31,21 +39,52 @@ impl PdfLayer {
         Self {
             name: name.into(),
             operations: Vec::new(),
+            color_spaces: Vec::new(),
         }
     }
 }
 
-impl Into<lopdf::Stream> for PdfLayer {
-    fn into(self) -> lopdf::Stream {
+impl TryFrom<PdfLayer> for lopdf::Stream {
+    type Error = Error;
+
+    fn try_from(layer: PdfLayer) -> Result<Self, Error> {
         use lopdf::{Dictionary, Stream};
-        let stream_content = lopdf::content::Content {
-            operations: self.operations,
-        };
+        let stream_content = encode_content(layer.operations)?;
 
         // page contents may not be compressed (todo: is this valid for XObjects?)
-        Stream::new(Dictionary::new(), stream_content.encode().unwrap())
-            .with_compression(false)
+        Ok(Stream::new(Dictionary::new(), stream_content).with_compression(false))
+    }
+}
+
+/// Encodes the operations of a content stream. Inline images (`OP_INLINE_IMAGE`) are
+/// written as `BI` <dictionary> `ID` <data> `EI`, the other operations by lopdf
+pub(crate) fn encode_content(operations: Vec<Operation>) -> Result<Vec<u8>, Error> {
+    use lopdf::content::Content;
+
+    let mut content = Vec::new();
+    let mut pending = Vec::new();
+
+    for mut operation in operations {
+        if operation.operator != OP_INLINE_IMAGE || operation.operands.is_empty() {
+            pending.push(operation);
+            continue;
+        }
+
+        let data = match operation.operands.pop() {
+            Some(lopdf::Object::String(data, _)) => data,
+            _ => return Err(PdfError::InvalidInlineImage.into()),
+        };
+
+        content.extend(Content { operations: pending.split_off(0) }.encode()?);
+        content.extend_from_slice(b"BI\n");
+        // encodes the dictionary as `/W 2 /H 1 ... ID\n`
+        content.extend(Content { operations: vec![Operation::new("ID", operation.operands)] }.encode()?);
+        content.extend(data);
+        content.extend_from_slice(b"\nEI\n");
     }
+
+    content.extend(Content { operations: pending }.encode()?);
+    Ok(content)
 }
 
 impl PdfLayer {
@@ -58,6 +97,13 @@ impl PdfLayer {
         }
     }
 
+    /// Add a path with several subpaths, curves and arcs to the layer
+    pub fn add_path(&mut self, path: Path) {
+        for op in path.into_stream_op() {
+            self.internal_add_operation(op);
+        }
+    }
+
     /// Begins a new text section
     /// You have to make sure to call `end_text_section` afterwards
     #[inline]
@@ -72,10 +118,12 @@ impl PdfLayer {
         self.internal_add_operation(Operation::new("ET", vec![]));
     }
 
-    /// Set the current fill color for the layer
+    /// Set the current fill color for the layer. Colors with an ICC profile (see
+    /// `PdfDocument::add_icc_profile`) are set in the `/ICCBased` color space of the profile,
+    /// `Lab`, `CalRgb` and `CalGray` colors in their CIE-based color space
     #[inline]
     pub fn set_fill_color(&mut self, fill_color: Color) -> () {
-        self.internal_add_operation(PdfColor::FillColor(fill_color));
+        self.add_color(PdfColor::FillColor(fill_color));
     }
 
     /// Set the current font, only valid in a `begin_text_section` to
@@ -94,7 +142,64 @@ impl PdfLayer {
     /// Set the current line / outline color for the layer
     #[inline]
     pub fn set_outline_color(&mut self, color: Color) {
-        self.internal_add_operation(PdfColor::OutlineColor(color));
+        self.add_color(PdfColor::OutlineColor(color));
+    }
+
+    // remembers the color space of CIE-based colors for the page resources
+    fn add_color(&mut self, color: PdfColor) {
+        let cie_color_space = match color {
+            PdfColor::FillColor(ref color) | PdfColor::OutlineColor(ref color) => color.cie_color_space(),
+        };
+        if let Some(color_space) = cie_color_space {
+            if !self.color_spaces.contains(&color_space) {
+                self.color_spaces.push(color_space);
+            }
+        }
+
+        for operation in color.into_operations() {
+            self.internal_add_operation(operation);
+        }
+    }
+
+    /// Sets the fill color in a special color space that was registered on the page (`cs` /
+    /// `scn`), for example a tint value of a `Separation` (spot color). The color has one value
+    /// per component of the color space, otherwise `PdfError::InvalidColor` is returned
+    pub fn set_fill_special_color<C>(&mut self, color_space: &Registered<C>, color: Vec<f64>) -> Result<(), Error>
+    where
+        C: Borrow<ImageColorSpace>,
+    {
+        self.add_special_color(color_space, color, OP_COLOR_SET_FILL_CS, OP_COLOR_SET_FILL_COLOR_ICC)
+    }
+
+    /// Sets the outline color in a special color space (`CS` / `SCN`), see `set_fill_special_color`
+    pub fn set_outline_special_color<C>(&mut self, color_space: &Registered<C>, color: Vec<f64>) -> Result<(), Error>
+    where
+        C: Borrow<ImageColorSpace>,
+    {
+        self.add_special_color(color_space, color, OP_COLOR_SET_STROKE_CS, OP_COLOR_SET_STROKE_COLOR_ICC)
+    }
+
+    fn add_special_color<C>(
+        &mut self,
+        color_space: &Registered<C>,
+        color: Vec<f64>,
+        set_cs: &str,
+        set_color: &str,
+    ) -> Result<(), Error>
+    where
+        C: Borrow<ImageColorSpace>,
+    {
+        if color.len() != color_space.object.borrow().num_components() {
+            return Err(PdfError::InvalidColor.into());
+        }
+
+        let name = format!("R{}", color_space.name_index);
+        self.internal_add_operation(Operation::new(set_cs, vec![lopdf::Object::Name(name.into_bytes())]));
+        self.internal_add_operation(Operation::new(
+            set_color,
+            color.into_iter().map(lopdf::Object::Real).collect(),
+        ));
+        Ok(())
     }
 
     /// Instantiate layers, forms and postscript items on the page
@@ -117,22 +222,32 @@ impl PdfLayer {
         // save graphics state
         self.save_graphics_state();
 
-        // apply ctm if any
-        let (mut t_x, mut t_y) = (Mm(0.0), Mm(0.0));
-        let (mut s_x, mut s_y) = (0.0, 0.0);
+        self.add_placement_transform(translate_x, translate_y, rotate_cw, scale_x, scale_y);
 
-        if let Some(tr_x) = translate_x {
-            t_x = tr_x;
-        }
-        if let Some(tr_y) = translate_y {
-            t_y = tr_y;
-        }
-        if let Some(sc_x) = scale_x {
-            s_x = sc_x;
-        }
-        if let Some(sc_y) = scale_y {
-            s_y = sc_y;
-        }
+        // invoke object
+        self.internal_add_operation(Operation::new(
+            "Do",
+            vec![lopdf::Object::Name(xobj.xobject_name())],
+        ));
+
+        // restore graphics state
+        self.restore_graphics_state();
+    }
+
+    // translates, rotates and scales the coordinate system of an image or XObject
+    fn add_placement_transform(
+        &mut self,
+        translate_x: Option<Mm>,
+        translate_y: Option<Mm>,
+        rotate_cw: Option<f64>,
+        scale_x: Option<f64>,
+        scale_y: Option<f64>,
+    ) {
+        // apply ctm if any, a missing scale keeps the size
+        let t_x = translate_x.unwrap_or(Mm(0.0));
+        let t_y = translate_y.unwrap_or(Mm(0.0));
+        let s_x = scale_x.unwrap_or(1.0);
+        let s_y = scale_y.unwrap_or(1.0);
 
         // translate, rotate, scale - order does not matter
 
@@ -146,21 +261,15 @@ impl PdfLayer {
             self.internal_add_operation(rotate_ctm);
         }
 
-        if s_x != 0.0 || s_y != 0.0 {
+        if s_x != 1.0 || s_y != 1.0 {
             let scale_ctm = CurTransMat::Scale(s_x, s_y);
             self.internal_add_operation(scale_ctm);
         }
-
-        // invoke object
-        self.internal_add_operation(Operation::new(
-            "Do",
-            vec![lopdf::Object::Name(xobj.xobject_name())],
-        ));
-
-        // restore graphics state
-        self.restore_graphics_state();
     }
 
+    /// Places an image on the layer. Without a scale, the image is sized for the `dpi`
+    /// (default: 300 DPI). Stencil masks (`ImageMasking::Stencil`) are painted in the
+    /// current fill color, see `set_fill_color`
     pub fn use_image<I>(
         &mut self,
         image: &Registered<I>,
@@ -189,6 +298,97 @@ impl PdfLayer {
         self.use_xobject(image, translate_x, translate_y, rotate_cw, image_w, image_h)
     }
 
+    /// Places an image into a rectangle (or at its natural size for a DPI), with a fit mode,
+    /// alignment and rotation around the center of the image, see `ImagePlacement`.
+    /// Images placed with `ImageFit::Cover` are clipped to the rectangle
+    pub fn place_image<I>(&mut self, image: &Registered<I>, placement: &ImagePlacement)
+    where
+        I: Borrow<Image>,
+    {
+        let xobject = &image.object.borrow().image;
+
+        self.save_graphics_state();
+        self.add_image_placement(xobject.width, xobject.height, placement);
+        self.internal_add_operation(Operation::new(
+            "Do",
+            vec![lopdf::Object::Name(image.xobject_name())],
+        ));
+        self.restore_graphics_state();
+    }
+
+    /// Places a small image as an inline image, see `place_image` and `use_inline_image`
+    pub fn place_inline_image(&mut self, image: &ImageXObject, placement: &ImagePlacement) -> Result<(), Error> {
+        let inline_image = image.to_inline_image()?;
+
+        self.save_graphics_state();
+        self.add_image_placement(image.width, image.height, placement);
+        self.internal_add_operation(inline_image);
+        self.restore_graphics_state();
+
+        Ok(())
+    }
+
+    // clips to the target rectangle (if needed) and maps the unit square of the image to the page
+    fn add_image_placement(&mut self, width: Px, height: Px, placement: &ImagePlacement) {
+        use lopdf::Object::Real;
+
+        let (matrix, clip) = placement.matrix(width, height);
+
+        if let Some(clip) = clip {
+            let rect = clip.iter().map(|v| Real(*v)).collect();
+            self.internal_add_operation(Operation::new(OP_PATH_CONST_RECT, rect));
+            self.internal_add_operation(Operation::new(OP_PATH_CONST_CLIP_NZ, vec![]));
+            self.internal_add_operation(Operation::new(OP_PATH_PAINT_END, vec![]));
+        }
+
+        self.internal_add_operation(CurTransMat::Raw(matrix));
+    }
+
+    /// Places an image with an arbitrary transformation matrix, which maps the unit square
+    /// of the image onto the page (in points). For example, `CurTransMat::Scale(200.0, 100.0)`
+    /// multiplied with a `CurTransMat::Skew` draws a skewed image of 200 x 100 pt
+    pub fn use_image_with_matrix<I>(&mut self, image: &Registered<I>, matrix: CurTransMat)
+    where
+        I: Borrow<Image>,
+    {
+        self.save_graphics_state();
+        self.internal_add_operation(matrix);
+        self.internal_add_operation(Operation::new(
+            "Do",
+            vec![lopdf::Object::Name(image.xobject_name())],
+        ));
+        self.restore_graphics_state();
+    }
+
+    /// Writes a small image directly into the content stream as an inline image, instead of
+    /// referencing an XObject resource. The arguments are the same as for `use_image`.
+    /// Fails if the image is larger than `INLINE_IMAGE_MAX_SIZE` after compression or uses
+    /// features that need resources, see `PdfError::InvalidInlineImage`
+    #[allow(clippy::too_many_arguments)]
+    pub fn use_inline_image(
+        &mut self,
+        image: &ImageXObject,
+        translate_x: Option<Mm>,
+        translate_y: Option<Mm>,
+        rotate_cw: Option<f64>,
+        scale_x: Option<f64>,
+        scale_y: Option<f64>,
+        dpi: Option<f64>,
+    ) -> Result<(), Error> {
+        let inline_image = image.to_inline_image()?;
+        let dpi = dpi.unwrap_or(300.0);
+
+        let image_w = image.width.into_pt(dpi).0 * scale_x.unwrap_or(1.);
+        let image_h = image.height.into_pt(dpi).0 * scale_y.unwrap_or(1.);
+
+        self.save_graphics_state();
+        self.add_placement_transform(translate_x, translate_y, rotate_cw, Some(image_w), Some(image_h));
+        self.internal_add_operation(inline_image);
+        self.restore_graphics_state();
+
+        Ok(())
+    }
+
     /// Change the graphics state of the current layer
     pub fn set_graphics_state<S>(&mut self, graphics_state: &Registered<S>)
     where
@@ -226,9 +426,10 @@ impl PdfLayer {
         self.internal_add_operation(line_cap);
     }
 
-    /// Set the current line join style for outlines
+    /// Set the current line dash pattern for outlines, a `DashPattern` or a `LineDashPattern`
     #[inline]
-    pub fn set_line_dash_pattern(&mut self, dash_pattern: LineDashPattern) {
+    pub fn set_line_dash_pattern<P: Into<DashPattern>>(&mut self, dash_pattern: P) {
+        let dash_pattern: DashPattern = dash_pattern.into();
         self.internal_add_operation(dash_pattern);
     }
 
@@ -392,14 +593,11 @@ impl PdfLayer {
     ///
     /// [Windows-1252]: https://en.wikipedia.org/wiki/Windows-1252
     #[inline]
-    pub fn write_text<S, F>(&mut self, text: S, font: &Registered<F>) -> ()
+    pub fn write_text<S, F>(&mut self, text: S, font: &Registered<F>) -> Result<(), Error>
     where
         S: Into<String>,
         F: Borrow<Font>,
     {
-        // NOTE: The unwrap() calls in this function are safe, since
-        // we've already checked the font for validity when it was added to the document
-
         use lopdf::Object::*;
         use lopdf::StringFormat::Hexadecimal;
 
@@ -415,16 +613,10 @@ impl PdfLayer {
 
         let bytes: Vec<u8> = {
             use rusttype::Codepoint as Cp;
-            use rusttype::FontCollection;
 
             if let Font::ExternalFont(face_direct_ref) = font.object.borrow() {
                 let mut list_gid = Vec::<u16>::new();
-                let collection =
-                    FontCollection::from_bytes(&*face_direct_ref.font_bytes).unwrap();
-                let font = collection
-                    .clone()
-                    .into_font()
-                    .unwrap_or(collection.font_at(0).unwrap());
+                let font = face_direct_ref.parse()?;
 
                 // convert into list of glyph ids - unicode magic
                 let char_iter = text.chars();
@@ -454,6 +646,8 @@ impl PdfLayer {
             "Tj",
             vec![String(bytes, Hexadecimal)],
         ));
+
+        Ok(())
     }
 
     /// Saves the current graphic state
@@ -482,7 +676,7 @@ impl PdfLayer {
         x: Mm,
         y: Mm,
         font: &Registered<F>,
-    ) -> ()
+    ) -> Result<(), Error>
     where
         S: Into<String>,
         F: Borrow<Font>,
@@ -490,8 +684,9 @@ impl PdfLayer {
         self.begin_text_section();
         self.set_font(font, font_size);
         self.set_text_cursor(x, y);
-        self.write_text(text, font);
+        self.write_text(text, font)?;
         self.end_text_section();
+        Ok(())
     }
 
     #[inline(always)]
@@ -2,11 +2,15 @@
 
 use lopdf;
 use crate::OffsetDateTime;
+use date::parse_xmp_date;
 use {
-	IccProfileType, PdfConformance, XmpMetadata, DocumentInfo, IccProfile
+	IccProfileType, PdfConformance, XmpMetadata, DocumentInfo, IccProfile,
+	XmpPacket, DublinCore, XmpBasic, AdobePdf,
 };
 
 use glob_defines::ICC_PROFILE_ECI_V2;
+use types::plugins::misc::document_info::{is_reserved_info_key, is_valid_custom_info_key};
+use types::plugins::xmp::xmp_metadata::PDFX_NAMESPACE;
 
 /// This is a wrapper in order to keep shared data between the documents XMP metadata and
 /// the "Info" dictionary in sync
@@ -20,6 +24,20 @@ pub struct PdfMetadata {
 	pub metadata_date: OffsetDateTime,
 	/// PDF document title
 	pub document_title: String,
+	/// Author of the document
+	pub author: Option<String>,
+	/// Subject (short description) of the document
+	pub subject: Option<String>,
+	/// Keywords of the document
+	pub keywords: Vec<String>,
+	/// Application that created the original document
+	pub creator: Option<String>,
+	/// Application that converted the document to PDF
+	pub producer: Option<String>,
+	/// Custom entries of the Info dictionary, as (key, value). The keys are also used as XMP
+	/// property names, entries whose key is not an XML name (see `PdfDocument::set_custom_info`)
+	/// or a standard Info key (`Title`, `Producer`, `CreationDate`, ...) are ignored
+	pub custom_info: Vec<(String, String)>,
 	/// Is the document trapped?
 	pub trapping: bool,
 	/// PDF document version
@@ -47,6 +65,12 @@ impl PdfMetadata {
 			modification_date: current_time.clone(),
 			metadata_date: current_time.clone(),
 			document_title: title.into(),
+			author: None,
+			subject: None,
+			keywords: Vec::new(),
+			creator: None,
+			producer: None,
+			custom_info: Vec::new(),
 			trapping: trapping,
 			document_version: document_version,
 			conformance: conformance,
@@ -56,29 +80,86 @@ impl PdfMetadata {
 		}
 	}
 
+	/// Keywords, as written into the Info dictionary and `pdf:Keywords`
+	pub fn keywords_string(&self)
+	-> String
+	{
+		self.keywords.join(", ")
+	}
+
+	/// Updates the metadata from a parsed XMP packet, for example from an existing document
+	pub fn update_from_xmp(&mut self, packet: &XmpPacket) {
+		if let Some(dc) = DublinCore::from_packet(packet) {
+			if let Some(title) = dc.title {
+				self.document_title = title;
+			}
+			if !dc.creator.is_empty() {
+				self.author = Some(dc.creator.join(", "));
+			}
+			if dc.description.is_some() {
+				self.subject = dc.description;
+			}
+			if !dc.subject.is_empty() {
+				self.keywords = dc.subject;
+			}
+		}
+
+		if let Some(xmp) = XmpBasic::from_packet(packet) {
+			if xmp.creator_tool.is_some() {
+				self.creator = xmp.creator_tool;
+			}
+			if let Some(date) = xmp.create_date.and_then(|d| parse_xmp_date(&d).ok()) {
+				self.creation_date = date;
+			}
+			if let Some(date) = xmp.modify_date.and_then(|d| parse_xmp_date(&d).ok()) {
+				self.modification_date = date;
+			}
+			if let Some(date) = xmp.metadata_date.and_then(|d| parse_xmp_date(&d).ok()) {
+				self.metadata_date = date;
+			}
+		}
+
+		if let Some(pdf) = AdobePdf::from_packet(packet) {
+			if pdf.producer.is_some() {
+				self.producer = pdf.producer;
+			}
+			if let Some(trapped) = pdf.trapped {
+				self.trapping = trapped;
+			}
+			if self.keywords.is_empty() {
+				if let Some(keywords) = pdf.keywords {
+					self.keywords = keywords.split(',')
+						.map(|k| k.trim().to_string())
+						.filter(|k| !k.is_empty())
+						.collect();
+				}
+			}
+		}
+
+		if let Some(pdfx) = packet.schema(PDFX_NAMESPACE) {
+			self.custom_info = pdfx.properties.iter()
+				.filter(|(key, _)| !is_reserved_info_key(key) && is_valid_custom_info_key(key))
+				.filter_map(|(key, value)| value.as_text().map(|v| (key.clone(), v.to_string())))
+				.collect();
+		}
+
+		self.xmp_metadata = XmpMetadata::from_packet(packet);
+	}
+
 	/// Consumes the metadata, returning the (Option<xmp_metadata>, document_info, icc_profile_stream).
 	pub fn into_obj(self)
 	-> (Option<lopdf::Object>, lopdf::Object, Option<IccProfile>)
 	{
 		let xmp_obj = {
 			if self.conformance.must_have_xmp_metadata() {
-				Some(self.xmp_metadata.into_obj(
-					 	self.conformance.clone(),
-						self.trapping,
-						self.creation_date.clone(),
-						self.modification_date.clone(),
-						self.metadata_date,
-						self.document_title.clone()))
+				Some(self.xmp_metadata.clone().into_obj(&self))
 			} else {
 				None
 			}
 		};
 
-		let doc_info_obj = self.document_info.into_obj(self.document_title,
-													   self.trapping,
-													   self.conformance.clone(),
-													   self.creation_date.clone(),
-													   self.modification_date.clone());
+		let doc_info_obj = self.document_info.into_obj(&self);
+
 		// add icc profile if necessary
 		let icc_profile = {
 		    if self.conformance.must_have_icc_profile() {
@@ -96,3 +177,39 @@ impl PdfMetadata {
 		(xmp_obj, doc_info_obj, icc_profile)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_metadata_xmp_roundtrip() {
+		let mut metadata = PdfMetadata::new("Grüße, 日本", 1, true, PdfConformance::A3_2012_PDF_1_7);
+		metadata.author = Some("Jane Doe".into());
+		metadata.subject = Some("Testing <metadata>".into());
+		metadata.keywords = vec!["pdf".into(), "xmp".into()];
+		metadata.creator = Some("Writer".into());
+		metadata.producer = Some("printpdf".into());
+		metadata.custom_info = vec![("InvoiceNumber".into(), "1234".into()), ("Due-Date".into(), "2021-01-31".into())];
+
+		let xml = metadata.xmp_metadata.to_packet(&metadata).to_xml();
+		let packet = XmpPacket::parse(&xml).unwrap();
+
+		let mut parsed = PdfMetadata::new("", 1, false, PdfConformance::A3_2012_PDF_1_7);
+		parsed.update_from_xmp(&packet);
+
+		assert_eq!(parsed.document_title, metadata.document_title);
+		assert_eq!(parsed.author, metadata.author);
+		assert_eq!(parsed.subject, metadata.subject);
+		assert_eq!(parsed.keywords, metadata.keywords);
+		assert_eq!(parsed.creator, metadata.creator);
+		assert_eq!(parsed.producer, metadata.producer);
+		assert_eq!(parsed.trapping, metadata.trapping);
+		assert_eq!(parsed.xmp_metadata.document_id, metadata.xmp_metadata.document_id);
+		assert_eq!(parsed.custom_info, metadata.custom_info);
+		assert_eq!(
+			packet.get_text("http://ns.adobe.com/pdfx/1.3/", "InvoiceNumber"),
+			Some("1234")
+		);
+	}
+}
@@ -3,7 +3,14 @@
 use lopdf;
 use types;
 
-use {Embeddable, Embedded, Mm, Pattern, PatternRef, PdfLayer, PdfResources, Pt, Registered};
+use std::convert::TryFrom;
+use std::collections::BTreeSet;
+use types::plugins::graphics::color::cie_parameters;
+use types::plugins::graphics::icc_profile::{IccColorSpace, ICC_COLOR_SPACE_PREFIX};
+use {
+    CurTransMat, EmbeddedFile, Embeddable, Embedded, Error, FileAttachmentAnnotation, Mm, Pattern,
+    PatternRef, PdfError, PdfLayer, PdfResources, Pt, Registered,
+};
 
 /// PDF page
 #[derive(Debug, Clone)]
@@ -18,6 +25,10 @@ pub struct PdfPage {
     pub(crate) resources: PdfResources,
     /// Resources used in this page
     pub(crate) resources_dict: lopdf::Dictionary,
+    /// File attachment annotations and the file specifications they point to
+    pub(crate) annotations: Vec<(FileAttachmentAnnotation, lopdf::ObjectId)>,
+    /// Files associated with this page (`/AF`), see PDF/A-3
+    pub(crate) associated_files: Vec<lopdf::ObjectId>,
 }
 
 impl PdfPage {
@@ -30,10 +41,14 @@ impl PdfPage {
             layers: Vec::new(),
             resources: PdfResources::new(),
             resources_dict: lopdf::Dictionary::new(),
+            annotations: Vec::new(),
+            associated_files: Vec::new(),
         }
     }
 
-    pub fn register<T: Embeddable + Clone>(&mut self, resource: &Embedded<T>) -> Registered<T> {
+    /// Adds an embedded resource to the resources of this page, so that it can be used
+    /// by the layers of this page
+    pub fn register<T: Embeddable + Clone>(&mut self, resource: &Embedded<T>) -> Result<Registered<T>, Error> {
         types::pdf_resources::register(&mut self.resources_dict, resource)
     }
 
@@ -52,13 +67,13 @@ impl PdfPage {
     pub(crate) fn collect_resources_and_streams(
         self,
         layers: &[(usize, lopdf::Object)],
-    ) -> (lopdf::Dictionary, Vec<lopdf::Stream>) {
+    ) -> Result<(lopdf::Dictionary, Vec<lopdf::Stream>), Error> {
         let cur_layers = layers.iter().map(|l| l.1.clone()).collect();
         let (mut resource_dictionary, ocg_refs) = self.resources.into_with_layers(cur_layers);
 
         // register resources
         for (key, set) in self.resources_dict.into_iter() {
-            let set = set.as_dict().unwrap();
+            let set = set.as_dict().map_err(|_| PdfError::InvalidResource)?;
             if let Some(dict) = resource_dictionary
                 .get_mut(&key)
                 .and_then(|o| o.as_dict_mut())
@@ -100,11 +115,134 @@ impl PdfPage {
             // Q
             // EMC
 
-            let layer_stream = layer.into();
+            let layer_stream = lopdf::Stream::try_from(layer)?;
             layer_streams.push(layer_stream);
         }
 
-        (resource_dictionary, layer_streams)
+        Ok((resource_dictionary, layer_streams))
+    }
+
+    /// Resource names of the `/ICCBased` color spaces of `IccProfileRef`s that are used by
+    /// the layers (`cs` / `CS` with the name of the profile)
+    pub(crate) fn icc_color_space_names(&self) -> BTreeSet<Vec<u8>> {
+        self.layers
+            .iter()
+            .flat_map(|layer| layer.operations.iter())
+            .filter(|op| op.operator == "cs" || op.operator == "CS")
+            .filter_map(|op| op.operands.first().and_then(|name| name.as_name().ok()))
+            .filter(|name| name.starts_with(ICC_COLOR_SPACE_PREFIX.as_bytes()))
+            .map(|name| name.to_vec())
+            .collect()
+    }
+
+    /// Adds the color spaces that are used by the layers to the color space resources: the
+    /// `/ICCBased` color spaces of `IccProfileRef`s and the CIE-based color spaces of `Lab`,
+    /// `CalRgb` and `CalGray` colors. Fails with `PdfError::InvalidColor` if a color doesn't
+    /// have one value per component of its ICC profile and with `PdfError::InvalidResource` if
+    /// two different CIE-based color spaces have the same name
+    pub(crate) fn add_color_spaces(
+        &mut self,
+        doc: &mut lopdf::Document,
+        icc_color_spaces: &[IccColorSpace],
+    ) -> Result<(), Error> {
+        for layer in &self.layers {
+            check_icc_colors(&layer.operations, icc_color_spaces)?;
+        }
+
+        let mut color_spaces = Vec::new();
+        for name in self.icc_color_space_names() {
+            let color_space = icc_color_spaces
+                .iter()
+                .find(|cs| cs.name.as_bytes() == &name[..])
+                .ok_or(PdfError::InvalidResource)?;
+            color_spaces.push((name, color_space.color_space.clone()));
+        }
+        let mut cie_color_spaces: Vec<(&String, Option<Vec<u8>>)> = Vec::new();
+        for (name, color_space) in self.layers.iter().flat_map(|layer| layer.color_spaces.iter()) {
+            let parameters = cie_parameters(color_space);
+            match cie_color_spaces.iter().find(|(n, _)| *n == name) {
+                Some((_, ref p)) if *p == parameters => continue,
+                Some(_) => return Err(PdfError::InvalidResource.into()),
+                None => {}
+            }
+            color_spaces.push((name.as_bytes().to_vec(), color_space.to_object(doc)?));
+            cie_color_spaces.push((name, parameters));
+        }
+
+        if color_spaces.is_empty() {
+            return Ok(());
+        }
+
+        if self.resources_dict.get(b"ColorSpace").is_err() {
+            self.resources_dict.set("ColorSpace", lopdf::Dictionary::new());
+        }
+        let resources = self
+            .resources_dict
+            .get_mut(b"ColorSpace")
+            .and_then(|o| o.as_dict_mut())
+            .map_err(|_| PdfError::InvalidResource)?;
+
+        for (name, color_space) in color_spaces {
+            resources.set(name, color_space);
+        }
+
+        Ok(())
+    }
+
+    /// Sizes (in points) at which the image XObjects of this page are placed, derived from
+    /// the transformation matrix of every `Do` operator of the layers
+    pub(crate) fn image_placements(&self) -> Vec<(lopdf::ObjectId, Pt, Pt)> {
+        use lopdf::Object::*;
+
+        let xobjects = match self.resources_dict.get(b"XObject").and_then(|o| o.as_dict()) {
+            Ok(xobjects) => xobjects,
+            Err(_) => return Vec::new(),
+        };
+
+        let number = |o: &lopdf::Object| match o {
+            Integer(i) => Some(*i as f64),
+            Real(r) => Some(*r),
+            _ => None,
+        };
+
+        let mut placements = Vec::new();
+
+        for layer in &self.layers {
+            let mut ctm = CurTransMat::Identity;
+            let mut stack = Vec::new();
+
+            for operation in &layer.operations {
+                match operation.operator.as_str() {
+                    "q" => stack.push(ctm),
+                    "Q" => ctm = stack.pop().unwrap_or(ctm),
+                    "cm" => {
+                        let m: Vec<f64> = operation.operands.iter().filter_map(number).collect();
+                        if m.len() == 6 {
+                            // CTM' = M x CTM
+                            ctm = CurTransMat::Raw([m[0], m[1], m[2], m[3], m[4], m[5]]).multiply(ctm);
+                        }
+                    }
+                    "Do" => {
+                        let id = operation
+                            .operands
+                            .first()
+                            .and_then(|name| name.as_name().ok())
+                            .and_then(|name| xobjects.get(name).ok())
+                            .and_then(|object| object.as_reference().ok());
+                        if let Some(id) = id {
+                            // the image is mapped to the unit square
+                            let ctm = ctm.as_array();
+                            let width = ctm[0].hypot(ctm[1]);
+                            let height = ctm[2].hypot(ctm[3]);
+                            placements.push((id, Pt(width), Pt(height)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        placements
     }
 
     /// __STUB__: Adds a pattern to the pages resources
@@ -113,6 +251,26 @@ impl PdfPage {
         self.resources.add_pattern(pattern)
     }
 
+    /// Shows an embedded file as an annotation on this page. The file is also
+    /// added to the associated files of the page.
+    #[inline]
+    pub fn add_file_attachment(
+        &mut self,
+        annotation: FileAttachmentAnnotation,
+        file: &Embedded<EmbeddedFile>,
+    ) {
+        self.annotations.push((annotation, file.object_id));
+        self.add_associated_file(file);
+    }
+
+    /// Associates an embedded file with this page (PDF/A-3 `/AF` entry)
+    #[inline]
+    pub fn add_associated_file(&mut self, file: &Embedded<EmbeddedFile>) {
+        if !self.associated_files.contains(&file.object_id) {
+            self.associated_files.push(file.object_id);
+        }
+    }
+
     /// Add a layer on top of this page.
     #[inline]
     pub fn add_layer(&mut self, layer: PdfLayer) {
@@ -121,3 +279,37 @@ impl PdfPage {
         self.layers.push(layer);
     }
 }
+
+// checks that the colors in `/ICCBased` color spaces have one value per component of the profile
+fn check_icc_colors(operations: &[lopdf::content::Operation], icc_color_spaces: &[IccColorSpace]) -> Result<(), Error> {
+    let num_components = |name: Option<&[u8]>| {
+        let name = name?;
+        icc_color_spaces.iter().find(|cs| cs.name.as_bytes() == name).map(|cs| cs.num_components)
+    };
+
+    // current fill and stroke color space, saved and restored with the graphics state
+    let mut color_spaces: (Option<&[u8]>, Option<&[u8]>) = (None, None);
+    let mut stack = Vec::new();
+
+    for operation in operations {
+        let name = operation.operands.first().and_then(|o| o.as_name().ok());
+        match operation.operator.as_str() {
+            "q" => stack.push(color_spaces),
+            "Q" => color_spaces = stack.pop().unwrap_or(color_spaces),
+            "cs" => color_spaces.0 = name,
+            "CS" => color_spaces.1 = name,
+            "g" | "rg" | "k" => color_spaces.0 = None,
+            "G" | "RG" | "K" => color_spaces.1 = None,
+            "sc" | "scn" | "SC" | "SCN" => {
+                let color_space = if operation.operator.starts_with('s') { color_spaces.0 } else { color_spaces.1 };
+                match num_components(color_space) {
+                    Some(n) if n != operation.operands.len() => return Err(PdfError::InvalidColor.into()),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
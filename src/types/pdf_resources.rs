@@ -1,9 +1,10 @@
 use std::ops::Deref;
 use std::rc::Rc;
+use {Error, PdfError};
 
 pub trait Embeddable {
     const KEY: &'static str;
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId>;
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error>;
 }
 
 impl<T> Embeddable for &T
@@ -11,7 +12,7 @@ where
     T: Embeddable,
 {
     const KEY: &'static str = T::KEY;
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         (*self).embed(doc)
     }
 }
@@ -21,7 +22,7 @@ where
     T: Embeddable,
 {
     const KEY: &'static str = T::KEY;
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         self.as_ref().embed(doc)
     }
 }
@@ -61,7 +62,7 @@ impl<T> Deref for Registered<T> {
 pub(crate) fn embed<T: Embeddable>(
     doc: &mut lopdf::Document,
     resource: T,
-) -> lopdf::Result<Embedded<T>> {
+) -> Result<Embedded<T>, Error> {
     let object_id = resource.embed(doc)?;
 
     Ok(Embedded {
@@ -73,7 +74,7 @@ pub(crate) fn embed<T: Embeddable>(
 pub(crate) fn register<T: Embeddable + Clone>(
     resources: &mut lopdf::Dictionary,
     resource: &Embedded<T>,
-) -> Registered<T> {
+) -> Result<Registered<T>, Error> {
     let object_id = resource.object_id;
 
     let name_index = match resources.get_mut(T::KEY.as_bytes()) {
@@ -91,16 +92,13 @@ pub(crate) fn register<T: Embeddable + Clone>(
             resources.set(T::KEY, dict);
             name_index
         }
-        _ => {
-            // error
-            panic!("expected a PDF Dictionary");
-        }
+        _ => return Err(PdfError::InvalidResource.into()),
     };
 
-    Registered {
+    Ok(Registered {
         embedded: Embedded::clone(resource),
         name_index: name_index as _,
-    }
+    })
 }
 
 #[cfg(test)]
@@ -116,7 +114,7 @@ mod tests {
 
     impl Embeddable for X {
         const KEY: &'static str = "xx";
-        fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+        fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
             Ok(doc.new_object_id())
         }
     }
@@ -130,7 +128,7 @@ mod tests {
 
     impl Embeddable for Y {
         const KEY: &'static str = "xx";
-        fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+        fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
             Ok(doc.new_object_id())
         }
     }
@@ -144,13 +142,13 @@ mod tests {
         x.hi();
 
         let mut dict = lopdf::Dictionary::new();
-        let _rx = register(&mut dict, &x);
-        let rx = register(&mut dict, &x);
+        let _rx = register(&mut dict, &x).unwrap();
+        let rx = register(&mut dict, &x).unwrap();
         dbg!(&dict);
         rx.hi();
 
         let mut dict = lopdf::Dictionary::new();
-        let rx = register(&mut dict, &x);
+        let rx = register(&mut dict, &x).unwrap();
         dbg!(&dict);
         rx.hi();
     }
@@ -164,13 +162,13 @@ mod tests {
         x.hi();
 
         let mut dict = lopdf::Dictionary::new();
-        let _rx = register(&mut dict, &x);
-        let rx = register(&mut dict, &x);
+        let _rx = register(&mut dict, &x).unwrap();
+        let rx = register(&mut dict, &x).unwrap();
         dbg!(&dict);
         rx.hi();
 
         let mut dict = lopdf::Dictionary::new();
-        let rx = register(&mut dict, &x);
+        let rx = register(&mut dict, &x).unwrap();
         dbg!(&dict);
         rx.hi();
     }
@@ -184,14 +182,27 @@ mod tests {
         x.hi();
 
         let mut dict = lopdf::Dictionary::new();
-        let _rx = register(&mut dict, &x);
-        let rx = register(&mut dict, &x);
+        let _rx = register(&mut dict, &x).unwrap();
+        let rx = register(&mut dict, &x).unwrap();
         dbg!(&dict);
         rx.hi();
 
         let mut dict = lopdf::Dictionary::new();
-        let rx = register(&mut dict, &x);
+        let rx = register(&mut dict, &x).unwrap();
         dbg!(&dict);
         rx.hi();
     }
+
+    #[test]
+    fn test_register_invalid_resources() {
+        let mut docu = lopdf::Document::new();
+        let x = embed(&mut docu, Y).unwrap();
+
+        let mut dict = lopdf::Dictionary::new();
+        dict.set("xx", 1);
+        match register(&mut dict, &x) {
+            Err(Error::Pdf(PdfError::InvalidResource)) => {}
+            _ => panic!("expected an invalid resource error"),
+        }
+    }
 }
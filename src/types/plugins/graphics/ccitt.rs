@@ -0,0 +1,476 @@
+//! CCITT Group 4 (T.6) encoding of 1-bit images (`CCITTFaxDecode`)
+
+use std::collections::HashMap;
+
+use {Error, PdfError};
+
+/// `/DecodeParms` of a `CCITTFaxDecode` image stream
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CcittFaxDecodeParms {
+    /// Encoding scheme: negative for pure two-dimensional (Group 4) encoding (`/K`)
+    pub k: i64,
+    /// Width of the image in pixels (`/Columns`)
+    pub columns: usize,
+    /// Height of the image in pixels (`/Rows`)
+    pub rows: usize,
+    /// If `true`, 1 bits are black pixels, otherwise 0 bits are black (`/BlackIs1`)
+    pub black_is_1: bool,
+    /// Whether the data ends with an end-of-block marker (`/EndOfBlock`)
+    pub end_of_block: bool,
+}
+
+impl CcittFaxDecodeParms {
+    /// Parameters of Group 4 encoded data
+    pub fn group_4(columns: usize, rows: usize) -> Self {
+        Self {
+            k: -1,
+            columns,
+            rows,
+            black_is_1: false,
+            end_of_block: true,
+        }
+    }
+}
+
+impl From<CcittFaxDecodeParms> for lopdf::Dictionary {
+    fn from(parms: CcittFaxDecodeParms) -> Self {
+        use lopdf::Object::{Boolean, Integer};
+        use std::iter::FromIterator;
+
+        lopdf::Dictionary::from_iter(vec![
+            ("K", Integer(parms.k)),
+            ("Columns", Integer(parms.columns as i64)),
+            ("Rows", Integer(parms.rows as i64)),
+            ("BlackIs1", Boolean(parms.black_is_1)),
+            ("EndOfBlock", Boolean(parms.end_of_block)),
+        ])
+    }
+}
+
+/// Encodes packed 1-bit rows (0 = black, 1 = white) as CCITT Group 4,
+/// followed by an end-of-block marker
+pub(crate) fn encode_g4(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let row_length = width.div_ceil(8);
+    let lines = (0..height).map(|y| {
+        let row = data.get(y * row_length..(y + 1) * row_length).unwrap_or(&[]);
+        changing_elements(row, width)
+    });
+    encode_g4_lines(lines, width)
+}
+
+/// Encodes the changing elements of every line (positions where the color
+/// changes, starting with white) as CCITT Group 4, followed by an end-of-block marker
+pub(crate) fn encode_g4_lines<I: IntoIterator<Item = Vec<usize>>>(lines: I, width: usize) -> Vec<u8> {
+    let tables = CodeTables::new();
+    let mut writer = BitWriter::default();
+    let mut reference = Vec::new();
+
+    for line in lines {
+        encode_line(&mut writer, &tables, &reference, &line, width);
+        reference = line;
+    }
+
+    // EOFB: two EOL codes
+    writer.write_bits(0x001, 12);
+    writer.write_bits(0x001, 12);
+    writer.finish()
+}
+
+/// Decodes CCITT Group 4 data into the changing elements of every line
+pub(crate) fn decode_g4_lines(data: &[u8], width: usize, height: usize) -> Result<Vec<Vec<usize>>, Error> {
+    let tables = CodeTables::new();
+    let mut reader = BitReader { data, pos: 0 };
+    let mut lines = Vec::with_capacity(height);
+    let mut reference = Vec::new();
+
+    for _ in 0..height {
+        let line = decode_line(&mut reader, &tables, &reference, width)?;
+        lines.push(line.clone());
+        reference = line;
+    }
+
+    Ok(lines)
+}
+
+/// Packs the changing elements of the lines into 1-bit rows (0 = black, 1 = white)
+#[cfg(test)]
+pub(crate) fn pack_lines(lines: &[Vec<usize>], width: usize) -> Vec<u8> {
+    let row_length = width.div_ceil(8);
+    let mut data = vec![0xFF; row_length * lines.len()];
+
+    for (y, line) in lines.iter().enumerate() {
+        // black runs are between an even and an odd changing element
+        for run in line.chunks(2) {
+            let end = run.get(1).cloned().unwrap_or(width);
+            for x in run[0]..end {
+                data[y * row_length + x / 8] &= !(0x80 >> (x % 8));
+            }
+        }
+    }
+
+    data
+}
+
+// positions where the color changes, starting with white (a 1 bit)
+fn changing_elements(row: &[u8], width: usize) -> Vec<usize> {
+    let mut changes = Vec::new();
+    let mut is_black = false;
+
+    for x in 0..width {
+        let bit = row.get(x / 8).map(|byte| byte & (0x80 >> (x % 8)) != 0).unwrap_or(true);
+        if bit == is_black {
+            changes.push(x);
+            is_black = !is_black;
+        }
+    }
+
+    changes
+}
+
+// b1: first changing element on the reference line right of a0 with the
+// opposite color of a0 (changing elements to black have an even index),
+// b2: the next changing element after b1
+fn find_b1_b2(reference: &[usize], a0: isize, a0_is_black: bool, width: usize) -> (usize, usize) {
+    let start = reference
+        .iter()
+        .position(|&b| b as isize > a0)
+        .unwrap_or(reference.len());
+
+    let mut index = start;
+    // the index parity of b1 must be even if a0 is white, odd if a0 is black
+    if (index % 2 == 1) != a0_is_black {
+        index += 1;
+    }
+
+    let b1 = reference.get(index).cloned().unwrap_or(width);
+    let b2 = reference.get(index + 1).cloned().unwrap_or(width);
+    (b1, b2)
+}
+
+fn encode_line(writer: &mut BitWriter, tables: &CodeTables, reference: &[usize], line: &[usize], width: usize) {
+    let mut a0: isize = -1;
+    let mut a0_is_black = false;
+
+    while a0 < width as isize {
+        let a1_index = line.iter().position(|&a| a as isize > a0).unwrap_or(line.len());
+        let a1 = line.get(a1_index).cloned().unwrap_or(width);
+        let (b1, b2) = find_b1_b2(reference, a0, a0_is_black, width);
+
+        if b2 < a1 {
+            // pass mode
+            writer.write_bits(0b0001, 4);
+            a0 = b2 as isize;
+        } else if (a1 as isize - b1 as isize).abs() <= 3 {
+            // vertical mode
+            let (code, length) = VERTICAL_CODES[(a1 as isize - b1 as isize + 3) as usize];
+            writer.write_bits(code, length);
+            a0 = a1 as isize;
+            a0_is_black = !a0_is_black;
+        } else {
+            // horizontal mode
+            let a2 = line.get(a1_index + 1).cloned().unwrap_or(width);
+            let start = a0.max(0) as usize;
+            writer.write_bits(0b001, 3);
+            tables.write_run(writer, a1 - start, a0_is_black);
+            tables.write_run(writer, a2 - a1, !a0_is_black);
+            a0 = a2 as isize;
+        }
+    }
+}
+
+fn decode_line(reader: &mut BitReader, tables: &CodeTables, reference: &[usize], width: usize) -> Result<Vec<usize>, Error> {
+    let mut line = Vec::new();
+    let mut a0: isize = -1;
+    let mut a0_is_black = false;
+
+    while a0 < width as isize {
+        let (b1, b2) = find_b1_b2(reference, a0, a0_is_black, width);
+
+        if reader.read_bit()? == 1 {
+            // V0
+            a0 = push_change(&mut line, b1 as isize, width)?;
+            a0_is_black = !a0_is_black;
+            continue;
+        }
+
+        let mode = match (reader.read_bit()?, reader.read_bit()?) {
+            (1, 1) => 1,
+            (1, 0) => -1,
+            (0, 1) => 0,
+            _ => match reader.read_bit()? {
+                1 => 2,
+                _ => match (reader.read_bit()?, reader.read_bit()?) {
+                    (1, 1) => 3,
+                    (1, 0) => -3,
+                    (0, 1) => match reader.read_bit()? {
+                        1 => 4,
+                        _ => -4,
+                    },
+                    _ => return Err(PdfError::InvalidImage.into()),
+                },
+            },
+        };
+
+        match mode {
+            // VR1 (011), VL1 (010)
+            1 | -1 => {
+                a0 = push_change(&mut line, b1 as isize + mode, width)?;
+                a0_is_black = !a0_is_black;
+            }
+            // horizontal mode (001)
+            0 => {
+                let start = a0.max(0) as usize;
+                let a1 = start + tables.read_run(reader, a0_is_black)?;
+                let a2 = a1 + tables.read_run(reader, !a0_is_black)?;
+                push_change(&mut line, a1 as isize, width)?;
+                a0 = push_change(&mut line, a2 as isize, width)?;
+            }
+            // pass mode (0001)
+            2 => a0 = b2 as isize,
+            // VR2 (000011), VL2 (000010)
+            3 | -3 => {
+                a0 = push_change(&mut line, b1 as isize + mode.signum() * 2, width)?;
+                a0_is_black = !a0_is_black;
+            }
+            // VR3 (0000011), VL3 (0000010)
+            _ => {
+                a0 = push_change(&mut line, b1 as isize + mode.signum() * 3, width)?;
+                a0_is_black = !a0_is_black;
+            }
+        }
+    }
+
+    Ok(line)
+}
+
+// adds a changing element to the line (the end of the line is not stored)
+fn push_change(line: &mut Vec<usize>, position: isize, width: usize) -> Result<isize, Error> {
+    let last = line.last().map(|&l| l as isize).unwrap_or(0);
+    if position < last || position > width as isize {
+        return Err(PdfError::InvalidImage.into());
+    }
+    if position < width as isize {
+        line.push(position as usize);
+    }
+    Ok(position)
+}
+
+// VL3, VL2, VL1, V0, VR1, VR2, VR3
+const VERTICAL_CODES: [(u32, u8); 7] = [
+    (0b0000010, 7),
+    (0b000010, 6),
+    (0b010, 3),
+    (0b1, 1),
+    (0b011, 3),
+    (0b000011, 6),
+    (0b0000011, 7),
+];
+
+const WHITE_TERMINATING_CODES: [&str; 64] = [
+    "00110101", "000111", "0111", "1000", "1011", "1100", "1110", "1111",
+    "10011", "10100", "00111", "01000", "001000", "000011", "110100", "110101",
+    "101010", "101011", "0100111", "0001100", "0001000", "0010111", "0000011", "0000100",
+    "0101000", "0101011", "0010011", "0100100", "0011000", "00000010", "00000011", "00011010",
+    "00011011", "00010010", "00010011", "00010100", "00010101", "00010110", "00010111", "00101000",
+    "00101001", "00101010", "00101011", "00101100", "00101101", "00000100", "00000101", "00001010",
+    "00001011", "01010010", "01010011", "01010100", "01010101", "00100100", "00100101", "01011000",
+    "01011001", "01011010", "01011011", "01001010", "01001011", "00110010", "00110011", "00110100",
+];
+
+// 64, 128, ..., 1728
+const WHITE_MAKEUP_CODES: [&str; 27] = [
+    "11011", "10010", "010111", "0110111", "00110110", "00110111", "01100100", "01100101",
+    "01101000", "01100111", "011001100", "011001101", "011010010", "011010011", "011010100",
+    "011010101", "011010110", "011010111", "011011000", "011011001", "011011010", "011011011",
+    "010011000", "010011001", "010011010", "011000", "010011011",
+];
+
+const BLACK_TERMINATING_CODES: [&str; 64] = [
+    "0000110111", "010", "11", "10", "011", "0011", "0010", "00011",
+    "000101", "000100", "0000100", "0000101", "0000111", "00000100", "00000111", "000011000",
+    "0000010111", "0000011000", "0000001000", "00001100111", "00001101000", "00001101100",
+    "00000110111", "00000101000", "00000010111", "00000011000", "000011001010", "000011001011",
+    "000011001100", "000011001101", "000001101000", "000001101001", "000001101010", "000001101011",
+    "000011010010", "000011010011", "000011010100", "000011010101", "000011010110", "000011010111",
+    "000001101100", "000001101101", "000011011010", "000011011011", "000001010100", "000001010101",
+    "000001010110", "000001010111", "000001100100", "000001100101", "000001010010", "000001010011",
+    "000000100100", "000000110111", "000000111000", "000000100111", "000000101000", "000001011000",
+    "000001011001", "000000101011", "000000101100", "000001011010", "000001100110", "000001100111",
+];
+
+// 64, 128, ..., 1728
+const BLACK_MAKEUP_CODES: [&str; 27] = [
+    "0000001111", "000011001000", "000011001001", "000001011011", "000000110011", "000000110100",
+    "000000110101", "0000001101100", "0000001101101", "0000001001010", "0000001001011",
+    "0000001001100", "0000001001101", "0000001110010", "0000001110011", "0000001110100",
+    "0000001110101", "0000001110110", "0000001110111", "0000001010010", "0000001010011",
+    "0000001010100", "0000001010101", "0000001011010", "0000001011011", "0000001100100",
+    "0000001100101",
+];
+
+// 1792, 1856, ..., 2560, shared by white and black runs
+const EXTENDED_MAKEUP_CODES: [&str; 13] = [
+    "00000001000", "00000001100", "00000001101", "000000010010", "000000010011", "000000010100",
+    "000000010101", "000000010110", "000000010111", "000000011100", "000000011101",
+    "000000011110", "000000011111",
+];
+
+// (code, length) of every run length, for white and black runs
+struct CodeTables {
+    white: HashMap<usize, (u32, u8)>,
+    black: HashMap<usize, (u32, u8)>,
+    white_lookup: HashMap<(u32, u8), usize>,
+    black_lookup: HashMap<(u32, u8), usize>,
+}
+
+impl CodeTables {
+    fn new() -> Self {
+        fn parse(code: &str) -> (u32, u8) {
+            (u32::from_str_radix(code, 2).unwrap_or(0), code.len() as u8)
+        }
+
+        fn table(terminating: &[&str], makeup: &[&str]) -> HashMap<usize, (u32, u8)> {
+            let terminating = terminating.iter().enumerate().map(|(run, code)| (run, parse(code)));
+            let makeup = makeup.iter().chain(EXTENDED_MAKEUP_CODES.iter())
+                .enumerate()
+                .map(|(i, code)| ((i + 1) * 64, parse(code)));
+            terminating.chain(makeup).collect()
+        }
+
+        let white = table(&WHITE_TERMINATING_CODES, &WHITE_MAKEUP_CODES);
+        let black = table(&BLACK_TERMINATING_CODES, &BLACK_MAKEUP_CODES);
+
+        Self {
+            white_lookup: white.iter().map(|(run, code)| (*code, *run)).collect(),
+            black_lookup: black.iter().map(|(run, code)| (*code, *run)).collect(),
+            white,
+            black,
+        }
+    }
+
+    fn write_run(&self, writer: &mut BitWriter, mut run: usize, is_black: bool) {
+        let table = if is_black { &self.black } else { &self.white };
+
+        while run > 2560 {
+            let (code, length) = table[&2560];
+            writer.write_bits(code, length);
+            run -= 2560;
+        }
+
+        if run >= 64 {
+            let (code, length) = table[&(run / 64 * 64)];
+            writer.write_bits(code, length);
+            run %= 64;
+        }
+
+        let (code, length) = table[&run];
+        writer.write_bits(code, length);
+    }
+
+    fn read_run(&self, reader: &mut BitReader, is_black: bool) -> Result<usize, Error> {
+        let lookup = if is_black { &self.black_lookup } else { &self.white_lookup };
+        let mut total = 0;
+
+        loop {
+            let mut code = 0;
+            let mut run = None;
+            for length in 1..=13 {
+                code = (code << 1) | reader.read_bit()?;
+                if let Some(r) = lookup.get(&(code, length)) {
+                    run = Some(*r);
+                    break;
+                }
+            }
+
+            let run = run.ok_or(PdfError::InvalidImage)?;
+            total += run;
+            // makeup codes are followed by more codes
+            if run < 64 {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, code: u32, length: u8) {
+        for i in (0..length).rev() {
+            self.buffer = (self.buffer << 1) | ((code >> i) & 1);
+            self.bits += 1;
+            if self.bits == 8 {
+                self.data.push(self.buffer as u8);
+                self.buffer = 0;
+                self.bits = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.data.push((self.buffer << (8 - self.bits)) as u8);
+        }
+        self.data
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read_bit(&mut self) -> Result<u32, Error> {
+        let byte = self.data.get(self.pos / 8).ok_or(PdfError::InvalidImage)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(u32::from(bit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_tables_are_prefix_free() {
+        let tables = CodeTables::new();
+        for table in &[&tables.white, &tables.black] {
+            let codes: Vec<String> = table
+                .values()
+                .map(|&(code, length)| format!("{:0width$b}", code, width = length as usize))
+                .collect();
+            for a in &codes {
+                for b in &codes {
+                    assert!(a == b || !b.starts_with(a.as_str()), "{} is a prefix of {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_g4_roundtrip() {
+        let (width, height) = (3000usize, 40);
+        let row_length = width.div_ceil(8);
+        let mut data = vec![0xFF; row_length * height];
+        for y in 0..height {
+            for x in 0..width {
+                let is_black = (x / (y + 10) + y / 5) % 4 == 0 || (x > 100 && x < 2900 && y == 20);
+                if is_black {
+                    data[y * row_length + x / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+
+        let encoded = encode_g4(&data, width, height);
+        assert!(encoded.len() < data.len() / 4);
+        let lines = decode_g4_lines(&encoded, width, height).unwrap();
+        assert!(pack_lines(&lines, width) == data);
+    }
+}
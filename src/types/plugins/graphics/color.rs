@@ -5,11 +5,13 @@ use image;
 use lopdf::content::Operation;
 
 use glob_defines::{
-    OP_COLOR_SET_FILL_CS_DEVICECMYK, OP_COLOR_SET_FILL_CS_DEVICEGRAY,
-    OP_COLOR_SET_FILL_CS_DEVICERGB, OP_COLOR_SET_STROKE_CS_DEVICECMYK,
+    OP_COLOR_SET_FILL_COLOR_ICC, OP_COLOR_SET_FILL_CS, OP_COLOR_SET_FILL_CS_DEVICECMYK,
+    OP_COLOR_SET_FILL_CS_DEVICEGRAY, OP_COLOR_SET_FILL_CS_DEVICERGB,
+    OP_COLOR_SET_STROKE_COLOR_ICC, OP_COLOR_SET_STROKE_CS, OP_COLOR_SET_STROKE_CS_DEVICECMYK,
     OP_COLOR_SET_STROKE_CS_DEVICEGRAY, OP_COLOR_SET_STROKE_CS_DEVICERGB,
 };
-use IccProfileRef;
+use utils::md5;
+use {IccProfileRef, ImageColorSpace};
 
 /// Tuple for differentiating outline and fill colors
 #[derive(Debug, Clone, PartialEq)]
@@ -18,11 +20,37 @@ pub enum PdfColor {
     OutlineColor(Color),
 }
 
+impl PdfColor {
+    /// Operations that set the color. Colors with an ICC profile are set in the `/ICCBased`
+    /// color space of the profile (`cs` / `scn`), CIE-based colors in their `/Lab`, `/CalRGB`
+    /// or `/CalGray` color space. The color spaces are added to the page resources on save
+    pub(crate) fn into_operations(self) -> Vec<Operation> {
+        use lopdf::Object::*;
+
+        let (color, set_cs, set_color) = match self {
+            PdfColor::FillColor(ref color) => (color, OP_COLOR_SET_FILL_CS, OP_COLOR_SET_FILL_COLOR_ICC),
+            PdfColor::OutlineColor(ref color) => (color, OP_COLOR_SET_STROKE_CS, OP_COLOR_SET_STROKE_COLOR_ICC),
+        };
+
+        let color_space = match (color.get_icc_profile(), color.cie_color_space()) {
+            (Some(Some(profile)), _) => profile.name.clone(),
+            (_, Some((name, _))) => name,
+            _ => return vec![self.into()],
+        };
+
+        vec![
+            Operation::new(set_cs, vec![Name(color_space.into_bytes())]),
+            Operation::new(set_color, color.clone().into_vec().into_iter().map(Real).collect()),
+        ]
+    }
+}
+
+// sets the color in the device color space, see `into_operations` for colors with an ICC profile.
+// CIE-based colors are only set with `scn`, their color space has to be set before
 impl Into<Operation> for PdfColor {
     fn into(self) -> Operation {
         use lopdf::Object::*;
 
-        // todo: incorporate ICC profile instead of just setting the default device cmyk color space
         let (color_identifier, color_vec) = {
             use self::PdfColor::*;
             match self {
@@ -31,6 +59,7 @@ impl Into<Operation> for PdfColor {
                         Color::Rgb(_) => OP_COLOR_SET_FILL_CS_DEVICERGB,
                         Color::Cmyk(_) | Color::SpotColor(_) => OP_COLOR_SET_FILL_CS_DEVICECMYK,
                         Color::Greyscale(_) => OP_COLOR_SET_FILL_CS_DEVICEGRAY,
+                        Color::Lab(_) | Color::CalRgb(_) | Color::CalGray(_) => OP_COLOR_SET_FILL_COLOR_ICC,
                     };
                     let cvec = fill.into_vec().into_iter().map(Real).collect();
                     (ci, cvec)
@@ -40,6 +69,7 @@ impl Into<Operation> for PdfColor {
                         Color::Rgb(_) => OP_COLOR_SET_STROKE_CS_DEVICERGB,
                         Color::Cmyk(_) | Color::SpotColor(_) => OP_COLOR_SET_STROKE_CS_DEVICECMYK,
                         Color::Greyscale(_) => OP_COLOR_SET_STROKE_CS_DEVICEGRAY,
+                        Color::Lab(_) | Color::CalRgb(_) | Color::CalGray(_) => OP_COLOR_SET_STROKE_COLOR_ICC,
                     };
 
                     let cvec = outline.into_vec().into_iter().map(Real).collect();
@@ -53,7 +83,7 @@ impl Into<Operation> for PdfColor {
 }
 
 /// Color space (enum for marking the number of bits a color has)
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ColorSpace {
     Rgb,
     Rgba,
@@ -77,6 +107,19 @@ impl From<image::ColorType> for ColorSpace {
     }
 }
 
+impl ColorSpace {
+    /// Number of components of a pixel (including the alpha channel)
+    pub fn num_components(self) -> usize {
+        use self::ColorSpace::*;
+        match self {
+            Greyscale | Palette => 1,
+            GreyscaleAlpha => 2,
+            Rgb => 3,
+            Rgba | Cmyk => 4,
+        }
+    }
+}
+
 impl Into<&'static str> for ColorSpace {
     fn into(self) -> &'static str {
         use self::ColorSpace::*;
@@ -91,9 +134,11 @@ impl Into<&'static str> for ColorSpace {
 }
 
 /// How many bits does a color have?
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ColorBits {
     Bit1,
+    Bit2,
+    Bit4,
     Bit8,
     Bit16,
 }
@@ -116,6 +161,8 @@ impl Into<i64> for ColorBits {
     fn into(self) -> i64 {
         match self {
             ColorBits::Bit1 => 1,
+            ColorBits::Bit2 => 2,
+            ColorBits::Bit4 => 4,
             ColorBits::Bit8 => 8,
             ColorBits::Bit16 => 16,
         }
@@ -129,6 +176,12 @@ pub enum Color {
     Cmyk(Cmyk),
     Greyscale(Greyscale),
     SpotColor(SpotColor),
+    /// CIE L*a*b* color, device independent
+    Lab(Lab),
+    /// Calibrated RGB color (CIE-based `CalRGB`)
+    CalRgb(CalRgb),
+    /// Calibrated gray (CIE-based `CalGray`)
+    CalGray(CalGray),
 }
 
 impl Color {
@@ -147,16 +200,39 @@ impl Color {
             Color::SpotColor(spot) => {
                 vec![spot.c, spot.m, spot.y, spot.k]
             }
+            Color::Lab(lab) => {
+                vec![lab.l, lab.a, lab.b]
+            }
+            Color::CalRgb(rgb) => {
+                vec![rgb.r, rgb.g, rgb.b]
+            }
+            Color::CalGray(gray) => {
+                vec![gray.gray]
+            }
         }
     }
 
+    /// CIE-based color space of `Lab`, `CalRgb` and `CalGray` colors, with the name of the
+    /// color space resource (a hash of the canonical parameters, see `cie_parameters`)
+    pub(crate) fn cie_color_space(&self) -> Option<(String, ImageColorSpace)> {
+        let color_space = match *self {
+            Color::Lab(ref lab) => ImageColorSpace::Lab(lab.color_space),
+            Color::CalRgb(ref rgb) => ImageColorSpace::CalRgb(rgb.color_space),
+            Color::CalGray(ref gray) => ImageColorSpace::CalGray(gray.color_space),
+            _ => return None,
+        };
+        let hash = md5(&cie_parameters(&color_space)?);
+        let name = format!("CIE{}", hash[..8].iter().map(|b| format!("{:02X}", b)).collect::<String>());
+        Some((name, color_space))
+    }
+
     /// Returns if the color has an icc profile attached
     pub fn get_icc_profile(&self) -> Option<&Option<IccProfileRef>> {
         match *self {
             Color::Rgb(ref rgb) => Some(&rgb.icc_profile),
             Color::Cmyk(ref cmyk) => Some(&cmyk.icc_profile),
             Color::Greyscale(ref gs) => Some(&gs.icc_profile),
-            Color::SpotColor(_) => None,
+            Color::SpotColor(_) | Color::Lab(_) | Color::CalRgb(_) | Color::CalGray(_) => None,
         }
     }
 }
@@ -223,7 +299,8 @@ impl Greyscale {
 /// Spot color
 /// Spot colors are like Cmyk, but without color space
 /// They are essentially "named" colors from specific vendors
-/// currently they are the same as a CMYK color.
+/// currently they are the same as a CMYK color. For spot colors that are kept
+/// as separate inks, use an `ImageColorSpace::Separation`
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct SpotColor {
     pub c: f64,
@@ -237,3 +314,160 @@ impl SpotColor {
         Self { c, m, y, k }
     }
 }
+
+/// Canonical parameters of a CIE-based color space: the family followed by the big-endian
+/// bits of every parameter (with `-0.0` as `0.0`). Two color spaces with the same parameters
+/// have the same resource name, page resources with the same name and different parameters
+/// are rejected
+pub(crate) fn cie_parameters(color_space: &ImageColorSpace) -> Option<Vec<u8>> {
+    let (family, parameters): (&[u8], Vec<f64>) = match *color_space {
+        ImageColorSpace::Lab(ref lab) => (b"Lab", [&lab.white_point[..], &lab.black_point, &lab.range].concat()),
+        ImageColorSpace::CalRgb(ref rgb) => {
+            (b"CalRGB", [&rgb.white_point[..], &rgb.black_point, &rgb.gamma, &rgb.matrix].concat())
+        }
+        ImageColorSpace::CalGray(ref gray) => {
+            (b"CalGray", [&gray.white_point[..], &gray.black_point, &[gray.gamma]].concat())
+        }
+        _ => return None,
+    };
+
+    let mut bytes = family.to_vec();
+    for parameter in parameters {
+        bytes.extend_from_slice(&(parameter + 0.0).to_bits().to_be_bytes());
+    }
+    Some(bytes)
+}
+
+/// White point of the D50 standard illuminant, the default of the CIE-based color spaces
+pub const D50_WHITE_POINT: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// Parameters of a CIE-based `Lab` color space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LabColorSpace {
+    /// Diffuse white point in CIE XYZ, Y has to be 1.0 (default: D50)
+    pub white_point: [f64; 3],
+    /// Diffuse black point in CIE XYZ (default: 0, 0, 0)
+    pub black_point: [f64; 3],
+    /// Range of a* and b*: `[a_min a_max b_min b_max]` (default: -100 to 100)
+    pub range: [f64; 4],
+}
+
+impl Default for LabColorSpace {
+    fn default() -> Self {
+        Self {
+            white_point: D50_WHITE_POINT,
+            black_point: [0.0; 3],
+            range: [-100.0, 100.0, -100.0, 100.0],
+        }
+    }
+}
+
+/// Parameters of a calibrated RGB (`CalRGB`) color space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CalRgbColorSpace {
+    /// Diffuse white point in CIE XYZ, Y has to be 1.0 (default: D50)
+    pub white_point: [f64; 3],
+    /// Diffuse black point in CIE XYZ (default: 0, 0, 0)
+    pub black_point: [f64; 3],
+    /// Gamma of the red, green and blue components (default: 1.0)
+    pub gamma: [f64; 3],
+    /// Linear transformation from the decoded components to XYZ, as
+    /// `[X_r Y_r Z_r X_g Y_g Z_g X_b Y_b Z_b]` (default: identity)
+    pub matrix: [f64; 9],
+}
+
+impl Default for CalRgbColorSpace {
+    fn default() -> Self {
+        Self {
+            white_point: D50_WHITE_POINT,
+            black_point: [0.0; 3],
+            gamma: [1.0; 3],
+            matrix: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Parameters of a calibrated gray (`CalGray`) color space
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CalGrayColorSpace {
+    /// Diffuse white point in CIE XYZ, Y has to be 1.0 (default: D50)
+    pub white_point: [f64; 3],
+    /// Diffuse black point in CIE XYZ (default: 0, 0, 0)
+    pub black_point: [f64; 3],
+    /// Gamma of the gray component (default: 1.0)
+    pub gamma: f64,
+}
+
+impl Default for CalGrayColorSpace {
+    fn default() -> Self {
+        Self {
+            white_point: D50_WHITE_POINT,
+            black_point: [0.0; 3],
+            gamma: 1.0,
+        }
+    }
+}
+
+/// CIE L*a*b* color
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lab {
+    /// Lightness, from 0 to 100
+    pub l: f64,
+    /// a* (green - red), within the range of the color space
+    pub a: f64,
+    /// b* (blue - yellow), within the range of the color space
+    pub b: f64,
+    /// Parameters of the color space
+    pub color_space: LabColorSpace,
+}
+
+impl Lab {
+    /// Creates a new Lab color, relative to D50
+    pub fn new(l: f64, a: f64, b: f64) -> Self {
+        Self {
+            l,
+            a,
+            b,
+            color_space: LabColorSpace::default(),
+        }
+    }
+
+    /// Sets the parameters of the color space
+    #[inline]
+    pub fn with_color_space(mut self, color_space: LabColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+}
+
+/// Calibrated RGB color
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CalRgb {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+    /// Parameters of the color space
+    pub color_space: CalRgbColorSpace,
+}
+
+impl CalRgb {
+    /// Creates a new calibrated RGB color
+    pub fn new(r: f64, g: f64, b: f64, color_space: CalRgbColorSpace) -> Self {
+        Self { r, g, b, color_space }
+    }
+}
+
+/// Calibrated gray
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CalGray {
+    pub gray: f64,
+    /// Parameters of the color space
+    pub color_space: CalGrayColorSpace,
+}
+
+impl CalGray {
+    /// Creates a new calibrated gray
+    pub fn new(gray: f64, color_space: CalGrayColorSpace) -> Self {
+        Self { gray, color_space }
+    }
+}
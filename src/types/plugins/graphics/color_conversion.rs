@@ -0,0 +1,300 @@
+//! Conversion of colors between RGB, CMYK, greyscale and Lab, e.g. to convert the RGB colors
+//! of a document to CMYK for PDF/X-1a. Without a profile, simple formulas are used (RGB and
+//! greyscale are treated as sRGB), with an ICC profile the colors of the profile's color space
+//! are converted through the profile
+
+use super::icc_cmm::{lab_to_xyz, xyz_to_lab, DeviceSpace, IccTransform};
+use {Cmyk, Color, Error, Greyscale, IccProfile, Lab, RenderingIntent, Rgb, D50_WHITE_POINT};
+
+/// sRGB (linear) to XYZ, adapted to D50 (Bradford)
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4360747, 0.3850649, 0.1430804],
+    [0.2225045, 0.7168786, 0.0606169],
+    [0.0139322, 0.0971045, 0.7141733],
+];
+
+/// XYZ (D50) to linear sRGB
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.1338561, -1.6168667, -0.4906146],
+    [-0.9787684, 1.9161415, 0.0334540],
+    [0.0719453, -0.2289914, 1.4052427],
+];
+
+/// Converts colors between RGB, CMYK, greyscale and Lab.
+///
+/// With an ICC profile (`with_profile`), colors are converted through the profile when they
+/// are converted to or from the color space of the profile: for a CMYK output profile, an RGB
+/// color is converted to Lab and then to the CMYK values of the profile. Device colors in other
+/// color spaces are treated as sRGB. The `icc_profile` of the converted colors is `None`.
+///
+/// ```rust
+/// use pdf_rs::*;
+///
+/// fn to_press_cmyk(color: &Color, cmyk_profile_bytes: Vec<u8>) -> Result<Cmyk, Error> {
+///     let profile = IccProfile::new(cmyk_profile_bytes, IccProfileType::Cmyk);
+///     let converter = ColorConverter::with_profile(&profile, RenderingIntent::RelativeColorimetric)?;
+///     Ok(converter.to_cmyk(color))
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ColorConverter {
+    /// Transform of the ICC profile, `None` for the simple formulas
+    transform: Option<IccTransform>,
+}
+
+impl ColorConverter {
+    /// Creates a converter that uses simple formulas
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a converter that converts through an RGB, CMYK or greyscale ICC profile.
+    /// Fails with `PdfError::InvalidIccProfile` if the profile can't be parsed
+    pub fn with_profile(profile: &IccProfile, intent: RenderingIntent) -> Result<Self, Error> {
+        Ok(Self {
+            transform: Some(IccTransform::new(profile.bytes(), intent)?),
+        })
+    }
+
+    /// Converts the color to CMYK
+    pub fn to_cmyk(&self, color: &Color) -> Cmyk {
+        match *color {
+            Color::Cmyk(ref cmyk) => return Cmyk::new(cmyk.c, cmyk.m, cmyk.y, cmyk.k, None),
+            Color::SpotColor(spot) => return Cmyk::new(spot.c, spot.m, spot.y, spot.k, None),
+            _ => {}
+        }
+        if let Some(v) = self.through_profile(DeviceSpace::Cmyk, color) {
+            return Cmyk::new(v[0], v[1], v[2], v[3], None);
+        }
+        if let Color::Greyscale(ref gray) = *color {
+            return Cmyk::new(0.0, 0.0, 0.0, 1.0 - clamp(gray.percent), None);
+        }
+        let [c, m, y, k] = rgb_to_cmyk(self.rgb_values(color));
+        Cmyk::new(c, m, y, k, None)
+    }
+
+    /// Converts the color to RGB
+    pub fn to_rgb(&self, color: &Color) -> Rgb {
+        if let Color::Rgb(ref rgb) = *color {
+            return Rgb::new(rgb.r, rgb.g, rgb.b, None);
+        }
+        if let Some(v) = self.through_profile(DeviceSpace::Rgb, color) {
+            return Rgb::new(v[0], v[1], v[2], None);
+        }
+        let [r, g, b] = self.rgb_values(color);
+        Rgb::new(r, g, b, None)
+    }
+
+    /// Converts the color to greyscale
+    pub fn to_greyscale(&self, color: &Color) -> Greyscale {
+        if let Color::Greyscale(ref gray) = *color {
+            return Greyscale::new(gray.percent, None);
+        }
+        if let Some(v) = self.through_profile(DeviceSpace::Gray, color) {
+            return Greyscale::new(v[0], None);
+        }
+        let percent = match *color {
+            Color::Rgb(_) | Color::Cmyk(_) | Color::SpotColor(_) if !self.in_profile(color) => {
+                let [r, g, b] = self.rgb_values(color);
+                0.299 * r + 0.587 * g + 0.114 * b
+            }
+            // lightness of the color as sRGB gray
+            _ => srgb_encode(lab_to_xyz([self.lab_values(color)[0], 0.0, 0.0], D50_WHITE_POINT)[1]),
+        };
+        Greyscale::new(percent, None)
+    }
+
+    /// Converts the color to Lab (D50)
+    pub fn to_lab(&self, color: &Color) -> Lab {
+        if let Color::Lab(lab) = *color {
+            return lab;
+        }
+        let [l, a, b] = self.lab_values(color);
+        Lab::new(l, a, b)
+    }
+
+    /// Device color space and values of device colors
+    fn device_values(color: &Color) -> Option<(DeviceSpace, Vec<f64>)> {
+        match *color {
+            Color::Rgb(ref rgb) => Some((DeviceSpace::Rgb, vec![rgb.r, rgb.g, rgb.b])),
+            Color::Cmyk(ref cmyk) => Some((DeviceSpace::Cmyk, vec![cmyk.c, cmyk.m, cmyk.y, cmyk.k])),
+            Color::SpotColor(spot) => Some((DeviceSpace::Cmyk, vec![spot.c, spot.m, spot.y, spot.k])),
+            Color::Greyscale(ref gray) => Some((DeviceSpace::Gray, vec![gray.percent])),
+            Color::Lab(_) | Color::CalRgb(_) | Color::CalGray(_) => None,
+        }
+    }
+
+    /// Is the color in the color space of the profile?
+    fn in_profile(&self, color: &Color) -> bool {
+        match (&self.transform, Self::device_values(color)) {
+            (Some(transform), Some((space, _))) => transform.device_space == space,
+            _ => false,
+        }
+    }
+
+    /// Converts the color to the values of the profile, if the profile is in the color space
+    fn through_profile(&self, space: DeviceSpace, color: &Color) -> Option<Vec<f64>> {
+        match self.transform {
+            Some(ref transform) if transform.device_space == space => {
+                Some(transform.lab_to_device(self.lab_values(color)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Lab (D50) of a color. CIE-based colors are adapted from their white point by scaling XYZ
+    fn lab_values(&self, color: &Color) -> [f64; 3] {
+        if let (Some(transform), Some((space, values))) = (&self.transform, Self::device_values(color)) {
+            if transform.device_space == space {
+                return transform.device_to_lab(&values);
+            }
+        }
+        match *color {
+            Color::Rgb(ref rgb) => srgb_to_lab([rgb.r, rgb.g, rgb.b]),
+            Color::Cmyk(ref cmyk) => srgb_to_lab(cmyk_to_rgb([cmyk.c, cmyk.m, cmyk.y, cmyk.k])),
+            Color::SpotColor(spot) => srgb_to_lab(cmyk_to_rgb([spot.c, spot.m, spot.y, spot.k])),
+            Color::Greyscale(ref gray) => srgb_to_lab([gray.percent; 3]),
+            Color::Lab(lab) => [lab.l, lab.a, lab.b],
+            Color::CalRgb(rgb) => {
+                let space = rgb.color_space;
+                let mut xyz = [0.0; 3];
+                for (i, value) in [rgb.r, rgb.g, rgb.b].iter().enumerate() {
+                    let linear = clamp(*value).powf(space.gamma[i]);
+                    for (c, component) in xyz.iter_mut().enumerate() {
+                        *component += linear * space.matrix[3 * i + c];
+                    }
+                }
+                xyz_to_lab(xyz, space.white_point)
+            }
+            Color::CalGray(gray) => {
+                let space = gray.color_space;
+                let y = clamp(gray.gray).powf(space.gamma);
+                let xyz = [space.white_point[0] * y, y, space.white_point[2] * y];
+                [xyz_to_lab(xyz, space.white_point)[0], 0.0, 0.0]
+            }
+        }
+    }
+
+    /// sRGB values of a color
+    fn rgb_values(&self, color: &Color) -> [f64; 3] {
+        match *color {
+            _ if self.in_profile(color) => lab_to_srgb(self.lab_values(color)),
+            Color::Rgb(ref rgb) => [clamp(rgb.r), clamp(rgb.g), clamp(rgb.b)],
+            Color::Cmyk(ref cmyk) => cmyk_to_rgb([cmyk.c, cmyk.m, cmyk.y, cmyk.k]),
+            Color::SpotColor(spot) => cmyk_to_rgb([spot.c, spot.m, spot.y, spot.k]),
+            Color::Greyscale(ref gray) => [clamp(gray.percent); 3],
+            Color::Lab(_) | Color::CalRgb(_) | Color::CalGray(_) => lab_to_srgb(self.lab_values(color)),
+        }
+    }
+}
+
+fn clamp(value: f64) -> f64 {
+    value.clamp(0.0, 1.0)
+}
+
+fn rgb_to_cmyk(rgb: [f64; 3]) -> [f64; 4] {
+    let [r, g, b] = rgb.map(clamp);
+    let k = 1.0 - r.max(g).max(b);
+    if k >= 1.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    [(1.0 - r - k) / (1.0 - k), (1.0 - g - k) / (1.0 - k), (1.0 - b - k) / (1.0 - k), k]
+}
+
+fn cmyk_to_rgb(cmyk: [f64; 4]) -> [f64; 3] {
+    let [c, m, y, k] = cmyk.map(clamp);
+    [(1.0 - c) * (1.0 - k), (1.0 - m) * (1.0 - k), (1.0 - y) * (1.0 - k)]
+}
+
+fn srgb_decode(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_encode(value: f64) -> f64 {
+    let value = clamp(value);
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn multiply(matrix: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn srgb_to_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let linear = rgb.map(|v| srgb_decode(clamp(v)));
+    xyz_to_lab(multiply(&SRGB_TO_XYZ, linear), D50_WHITE_POINT)
+}
+
+fn lab_to_srgb(lab: [f64; 3]) -> [f64; 3] {
+    multiply(&XYZ_TO_SRGB, lab_to_xyz(lab, D50_WHITE_POINT)).map(srgb_encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glob_defines::ICC_PROFILE_ECI_V2;
+    use {CalGray, CalGrayColorSpace, IccProfileRef, IccProfileType};
+
+    fn assert_close(actual: &[f64], expected: &[f64], tolerance: f64) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_naive_conversion() {
+        let converter = ColorConverter::new();
+        let red = Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None));
+
+        let cmyk = converter.to_cmyk(&red);
+        assert_close(&[cmyk.c, cmyk.m, cmyk.y, cmyk.k], &[0.0, 1.0, 1.0, 0.0], 1e-9);
+        let rgb = converter.to_rgb(&Color::Cmyk(Cmyk::new(0.0, 0.5, 0.5, 0.5, None)));
+        assert_close(&[rgb.r, rgb.g, rgb.b], &[0.5, 0.25, 0.25], 1e-9);
+        assert!((converter.to_greyscale(&red).percent - 0.299).abs() < 1e-9);
+        let black = converter.to_cmyk(&Color::Greyscale(Greyscale::new(0.0, None)));
+        assert_close(&[black.c, black.m, black.y, black.k], &[0.0, 0.0, 0.0, 1.0], 1e-9);
+
+        let white = converter.to_lab(&Color::Rgb(Rgb::new(1.0, 1.0, 1.0, None)));
+        assert_close(&[white.l, white.a, white.b], &[100.0, 0.0, 0.0], 0.05);
+        let lab = converter.to_lab(&red);
+        assert_close(&[lab.l, lab.a, lab.b], &[54.29, 80.8, 69.89], 0.1);
+        let rgb = converter.to_rgb(&Color::Lab(lab));
+        assert_close(&[rgb.r, rgb.g, rgb.b], &[1.0, 0.0, 0.0], 1e-3);
+
+        let gray = CalGray::new(0.5, CalGrayColorSpace { gamma: 2.2, ..Default::default() });
+        let lab = converter.to_lab(&Color::CalGray(gray));
+        assert_close(&[lab.l, lab.a, lab.b], &[53.78, 0.0, 0.0], 0.1);
+    }
+
+    #[test]
+    fn test_profile_conversion() {
+        let profile = IccProfile::new(ICC_PROFILE_ECI_V2.to_vec(), IccProfileType::Cmyk);
+        let converter = ColorConverter::with_profile(&profile, RenderingIntent::RelativeColorimetric).unwrap();
+
+        let red = converter.to_cmyk(&Color::Rgb(Rgb::new(1.0, 0.0, 0.0, None)));
+        assert!(red.c < 0.1 && red.m > 0.8 && red.y > 0.8 && red.k < 0.1);
+
+        // paper white is (almost) white, the profile is used in both directions
+        let paper = converter.to_rgb(&Color::Cmyk(Cmyk::new(0.0, 0.0, 0.0, 0.0, None)));
+        assert!(paper.r > 0.9 && paper.g > 0.9 && paper.b > 0.9);
+        let white = converter.to_cmyk(&Color::Lab(Lab::new(100.0, 0.0, 0.0)));
+        assert!(white.c + white.m + white.y + white.k < 0.05);
+
+        // colors that are already in the target color space lose their profile
+        let icc = || Some(IccProfileRef::new(0));
+        let rgb = converter.to_rgb(&Color::Rgb(Rgb::new(0.2, 0.4, 0.6, icc())));
+        assert_eq!(rgb, Rgb::new(0.2, 0.4, 0.6, None));
+        assert!(converter.to_greyscale(&Color::Greyscale(Greyscale::new(0.5, icc()))).icc_profile.is_none());
+        assert!(converter.to_cmyk(&Color::Cmyk(Cmyk::new(0.0, 0.0, 0.0, 1.0, icc()))).icc_profile.is_none());
+
+        let invalid = IccProfile::new(vec![0; 128], IccProfileType::Cmyk);
+        assert!(ColorConverter::with_profile(&invalid, RenderingIntent::Perceptual).is_err());
+    }
+}
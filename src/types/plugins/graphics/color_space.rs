@@ -0,0 +1,534 @@
+//! Special color spaces of images and vector graphics: `/Indexed`, `/Separation`, `/DeviceN`,
+//! `/ICCBased` and the CIE-based `/Lab`, `/CalRGB` and `/CalGray`
+
+use lopdf;
+use lopdf::Object::*;
+use lopdf::StringFormat::Hexadecimal;
+use std::iter::FromIterator;
+
+use utils::to_pdf_reals;
+use {
+    CalGrayColorSpace, CalRgbColorSpace, ColorSpace, Embeddable, Embedded, Error, IccProfile,
+    LabColorSpace, PdfError,
+};
+
+/// A colorant (ink) of a `Separation` or `DeviceN` color space
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colorant {
+    /// Name of the colorant, for example `PANTONE 185 C`
+    pub name: ::std::string::String,
+    /// Appearance of the colorant at full tint in the alternate color space, used by
+    /// devices that don't have the colorant (one value between 0.0 and 1.0 per component)
+    pub alternate_color: Vec<f64>,
+}
+
+impl Colorant {
+    /// Creates a new colorant
+    pub fn new<S: Into<::std::string::String>>(name: S, alternate_color: Vec<f64>) -> Self {
+        Self {
+            name: name.into(),
+            alternate_color,
+        }
+    }
+}
+
+/// Color space of an image that can't be expressed with a `ColorSpace`. Embedded and
+/// registered on a page, it is also used for vector graphics, see
+/// `PdfLayer::set_fill_special_color`:
+///
+/// ```rust
+/// use pdf_rs::*;
+///
+/// let mut doc = PdfDocument::new("spot colors");
+/// let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+/// let mut layer = PdfLayer::new("Layer 1");
+///
+/// let pantone = ImageColorSpace::separation(
+///     Colorant::new("PANTONE 185 C", vec![0.0, 0.91, 0.76, 0.0]),
+///     ColorSpace::Cmyk,
+/// );
+/// let pantone = page.register(&doc.embed(pantone).unwrap()).unwrap();
+///
+/// // 80% tint
+/// layer.set_fill_special_color(&pantone, vec![0.8]).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageColorSpace {
+    /// `DeviceGray`, `DeviceRGB` or `DeviceCMYK`
+    Device(ColorSpace),
+    /// Every sample is an index into a table of colors in the `base` color space
+    Indexed {
+        /// Color space of the colors in the lookup table, can't be `Indexed`
+        base: Box<ImageColorSpace>,
+        /// Highest valid index, the table has `hival + 1` colors
+        hival: u8,
+        /// The colors, one byte per component of the base color space
+        lookup: Vec<u8>,
+    },
+    /// A single colorant, the samples are the tint values
+    Separation {
+        /// The colorant
+        colorant: Colorant,
+        /// Device color space used if the output device doesn't have the colorant
+        alternate: ColorSpace,
+    },
+    /// Several colorants (for example a duotone or a multichannel image)
+    DeviceN {
+        /// The colorants, one sample per colorant
+        colorants: Vec<Colorant>,
+        /// Device color space used if the output device doesn't have the colorants
+        alternate: ColorSpace,
+    },
+    /// `DeviceN` color space with the `NChannel` subtype (PDF 1.6), which also describes
+    /// every colorant as a `Separation`, so that devices can blend the colorants themselves
+    NChannel {
+        /// The colorants, one sample per colorant
+        colorants: Vec<Colorant>,
+        /// Device color space used if the output device doesn't have the colorants
+        alternate: ColorSpace,
+    },
+    /// An ICC profile that is embedded once and shared between images
+    IccBased {
+        /// The embedded `IccProfile`
+        profile: lopdf::ObjectId,
+        /// Number of color components of the profile
+        components: usize,
+    },
+    /// CIE L*a*b* color space
+    Lab(LabColorSpace),
+    /// Calibrated RGB color space
+    CalRgb(CalRgbColorSpace),
+    /// Calibrated gray color space
+    CalGray(CalGrayColorSpace),
+}
+
+impl ImageColorSpace {
+    /// Indexed color space with a lookup table in the `base` color space,
+    /// for example the palette of a PNG image
+    pub fn indexed(base: ImageColorSpace, lookup: Vec<u8>) -> Result<Self, Error> {
+        let base_components = base.num_components();
+        let colors = lookup.len() / base_components.max(1);
+
+        let is_valid = !matches!(base, ImageColorSpace::Indexed { .. })
+            && base_components > 0
+            && colors > 0
+            && colors <= 256
+            && lookup.len().is_multiple_of(base_components);
+
+        if !is_valid {
+            return Err(PdfError::InvalidImage.into());
+        }
+
+        Ok(ImageColorSpace::Indexed {
+            base: Box::new(base),
+            hival: (colors - 1) as u8,
+            lookup,
+        })
+    }
+
+    /// Separation color space for a spot color (a single colorant)
+    #[inline]
+    pub fn separation(colorant: Colorant, alternate: ColorSpace) -> Self {
+        ImageColorSpace::Separation { colorant, alternate }
+    }
+
+    /// The special `All` separation, paints on all separations of the output device
+    /// (for example registration marks)
+    pub fn all_separations() -> Self {
+        Self::separation(Colorant::new("All", vec![1.0; 4]), ColorSpace::Cmyk)
+    }
+
+    /// The special `None` separation, which never paints anything
+    pub fn no_separation() -> Self {
+        Self::separation(Colorant::new("None", vec![0.0; 4]), ColorSpace::Cmyk)
+    }
+
+    /// ICCBased color space that references an embedded profile
+    pub fn icc_based(profile: &Embedded<IccProfile>) -> Self {
+        ImageColorSpace::IccBased {
+            profile: profile.object_id,
+            components: profile.num_components(),
+        }
+    }
+
+    /// Number of color components of a sample
+    pub fn num_components(&self) -> usize {
+        match self {
+            ImageColorSpace::Device(color_space) => color_space.num_components(),
+            ImageColorSpace::Indexed { .. } | ImageColorSpace::Separation { .. } => 1,
+            ImageColorSpace::DeviceN { colorants, .. } | ImageColorSpace::NChannel { colorants, .. } => {
+                colorants.len()
+            }
+            ImageColorSpace::IccBased { components, .. } => *components,
+            ImageColorSpace::Lab(_) | ImageColorSpace::CalRgb(_) => 3,
+            ImageColorSpace::CalGray(_) => 1,
+        }
+    }
+
+    /// Writes the color space, adds the tint transform functions to the document
+    pub(crate) fn to_object(&self, doc: &mut lopdf::Document) -> Result<lopdf::Object, Error> {
+        Ok(match self {
+            ImageColorSpace::Device(color_space) => {
+                let name: &'static str = device_color_space(*color_space)?.into();
+                Name(name.into())
+            }
+            ImageColorSpace::Indexed { base, hival, lookup } => Array(vec![
+                Name("Indexed".into()),
+                base.to_object(doc)?,
+                Integer(i64::from(*hival)),
+                String(lookup.clone(), Hexadecimal),
+            ]),
+            ImageColorSpace::Separation { colorant, alternate } => {
+                let alternate = device_color_space(*alternate)?;
+                check_colorant(colorant, alternate)?;
+                let name: &'static str = alternate.into();
+                Array(vec![
+                    Name("Separation".into()),
+                    Name(colorant.name.as_bytes().to_vec()),
+                    Name(name.into()),
+                    Dictionary(separation_tint_transform(colorant, alternate)),
+                ])
+            }
+            ImageColorSpace::DeviceN { colorants, alternate } => {
+                device_n(doc, colorants, *alternate)?
+            }
+            ImageColorSpace::NChannel { colorants, alternate } => {
+                let mut separations = lopdf::Dictionary::new();
+                for colorant in colorants {
+                    let separation = Self::separation(colorant.clone(), *alternate).to_object(doc)?;
+                    separations.set(colorant.name.as_bytes().to_vec(), separation);
+                }
+                let attributes = lopdf::Dictionary::from_iter(vec![
+                    ("Subtype", Name("NChannel".into())),
+                    ("Colorants", Dictionary(separations)),
+                ]);
+
+                let mut device_n = device_n(doc, colorants, *alternate)?;
+                if let Array(ref mut array) = device_n {
+                    array.push(Dictionary(attributes));
+                }
+                device_n
+            }
+            ImageColorSpace::IccBased { profile, .. } => {
+                Array(vec![Name("ICCBased".into()), Reference(*profile)])
+            }
+            ImageColorSpace::Lab(lab) => {
+                let mut dict = cie_dictionary(lab.white_point, lab.black_point)?;
+                dict.set("Range", to_pdf_reals(&lab.range));
+                Array(vec![Name("Lab".into()), Dictionary(dict)])
+            }
+            ImageColorSpace::CalRgb(rgb) => {
+                let mut dict = cie_dictionary(rgb.white_point, rgb.black_point)?;
+                dict.set("Gamma", to_pdf_reals(&rgb.gamma));
+                dict.set("Matrix", to_pdf_reals(&rgb.matrix));
+                Array(vec![Name("CalRGB".into()), Dictionary(dict)])
+            }
+            ImageColorSpace::CalGray(gray) => {
+                let mut dict = cie_dictionary(gray.white_point, gray.black_point)?;
+                dict.set("Gamma", Real(gray.gamma));
+                Array(vec![Name("CalGray".into()), Dictionary(dict)])
+            }
+        })
+    }
+}
+
+impl Embeddable for ImageColorSpace {
+    const KEY: &'static str = "ColorSpace";
+
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        let object = self.to_object(doc)?;
+        Ok(doc.add_object(object))
+    }
+}
+
+// [/DeviceN [names] alternate tintTransform]
+fn device_n(doc: &mut lopdf::Document, colorants: &[Colorant], alternate: ColorSpace) -> Result<lopdf::Object, Error> {
+    let alternate = device_color_space(alternate)?;
+    for colorant in colorants {
+        check_colorant(colorant, alternate)?;
+    }
+    if colorants.is_empty() {
+        return Err(PdfError::InvalidImage.into());
+    }
+    let name: &'static str = alternate.into();
+    let names = colorants.iter().map(|c| Name(c.name.as_bytes().to_vec())).collect();
+    let tint_transform = doc.add_object(device_n_tint_transform(colorants, alternate));
+    Ok(Array(vec![
+        Name("DeviceN".into()),
+        Array(names),
+        Name(name.into()),
+        Reference(tint_transform),
+    ]))
+}
+
+// white point and black point of a CIE-based color space, the Y of the white point has to
+// be 1.0 and X and Z positive, the black point can't be negative
+fn cie_dictionary(white_point: [f64; 3], black_point: [f64; 3]) -> Result<lopdf::Dictionary, Error> {
+    let [x, y, z] = white_point;
+    if (y - 1.0).abs() > 1e-6 || x <= 0.0 || z <= 0.0 || black_point.iter().any(|v| *v < 0.0) {
+        return Err(PdfError::InvalidResource.into());
+    }
+
+    let mut dict = lopdf::Dictionary::new();
+    dict.set("WhitePoint", to_pdf_reals(&white_point));
+    if black_point != [0.0; 3] {
+        dict.set("BlackPoint", to_pdf_reals(&black_point));
+    }
+    Ok(dict)
+}
+
+// only the device color spaces without alpha channel can be written as a PDF color space
+fn device_color_space(color_space: ColorSpace) -> Result<ColorSpace, Error> {
+    match color_space {
+        ColorSpace::Greyscale | ColorSpace::Rgb | ColorSpace::Cmyk => Ok(color_space),
+        _ => Err(PdfError::InvalidImage.into()),
+    }
+}
+
+fn check_colorant(colorant: &Colorant, alternate: ColorSpace) -> Result<(), Error> {
+    if colorant.name.is_empty() || colorant.alternate_color.len() != alternate.num_components() {
+        return Err(PdfError::InvalidImage.into());
+    }
+    Ok(())
+}
+
+// color of the alternate color space without any colorant (paper white)
+fn no_tint(alternate: ColorSpace) -> f64 {
+    match alternate {
+        ColorSpace::Cmyk => 0.0,
+        _ => 1.0,
+    }
+}
+
+// exponential interpolation (function type 2) from no tint to the full tint
+fn separation_tint_transform(colorant: &Colorant, alternate: ColorSpace) -> lopdf::Dictionary {
+    let c0 = vec![Real(no_tint(alternate)); colorant.alternate_color.len()];
+    let c1 = colorant.alternate_color.iter().map(|c| Real(c.clamp(0.0, 1.0))).collect();
+
+    lopdf::Dictionary::from_iter(vec![
+        ("FunctionType", Integer(2)),
+        ("Domain", Array(vec![Real(0.0), Real(1.0)])),
+        ("C0", Array(c0)),
+        ("C1", Array(c1)),
+        ("N", Real(1.0)),
+    ])
+}
+
+// PostScript calculator function (function type 4) that mixes the colorants:
+// subtractive (CMYK) components are added, additive (RGB, gray) components are
+// multiplied, as if the inks were printed on top of each other
+fn device_n_tint_transform(colorants: &[Colorant], alternate: ColorSpace) -> lopdf::Stream {
+    let inputs = colorants.len();
+    let outputs = alternate.num_components();
+    let is_subtractive = alternate == ColorSpace::Cmyk;
+    let mut program = vec!["{".to_string()];
+
+    for output in 0..outputs {
+        program.push(if is_subtractive { "0".into() } else { "1".into() });
+        for (input, colorant) in colorants.iter().enumerate() {
+            let color = colorant.alternate_color[output].clamp(0.0, 1.0);
+            // copy the tint: skip the later tints, the partial result and the finished outputs
+            let depth = (inputs - 1 - input) + 1 + output;
+            if is_subtractive {
+                program.push(format!("{} index {} mul add", depth, format_number(color)));
+            } else {
+                program.push(format!("{} index {} mul 1 exch sub mul", depth, format_number(1.0 - color)));
+            }
+        }
+        if is_subtractive {
+            program.push("dup 1 gt { pop 1 } if".into());
+        }
+    }
+
+    // move the outputs below the tints and remove the tints
+    program.push(format!("{} {} roll", inputs + outputs, outputs));
+    program.extend((0..inputs).map(|_| "pop".to_string()));
+    program.push("}".into());
+
+    let range = |n: usize| Array((0..n).flat_map(|_| vec![Real(0.0), Real(1.0)]).collect());
+    let dict = lopdf::Dictionary::from_iter(vec![
+        ("FunctionType", Integer(4)),
+        ("Domain", range(inputs)),
+        ("Range", range(outputs)),
+    ]);
+
+    lopdf::Stream::new(dict, program.join(" ").into_bytes())
+}
+
+// decimal number with at most 4 decimal places, as used in PostScript functions
+fn format_number(value: f64) -> ::std::string::String {
+    let formatted = format!("{:.4}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "" | "-0" => "0".into(),
+        other => other.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // evaluates the subset of PostScript that is used by the tint transforms
+    fn run_program(program: &str, inputs: &[f64]) -> Vec<f64> {
+        let mut stack = inputs.to_vec();
+        let tokens: Vec<&str> = program.split_whitespace().collect();
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i] {
+                "{" | "}" => {}
+                "index" => {
+                    let n = stack.pop().unwrap() as usize;
+                    let value = stack[stack.len() - 1 - n];
+                    stack.push(value);
+                }
+                "mul" | "add" | "sub" => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    stack.push(match tokens[i] {
+                        "mul" => a * b,
+                        "add" => a + b,
+                        _ => a - b,
+                    });
+                }
+                "exch" => {
+                    let len = stack.len();
+                    stack.swap(len - 1, len - 2);
+                }
+                "dup" => stack.push(*stack.last().unwrap()),
+                "pop" => {
+                    stack.pop();
+                }
+                "roll" => {
+                    let j = stack.pop().unwrap() as usize;
+                    let n = stack.pop().unwrap() as usize;
+                    let start = stack.len() - n;
+                    stack[start..].rotate_right(j % n);
+                }
+                // "1 gt { pop 1 } if"
+                "gt" => {
+                    let limit = stack.pop().unwrap();
+                    if stack.pop().unwrap() > limit {
+                        stack.pop();
+                        stack.push(1.0);
+                    }
+                    i += 5;
+                }
+                number => stack.push(number.parse().unwrap()),
+            }
+            i += 1;
+        }
+        stack
+    }
+
+    #[test]
+    fn test_device_n_tint_transform() {
+        let colorants = vec![
+            Colorant::new("Orange", vec![0.0, 0.5, 1.0, 0.0]),
+            Colorant::new("Black", vec![0.0, 0.0, 0.0, 1.0]),
+        ];
+        let stream = device_n_tint_transform(&colorants, ColorSpace::Cmyk);
+        let program = ::std::string::String::from_utf8(stream.content).unwrap();
+        let result = run_program(&program, &[0.5, 0.25]);
+        assert_eq!(result, vec![0.0, 0.25, 0.5, 0.25]);
+
+        let colorants = vec![Colorant::new("Red", vec![1.0, 0.0, 0.0]), Colorant::new("Blue", vec![0.0, 0.0, 1.0])];
+        let stream = device_n_tint_transform(&colorants, ColorSpace::Rgb);
+        let program = ::std::string::String::from_utf8(stream.content).unwrap();
+        assert_eq!(run_program(&program, &[1.0, 0.0]), vec![1.0, 0.0, 0.0]);
+        assert_eq!(run_program(&program, &[1.0, 1.0]), vec![0.0, 0.0, 0.0]);
+        assert_eq!(run_program(&program, &[0.0, 0.0]), vec![1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_spot_color_spaces() {
+        let mut doc = lopdf::Document::with_version("1.6");
+
+        let object = ImageColorSpace::all_separations().to_object(&mut doc).unwrap();
+        let array = object.as_array().unwrap();
+        assert_eq!(array[0].as_name().unwrap(), b"Separation");
+        assert_eq!(array[1].as_name().unwrap(), b"All");
+        assert_eq!(array[2].as_name().unwrap(), b"DeviceCMYK");
+
+        let colorants = vec![
+            Colorant::new("PANTONE 185 C", vec![0.0, 0.91, 0.76, 0.0]),
+            Colorant::new("Black", vec![0.0, 0.0, 0.0, 1.0]),
+        ];
+        let n_channel = ImageColorSpace::NChannel { colorants, alternate: ColorSpace::Cmyk };
+        assert_eq!(n_channel.num_components(), 2);
+        let object = n_channel.to_object(&mut doc).unwrap();
+        let array = object.as_array().unwrap();
+        assert_eq!(array[0].as_name().unwrap(), b"DeviceN");
+        let attributes = array[4].as_dict().unwrap();
+        assert_eq!(attributes.get(b"Subtype").and_then(|o| o.as_name()).unwrap(), b"NChannel");
+        let separations = attributes.get(b"Colorants").and_then(|o| o.as_dict()).unwrap();
+        assert_eq!(separations.len(), 2);
+
+        // the alternate color has to match the alternate color space
+        let invalid = ImageColorSpace::separation(Colorant::new("Red", vec![1.0]), ColorSpace::Rgb);
+        assert!(invalid.embed(&mut doc).is_err());
+    }
+
+    #[test]
+    fn test_cie_color_spaces() {
+        let mut doc = lopdf::Document::with_version("1.5");
+
+        let object = ImageColorSpace::Lab(LabColorSpace::default()).to_object(&mut doc).unwrap();
+        let array = object.as_array().unwrap();
+        assert_eq!(array[0].as_name().unwrap(), b"Lab");
+        let dict = array[1].as_dict().unwrap();
+        assert_eq!(dict.get(b"Range").and_then(|o| o.as_array()).unwrap().len(), 4);
+        assert!(dict.get(b"BlackPoint").is_err());
+
+        let cal_rgb = CalRgbColorSpace {
+            gamma: [2.2; 3],
+            black_point: [0.01, 0.01, 0.01],
+            ..CalRgbColorSpace::default()
+        };
+        let object = ImageColorSpace::CalRgb(cal_rgb).to_object(&mut doc).unwrap();
+        let dict = object.as_array().unwrap()[1].as_dict().unwrap().clone();
+        assert_eq!(dict.get(b"Matrix").and_then(|o| o.as_array()).unwrap().len(), 9);
+        assert!(dict.get(b"BlackPoint").is_ok());
+
+        let invalid = CalGrayColorSpace {
+            white_point: [0.95, 0.9, 1.09],
+            ..CalGrayColorSpace::default()
+        };
+        assert!(ImageColorSpace::CalGray(invalid).to_object(&mut doc).is_err());
+    }
+
+    #[test]
+    fn test_indexed_color_space() {
+        let palette = vec![255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let indexed = ImageColorSpace::indexed(ImageColorSpace::Device(ColorSpace::Rgb), palette.clone()).unwrap();
+        assert_eq!(indexed.num_components(), 1);
+
+        let mut doc = lopdf::Document::with_version("1.5");
+        let object = indexed.to_object(&mut doc).unwrap();
+        let array = object.as_array().unwrap();
+        assert_eq!(array[0].as_name().unwrap(), b"Indexed");
+        assert_eq!(array[1].as_name().unwrap(), b"DeviceRGB");
+        assert_eq!(array[2].as_i64().unwrap(), 2);
+        assert_eq!(array[3].as_str().unwrap(), &palette[..]);
+
+        assert!(ImageColorSpace::indexed(ImageColorSpace::Device(ColorSpace::Rgb), vec![0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_special_color_components() {
+        use {Mm, PdfDocument, PdfLayer, PdfPage};
+
+        let mut doc = PdfDocument::new("spot colors");
+        let mut page = PdfPage::new(Mm(210.0), Mm(297.0));
+        let mut layer = PdfLayer::new("Layer 1");
+
+        let spot = ImageColorSpace::separation(Colorant::new("Spot", vec![0.0, 1.0, 1.0, 0.0]), ColorSpace::Cmyk);
+        let spot = page.register(&doc.embed(spot).unwrap()).unwrap();
+
+        layer.set_fill_special_color(&spot, vec![0.5]).unwrap();
+        assert!(layer.set_fill_special_color(&spot, vec![]).is_err());
+        assert!(layer.set_outline_special_color(&spot, vec![0.5, 0.5]).is_err());
+        // only the valid color was written (`cs` and `scn`)
+        assert_eq!(layer.operations.len(), 2);
+    }
+}
@@ -1,8 +1,8 @@
-//! Current transformation matrix, for transforming shapes (rotate, translate, scale)
+//! Current transformation matrix, for transforming shapes (rotate, translate, scale, skew)
 
 use lopdf;
 use lopdf::content::Operation;
-use {Mm, Pt};
+use {Mm, Point, Pt};
 
 /// PDF "current transformation matrix". Once set, will operate on all following shapes,
 /// until the `layer.restore_graphics_state()` is called. It is important to
@@ -17,10 +17,98 @@ pub enum CurTransMat {
     /// Scale matrix (1.0 = 100% scale, no change)
     /// X and Y can have different values
     Scale(f64, f64),
+    /// Skew matrix, skews the x axis by the first and the y axis by the second angle (in degrees)
+    Skew(f64, f64),
+    /// Arbitrary matrix `[a b c d e f]`, maps a point (x, y) to
+    /// (a * x + c * y + e, b * x + d * y + f)
+    Raw([f64; 6]),
     /// Identity matrix
     Identity,
 }
 
+impl CurTransMat {
+    /// Reflection across a line through the origin, at `angle` degrees from the x axis.
+    /// `reflect(0.0)` mirrors vertically, `reflect(90.0)` mirrors horizontally
+    pub fn reflect(angle: f64) -> Self {
+        let rad = (2.0 * angle).to_radians();
+        CurTransMat::Raw([rad.cos(), rad.sin(), rad.sin(), -rad.cos(), 0.0, 0.0])
+    }
+
+    /// Rotation (like `Rotate`) around a center point instead of the origin
+    pub fn rotate_about(rotate: f64, center: Point) -> Self {
+        let (x, y) = (center.x.0, center.y.0);
+        CurTransMat::Raw([1.0, 0.0, 0.0, 1.0, -x, -y])
+            .multiply(CurTransMat::Rotate(rotate))
+            .multiply(CurTransMat::Raw([1.0, 0.0, 0.0, 1.0, x, y]))
+    }
+
+    /// Returns the six numbers of the matrix
+    #[inline]
+    pub fn as_array(&self) -> [f64; 6] {
+        (*self).into()
+    }
+
+    /// Matrix product `self x other`: the result transforms by `self` first, then by `other`.
+    /// Setting `a` and then `b` with `set_ctm` is the same as setting `b.multiply(a)`
+    pub fn multiply(self, other: CurTransMat) -> Self {
+        let m1 = self.as_array();
+        let m2 = other.as_array();
+        CurTransMat::Raw([
+            m1[0] * m2[0] + m1[1] * m2[2],
+            m1[0] * m2[1] + m1[1] * m2[3],
+            m1[2] * m2[0] + m1[3] * m2[2],
+            m1[2] * m2[1] + m1[3] * m2[3],
+            m1[4] * m2[0] + m1[5] * m2[2] + m2[4],
+            m1[4] * m2[1] + m1[5] * m2[3] + m2[5],
+        ])
+    }
+
+    /// Inverse matrix, `None` if the matrix can't be inverted (e.g. a scale of 0)
+    pub fn invert(self) -> Option<Self> {
+        let [a, b, c, d, e, f] = self.as_array();
+        let det = a * d - b * c;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        Some(CurTransMat::Raw([
+            d / det,
+            -b / det,
+            -c / det,
+            a / det,
+            (c * f - d * e) / det,
+            (b * e - a * f) / det,
+        ]))
+    }
+
+    /// Transforms a point with the matrix
+    pub fn transform_point(&self, point: Point) -> Point {
+        let [a, b, c, d, e, f] = self.as_array();
+        let (x, y) = (point.x.0, point.y.0);
+        Point {
+            x: Pt(a * x + c * y + e),
+            y: Pt(b * x + d * y + f),
+        }
+    }
+
+    /// Bounding box of a transformed rectangle, returned as the lower left and upper right corner
+    pub fn transform_bbox(&self, lower_left: Point, upper_right: Point) -> (Point, Point) {
+        let corners = [
+            (lower_left.x, lower_left.y),
+            (upper_right.x, lower_left.y),
+            (upper_right.x, upper_right.y),
+            (lower_left.x, upper_right.y),
+        ];
+        let transformed: Vec<Point> = corners.iter().map(|&(x, y)| self.transform_point(Point { x, y })).collect();
+
+        let min = |f: fn(&Point) -> f64| transformed.iter().map(f).fold(f64::INFINITY, f64::min);
+        let max = |f: fn(&Point) -> f64| transformed.iter().map(f).fold(f64::NEG_INFINITY, f64::max);
+        (
+            Point { x: Pt(min(|p| p.x.0)), y: Pt(min(|p| p.y.0)) },
+            Point { x: Pt(max(|p| p.x.0)), y: Pt(max(|p| p.y.0)) },
+        )
+    }
+}
+
 /// Text matrix. Text placement is a bit different, but uses the same
 /// concepts as a CTM that's why it's merged here
 ///
@@ -34,6 +122,14 @@ pub enum TextMatrix {
     /// Text translate matrix, used for indenting (transforming) text
     /// (different to regular text placement)
     Translate(Mm, Mm),
+    /// Arbitrary matrix `[a b c d e f]`, e.g. for skewed or mirrored text
+    Raw([f64; 6]),
+}
+
+impl From<CurTransMat> for TextMatrix {
+    fn from(ctm: CurTransMat) -> Self {
+        TextMatrix::Raw(ctm.into())
+    }
 }
 
 impl Into<[f64; 6]> for TextMatrix {
@@ -49,6 +145,7 @@ impl Into<[f64; 6]> for TextMatrix {
                 [ 1.0, 0.0, 0.0, 1.0, x_pt.0, y_pt.0 ] 
             }
             Rotate(rot) => { let rad = (360.0 - rot).to_radians(); [rad.cos(), -rad.sin(), rad.sin(), rad.cos(), 0.0, 0.0 ] /* cos sin -sin cos 0 0 cm */ }
+            Raw(matrix) => matrix,
         }
     }
 }
@@ -74,6 +171,11 @@ impl Into<[f64; 6]> for CurTransMat {
                 // x 0 0 y 0 0 cm
                 [ x, 0.0, 0.0, y, 0.0, 0.0 ] 
             }
+            Skew(alpha, beta) => {
+                // 1 tan(a) tan(b) 1 0 0 cm
+                [ 1.0, alpha.to_radians().tan(), beta.to_radians().tan(), 1.0, 0.0, 0.0 ]
+            }
+            Raw(matrix) => matrix,
             Identity => { 
                 [ 1.0, 0.0, 0.0, 1.0, 0.0, 0.0 ] 
             }
@@ -131,3 +233,32 @@ fn test_ctm_translate()
     let ctm_rot_arr: [f64; 6] = ctm_rot.into();
     assert_eq!([0.8660254037844384, 0.5000000000000004, -0.5000000000000004, 0.8660254037844384, 0.0, 0.0], ctm_rot_arr);
 }
+
+#[test]
+fn test_ctm_compose()
+{
+    use self::*;
+
+    let close = |a: [f64; 6], b: [f64; 6]| a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-9);
+    let point = |x: f64, y: f64| Point { x: Pt(x), y: Pt(y) };
+
+    // translate first, then scale
+    let m = CurTransMat::Raw([1.0, 0.0, 0.0, 1.0, 10.0, 20.0]).multiply(CurTransMat::Scale(2.0, 3.0));
+    assert!(close(m.as_array(), [2.0, 0.0, 0.0, 3.0, 20.0, 60.0]));
+
+    let skew = CurTransMat::Skew(45.0, 0.0).multiply(m);
+    let inverse = skew.invert().unwrap();
+    assert!(close(skew.multiply(inverse).as_array(), CurTransMat::Identity.as_array()));
+    assert!(CurTransMat::Scale(0.0, 1.0).invert().is_none());
+
+    let p = CurTransMat::reflect(90.0).transform_point(point(3.0, 4.0));
+    assert!((p.x.0 + 3.0).abs() < 1e-9 && (p.y.0 - 4.0).abs() < 1e-9);
+
+    // the center stays in place
+    let c = CurTransMat::rotate_about(90.0, point(5.0, 5.0)).transform_point(point(5.0, 5.0));
+    assert!((c.x.0 - 5.0).abs() < 1e-9 && (c.y.0 - 5.0).abs() < 1e-9);
+
+    let (ll, ur) = CurTransMat::Rotate(90.0).transform_bbox(point(1.0, 1.0), point(3.0, 2.0));
+    assert!((ll.x.0 + 2.0).abs() < 1e-9 && (ll.y.0 - 1.0).abs() < 1e-9);
+    assert!((ur.x.0 + 1.0).abs() < 1e-9 && (ur.y.0 - 3.0).abs() < 1e-9);
+}
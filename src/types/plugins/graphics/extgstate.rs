@@ -38,7 +38,7 @@ use lopdf::content::Operation;
 use lopdf::Object::*;
 use std::collections::HashSet;
 use std::string::String;
-use Embeddable;
+use {Embeddable, Error, Function, PdfError};
 
 // identifiers for tracking the changed fields
 pub(crate) const LINE_WIDTH: &'static str = "line_width";
@@ -105,7 +105,7 @@ pub struct ExtendedGraphicsState {
     /// __(Optional; PDF 1.3)__ The line dash pattern, expressed as an array of the form
     /// [ dashArray dashPhase ] , where dashArray is itself an array and dashPhase is an
     /// integer (see “Line Dash Pattern” on page 217).
-    pub(crate) line_dash_pattern: Option<LineDashPattern>,
+    pub(crate) line_dash_pattern: Option<DashPattern>,
 
     /* RI name (or ri inside a stream)*/
     /// __(Optional; PDF 1.3)__ The name of the rendering intent (see “Rendering
@@ -286,6 +286,14 @@ impl ExtendedGraphicsStateBuilder {
         self
     }
 
+    /// Sets the line dash pattern, a `DashPattern` or a `LineDashPattern`
+    #[inline]
+    pub fn with_line_dash_pattern<P: Into<DashPattern>>(mut self, dash_pattern: P) -> Self {
+        self.gs.line_dash_pattern = Some(dash_pattern.into());
+        self.gs.changed_fields.insert(LINE_DASH_PATTERN);
+        self
+    }
+
     /// Sets the rendering intent
     #[inline]
     pub fn with_rendering_intent(mut self, rendering_intent: RenderingIntent) -> Self {
@@ -515,12 +523,69 @@ impl Default for ExtendedGraphicsState {
 impl Embeddable for ExtendedGraphicsState {
     const KEY: &'static str = "ExtGState";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
-        let object: lopdf::Object = self.clone().into();
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        let mut object: lopdf::Object = self.clone().into();
+        if let Dictionary(ref mut dict) = object {
+            self.embed_functions(doc, dict)?;
+        }
         Ok(doc.add_object(object))
     }
 }
 
+impl ExtendedGraphicsState {
+    // the transfer, black-generation and undercolor-removal functions are indirect objects,
+    // so they can only be written when the graphics state is embedded
+    fn embed_functions(&self, doc: &mut lopdf::Document, dict: &mut lopdf::Dictionary) -> Result<(), Error> {
+        if self.changed_fields.contains(BLACK_GENERATION_EXTRA) {
+            if let Some(ref black_generation_extra) = self.black_generation_extra {
+                let object = match *black_generation_extra {
+                    BlackGenerationExtraFunction::Default => default_name(),
+                    BlackGenerationExtraFunction::Function(ref f) => single_function_object(doc, f)?,
+                };
+                dict.set("BG2", object);
+            }
+        }
+
+        if self.changed_fields.contains(UNDERCOLOR_REMOVAL_EXTRA) {
+            if let Some(ref under_color_removal_extra) = self.under_color_removal_extra {
+                let object = match *under_color_removal_extra {
+                    UnderColorRemovalExtraFunction::Default => default_name(),
+                    UnderColorRemovalExtraFunction::Function(ref f) => single_function_object(doc, f)?,
+                };
+                dict.set("UCR2", object);
+            }
+        }
+
+        if self.changed_fields.contains(TRANSFER_FUNCTION) {
+            if let Some(ref transfer_function) = self.transfer_function {
+                let object = match *transfer_function {
+                    TransferFunction::Identity => identity_name(),
+                    TransferFunction::Function(ref f) => single_function_object(doc, f)?,
+                    TransferFunction::PerColorant(ref f) => per_colorant_object(doc, f)?,
+                };
+                dict.set("TR", object);
+            }
+        }
+
+        if self.changed_fields.contains(TRANSFER_FUNCTION_EXTRA) {
+            if let Some(ref transfer_extra_function) = self.transfer_extra_function {
+                let object = match *transfer_extra_function {
+                    TransferExtraFunction::Default => default_name(),
+                    TransferExtraFunction::Identity => identity_name(),
+                    TransferExtraFunction::Function(ref f) => single_function_object(doc, f)?,
+                    TransferExtraFunction::PerColorant(ref f) => per_colorant_object(doc, f)?,
+                };
+                dict.set("TR2", object);
+            }
+        }
+
+        if !dict.is_empty() {
+            dict.set("Type", "ExtGState");
+        }
+        Ok(())
+    }
+}
+
 impl Into<lopdf::Object> for ExtendedGraphicsState {
     /// Compares the current graphics state with the previous one and returns an
     /// "optimized" graphics state, meaning only the fields that have changed in
@@ -592,9 +657,9 @@ impl Into<lopdf::Object> for ExtendedGraphicsState {
         }
 
         // set optional parameters
-        if let Some(ldp) = self.line_dash_pattern {
+        if let Some(ref ldp) = self.line_dash_pattern {
             if self.changed_fields.contains(LINE_DASH_PATTERN) {
-                let pattern: lopdf::Object = ldp.into();
+                let pattern: lopdf::Object = ldp.clone().into();
                 gs_operations.push(("D".to_string(), pattern));
             }
         }
@@ -606,35 +671,19 @@ impl Into<lopdf::Object> for ExtendedGraphicsState {
             }
         }
 
-        // todo: transfer functions, halftone functions,
-        // black generation, undercolor removal
+        // todo: halftone functions, black generation and undercolor removal (BG, UCR)
         // these types cannot yet be converted into lopdf::Objects,
-        // need to implement Into<Object> for them
+        // need to implement Into<Object> for them.
+        // BG2, UCR2, TR and TR2 are functions, they are written in `embed`
 
         if self.changed_fields.contains(BLACK_GENERATION) {
             if let Some(ref _black_generation) = self.black_generation {}
         }
 
-        if self.changed_fields.contains(BLACK_GENERATION_EXTRA) {
-            if let Some(ref _black_generation_extra) = self.black_generation_extra {}
-        }
-
         if self.changed_fields.contains(UNDERCOLOR_REMOVAL) {
             if let Some(ref _under_color_removal) = self.under_color_removal {}
         }
 
-        if self.changed_fields.contains(UNDERCOLOR_REMOVAL_EXTRA) {
-            if let Some(ref _under_color_removal_extra) = self.under_color_removal_extra {}
-        }
-
-        if self.changed_fields.contains(TRANSFER_FUNCTION) {
-            if let Some(ref _transfer_function) = self.transfer_function {}
-        }
-
-        if self.changed_fields.contains(TRANSFER_FUNCTION_EXTRA) {
-            if let Some(ref _transfer_extra_function) = self.transfer_extra_function {}
-        }
-
         if self.changed_fields.contains(HALFTONE_DICTIONARY) {
             if let Some(ref _halftone_dictionary) = self.halftone_dictionary {}
         }
@@ -705,8 +754,15 @@ pub enum BlackGenerationFunction {
     WithUnderColorRemoval,
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum BlackGenerationExtraFunction {}
+/// Black-generation function (`BG2`), same as `BlackGenerationFunction` except that it
+/// can reset the function to the one at the start of the page
+#[derive(Debug, PartialEq, Clone)]
+pub enum BlackGenerationExtraFunction {
+    /// The black-generation function that was in effect at the start of the page (`/Default`)
+    Default,
+    /// A function with one input and one output that maps [0.0 1.0] to [0.0 1.0]
+    Function(Function),
+}
 
 /// See `BlackGenerationFunction`, too. Undercolor removal reduces the amounts
 /// of the cyan, magenta, and yellow components to compensate for the amount of
@@ -722,14 +778,78 @@ pub enum UnderColorRemovalFunction {
     Default,
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum UnderColorRemovalExtraFunction {}
+/// Undercolor-removal function (`UCR2`), same as `UnderColorRemovalFunction` except that it
+/// can reset the function to the one at the start of the page
+#[derive(Debug, PartialEq, Clone)]
+pub enum UnderColorRemovalExtraFunction {
+    /// The undercolor-removal function that was in effect at the start of the page (`/Default`)
+    Default,
+    /// A function with one input and one output that maps [0.0 1.0] to [-1.0 1.0]
+    Function(Function),
+}
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum TransferFunction {}
+/// Transfer function (`TR`), adjusts the values of the color components to compensate for
+/// the characteristics of the output device, e.g. for dot gain. Every function has one
+/// input and one output, both within [0.0 1.0]
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransferFunction {
+    /// The identity function (`/Identity`)
+    Identity,
+    /// One function for all process colorants
+    Function(Function),
+    /// One function for each process colorant: cyan, magenta, yellow and black
+    /// (or red, green, blue and gray)
+    PerColorant(Box<[Function; 4]>),
+}
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub enum TransferExtraFunction {}
+/// Transfer function (`TR2`), same as `TransferFunction` except that it can reset the
+/// function to the one at the start of the page
+#[derive(Debug, PartialEq, Clone)]
+pub enum TransferExtraFunction {
+    /// The transfer function that was in effect at the start of the page (`/Default`)
+    Default,
+    /// The identity function (`/Identity`)
+    Identity,
+    /// One function for all process colorants
+    Function(Function),
+    /// One function for each process colorant: cyan, magenta, yellow and black
+    /// (or red, green, blue and gray)
+    PerColorant(Box<[Function; 4]>),
+}
+
+impl From<TransferFunction> for TransferExtraFunction {
+    fn from(function: TransferFunction) -> Self {
+        match function {
+            TransferFunction::Identity => TransferExtraFunction::Identity,
+            TransferFunction::Function(f) => TransferExtraFunction::Function(f),
+            TransferFunction::PerColorant(f) => TransferExtraFunction::PerColorant(f),
+        }
+    }
+}
+
+// embeds a function that maps one value to one value
+fn single_function_object(doc: &mut lopdf::Document, function: &Function) -> Result<lopdf::Object, Error> {
+    if function.num_inputs() != 1 || function.num_outputs() != 1 {
+        return Err(PdfError::InvalidFunction.into());
+    }
+    Ok(Reference(function.embed(doc)?))
+}
+
+fn per_colorant_object(doc: &mut lopdf::Document, functions: &[Function; 4]) -> Result<lopdf::Object, Error> {
+    let functions = functions
+        .iter()
+        .map(|function| single_function_object(doc, function))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Array(functions))
+}
+
+fn default_name() -> lopdf::Object {
+    Name("Default".into())
+}
+
+fn identity_name() -> lopdf::Object {
+    Name("Identity".into())
+}
 
 /// In PDF 1.2, the graphics state includes a current halftone parameter,
 /// which determines the halftoning process to be used by the painting operators.
@@ -1422,18 +1542,175 @@ impl Into<(Vec<i64>, i64)> for LineDashPattern {
 
 impl Into<Operation> for LineDashPattern {
     fn into(self) -> Operation {
-        let (dash_array, offset) = self.into();
-        let dash_array_ints = dash_array.into_iter().map(Integer).collect();
-        Operation::new("d", vec![Array(dash_array_ints), Integer(offset)])
+        DashPattern::from(self).into()
     }
 }
 
 impl Into<lopdf::Object> for LineDashPattern {
     fn into(self) -> lopdf::Object {
-        use lopdf::Object::*;
-        let (dash_array, offset) = self.into();
-        let mut dash_array_ints: Vec<lopdf::Object> = dash_array.into_iter().map(Integer).collect();
-        dash_array_ints.push(Integer(offset));
-        Array(dash_array_ints)
+        DashPattern::from(self).into()
+    }
+}
+
+/// Line dash pattern with any number of dash and gap lengths (in points), e.g.
+/// `DashPattern::new(vec![6.0, 2.0, 1.5, 2.0, 1.5, 2.0], 0.0)` for a dash-dot-dot line.
+/// The lengths alternate between dashes and gaps and repeat, an odd number of lengths
+/// is repeated twice (so `[3]` is 3 on, 3 off). The phase is the distance into the pattern
+/// at which the line starts
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DashPattern {
+    /// Alternating dash and gap lengths, a solid line if empty
+    pub dash_array: Vec<f64>,
+    /// Distance into the pattern at which the line starts
+    pub phase: f64,
+}
+
+impl DashPattern {
+    /// Creates a new dash pattern
+    pub fn new(dash_array: Vec<f64>, phase: f64) -> Self {
+        Self { dash_array, phase }
+    }
+
+    /// Solid line, resets the dash pattern
+    pub fn solid() -> Self {
+        Self::default()
     }
+
+    // PDF doesn't allow negative lengths or only zeros, these are written as a solid line
+    fn normalized_array(&self) -> Vec<f64> {
+        let is_valid = self.dash_array.iter().all(|v| v.is_finite() && *v >= 0.0)
+            && self.dash_array.iter().any(|v| *v > 0.0);
+        if is_valid {
+            self.dash_array.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
+    // a negative phase is wrapped into the pattern (it is a repeating pattern), a NaN or
+    // infinite phase (and the phase of a solid line) is written as 0
+    fn normalized_phase(&self, dash_array: &[f64]) -> f64 {
+        let mut period: f64 = dash_array.iter().sum();
+        // an odd number of lengths repeats with dashes and gaps swapped
+        if dash_array.len() % 2 == 1 {
+            period *= 2.0;
+        }
+        if !self.phase.is_finite() || period <= 0.0 {
+            0.0
+        } else if self.phase < 0.0 {
+            self.phase.rem_euclid(period)
+        } else {
+            self.phase
+        }
+    }
+}
+
+impl From<LineDashPattern> for DashPattern {
+    fn from(pattern: LineDashPattern) -> Self {
+        let (dash_array, offset): (Vec<i64>, i64) = pattern.into();
+        Self {
+            dash_array: dash_array.into_iter().map(|v| v as f64).collect(),
+            phase: offset as f64,
+        }
+    }
+}
+
+// whole numbers are written as integers
+fn dash_number(value: f64) -> lopdf::Object {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Integer(value as i64)
+    } else {
+        Real(value)
+    }
+}
+
+impl From<DashPattern> for Operation {
+    fn from(pattern: DashPattern) -> Self {
+        let dash_array = pattern.normalized_array();
+        let phase = pattern.normalized_phase(&dash_array);
+        Operation::new("d", vec![Array(dash_array.into_iter().map(dash_number).collect()), dash_number(phase)])
+    }
+}
+
+impl From<DashPattern> for lopdf::Object {
+    fn from(pattern: DashPattern) -> Self {
+        // [ dashArray dashPhase ]
+        let dash_array = pattern.normalized_array();
+        let phase = pattern.normalized_phase(&dash_array);
+        Array(vec![Array(dash_array.into_iter().map(dash_number).collect()), dash_number(phase)])
+    }
+}
+
+#[test]
+fn test_dash_pattern() {
+    let pattern = DashPattern::new(vec![6.0, 2.0, 1.5, 2.0, 1.5, 2.0], 0.5);
+    let operation: Operation = pattern.clone().into();
+    assert_eq!(operation.operator, "d");
+    assert_eq!(operation.operands[0].as_array().unwrap().len(), 6);
+
+    // the graphics state entry has the form [ dashArray dashPhase ]
+    let object: lopdf::Object = pattern.into();
+    let entry = object.as_array().unwrap();
+    assert_eq!(entry[0].as_array().unwrap().len(), 6);
+    assert_eq!(entry[1].as_f64().unwrap(), 0.5);
+
+    let mut legacy = LineDashPattern::default();
+    legacy.dash_1 = Some(3);
+    legacy.gap_1 = Some(1);
+    assert_eq!(DashPattern::from(legacy), DashPattern::new(vec![3.0, 1.0], 0.0));
+
+    // only zeros are written as a solid line
+    let object: lopdf::Object = DashPattern::new(vec![0.0, 0.0], 0.0).into();
+    assert!(object.as_array().unwrap()[0].as_array().unwrap().is_empty());
+
+    // negative phases are wrapped into the pattern, NaN is written as 0
+    let phase = |pattern: DashPattern| match Operation::from(pattern).operands[1] {
+        Integer(i) => i as f64,
+        Real(r) => r,
+        _ => panic!("the phase is a number"),
+    };
+    assert_eq!(phase(DashPattern::new(vec![3.0, 1.0], -1.0)), 3.0);
+    assert_eq!(phase(DashPattern::new(vec![3.0], -1.0)), 5.0);
+    assert_eq!(phase(DashPattern::new(vec![3.0, 1.0], f64::NAN)), 0.0);
+    assert_eq!(phase(DashPattern::new(vec![], -2.0)), 0.0);
+}
+
+#[test]
+fn test_transfer_functions() {
+    use {ExponentialFunction, PostScriptFunction};
+
+    let invert = Function::from(PostScriptFunction::new(vec![0.0, 1.0], vec![0.0, 1.0], "1 exch sub"));
+    let gamma = Function::from(ExponentialFunction::gamma(1.8));
+    let state = ExtendedGraphicsStateBuilder::new()
+        .with_transfer(Some(TransferFunction::Function(invert)))
+        .with_transfer_extra(Some(TransferExtraFunction::PerColorant(Box::new([
+            gamma.clone(),
+            gamma.clone(),
+            gamma.clone(),
+            gamma.clone(),
+        ]))))
+        .with_black_generation_extra(Some(BlackGenerationExtraFunction::Default))
+        .with_undercolor_removal_extra(Some(UnderColorRemovalExtraFunction::Function(gamma)))
+        .build();
+
+    let mut doc = lopdf::Document::with_version("1.4");
+    let id = state.embed(&mut doc).unwrap();
+    let dict = doc.get_object(id).unwrap().as_dict().unwrap().clone();
+
+    assert_eq!(dict.get(b"Type").unwrap().as_name_str().unwrap(), "ExtGState");
+    let transfer = doc.get_object(dict.get(b"TR").unwrap().as_reference().unwrap()).unwrap();
+    assert_eq!(transfer.as_stream().unwrap().content, b"{ 1 exch sub }".to_vec());
+    assert_eq!(dict.get(b"TR2").unwrap().as_array().unwrap().len(), 4);
+    assert_eq!(dict.get(b"BG2").unwrap().as_name_str().unwrap(), "Default");
+    assert!(dict.get(b"UCR2").unwrap().as_reference().is_ok());
+
+    let identity = ExtendedGraphicsStateBuilder::new().with_transfer(Some(TransferFunction::Identity)).build();
+    let id = identity.embed(&mut doc).unwrap();
+    let dict = doc.get_object(id).unwrap().as_dict().unwrap();
+    assert_eq!(dict.get(b"TR").unwrap().as_name_str().unwrap(), "Identity");
+
+    // transfer functions map one value to one value
+    let rgb = Function::from(ExponentialFunction::new(vec![0.0; 3], vec![1.0; 3], 1.0));
+    let invalid = ExtendedGraphicsStateBuilder::new().with_transfer(Some(TransferFunction::Function(rgb))).build();
+    assert!(invalid.embed(&mut doc).is_err());
 }
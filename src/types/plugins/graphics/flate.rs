@@ -0,0 +1,264 @@
+//! FlateDecode compression of image data with PNG predictors
+
+use flate2::write::{ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+use std::io::Write;
+
+use {ColorBits, Error, PdfError};
+
+/// PNG predictor, applied to every row of the image before compressing it.
+/// Every row is prefixed with the filter type, so all predictors can be read by PDF readers
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PngPredictor {
+    /// No prediction (`/Predictor 10`)
+    None,
+    /// Difference to the pixel to the left (`/Predictor 11`)
+    Sub,
+    /// Difference to the pixel above (`/Predictor 12`)
+    Up,
+    /// Difference to the average of the left and the upper pixel (`/Predictor 13`)
+    Average,
+    /// Paeth predictor (`/Predictor 14`)
+    Paeth,
+    /// Chooses the best predictor for each row (`/Predictor 15`)
+    #[default]
+    Optimum,
+}
+
+impl From<PngPredictor> for i64 {
+    fn from(predictor: PngPredictor) -> Self {
+        match predictor {
+            PngPredictor::None => 10,
+            PngPredictor::Sub => 11,
+            PngPredictor::Up => 12,
+            PngPredictor::Average => 13,
+            PngPredictor::Paeth => 14,
+            PngPredictor::Optimum => 15,
+        }
+    }
+}
+
+/// Compression of raw (unfiltered) image data when the image is embedded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageCompression {
+    /// Write the raw samples
+    None,
+    /// `FlateDecode` with a PNG predictor
+    Flate {
+        /// zlib compression level, from 0 (fastest) to 9 (smallest)
+        level: u32,
+        /// PNG predictor for the rows of the image
+        predictor: PngPredictor,
+    },
+    /// `CCITTFaxDecode` (Group 4) for 1-bit images with one color component,
+    /// other images are compressed with the default `FlateDecode` compression
+    CcittG4,
+    /// `JBIG2Decode` (lossless generic region) for 1-bit images with one color component,
+    /// other images are compressed with the default `FlateDecode` compression. Needs PDF 1.4
+    Jbig2,
+}
+
+impl Default for ImageCompression {
+    fn default() -> Self {
+        ImageCompression::Flate {
+            level: 6,
+            predictor: PngPredictor::Optimum,
+        }
+    }
+}
+
+/// `/DecodeParms` of a `FlateDecode` image stream that uses a PNG predictor
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FlateDecodeParms {
+    /// PNG predictor that was used to encode the rows
+    pub predictor: PngPredictor,
+    /// Number of color components per pixel (`/Colors`)
+    pub colors: usize,
+    /// Bits per color component (`/BitsPerComponent`)
+    pub bits_per_component: ColorBits,
+    /// Width of the image in pixels (`/Columns`)
+    pub columns: usize,
+}
+
+impl From<FlateDecodeParms> for lopdf::Dictionary {
+    fn from(parms: FlateDecodeParms) -> Self {
+        use lopdf::Object::Integer;
+        use std::iter::FromIterator;
+
+        lopdf::Dictionary::from_iter(vec![
+            ("Predictor", Integer(parms.predictor.into())),
+            ("Colors", Integer(parms.colors as i64)),
+            ("BitsPerComponent", Integer(parms.bits_per_component.into())),
+            ("Columns", Integer(parms.columns as i64)),
+        ])
+    }
+}
+
+/// Applies the PNG predictor to the rows of the image and compresses the result
+pub(crate) fn compress_image(data: &[u8], parms: FlateDecodeParms, level: u32) -> Vec<u8> {
+    deflate(&png_predict(data, parms), level)
+}
+
+/// zlib-compresses the data
+pub(crate) fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level.min(9)));
+    // writing into a Vec can't fail
+    encoder.write_all(data).ok();
+    encoder.finish().unwrap_or_default()
+}
+
+/// Decompresses zlib-compressed data
+pub(crate) fn inflate(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = ZlibDecoder::new(Vec::new());
+    decoder
+        .write_all(data)
+        .and_then(|_| decoder.finish())
+        .map_err(|_| PdfError::InvalidImage.into())
+}
+
+// PNG filter types, the first byte of every row
+const FILTER_NONE: u8 = 0;
+const FILTER_SUB: u8 = 1;
+const FILTER_UP: u8 = 2;
+const FILTER_AVERAGE: u8 = 3;
+const FILTER_PAETH: u8 = 4;
+
+/// Filters every row of the image with the predictor, prefixing it with the filter type
+fn png_predict(data: &[u8], parms: FlateDecodeParms) -> Vec<u8> {
+    let bits: i64 = parms.bits_per_component.into();
+    let bits_per_pixel = parms.colors * bits as usize;
+    let row_length = (parms.columns * bits_per_pixel).div_ceil(8);
+    // distance to the corresponding byte of the previous pixel, at least one byte
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8).max(1);
+
+    if row_length == 0 {
+        return Vec::new();
+    }
+
+    let mut previous = vec![0; row_length];
+    let mut current = vec![0; row_length];
+    let mut filtered = vec![0; row_length];
+    let mut output = Vec::with_capacity(data.len() + data.len() / row_length + 1);
+
+    for row in data.chunks(row_length) {
+        // an incomplete last row is padded with zeros
+        current[..row.len()].copy_from_slice(row);
+        current[row.len()..].iter_mut().for_each(|b| *b = 0);
+
+        let filter_type = match parms.predictor {
+            PngPredictor::None => FILTER_NONE,
+            PngPredictor::Sub => FILTER_SUB,
+            PngPredictor::Up => FILTER_UP,
+            PngPredictor::Average => FILTER_AVERAGE,
+            PngPredictor::Paeth => FILTER_PAETH,
+            // minimum sum of absolute differences heuristic, as recommended by the PNG spec
+            PngPredictor::Optimum => {
+                let mut best = (FILTER_NONE, u64::MAX);
+                for filter_type in FILTER_NONE..=FILTER_PAETH {
+                    filter_row(filter_type, &current, &previous, bytes_per_pixel, &mut filtered);
+                    let sum = filtered.iter().map(|b| u64::from((*b as i8).unsigned_abs())).sum();
+                    if sum < best.1 {
+                        best = (filter_type, sum);
+                    }
+                }
+                best.0
+            }
+        };
+
+        filter_row(filter_type, &current, &previous, bytes_per_pixel, &mut filtered);
+        output.push(filter_type);
+        output.extend_from_slice(&filtered);
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+
+    output
+}
+
+/// Reverses the PNG predictor, like a PDF reader does. Every row starts with the filter type
+pub(crate) fn png_unpredict(data: &[u8], parms: FlateDecodeParms) -> Vec<u8> {
+    let bits: i64 = parms.bits_per_component.into();
+    let bits_per_pixel = parms.colors * bits as usize;
+    let row_length = (parms.columns * bits_per_pixel).div_ceil(8);
+    let bpp = bits_per_pixel.div_ceil(8).max(1);
+
+    let mut output: Vec<u8> = Vec::with_capacity(data.len());
+    for (row_idx, row) in data.chunks_exact(row_length + 1).enumerate() {
+        let start = output.len();
+        for i in 0..row_length {
+            let left = if i >= bpp { output[start + i - bpp] } else { 0 };
+            let up = if row_idx > 0 { output[start - row_length + i] } else { 0 };
+            let upper_left = if row_idx > 0 && i >= bpp { output[start - row_length + i - bpp] } else { 0 };
+            let prediction = match row[0] {
+                FILTER_SUB => left,
+                FILTER_UP => up,
+                FILTER_AVERAGE => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                FILTER_PAETH => paeth(left, up, upper_left),
+                _ => 0,
+            };
+            output.push(row[i + 1].wrapping_add(prediction));
+        }
+    }
+    output
+}
+
+fn filter_row(filter_type: u8, row: &[u8], previous: &[u8], bpp: usize, output: &mut [u8]) {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up = previous[i];
+        let upper_left = if i >= bpp { previous[i - bpp] } else { 0 };
+
+        let prediction = match filter_type {
+            FILTER_SUB => left,
+            FILTER_UP => up,
+            FILTER_AVERAGE => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            FILTER_PAETH => paeth(left, up, upper_left),
+            _ => 0,
+        };
+
+        output[i] = row[i].wrapping_sub(prediction);
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let pa = (p - i16::from(a)).abs();
+    let pb = (p - i16::from(b)).abs();
+    let pc = (p - i16::from(c)).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_predictor_roundtrip() {
+        let data: Vec<u8> = (0..5 * 4 * 3).map(|i| (i * 37 % 251) as u8).collect();
+
+        for predictor in &[
+            PngPredictor::None,
+            PngPredictor::Sub,
+            PngPredictor::Up,
+            PngPredictor::Average,
+            PngPredictor::Paeth,
+            PngPredictor::Optimum,
+        ] {
+            let parms = FlateDecodeParms {
+                predictor: *predictor,
+                colors: 3,
+                bits_per_component: ColorBits::Bit8,
+                columns: 5,
+            };
+            let compressed = compress_image(&data, parms, 9);
+            let predicted = inflate(&compressed).unwrap();
+            assert_eq!(predicted.len(), 4 * (5 * 3 + 1));
+            assert_eq!(png_unpredict(&predicted, parms), data);
+        }
+    }
+}
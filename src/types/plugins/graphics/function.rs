@@ -0,0 +1,393 @@
+//! PDF function objects (sampled, exponential, stitching and PostScript calculator functions),
+//! as used by transfer functions, black generation, undercolor removal, shadings and tint transforms
+
+use lopdf;
+use lopdf::Object::*;
+use std::iter::FromIterator;
+use utils::to_pdf_reals;
+use {Embeddable, Error, PdfError};
+
+/// A PDF function, maps m input values to n output values
+#[derive(Debug, PartialEq, Clone)]
+pub enum Function {
+    /// Type 0: sampled function
+    Sampled(SampledFunction),
+    /// Type 2: exponential interpolation function
+    Exponential(ExponentialFunction),
+    /// Type 3: stitching function, combines 1-input functions to one function
+    Stitching(StitchingFunction),
+    /// Type 4: PostScript calculator function
+    PostScript(PostScriptFunction),
+}
+
+impl Function {
+    /// Number of input values
+    pub fn num_inputs(&self) -> usize {
+        match *self {
+            Function::Sampled(ref f) => f.domain.len() / 2,
+            Function::Exponential(_) | Function::Stitching(_) => 1,
+            Function::PostScript(ref f) => f.domain.len() / 2,
+        }
+    }
+
+    /// Number of output values
+    pub fn num_outputs(&self) -> usize {
+        match *self {
+            Function::Sampled(ref f) => f.range.len() / 2,
+            Function::Exponential(ref f) => f.c0.len(),
+            Function::Stitching(ref f) => f.functions.first().map_or(0, |f| f.num_outputs()),
+            Function::PostScript(ref f) => f.range.len() / 2,
+        }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let valid = match *self {
+            Function::Sampled(ref f) => {
+                let inputs = f.domain.len() / 2;
+                let outputs = f.range.len() / 2;
+                is_intervals(&f.domain)
+                    && is_intervals(&f.range)
+                    && f.size.len() == inputs
+                    && f.size.iter().all(|s| *s > 0)
+                    && f.size.iter().try_fold(outputs, |count, s| count.checked_mul(*s)) == Some(f.samples.len())
+                    && [1, 2, 4, 8, 12, 16, 24, 32].contains(&f.bits_per_sample)
+            }
+            Function::Exponential(ref f) => {
+                is_intervals(&f.domain)
+                    && f.c0.len() == f.c1.len()
+                    && !f.c0.is_empty()
+                    && f.range.as_ref().is_none_or(|r| r.len() == 2 * f.c0.len())
+            }
+            Function::Stitching(ref f) => {
+                let k = f.functions.len();
+                let outputs = self.num_outputs();
+                is_intervals(&f.domain)
+                    && k > 0
+                    && f.bounds.len() == k - 1
+                    && f.bounds.windows(2).all(|pair| pair[0] < pair[1])
+                    && f.bounds.iter().all(|b| f.domain[0] <= *b && *b <= f.domain[1])
+                    && f.encode.len() == 2 * k
+                    && f.functions.iter().all(|f| f.num_inputs() == 1 && f.num_outputs() == outputs)
+                    && f.range.as_ref().is_none_or(|r| r.len() == 2 * outputs)
+            }
+            Function::PostScript(ref f) => {
+                is_intervals(&f.domain) && is_intervals(&f.range) && !f.program.trim().is_empty()
+            }
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(PdfError::InvalidFunction.into())
+        }
+    }
+}
+
+impl Embeddable for Function {
+    const KEY: &'static str = "NEVER";
+
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        self.check()?;
+
+        let object = match *self {
+            Function::Sampled(ref f) => {
+                let mut dict = function_dictionary(0, &f.domain, Some(&f.range));
+                dict.set("Size", Array(f.size.iter().map(|s| Integer(*s as i64)).collect()));
+                dict.set("BitsPerSample", Integer(f.bits_per_sample.into()));
+                if f.cubic {
+                    dict.set("Order", Integer(3));
+                }
+                Stream(lopdf::Stream::new(dict, f.sample_data()))
+            }
+            Function::Exponential(ref f) => {
+                let mut dict = function_dictionary(2, &f.domain, f.range.as_ref().map(|r| &r[..]));
+                dict.set("C0", to_pdf_reals(&f.c0));
+                dict.set("C1", to_pdf_reals(&f.c1));
+                dict.set("N", Real(f.exponent));
+                Dictionary(dict)
+            }
+            Function::Stitching(ref f) => {
+                let functions = f
+                    .functions
+                    .iter()
+                    .map(|function| function.embed(doc).map(Reference))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let mut dict = function_dictionary(3, &f.domain, f.range.as_ref().map(|r| &r[..]));
+                dict.set("Functions", Array(functions));
+                dict.set("Bounds", to_pdf_reals(&f.bounds));
+                dict.set("Encode", to_pdf_reals(&f.encode));
+                Dictionary(dict)
+            }
+            Function::PostScript(ref f) => {
+                let dict = function_dictionary(4, &f.domain, Some(&f.range));
+                let program = f.program.trim();
+                let program = if program.starts_with('{') {
+                    program.to_string()
+                } else {
+                    format!("{{ {} }}", program)
+                };
+                Stream(lopdf::Stream::new(dict, program.into_bytes()))
+            }
+        };
+
+        Ok(doc.add_object(object))
+    }
+}
+
+/// Type 0 function: a table of sample values, interpolated between the samples
+#[derive(Debug, PartialEq, Clone)]
+pub struct SampledFunction {
+    /// Input intervals, `[min_0 max_0 min_1 max_1 ...]`
+    pub domain: Vec<f64>,
+    /// Output intervals, `[min_0 max_0 min_1 max_1 ...]`
+    pub range: Vec<f64>,
+    /// Number of samples in each input dimension
+    pub size: Vec<usize>,
+    /// Bits that the samples are stored with (default: 16)
+    pub bits_per_sample: u8,
+    /// Use cubic spline interpolation instead of linear interpolation
+    pub cubic: bool,
+    /// The samples within the range, the outputs of a sample are stored consecutively,
+    /// the first input dimension varies fastest
+    pub samples: Vec<f64>,
+}
+
+impl SampledFunction {
+    /// Creates a new sampled function
+    pub fn new(domain: Vec<f64>, range: Vec<f64>, size: Vec<usize>, samples: Vec<f64>) -> Self {
+        Self {
+            domain,
+            range,
+            size,
+            bits_per_sample: 16,
+            cubic: false,
+            samples,
+        }
+    }
+
+    /// Creates a function that maps [0.0 1.0] to [0.0 1.0] with evenly spaced samples,
+    /// e.g. for a transfer curve
+    pub fn curve(samples: Vec<f64>) -> Self {
+        Self::new(vec![0.0, 1.0], vec![0.0, 1.0], vec![samples.len()], samples)
+    }
+
+    // samples, scaled to the range and packed with the bits per sample (big endian)
+    fn sample_data(&self) -> Vec<u8> {
+        let bits = u32::from(self.bits_per_sample);
+        let max = ((1u64 << bits) - 1) as f64;
+        let outputs = self.range.len() / 2;
+
+        let mut data = Vec::new();
+        let (mut buffer, mut buffered_bits) = (0u64, 0);
+        for (i, sample) in self.samples.iter().enumerate() {
+            let (min, max_range) = (self.range[2 * (i % outputs)], self.range[2 * (i % outputs) + 1]);
+            let normalized = if max_range > min { (sample - min) / (max_range - min) } else { 0.0 };
+            buffer = (buffer << bits) | (normalized.clamp(0.0, 1.0) * max).round() as u64;
+            buffered_bits += bits;
+            while buffered_bits >= 8 {
+                buffered_bits -= 8;
+                data.push((buffer >> buffered_bits) as u8);
+            }
+            buffer &= (1 << buffered_bits) - 1;
+        }
+        if buffered_bits > 0 {
+            data.push((buffer << (8 - buffered_bits)) as u8);
+        }
+        data
+    }
+}
+
+impl From<SampledFunction> for Function {
+    fn from(function: SampledFunction) -> Self {
+        Function::Sampled(function)
+    }
+}
+
+/// Type 2 function: `C0 + x^N * (C1 - C0)` for one input value
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExponentialFunction {
+    /// Input interval (default: [0.0 1.0])
+    pub domain: [f64; 2],
+    /// Output intervals, optional
+    pub range: Option<Vec<f64>>,
+    /// Output values for x = 0.0
+    pub c0: Vec<f64>,
+    /// Output values for x = 1.0
+    pub c1: Vec<f64>,
+    /// Interpolation exponent
+    pub exponent: f64,
+}
+
+impl ExponentialFunction {
+    /// Creates a new exponential function on the domain [0.0 1.0]
+    pub fn new(c0: Vec<f64>, c1: Vec<f64>, exponent: f64) -> Self {
+        Self {
+            domain: [0.0, 1.0],
+            range: None,
+            c0,
+            c1,
+            exponent,
+        }
+    }
+
+    /// Creates a gamma curve `x^gamma` with one output value
+    pub fn gamma(gamma: f64) -> Self {
+        Self::new(vec![0.0], vec![1.0], gamma)
+    }
+}
+
+impl From<ExponentialFunction> for Function {
+    fn from(function: ExponentialFunction) -> Self {
+        Function::Exponential(function)
+    }
+}
+
+/// Type 3 function: splits the domain into subdomains (at the `bounds`), each subdomain
+/// is mapped to the domain of its function with `encode`
+#[derive(Debug, PartialEq, Clone)]
+pub struct StitchingFunction {
+    /// Input interval (default: [0.0 1.0])
+    pub domain: [f64; 2],
+    /// Output intervals, optional
+    pub range: Option<Vec<f64>>,
+    /// The 1-input functions, all with the same number of outputs
+    pub functions: Vec<Function>,
+    /// k - 1 increasing values within the domain, where the functions are stitched
+    pub bounds: Vec<f64>,
+    /// Pairs of values that map each subdomain to the domain of its function
+    pub encode: Vec<f64>,
+}
+
+impl StitchingFunction {
+    /// Creates a new stitching function on the domain [0.0 1.0], each function
+    /// gets its subdomain mapped to [0.0 1.0]
+    pub fn new(functions: Vec<Function>, bounds: Vec<f64>) -> Self {
+        let encode = functions.iter().flat_map(|_| vec![0.0, 1.0]).collect();
+        Self {
+            domain: [0.0, 1.0],
+            range: None,
+            functions,
+            bounds,
+            encode,
+        }
+    }
+}
+
+impl From<StitchingFunction> for Function {
+    fn from(function: StitchingFunction) -> Self {
+        Function::Stitching(function)
+    }
+}
+
+/// Type 4 function: a program in a subset of PostScript, e.g. `{ 1 exch sub }`
+#[derive(Debug, PartialEq, Clone)]
+pub struct PostScriptFunction {
+    /// Input intervals, `[min_0 max_0 min_1 max_1 ...]`
+    pub domain: Vec<f64>,
+    /// Output intervals, `[min_0 max_0 min_1 max_1 ...]`
+    pub range: Vec<f64>,
+    /// The program, the enclosing braces are added if they are missing
+    pub program: ::std::string::String,
+}
+
+impl PostScriptFunction {
+    /// Creates a new PostScript calculator function
+    pub fn new<S: Into<::std::string::String>>(domain: Vec<f64>, range: Vec<f64>, program: S) -> Self {
+        Self {
+            domain,
+            range,
+            program: program.into(),
+        }
+    }
+}
+
+impl From<PostScriptFunction> for Function {
+    fn from(function: PostScriptFunction) -> Self {
+        Function::PostScript(function)
+    }
+}
+
+// non-empty pairs of min / max values
+fn is_intervals(values: &[f64]) -> bool {
+    !values.is_empty() && values.len().is_multiple_of(2) && values.chunks(2).all(|pair| pair[0] <= pair[1])
+}
+
+fn function_dictionary(function_type: i64, domain: &[f64], range: Option<&[f64]>) -> lopdf::Dictionary {
+    let mut dict = lopdf::Dictionary::from_iter(vec![
+        ("FunctionType", Integer(function_type)),
+        ("Domain", to_pdf_reals(domain)),
+    ]);
+    if let Some(range) = range {
+        dict.set("Range", to_pdf_reals(range));
+    }
+    dict
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embed_functions() {
+        let mut doc = lopdf::Document::with_version("1.4");
+
+        let sampled = Function::from(SampledFunction::curve(vec![0.0, 0.25, 1.0]));
+        let id = sampled.embed(&mut doc).unwrap();
+        let stream = doc.get_object(id).unwrap().as_stream().unwrap();
+        assert_eq!(stream.dict.get(b"FunctionType").unwrap().as_i64().unwrap(), 0);
+        assert_eq!(stream.dict.get(b"BitsPerSample").unwrap().as_i64().unwrap(), 16);
+        assert_eq!(stream.content, vec![0x00, 0x00, 0x40, 0x00, 0xFF, 0xFF]);
+
+        // 4-bit samples are packed
+        let mut packed = SampledFunction::curve(vec![0.0, 1.0, 0.0]);
+        packed.bits_per_sample = 4;
+        let id = Function::from(packed).embed(&mut doc).unwrap();
+        assert_eq!(doc.get_object(id).unwrap().as_stream().unwrap().content, vec![0x0F, 0x00]);
+
+        let stitching = Function::from(StitchingFunction::new(
+            vec![ExponentialFunction::gamma(2.2).into(), ExponentialFunction::new(vec![1.0], vec![0.0], 1.0).into()],
+            vec![0.5],
+        ));
+        let id = stitching.embed(&mut doc).unwrap();
+        let dict = doc.get_object(id).unwrap().as_dict().unwrap();
+        assert_eq!(dict.get(b"FunctionType").unwrap().as_i64().unwrap(), 3);
+        let functions = dict.get(b"Functions").unwrap().as_array().unwrap();
+        let exponential = doc.get_object(functions[0].as_reference().unwrap()).unwrap().as_dict().unwrap();
+        assert_eq!(exponential.get(b"N").unwrap().as_f64().unwrap(), 2.2);
+        assert_eq!(dict.get(b"Encode").unwrap().as_array().unwrap().len(), 4);
+
+        let postscript = Function::from(PostScriptFunction::new(vec![0.0, 1.0], vec![0.0, 1.0], "1 exch sub"));
+        assert_eq!((postscript.num_inputs(), postscript.num_outputs()), (1, 1));
+        let id = postscript.embed(&mut doc).unwrap();
+        assert_eq!(doc.get_object(id).unwrap().as_stream().unwrap().content, b"{ 1 exch sub }".to_vec());
+    }
+
+    #[test]
+    fn test_invalid_functions() {
+        let mut doc = lopdf::Document::with_version("1.4");
+
+        let wrong_sample_count = SampledFunction::new(vec![0.0, 1.0], vec![0.0, 1.0], vec![3], vec![0.0, 1.0]);
+        let different_outputs = StitchingFunction::new(
+            vec![ExponentialFunction::gamma(1.0).into(), ExponentialFunction::new(vec![0.0; 3], vec![1.0; 3], 1.0).into()],
+            vec![0.5],
+        );
+        let missing_bounds = StitchingFunction::new(vec![ExponentialFunction::gamma(1.0).into(); 2], vec![]);
+        let decreasing_bounds = StitchingFunction::new(vec![ExponentialFunction::gamma(1.0).into(); 3], vec![0.6, 0.3]);
+        let bounds_outside_domain = StitchingFunction::new(vec![ExponentialFunction::gamma(1.0).into(); 2], vec![1.5]);
+        let size_overflow =
+            SampledFunction::new(vec![0.0, 1.0, 0.0, 1.0], vec![0.0, 1.0], vec![usize::MAX, 2], vec![0.0; 2]);
+        let empty_program = PostScriptFunction::new(vec![0.0, 1.0], vec![0.0, 1.0], " ");
+
+        for function in [
+            Function::from(wrong_sample_count),
+            different_outputs.into(),
+            missing_bounds.into(),
+            decreasing_bounds.into(),
+            bounds_outside_domain.into(),
+            size_overflow.into(),
+            empty_program.into(),
+        ] {
+            assert!(function.embed(&mut doc).is_err());
+        }
+    }
+}
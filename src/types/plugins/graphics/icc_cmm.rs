@@ -0,0 +1,824 @@
+//! Minimal color management module: evaluates the transforms of ICC profiles (matrix / TRC,
+//! `lut8`, `lut16`, `lutAtoB` and `lutBtoA`) to convert device colors from and to CIE Lab (D50)
+
+use {Error, PdfError, RenderingIntent};
+// illuminant of the profile connection space
+use D50_WHITE_POINT as D50;
+
+/// Color space of the device values of a profile
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum DeviceSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+impl DeviceSpace {
+    pub(crate) fn num_components(self) -> usize {
+        match self {
+            DeviceSpace::Gray => 1,
+            DeviceSpace::Rgb => 3,
+            DeviceSpace::Cmyk => 4,
+        }
+    }
+}
+
+/// Transform of an ICC profile between device values and Lab
+#[derive(Debug, Clone)]
+pub(crate) struct IccTransform {
+    /// Color space of the device values
+    pub(crate) device_space: DeviceSpace,
+    /// Device values to the profile connection space
+    to_pcs: Transform,
+    /// Profile connection space to device values
+    from_pcs: Transform,
+    /// Media white point, for the absolute colorimetric intent
+    media_white: [f64; 3],
+    /// Absolute colorimetric intent
+    absolute: bool,
+}
+
+impl IccTransform {
+    /// Parses the profile and selects the transforms for the rendering intent
+    pub(crate) fn new(data: &[u8], intent: RenderingIntent) -> Result<Self, Error> {
+        Self::parse(data, intent).ok_or_else(|| PdfError::InvalidIccProfile.into())
+    }
+
+    fn parse(data: &[u8], intent: RenderingIntent) -> Option<Self> {
+        let reader = Reader(data);
+        if data.len() < 132 || reader.bytes(36, 4)? != b"acsp" {
+            return None;
+        }
+
+        let device_space = match reader.bytes(16, 4)? {
+            b"GRAY" => DeviceSpace::Gray,
+            b"RGB " => DeviceSpace::Rgb,
+            b"CMYK" => DeviceSpace::Cmyk,
+            _ => return None,
+        };
+        let pcs = match reader.bytes(20, 4)? {
+            b"Lab " => Pcs::Lab,
+            b"XYZ " => Pcs::Xyz,
+            _ => return None,
+        };
+
+        let tag_count = reader.u32(128)? as usize;
+        let mut tags = Vec::with_capacity(tag_count.min(256));
+        for i in 0..tag_count.min(256) {
+            let entry = 132 + 12 * i;
+            let signature = reader.bytes(entry, 4)?;
+            let offset = reader.u32(entry + 4)? as usize;
+            let size = reader.u32(entry + 8)? as usize;
+            tags.push((signature, reader.slice(offset, size)?));
+        }
+        let tag = |signature: &[u8]| tags.iter().find(|(s, _)| *s == signature).map(|(_, data)| Reader(data));
+
+        let media_white = tag(b"wtpt").and_then(|t| t.xyz(8)).unwrap_or(D50);
+
+        // perceptual: 0, relative and absolute colorimetric: 1, saturation: 2
+        let index = match intent {
+            RenderingIntent::Perceptual => b'0',
+            RenderingIntent::RelativeColorimetric | RenderingIntent::AbsoluteColorimetric => b'1',
+            RenderingIntent::Saturation => b'2',
+        };
+        let lut = |prefix: &[u8; 3], from_pcs: bool| {
+            let signature = |i: u8| [prefix[0], prefix[1], prefix[2], i];
+            tag(&signature(index))
+                .or_else(|| tag(&signature(b'0')))
+                .and_then(|t| Lut::parse(t, from_pcs, pcs))
+        };
+
+        let channels = device_space.num_components();
+        let (to_pcs, from_pcs) = match (lut(b"A2B", false), lut(b"B2A", true)) {
+            (Some(to_pcs), Some(from_pcs)) => (Transform::Lut(to_pcs), Transform::Lut(from_pcs)),
+            _ => {
+                let matrix_trc = MatrixTrc::parse(&tag, device_space)?;
+                (Transform::MatrixTrc(matrix_trc.clone()), Transform::MatrixTrc(matrix_trc))
+            }
+        };
+
+        let valid = |transform: &Transform, expected| transform.lut_channels().is_none_or(|c| c == expected);
+        if !valid(&to_pcs, (channels, 3)) || !valid(&from_pcs, (3, channels)) {
+            return None;
+        }
+
+        Some(Self {
+            device_space,
+            to_pcs,
+            from_pcs,
+            media_white,
+            absolute: intent == RenderingIntent::AbsoluteColorimetric,
+        })
+    }
+
+    /// Converts device values (0.0 - 1.0) to Lab
+    pub(crate) fn device_to_lab(&self, device: &[f64]) -> [f64; 3] {
+        let mut xyz = match self.to_pcs {
+            Transform::MatrixTrc(ref m) => m.to_xyz(device),
+            Transform::Lut(ref lut) => lut.to_pcs(device),
+        };
+        if self.absolute {
+            for i in 0..3 {
+                xyz[i] *= self.media_white[i] / D50[i];
+            }
+        }
+        xyz_to_lab(xyz, D50)
+    }
+
+    /// Converts Lab to device values (0.0 - 1.0)
+    pub(crate) fn lab_to_device(&self, lab: [f64; 3]) -> Vec<f64> {
+        let mut xyz = lab_to_xyz(lab, D50);
+        if self.absolute {
+            for i in 0..3 {
+                xyz[i] *= D50[i] / self.media_white[i];
+            }
+        }
+        match self.from_pcs {
+            Transform::MatrixTrc(ref m) => m.xyz_to_device(xyz),
+            Transform::Lut(ref lut) => lut.pcs_to_device(xyz),
+        }
+    }
+}
+
+/// CIE XYZ to Lab, relative to a white point
+pub(crate) fn xyz_to_lab(xyz: [f64; 3], white: [f64; 3]) -> [f64; 3] {
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(xyz[0] / white[0]), f(xyz[1] / white[1]), f(xyz[2] / white[2]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Lab to CIE XYZ, relative to a white point
+pub(crate) fn lab_to_xyz(lab: [f64; 3], white: [f64; 3]) -> [f64; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let fx = fy + lab[1] / 500.0;
+    let fz = fy - lab[2] / 200.0;
+    let f_inv = |t: f64| {
+        if t > 6.0 / 29.0 {
+            t * t * t
+        } else {
+            (116.0 * t - 16.0) * 27.0 / 24389.0
+        }
+    };
+    [white[0] * f_inv(fx), white[1] * f_inv(fy), white[2] * f_inv(fz)]
+}
+
+/// Encoding of the profile connection space
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Pcs {
+    Lab,
+    Xyz,
+}
+
+#[derive(Debug, Clone)]
+enum Transform {
+    MatrixTrc(MatrixTrc),
+    Lut(Lut),
+}
+
+impl Transform {
+    /// Number of input and output channels of a lookup table
+    fn lut_channels(&self) -> Option<(usize, usize)> {
+        match self {
+            Transform::MatrixTrc(_) => None,
+            Transform::Lut(lut) => Some((lut.inputs, lut.outputs)),
+        }
+    }
+}
+
+/// Matrix / tone reproduction curve profile (RGB or gray)
+#[derive(Debug, Clone)]
+struct MatrixTrc {
+    /// Columns: the XYZ of the red, green and blue colorant
+    matrix: [[f64; 3]; 3],
+    /// One curve per channel
+    curves: Vec<Curve>,
+}
+
+impl MatrixTrc {
+    fn parse<'a, F>(tag: &F, device_space: DeviceSpace) -> Option<Self>
+    where
+        F: Fn(&[u8]) -> Option<Reader<'a>>,
+    {
+        let curve = |signature: &[u8]| tag(signature).and_then(|t| Curve::parse(t, 0)).map(|(c, _)| c);
+        match device_space {
+            DeviceSpace::Gray => Some(Self {
+                matrix: [[0.0; 3]; 3],
+                curves: vec![curve(b"kTRC")?],
+            }),
+            DeviceSpace::Rgb => Some(Self {
+                matrix: [
+                    tag(b"rXYZ")?.xyz(8)?,
+                    tag(b"gXYZ")?.xyz(8)?,
+                    tag(b"bXYZ")?.xyz(8)?,
+                ],
+                curves: vec![curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?],
+            }),
+            DeviceSpace::Cmyk => None,
+        }
+    }
+
+    fn to_xyz(&self, device: &[f64]) -> [f64; 3] {
+        if self.curves.len() == 1 {
+            let y = self.curves[0].eval(device[0]);
+            return [D50[0] * y, y, D50[2] * y];
+        }
+        let linear: Vec<f64> = self.curves.iter().zip(device).map(|(c, v)| c.eval(*v)).collect();
+        let mut xyz = [0.0; 3];
+        for (column, value) in self.matrix.iter().zip(&linear) {
+            for i in 0..3 {
+                xyz[i] += column[i] * value;
+            }
+        }
+        xyz
+    }
+
+    fn xyz_to_device(&self, xyz: [f64; 3]) -> Vec<f64> {
+        if self.curves.len() == 1 {
+            return vec![self.curves[0].eval_inverse(xyz[1])];
+        }
+        let inverse = match invert_3x3(self.matrix) {
+            Some(inverse) => inverse,
+            None => return vec![0.0; 3],
+        };
+        (0..3)
+            .map(|channel| {
+                let linear = (0..3).map(|i| inverse[i][channel] * xyz[i]).sum::<f64>();
+                self.curves[channel].eval_inverse(linear.clamp(0.0, 1.0))
+            })
+            .collect()
+    }
+}
+
+// inverse of a matrix that is stored as columns
+fn invert_3x3(m: [[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2]) - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+        + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2]);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // cofactor of the transposed position
+            let (r1, r2) = ((j + 1) % 3, (j + 2) % 3);
+            let (c1, c2) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]) / det;
+        }
+    }
+    Some(inverse)
+}
+
+/// One-dimensional curve (`curv` or `para`)
+#[derive(Debug, Clone)]
+enum Curve {
+    Gamma(f64),
+    Table(Vec<f64>),
+    Parametric(u16, [f64; 7]),
+}
+
+impl Curve {
+    /// Parses a curve, returns the curve and its size (padded to 4 bytes)
+    fn parse(reader: Reader, offset: usize) -> Option<(Self, usize)> {
+        match reader.bytes(offset, 4)? {
+            b"curv" => {
+                let count = reader.u32(offset + 8)? as usize;
+                let curve = match count {
+                    0 => Curve::Gamma(1.0),
+                    1 => Curve::Gamma(f64::from(reader.u16(offset + 12)?) / 256.0),
+                    _ => Curve::Table(
+                        (0..count)
+                            .map(|i| reader.u16(offset + 12 + 2 * i).map(|v| f64::from(v) / 65535.0))
+                            .collect::<Option<_>>()?,
+                    ),
+                };
+                Some((curve, (12 + 2 * count).div_ceil(4) * 4))
+            }
+            b"para" => {
+                let function = reader.u16(offset + 8)?;
+                let count = *[1, 3, 4, 5, 7].get(function as usize)?;
+                let mut params = [0.0; 7];
+                for (i, param) in params.iter_mut().enumerate().take(count) {
+                    *param = reader.s15f16(offset + 12 + 4 * i)?;
+                }
+                Some((Curve::Parametric(function, params), 12 + 4 * count))
+            }
+            _ => None,
+        }
+    }
+
+    fn eval(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        let y = match self {
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) => interpolate(table, x),
+            Curve::Parametric(function, p) => {
+                let [g, a, b, c, d, e, f] = *p;
+                let power = |x: f64| (a * x + b).max(0.0).powf(g);
+                match function {
+                    0 => x.powf(g),
+                    1 if x >= -b / a => power(x),
+                    1 => 0.0,
+                    2 if x >= -b / a => power(x) + c,
+                    2 => c,
+                    3 if x >= d => power(x),
+                    3 => c * x,
+                    _ if x >= d => power(x) + e,
+                    _ => c * x + f,
+                }
+            }
+        };
+        y.clamp(0.0, 1.0)
+    }
+
+    // curves are monotonic, so the inverse is found by bisection
+    fn eval_inverse(&self, y: f64) -> f64 {
+        let increasing = self.eval(1.0) >= self.eval(0.0);
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..40 {
+            let mid = (low + high) / 2.0;
+            if (self.eval(mid) < y) == increasing {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        (low + high) / 2.0
+    }
+}
+
+fn interpolate(table: &[f64], x: f64) -> f64 {
+    if table.len() < 2 {
+        return table.first().copied().unwrap_or(x);
+    }
+    let position = x * (table.len() - 1) as f64;
+    let index = (position.floor() as usize).min(table.len() - 2);
+    let fraction = position - index as f64;
+    table[index] + (table[index + 1] - table[index]) * fraction
+}
+
+/// Multidimensional color lookup table
+#[derive(Debug, Clone)]
+struct Clut {
+    grid: Vec<usize>,
+    outputs: usize,
+    data: Vec<f64>,
+}
+
+impl Clut {
+    fn parse(reader: Reader, offset: usize, grid: Vec<usize>, outputs: usize, bytes: usize) -> Option<Self> {
+        if grid.iter().any(|g| *g < 2) {
+            return None;
+        }
+        let count = grid.iter().try_fold(outputs, |count, g| count.checked_mul(*g))?;
+        let data = (0..count)
+            .map(|i| match bytes {
+                1 => reader.u8(offset + i).map(|v| f64::from(v) / 255.0),
+                _ => reader.u16(offset + 2 * i).map(|v| f64::from(v) / 65535.0),
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { grid, outputs, data })
+    }
+
+    // multilinear interpolation, the first input varies slowest
+    fn eval(&self, input: &[f64]) -> Vec<f64> {
+        let inputs = self.grid.len();
+        let mut base = 0;
+        let mut fractions = Vec::with_capacity(inputs);
+        let mut strides = vec![0; inputs];
+
+        let mut stride = self.outputs;
+        for i in (0..inputs).rev() {
+            strides[i] = stride;
+            stride *= self.grid[i];
+        }
+        for i in 0..inputs {
+            let position = input[i].clamp(0.0, 1.0) * (self.grid[i] - 1) as f64;
+            let index = (position.floor() as usize).min(self.grid[i] - 2);
+            fractions.push(position - index as f64);
+            base += index * strides[i];
+        }
+
+        let mut output = vec![0.0; self.outputs];
+        for corner in 0..(1usize << inputs) {
+            let mut weight = 1.0;
+            let mut offset = base;
+            for i in 0..inputs {
+                if corner & (1 << (inputs - 1 - i)) != 0 {
+                    weight *= fractions[i];
+                    offset += strides[i];
+                } else {
+                    weight *= 1.0 - fractions[i];
+                }
+            }
+            if weight != 0.0 {
+                for (o, value) in output.iter_mut().enumerate() {
+                    *value += weight * self.data[offset + o];
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Processing element of a lookup table transform
+#[derive(Debug, Clone)]
+enum Stage {
+    Curves(Vec<Curve>),
+    /// 3x3 matrix (row by row) and an offset
+    Matrix([f64; 12]),
+    Clut(Clut),
+}
+
+/// Whether every stage gets as many channels as the previous stage produces
+/// (matrices work on three channels) and the last stage produces the outputs
+fn channels_match(stages: &[Stage], inputs: usize, outputs: usize) -> bool {
+    let mut channels = inputs;
+    for stage in stages {
+        channels = match stage {
+            Stage::Curves(curves) if curves.len() == channels => channels,
+            Stage::Matrix(_) if channels == 3 => 3,
+            Stage::Clut(clut) if clut.grid.len() == channels => clut.outputs,
+            _ => return false,
+        };
+    }
+    channels == outputs
+}
+
+/// How the profile connection space values are normalized to 0.0 - 1.0
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum PcsEncoding {
+    /// Lab with 0xFF00 as L = 100 (`lut16` in version 2 profiles)
+    Legacy16,
+    /// Lab with 0xFFFF (or 0xFF) as L = 100
+    Normal,
+}
+
+/// `lut8`, `lut16`, `lutAtoB` or `lutBtoA` transform
+#[derive(Debug, Clone)]
+struct Lut {
+    inputs: usize,
+    outputs: usize,
+    stages: Vec<Stage>,
+    pcs: Pcs,
+    encoding: PcsEncoding,
+}
+
+impl Lut {
+    fn parse(reader: Reader, from_pcs: bool, pcs: Pcs) -> Option<Self> {
+        let inputs = reader.u8(8)? as usize;
+        let outputs = reader.u8(9)? as usize;
+        if inputs == 0 || outputs == 0 || inputs > 8 || outputs > 15 {
+            return None;
+        }
+
+        let mut stages = Vec::new();
+        let encoding = match reader.bytes(0, 4)? {
+            signature @ b"mft1" | signature @ b"mft2" => {
+                let is_lut16 = signature == b"mft2";
+                let grid_points = reader.u8(10)? as usize;
+
+                // the matrix is only used for XYZ input
+                if from_pcs && pcs == Pcs::Xyz {
+                    let mut matrix = [0.0; 12];
+                    for (i, value) in matrix.iter_mut().enumerate().take(9) {
+                        *value = reader.s15f16(12 + 4 * i)?;
+                    }
+                    stages.push(Stage::Matrix(matrix));
+                }
+
+                let (entries_in, entries_out, mut offset, bytes) = if is_lut16 {
+                    (reader.u16(48)? as usize, reader.u16(50)? as usize, 52, 2)
+                } else {
+                    (256, 256, 48, 1)
+                };
+
+                let tables = |count: usize, entries: usize, offset: &mut usize| -> Option<Vec<Curve>> {
+                    let mut curves = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let table = (0..entries)
+                            .map(|i| match bytes {
+                                1 => reader.u8(*offset + i).map(|v| f64::from(v) / 255.0),
+                                _ => reader.u16(*offset + 2 * i).map(|v| f64::from(v) / 65535.0),
+                            })
+                            .collect::<Option<_>>()?;
+                        curves.push(Curve::Table(table));
+                        *offset += entries * bytes;
+                    }
+                    Some(curves)
+                };
+
+                stages.push(Stage::Curves(tables(inputs, entries_in, &mut offset)?));
+                let clut = Clut::parse(reader, offset, vec![grid_points; inputs], outputs, bytes)?;
+                offset += clut.data.len() * bytes;
+                stages.push(Stage::Clut(clut));
+                stages.push(Stage::Curves(tables(outputs, entries_out, &mut offset)?));
+
+                if is_lut16 {
+                    PcsEncoding::Legacy16
+                } else {
+                    PcsEncoding::Normal
+                }
+            }
+            signature @ b"mAB " | signature @ b"mBA " => {
+                if (signature == b"mBA ") != from_pcs {
+                    return None;
+                }
+                let offset = |position: usize| reader.u32(position).map(|o| o as usize);
+                let (b, matrix, m, clut, a) = (offset(12)?, offset(16)?, offset(20)?, offset(24)?, offset(28)?);
+
+                let curves = |start: usize, count: usize| -> Option<Stage> {
+                    let mut curves = Vec::with_capacity(count);
+                    let mut position = start;
+                    for _ in 0..count {
+                        let (curve, size) = Curve::parse(reader, position)?;
+                        curves.push(curve);
+                        position += size;
+                    }
+                    Some(Stage::Curves(curves))
+                };
+                let matrix_stage = || -> Option<Stage> {
+                    let mut values = [0.0; 12];
+                    for (i, value) in values.iter_mut().enumerate() {
+                        *value = reader.s15f16(matrix + 4 * i)?;
+                    }
+                    Some(Stage::Matrix(values))
+                };
+                let clut_stage = |grid_inputs: usize, grid_outputs: usize| -> Option<Stage> {
+                    let grid = (0..grid_inputs).map(|i| reader.u8(clut + i).map(usize::from)).collect::<Option<_>>()?;
+                    let bytes = reader.u8(clut + 16)? as usize;
+                    Some(Stage::Clut(Clut::parse(reader, clut + 20, grid, grid_outputs, bytes)?))
+                };
+
+                if from_pcs {
+                    // B curves, matrix, M curves, CLUT, A curves
+                    stages.push(curves(b, inputs)?);
+                    if matrix != 0 {
+                        stages.push(matrix_stage()?);
+                    }
+                    if m != 0 {
+                        stages.push(curves(m, inputs)?);
+                    }
+                    if clut != 0 {
+                        stages.push(clut_stage(inputs, outputs)?);
+                    }
+                    if a != 0 {
+                        stages.push(curves(a, outputs)?);
+                    }
+                } else {
+                    // A curves, CLUT, M curves, matrix, B curves
+                    if a != 0 {
+                        stages.push(curves(a, inputs)?);
+                    }
+                    if clut != 0 {
+                        stages.push(clut_stage(inputs, outputs)?);
+                    }
+                    if m != 0 {
+                        stages.push(curves(m, outputs)?);
+                    }
+                    if matrix != 0 {
+                        stages.push(matrix_stage()?);
+                    }
+                    stages.push(curves(b, outputs)?);
+                }
+                PcsEncoding::Normal
+            }
+            _ => return None,
+        };
+
+        if !channels_match(&stages, inputs, outputs) {
+            return None;
+        }
+
+        Some(Self {
+            inputs,
+            outputs,
+            stages,
+            pcs,
+            encoding,
+        })
+    }
+
+    fn eval(&self, input: &[f64]) -> Vec<f64> {
+        let mut values = input.to_vec();
+        for stage in &self.stages {
+            values = match stage {
+                Stage::Curves(curves) => curves.iter().zip(&values).map(|(c, v)| c.eval(*v)).collect(),
+                Stage::Matrix(m) => (0..3)
+                    .map(|row| {
+                        let value = m[3 * row] * values[0] + m[3 * row + 1] * values[1] + m[3 * row + 2] * values[2];
+                        (value + m[9 + row]).clamp(0.0, 1.0)
+                    })
+                    .collect(),
+                Stage::Clut(clut) => clut.eval(&values),
+            };
+        }
+        values
+    }
+
+    fn to_pcs(&self, device: &[f64]) -> [f64; 3] {
+        let encoded = self.eval(device);
+        self.decode_pcs([encoded[0], encoded[1], encoded[2]])
+    }
+
+    fn pcs_to_device(&self, xyz: [f64; 3]) -> Vec<f64> {
+        let encoded = self.encode_pcs(xyz);
+        self.eval(&encoded)
+    }
+
+    // normalized PCS values to XYZ
+    fn decode_pcs(&self, values: [f64; 3]) -> [f64; 3] {
+        match self.pcs {
+            // u1Fixed15: 0x8000 is 1.0
+            Pcs::Xyz => [0, 1, 2].map(|i| values[i] * 65535.0 / 32768.0),
+            Pcs::Lab => {
+                let scale = match self.encoding {
+                    PcsEncoding::Legacy16 => 65535.0 / 65280.0,
+                    PcsEncoding::Normal => 1.0,
+                };
+                let lab = [
+                    values[0] * scale * 100.0,
+                    values[1] * scale * 255.0 - 128.0,
+                    values[2] * scale * 255.0 - 128.0,
+                ];
+                lab_to_xyz(lab, D50)
+            }
+        }
+    }
+
+    // XYZ to normalized PCS values
+    fn encode_pcs(&self, xyz: [f64; 3]) -> [f64; 3] {
+        let encoded = match self.pcs {
+            Pcs::Xyz => [0, 1, 2].map(|i| xyz[i] * 32768.0 / 65535.0),
+            Pcs::Lab => {
+                let scale = match self.encoding {
+                    PcsEncoding::Legacy16 => 65280.0 / 65535.0,
+                    PcsEncoding::Normal => 1.0,
+                };
+                let lab = xyz_to_lab(xyz, D50);
+                [
+                    lab[0] / 100.0 * scale,
+                    (lab[1] + 128.0) / 255.0 * scale,
+                    (lab[2] + 128.0) / 255.0 * scale,
+                ]
+            }
+        };
+        encoded.map(|v| v.clamp(0.0, 1.0))
+    }
+}
+
+/// Bounds-checked big endian reader
+#[derive(Debug, Copy, Clone)]
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn slice(&self, offset: usize, size: usize) -> Option<&'a [u8]> {
+        self.0.get(offset..offset.checked_add(size)?)
+    }
+
+    fn bytes(&self, offset: usize, size: usize) -> Option<&'a [u8]> {
+        self.slice(offset, size)
+    }
+
+    fn u8(&self, offset: usize) -> Option<u8> {
+        self.0.get(offset).copied()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let b = self.slice(offset, 2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let b = self.slice(offset, 4)?;
+        Some(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn s15f16(&self, offset: usize) -> Option<f64> {
+        self.u32(offset).map(|v| f64::from(v as i32) / 65536.0)
+    }
+
+    // XYZType: signature, reserved and three s15Fixed16 numbers
+    fn xyz(&self, offset: usize) -> Option<[f64; 3]> {
+        Some([self.s15f16(offset)?, self.s15f16(offset + 4)?, self.s15f16(offset + 8)?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glob_defines::ICC_PROFILE_ECI_V2;
+
+    fn delta_e(a: [f64; 3], b: [f64; 3]) -> f64 {
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+    }
+
+    // version 2 RGB profile with the sRGB primaries (adapted to D50) and a gamma of 2.2
+    fn matrix_trc_profile() -> Vec<u8> {
+        let s15f16 = |v: f64| ((v * 65536.0).round() as i32).to_be_bytes();
+        let xyz = |v: [f64; 3]| {
+            let mut data = b"XYZ \0\0\0\0".to_vec();
+            for value in &v {
+                data.extend_from_slice(&s15f16(*value));
+            }
+            data
+        };
+        let curve = b"curv\0\0\0\0\0\0\0\x01\x02\x33\0\0".to_vec();
+        profile(b"RGB ", b"XYZ ", vec![
+            (b"wtpt", xyz(D50)),
+            (b"rXYZ", xyz([0.4361, 0.2225, 0.0139])),
+            (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+            (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+            (b"rTRC", curve.clone()),
+            (b"gTRC", curve.clone()),
+            (b"bTRC", curve),
+        ])
+    }
+
+    fn profile(color_space: &[u8; 4], pcs: &[u8; 4], tags: Vec<(&[u8], Vec<u8>)>) -> Vec<u8> {
+        let mut header = vec![0; 128];
+        header[8] = 2;
+        header[12..16].copy_from_slice(b"mntr");
+        header[16..20].copy_from_slice(color_space);
+        header[20..24].copy_from_slice(pcs);
+        header[36..40].copy_from_slice(b"acsp");
+
+        let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+        let mut data = Vec::new();
+        let mut offset = 128 + 4 + 12 * tags.len();
+        for (signature, tag) in &tags {
+            table.extend_from_slice(signature);
+            table.extend_from_slice(&(offset as u32).to_be_bytes());
+            table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+            data.extend_from_slice(tag);
+            offset += tag.len();
+        }
+
+        let mut profile = header;
+        profile.extend(table);
+        profile.extend(data);
+        let size = (profile.len() as u32).to_be_bytes();
+        profile[0..4].copy_from_slice(&size);
+        profile
+    }
+
+    #[test]
+    fn test_matrix_trc_profile() {
+        let transform = IccTransform::new(&matrix_trc_profile(), RenderingIntent::Perceptual).unwrap();
+        assert_eq!(transform.device_space, DeviceSpace::Rgb);
+
+        let white = transform.device_to_lab(&[1.0, 1.0, 1.0]);
+        assert!(delta_e(white, [100.0, 0.0, 0.0]) < 0.5);
+
+        let device = transform.lab_to_device(transform.device_to_lab(&[0.8, 0.3, 0.1]));
+        for (value, expected) in device.iter().zip(&[0.8, 0.3, 0.1]) {
+            assert!((value - expected).abs() < 1e-3);
+        }
+
+        assert!(IccTransform::new(&[0; 200], RenderingIntent::Perceptual).is_err());
+    }
+
+    #[test]
+    fn test_lut_profile() {
+        let transform = IccTransform::new(ICC_PROFILE_ECI_V2, RenderingIntent::RelativeColorimetric).unwrap();
+        assert_eq!(transform.device_space, DeviceSpace::Cmyk);
+
+        // paper white and black ink
+        let paper = transform.device_to_lab(&[0.0, 0.0, 0.0, 0.0]);
+        assert!(paper[0] > 95.0 && paper[1].abs() < 3.0 && paper[2].abs() < 5.0);
+        assert!(transform.device_to_lab(&[0.0, 0.0, 0.0, 1.0])[0] < 30.0);
+
+        // a color inside the gamut survives the round trip
+        let lab = transform.device_to_lab(&[0.2, 0.6, 0.1, 0.05]);
+        let round_trip = transform.device_to_lab(&transform.lab_to_device(lab));
+        assert!(delta_e(lab, round_trip) < 3.0);
+
+        let absolute = IccTransform::new(ICC_PROFILE_ECI_V2, RenderingIntent::AbsoluteColorimetric).unwrap();
+        assert!(absolute.device_to_lab(&[0.0; 4])[0] < paper[0]);
+    }
+
+    #[test]
+    fn test_lut_channel_mismatch() {
+        // lutAtoB / lutBtoA with only B curves, which can't change the number of channels
+        let lut = |signature: &[u8], inputs: u8, outputs: u8, curves: usize| {
+            let mut data = signature.to_vec();
+            data.extend_from_slice(&[0, 0, 0, 0, inputs, outputs, 0, 0]);
+            data.extend_from_slice(&32u32.to_be_bytes());
+            data.extend_from_slice(&[0; 16]);
+            for _ in 0..curves {
+                data.extend_from_slice(b"curv\0\0\0\0\0\0\0\0");
+            }
+            data
+        };
+        let gray = profile(b"GRAY", b"Lab ", vec![
+            (b"A2B0", lut(b"mAB ", 1, 3, 3)),
+            (b"B2A0", lut(b"mBA ", 3, 1, 3)),
+        ]);
+        assert!(IccTransform::new(&gray, RenderingIntent::Perceptual).is_err());
+    }
+}
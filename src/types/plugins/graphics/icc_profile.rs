@@ -2,6 +2,9 @@
 
 extern crate lopdf;
 
+use std::collections::BTreeSet;
+use {Embeddable, Error};
+
 /// Type of the icc profile
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum IccProfileType {
@@ -55,6 +58,32 @@ impl IccProfile {
         self
     }
 
+    /// Binary data of the profile
+    pub(crate) fn bytes(&self)
+    -> &[u8]
+    {
+        &self.icc
+    }
+
+    /// Number of color components of the profile
+    pub fn num_components(&self)
+    -> usize
+    {
+        match self.icc_type {
+            IccProfileType::Cmyk => 4,
+            IccProfileType::Rgb => 3,
+            IccProfileType::Greyscale => 1,
+        }
+    }
+}
+
+impl Embeddable for IccProfile {
+    const KEY: &'static str = "NEVER";
+
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        let stream: lopdf::Stream = self.clone().into();
+        Ok(doc.add_object(stream))
+    }
 }
 
 impl Into<lopdf::Stream> for IccProfile {
@@ -81,15 +110,9 @@ impl Into<lopdf::Stream> for IccProfile {
         }
 
         if self.has_range {
-            stream_dict.set("Range", Array(vec![
-                                        Real(0.0),
-                                        Real(1.0),
-                                        Real(0.0),
-                                        Real(1.0),
-                                        Real(0.0),
-                                        Real(1.0),
-                                        Real(0.0),
-                                        Real(1.0)]));
+            // [0 1] for every component
+            let range = (0..num_icc_fields).flat_map(|_| vec![Real(0.0), Real(1.0)]).collect();
+            stream_dict.set("Range", Array(range));
         }
 
         LoStream::new(stream_dict, self.icc)
@@ -108,11 +131,14 @@ impl IccProfileRef {
     -> Self
     {
         Self {
-            name: format!("/ICC{}", index)
+            name: format!("{}{}", ICC_COLOR_SPACE_PREFIX, index)
         }
     }
 }
 
+/// Prefix of the names of the `/ICCBased` color spaces in the page resources
+pub(crate) const ICC_COLOR_SPACE_PREFIX: &str = "ICC";
+
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct IccProfileList {
     profiles: Vec<IccProfile>,
@@ -126,12 +152,48 @@ impl IccProfileList {
         Self::default()
     }
 
-    /// Adds an ICC profile
+    /// Adds an ICC profile. Adding the same profile again returns the existing reference
     pub fn add_profile(&mut self, profile: IccProfile)
     -> IccProfileRef
     {
+        if let Some(index) = self.profiles.iter().position(|p| *p == profile) {
+            return IccProfileRef::new(index);
+        }
         let cur_len = self.profiles.len();
         self.profiles.push(profile);
         IccProfileRef::new(cur_len)
     }
+
+    /// Embeds the profiles with the given resource names once, returns the `/ICCBased`
+    /// color spaces by their resource name
+    pub(crate) fn embed_color_spaces(&self, doc: &mut lopdf::Document, used: &BTreeSet<Vec<u8>>)
+    -> Result<Vec<IccColorSpace>, Error>
+    {
+        use lopdf::Object::*;
+
+        let mut color_spaces = Vec::new();
+        for (index, profile) in self.profiles.iter().enumerate() {
+            let name = IccProfileRef::new(index).name;
+            if !used.contains(name.as_bytes()) {
+                continue;
+            }
+            let profile_id = profile.embed(doc)?;
+            color_spaces.push(IccColorSpace {
+                name,
+                color_space: Array(vec![Name("ICCBased".into()), Reference(profile_id)]),
+                num_components: profile.num_components(),
+            });
+        }
+        Ok(color_spaces)
+    }
+}
+
+/// Embedded `/ICCBased` color space of an `IccProfileList`
+#[derive(Debug, Clone)]
+pub(crate) struct IccColorSpace {
+    /// Resource name (see `IccProfileRef`)
+    pub(crate) name: String,
+    pub(crate) color_space: lopdf::Object,
+    /// Number of components (`/N`) of the profile
+    pub(crate) num_components: usize,
 }
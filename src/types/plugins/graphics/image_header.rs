@@ -0,0 +1,505 @@
+//! Reads the headers of JPEG, JPEG 2000, PNG and TIFF files, so that they can be embedded
+//! into the PDF without decoding them (`DCTDecode` / `JPXDecode` / `FlateDecode` / `CCITTFaxDecode`)
+
+use super::flate::inflate;
+use {Error, PdfError};
+
+/// Information about an encoded image, as read from its headers
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ImageHeader {
+    pub width: usize,
+    pub height: usize,
+    /// Number of color components (1 = gray, 3 = RGB / YCbCr, 4 = CMYK / YCCK)
+    pub components: usize,
+    /// Bits per component
+    pub bits_per_component: u8,
+    /// Adobe (Photoshop) JPEGs store CMYK values inverted, they need a `/Decode [1 0 1 0 1 0 1 0]`
+    pub inverted: bool,
+    /// Embedded ICC profile
+    pub icc_profile: Option<Vec<u8>>,
+}
+
+/// Reads the SOF, APP2 (ICC profile) and APP14 (Adobe) segments of a JPEG file
+pub(crate) fn parse_jpeg_header(data: &[u8]) -> Result<ImageHeader, Error> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    let mut pos = 2;
+    let mut frame = None;
+    let mut is_adobe = false;
+    let mut icc_chunks = Vec::<(u8, &[u8])>::new();
+
+    loop {
+        // markers may be padded with any number of 0xFF bytes
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+
+        if data.get(pos) != Some(&0xFF) {
+            return Err(PdfError::InvalidImage.into());
+        }
+
+        let marker = *data.get(pos + 1).ok_or(PdfError::InvalidImage)?;
+        pos += 2;
+
+        match marker {
+            // markers without a segment
+            0x01 | 0xD0..=0xD7 => continue,
+            // start of scan or end of image: the headers are finished
+            0xDA | 0xD9 => break,
+            _ => {}
+        }
+
+        let length = read_u16(data, pos)? as usize;
+        let segment = data.get(pos + 2..pos + length).ok_or(PdfError::InvalidImage)?;
+        pos += length;
+
+        match marker {
+            // SOF0 (baseline), SOF1 (extended), SOF2 (progressive)
+            0xC0..=0xC2 => {
+                frame = Some((
+                    *segment.first().ok_or(PdfError::InvalidImage)?,
+                    read_u16(segment, 1)? as usize,
+                    read_u16(segment, 3)? as usize,
+                    *segment.get(5).ok_or(PdfError::InvalidImage)? as usize,
+                ));
+            }
+            // lossless, hierarchical and arithmetic coded JPEGs can't be read by PDF viewers
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                return Err(PdfError::InvalidImage.into());
+            }
+            // APP2: ICC profile, possibly split over multiple segments
+            0xE2 if segment.starts_with(b"ICC_PROFILE\0") && segment.len() >= 14 => {
+                icc_chunks.push((segment[12], &segment[14..]));
+            }
+            // APP14: Adobe
+            0xEE if segment.starts_with(b"Adobe") => {
+                is_adobe = true;
+            }
+            _ => {}
+        }
+    }
+
+    let (bits_per_component, height, width, components) = frame.ok_or(PdfError::InvalidImage)?;
+
+    // DCTDecode only supports 8-bit samples
+    if bits_per_component != 8 || width == 0 || height == 0 {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    let icc_profile = if icc_chunks.is_empty() {
+        None
+    } else {
+        icc_chunks.sort_by_key(|&(index, _)| index);
+        Some(icc_chunks.into_iter().flat_map(|(_, chunk)| chunk.iter().cloned()).collect())
+    };
+
+    Ok(ImageHeader {
+        width,
+        height,
+        components,
+        bits_per_component,
+        inverted: is_adobe && components == 4,
+        icc_profile,
+    })
+}
+
+/// Color space of a JPEG 2000 image, from the `colr` box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum JpxColorSpace {
+    Greyscale,
+    Rgb,
+    Cmyk,
+}
+
+/// Reads the header of a JP2 file (`ihdr` and `colr` boxes) or of a raw
+/// JPEG 2000 codestream (`SIZ` marker)
+pub(crate) fn parse_jpx_header(data: &[u8]) -> Result<(ImageHeader, Option<JpxColorSpace>), Error> {
+    const JP2_SIGNATURE: [u8; 12] = [0, 0, 0, 0x0C, b'j', b'P', b' ', b' ', 0x0D, 0x0A, 0x87, 0x0A];
+
+    if data.starts_with(&[0xFF, 0x4F, 0xFF, 0x51]) {
+        return Ok((parse_jpx_codestream(data)?, None));
+    }
+
+    if !data.starts_with(&JP2_SIGNATURE) {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    let mut header = None;
+    let mut color_space = None;
+    let mut icc_profile = None;
+    let mut codestream = None;
+
+    for (box_type, contents) in jp2_boxes(&data[JP2_SIGNATURE.len()..])? {
+        match &box_type {
+            b"jp2h" => {
+                for (box_type, contents) in jp2_boxes(contents)? {
+                    match &box_type {
+                        b"ihdr" => {
+                            header = Some((
+                                read_u32(contents, 0)? as usize,
+                                read_u32(contents, 4)? as usize,
+                                read_u16(contents, 8)? as usize,
+                                *contents.get(10).ok_or(PdfError::InvalidImage)?,
+                            ));
+                        }
+                        // only the first colr box is used
+                        b"colr" if color_space.is_none() && icc_profile.is_none() => {
+                            match contents.first() {
+                                Some(1) => {
+                                    color_space = match read_u32(contents, 3)? {
+                                        12 => Some(JpxColorSpace::Cmyk),
+                                        17 => Some(JpxColorSpace::Greyscale),
+                                        // sRGB, sYCC, e-sRGB and ROMM-RGB are decoded to RGB
+                                        16 | 18 | 20 | 21 => Some(JpxColorSpace::Rgb),
+                                        _ => None,
+                                    }
+                                }
+                                Some(2) | Some(3) => {
+                                    icc_profile = contents.get(3..).map(|icc| icc.to_vec());
+                                }
+                                _ => {}
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            b"jp2c" => codestream = Some(contents),
+            _ => {}
+        }
+    }
+
+    let (height, width, components, bits) = header.ok_or(PdfError::InvalidImage)?;
+
+    // 0xFF: the components have different bit depths, use the codestream
+    let bits_per_component = match (bits, codestream) {
+        (0xFF, Some(codestream)) => parse_jpx_codestream(codestream)?.bits_per_component,
+        (0xFF, None) => return Err(PdfError::InvalidImage.into()),
+        (bits, _) => (bits & 0x7F) + 1,
+    };
+
+    if width == 0 || height == 0 {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    Ok((
+        ImageHeader {
+            width,
+            height,
+            components,
+            bits_per_component,
+            inverted: false,
+            icc_profile,
+        },
+        color_space,
+    ))
+}
+
+/// Chunks of a PNG file that are needed to embed the image data as it is
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PngImage {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: u8,
+    /// 0 = gray, 2 = RGB, 3 = palette, 4 = gray + alpha, 6 = RGBA
+    pub color_type: u8,
+    pub interlaced: bool,
+    /// Concatenated IDAT chunks: zlib-compressed, PNG-filtered rows
+    pub idat: Vec<u8>,
+    /// Decompressed profile of the iCCP chunk
+    pub icc_profile: Option<Vec<u8>>,
+    /// RGB entries of the PLTE chunk
+    pub palette: Option<Vec<u8>>,
+    /// Contents of the tRNS chunk (transparent colors): the alpha values of the palette
+    /// entries, or the 16-bit gray / RGB sample values of the transparent color
+    pub transparency: Option<Vec<u8>>,
+}
+
+/// Reads the IHDR, iCCP, PLTE, tRNS and IDAT chunks of a PNG file
+pub(crate) fn parse_png(data: &[u8]) -> Result<PngImage, Error> {
+    const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    let mut header = None;
+    let mut idat = Vec::new();
+    let mut icc_profile = None;
+    let mut palette = None;
+    let mut transparency = None;
+
+    while pos + 8 <= data.len() {
+        let length = read_u32(data, pos)? as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let contents = data.get(pos + 8..pos + 8 + length).ok_or(PdfError::InvalidImage)?;
+        // length, type, contents, CRC
+        pos += length + 12;
+
+        match chunk_type {
+            b"IHDR" => {
+                header = Some((
+                    read_u32(contents, 0)? as usize,
+                    read_u32(contents, 4)? as usize,
+                    *contents.get(8).ok_or(PdfError::InvalidImage)?,
+                    *contents.get(9).ok_or(PdfError::InvalidImage)?,
+                    *contents.get(12).ok_or(PdfError::InvalidImage)? != 0,
+                ));
+            }
+            b"iCCP" => {
+                // profile name, null separator, compression method, compressed profile
+                let name_end = contents.iter().position(|b| *b == 0).ok_or(PdfError::InvalidImage)?;
+                let profile = contents.get(name_end + 2..).ok_or(PdfError::InvalidImage)?;
+                icc_profile = Some(inflate(profile)?);
+            }
+            b"PLTE" => palette = Some(contents.to_vec()),
+            b"tRNS" => transparency = Some(contents.to_vec()),
+            b"IDAT" => idat.extend_from_slice(contents),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (width, height, bit_depth, color_type, interlaced) = header.ok_or(PdfError::InvalidImage)?;
+
+    if width == 0 || height == 0 || idat.is_empty() {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    Ok(PngImage {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced,
+        idat,
+        icc_profile,
+        palette,
+        transparency,
+    })
+}
+
+/// Group 4 compressed strips of a bilevel TIFF file
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TiffG4Image {
+    pub width: usize,
+    pub height: usize,
+    pub rows_per_strip: usize,
+    /// Compressed strips, with the bit order of every byte fixed for `FillOrder` 2
+    pub strips: Vec<Vec<u8>>,
+    /// `PhotometricInterpretation` is BlackIsZero: the white runs are black pixels
+    pub black_is_1: bool,
+}
+
+/// Reads the first image of a TIFF file that is compressed with CCITT Group 4
+pub(crate) fn parse_tiff_g4(data: &[u8]) -> Result<TiffG4Image, Error> {
+    let big_endian = match data.get(..4) {
+        Some(b"II*\0") => false,
+        Some(b"MM\0*") => true,
+        _ => return Err(PdfError::InvalidImage.into()),
+    };
+
+    let read = |pos: usize, size: usize| -> Result<usize, Error> {
+        let bytes = data.get(pos..pos + size).ok_or(PdfError::InvalidImage)?;
+        let fold = |value: usize, byte: &u8| (value << 8) | usize::from(*byte);
+        Ok(if big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        })
+    };
+
+    // all values of a SHORT or LONG field
+    let read_values = |entry: usize| -> Result<Vec<usize>, Error> {
+        let size = match read(entry + 2, 2)? {
+            3 => 2,
+            4 => 4,
+            _ => return Err(PdfError::InvalidImage.into()),
+        };
+        let count = read(entry + 4, 4)?;
+        // values that fit into four bytes are stored in the entry itself
+        let start = if count * size <= 4 { entry + 8 } else { read(entry + 8, 4)? };
+        (0..count).map(|i| read(start + i * size, size)).collect()
+    };
+
+    let ifd = read(4, 4)?;
+    let mut width = None;
+    let mut height = None;
+    let mut compression = 1;
+    let mut photometric = 0;
+    let mut fill_order = 1;
+    let mut offsets = Vec::new();
+    let mut rows_per_strip = None;
+    let mut byte_counts = Vec::new();
+
+    for i in 0..read(ifd, 2)? {
+        let entry = ifd + 2 + i * 12;
+        let values = read_values(entry);
+        let first = || values.as_ref().ok().and_then(|v| v.first().cloned()).ok_or(PdfError::InvalidImage);
+
+        match read(entry, 2)? {
+            256 => width = Some(first()?),
+            257 => height = Some(first()?),
+            259 => compression = first()?,
+            262 => photometric = first()?,
+            266 => fill_order = first()?,
+            273 => offsets = values?,
+            278 => rows_per_strip = Some(first()?),
+            279 => byte_counts = values?,
+            _ => {}
+        }
+    }
+
+    let width = width.ok_or(PdfError::InvalidImage)?;
+    let height = height.ok_or(PdfError::InvalidImage)?;
+
+    // compression 4 is CCITT T.6 (Group 4)
+    if compression != 4 || width == 0 || height == 0 || offsets.is_empty() || offsets.len() != byte_counts.len() {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    let strips = offsets
+        .iter()
+        .zip(byte_counts.iter())
+        .map(|(offset, count)| {
+            let strip = data.get(*offset..offset + count).ok_or(PdfError::InvalidImage)?;
+            Ok(match fill_order {
+                2 => strip.iter().map(|b| b.reverse_bits()).collect(),
+                _ => strip.to_vec(),
+            })
+        })
+        .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+    Ok(TiffG4Image {
+        width,
+        height,
+        rows_per_strip: rows_per_strip.unwrap_or(height).min(height),
+        strips,
+        black_is_1: photometric == 1,
+    })
+}
+
+// SIZ marker segment of a JPEG 2000 codestream
+fn parse_jpx_codestream(data: &[u8]) -> Result<ImageHeader, Error> {
+    if !data.starts_with(&[0xFF, 0x4F, 0xFF, 0x51]) {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    // skip SOC, SIZ, Lsiz and Rsiz
+    let siz = data.get(8..).ok_or(PdfError::InvalidImage)?;
+    let width = read_u32(siz, 0)?.saturating_sub(read_u32(siz, 8)?) as usize;
+    let height = read_u32(siz, 4)?.saturating_sub(read_u32(siz, 12)?) as usize;
+    let components = read_u16(siz, 32)? as usize;
+    let bits_per_component = (*siz.get(34).ok_or(PdfError::InvalidImage)? & 0x7F) + 1;
+
+    if width == 0 || height == 0 {
+        return Err(PdfError::InvalidImage.into());
+    }
+
+    Ok(ImageHeader {
+        width,
+        height,
+        components,
+        bits_per_component,
+        inverted: false,
+        icc_profile: None,
+    })
+}
+
+// box type and contents
+type Jp2Box<'a> = ([u8; 4], &'a [u8]);
+
+// splits the data into JP2 boxes
+fn jp2_boxes(mut data: &[u8]) -> Result<Vec<Jp2Box<'_>>, Error> {
+    let mut boxes = Vec::new();
+
+    while data.len() >= 8 {
+        let length = read_u32(data, 0)? as u64;
+        let box_type = [data[4], data[5], data[6], data[7]];
+
+        let (header_length, length) = match length {
+            // the box extends to the end of the file
+            0 => (8, data.len() as u64),
+            // 64-bit length
+            1 => (16, (u64::from(read_u32(data, 8)?) << 32) | u64::from(read_u32(data, 12)?)),
+            length => (8, length),
+        };
+
+        if length < header_length || length > data.len() as u64 {
+            return Err(PdfError::InvalidImage.into());
+        }
+
+        boxes.push((box_type, &data[header_length as usize..length as usize]));
+        data = &data[length as usize..];
+    }
+
+    Ok(boxes)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, Error> {
+    match data.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(PdfError::InvalidImage.into()),
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32, Error> {
+    match data.get(pos..pos + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(PdfError::InvalidImage.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jpeg_header() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // APP14 Adobe segment
+        jpeg.extend_from_slice(&[0xFF, 0xEE, 0x00, 0x0E]);
+        jpeg.extend_from_slice(b"Adobe\x00\x64\x00\x00\x00\x00\x02");
+        // ICC profile, split into two segments (in reverse order)
+        jpeg.extend_from_slice(&[0xFF, 0xE2, 0x00, 0x12]);
+        jpeg.extend_from_slice(b"ICC_PROFILE\x00\x02\x02WX");
+        jpeg.extend_from_slice(&[0xFF, 0xE2, 0x00, 0x12]);
+        jpeg.extend_from_slice(b"ICC_PROFILE\x00\x01\x02YZ");
+        // SOF0: 8 bits, 20 x 10 pixels, 4 components
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x08, 8, 0, 10, 0, 20, 4]);
+        jpeg.extend_from_slice(&[0xFF, 0xDA]);
+
+        let header = parse_jpeg_header(&jpeg).unwrap();
+        assert_eq!(header.width, 20);
+        assert_eq!(header.height, 10);
+        assert_eq!(header.components, 4);
+        assert_eq!(header.bits_per_component, 8);
+        assert!(header.inverted);
+        assert_eq!(header.icc_profile, Some(b"YZWX".to_vec()));
+
+        assert!(parse_jpeg_header(b"not a jpeg").is_err());
+    }
+
+    #[test]
+    fn test_parse_jpx_header() {
+        let mut jp2 = vec![0, 0, 0, 0x0C, b'j', b'P', b' ', b' ', 0x0D, 0x0A, 0x87, 0x0A];
+        // jp2h superbox with ihdr (10 x 20 pixels, 3 components, 8 bits) and colr (sRGB)
+        jp2.extend_from_slice(&[0, 0, 0, 45]);
+        jp2.extend_from_slice(b"jp2h");
+        jp2.extend_from_slice(&[0, 0, 0, 22]);
+        jp2.extend_from_slice(b"ihdr");
+        jp2.extend_from_slice(&[0, 0, 0, 20, 0, 0, 0, 10, 0, 3, 7, 7, 0, 0]);
+        jp2.extend_from_slice(&[0, 0, 0, 15]);
+        jp2.extend_from_slice(b"colr");
+        jp2.extend_from_slice(&[1, 0, 0, 0, 0, 0, 16]);
+
+        let (header, color_space) = parse_jpx_header(&jp2).unwrap();
+        assert_eq!((header.width, header.height), (10, 20));
+        assert_eq!(header.components, 3);
+        assert_eq!(header.bits_per_component, 8);
+        assert_eq!(color_space, Some(JpxColorSpace::Rgb));
+    }
+}
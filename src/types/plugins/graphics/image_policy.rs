@@ -0,0 +1,498 @@
+//! Downsampling and recompression of the images of a document, see `PdfDocument::set_image_policy`
+
+use std::collections::HashMap;
+
+use {Error, Pt};
+
+#[cfg(feature = "image")]
+use Px;
+
+/// Resampling filter that is used to downsample images
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ResampleFilter {
+    /// Bicubic interpolation (Catmull-Rom), sharp results
+    #[default]
+    Bicubic,
+    /// Average of the source pixels covered by a target pixel, fast and smooth
+    Average,
+}
+
+/// Per-document policy for the resolution and compression of images. The effective
+/// resolution of an image is computed from the largest size at which it is placed on a page
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImagePolicy {
+    /// Images with a higher effective resolution are downsampled (`None`: never downsample)
+    pub max_dpi: Option<f64>,
+    /// Resolution of downsampled images
+    pub target_dpi: f64,
+    /// Resampling filter used for downsampling
+    pub filter: ResampleFilter,
+    /// Re-encodes greyscale and RGB images as JPEG with this quality (1 - 100)
+    pub jpeg_quality: Option<u8>,
+    /// Converts RGB and CMYK images to greyscale
+    pub greyscale: bool,
+}
+
+impl Default for ImagePolicy {
+    fn default() -> Self {
+        Self {
+            max_dpi: Some(450.0),
+            target_dpi: 300.0,
+            filter: ResampleFilter::default(),
+            jpeg_quality: None,
+            greyscale: false,
+        }
+    }
+}
+
+impl ImagePolicy {
+    /// Downsamples images above `max_dpi` to `target_dpi`
+    pub fn downsample(max_dpi: f64, target_dpi: f64) -> Self {
+        Self {
+            max_dpi: Some(max_dpi),
+            target_dpi,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the resampling filter
+    #[inline]
+    pub fn with_filter(mut self, filter: ResampleFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Re-encodes greyscale and RGB images as JPEG (quality from 1 to 100)
+    #[inline]
+    pub fn with_jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = Some(quality.clamp(1, 100));
+        self
+    }
+
+    /// Converts color images to greyscale
+    #[inline]
+    pub fn with_greyscale(mut self, greyscale: bool) -> Self {
+        self.greyscale = greyscale;
+        self
+    }
+}
+
+/// Result of applying an `ImagePolicy`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct ImagePolicyReport {
+    /// Number of images that were downsampled or recompressed
+    pub images_processed: usize,
+    /// Size of the image data of these images before
+    pub bytes_before: usize,
+    /// Size of the image data of these images after
+    pub bytes_after: usize,
+}
+
+impl ImagePolicyReport {
+    /// Bytes saved by the policy
+    pub fn bytes_saved(&self) -> usize {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Original stream of an image that the policy changed and the resolution it was processed for,
+/// so that the image can be processed again if it is placed larger later on
+#[derive(Debug, Clone)]
+pub(crate) struct ProcessedImage {
+    pub(crate) original: lopdf::Stream,
+    pub(crate) dpi: f64,
+}
+
+/// Lowest effective resolution (in DPI) of every placed image
+pub(crate) fn effective_dpi(
+    doc: &lopdf::Document,
+    placements: &[(lopdf::ObjectId, Pt, Pt)],
+) -> HashMap<lopdf::ObjectId, f64> {
+    let mut dpi = HashMap::new();
+
+    for (id, width, height) in placements {
+        let dict = match doc.get_object(*id).and_then(|o| o.as_stream()) {
+            Ok(stream) => &stream.dict,
+            Err(_) => continue,
+        };
+        let size = |key: &[u8]| dict.get(key).and_then(|o| o.as_i64()).unwrap_or(0) as f64;
+
+        // pixels per inch, the direction with the lower resolution counts
+        let dpi_x = size(b"Width") / (width.0 / 72.0);
+        let dpi_y = size(b"Height") / (height.0 / 72.0);
+        let image_dpi = dpi_x.min(dpi_y);
+
+        if image_dpi.is_finite() && image_dpi > 0.0 {
+            let entry = dpi.entry(*id).or_insert(image_dpi);
+            *entry = entry.min(image_dpi);
+        }
+    }
+
+    dpi
+}
+
+/// Applies the policy to the placed images of the document, the original streams
+/// of the changed images are added to `processed_images`
+#[cfg(feature = "image")]
+pub(crate) fn apply_image_policy(
+    doc: &mut lopdf::Document,
+    dpi: &HashMap<lopdf::ObjectId, f64>,
+    policy: &ImagePolicy,
+    processed_images: &mut HashMap<lopdf::ObjectId, ProcessedImage>,
+) -> Result<ImagePolicyReport, Error> {
+    let mut report = ImagePolicyReport::default();
+
+    for (id, image_dpi) in dpi {
+        let scale = match policy.max_dpi {
+            Some(max_dpi) if *image_dpi > max_dpi => policy.target_dpi / image_dpi,
+            _ => 1.0,
+        };
+
+        let image = match DecodedImage::from_stream(doc, *id) {
+            Some(image) => image,
+            None => continue,
+        };
+
+        let is_color = image.components > 1;
+        if scale >= 1.0 && policy.jpeg_quality.is_none() && !(policy.greyscale && is_color) {
+            continue;
+        }
+
+        let before = image.encoded_size;
+        let processed = image.process(scale, policy);
+        let (content, dict_entries) = processed.encode(policy.jpeg_quality)?;
+
+        // recompressing without downsampling can make the image larger
+        if scale >= 1.0 && content.len() >= before {
+            continue;
+        }
+
+        if let Ok(stream) = doc.get_object_mut(*id).and_then(|o| o.as_stream_mut()) {
+            processed_images.insert(*id, ProcessedImage { original: stream.clone(), dpi: *image_dpi });
+            for key in &[&b"Filter"[..], b"DecodeParms"] {
+                stream.dict.remove(key);
+            }
+            for (key, value) in dict_entries.iter() {
+                stream.dict.set(key.clone(), value.clone());
+            }
+            report.images_processed += 1;
+            report.bytes_before += before;
+            report.bytes_after += content.len();
+            stream.set_content(content);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Applies the policy to the placed images of the document (needs the `image` feature)
+#[cfg(not(feature = "image"))]
+pub(crate) fn apply_image_policy(
+    _: &mut lopdf::Document,
+    _: &HashMap<lopdf::ObjectId, f64>,
+    _: &ImagePolicy,
+    _: &mut HashMap<lopdf::ObjectId, ProcessedImage>,
+) -> Result<ImagePolicyReport, Error> {
+    Ok(ImagePolicyReport::default())
+}
+
+/// 8-bit samples of an image stream
+#[cfg(feature = "image")]
+struct DecodedImage {
+    width: usize,
+    height: usize,
+    components: usize,
+    samples: Vec<u8>,
+    encoded_size: usize,
+    /// Whether the samples were converted to greyscale, the color space is then replaced
+    greyscale_converted: bool,
+}
+
+#[cfg(feature = "image")]
+impl DecodedImage {
+    /// Decodes an image with 8-bit gray, RGB or CMYK samples (raw, `FlateDecode` or `DCTDecode`).
+    /// Masks, indexed images, decode arrays and premultiplied images are not supported
+    fn from_stream(doc: &lopdf::Document, id: lopdf::ObjectId) -> Option<Self> {
+        use super::flate::{inflate, png_unpredict, FlateDecodeParms, PngPredictor};
+        use ColorBits;
+
+        let stream = doc.get_object(id).and_then(|o| o.as_stream()).ok()?;
+        let dict = &stream.dict;
+
+        let has_key = |key: &[u8]| dict.get(key).is_ok();
+        let bits = dict.get(b"BitsPerComponent").ok()?.as_i64().ok()?;
+        if has_key(b"ImageMask") || has_key(b"Mask") || has_key(b"Decode") || bits != 8 {
+            return None;
+        }
+
+        // a premultiplied soft mask needs the original size
+        if let Ok(soft_mask) = dict.get(b"SMask").and_then(|o| o.as_reference()) {
+            let soft_mask = doc.get_object(soft_mask).and_then(|o| o.as_stream()).ok()?;
+            if soft_mask.dict.get(b"Matte").is_ok() {
+                return None;
+            }
+        }
+
+        let components = match dict.get(b"ColorSpace").ok()? {
+            lopdf::Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" => 1,
+                b"DeviceRGB" => 3,
+                b"DeviceCMYK" => 4,
+                _ => return None,
+            },
+            lopdf::Object::Array(array) if array.first()?.as_name().ok()? == b"ICCBased" => {
+                let profile = doc.get_object(array.get(1)?.as_reference().ok()?).and_then(|o| o.as_stream()).ok()?;
+                profile.dict.get(b"N").ok()?.as_i64().ok()? as usize
+            }
+            _ => return None,
+        };
+
+        let width = dict.get(b"Width").ok()?.as_i64().ok()? as usize;
+        let height = dict.get(b"Height").ok()?.as_i64().ok()? as usize;
+
+        let filter = match dict.get(b"Filter") {
+            Ok(lopdf::Object::Name(name)) => Some(name.as_slice()),
+            Ok(lopdf::Object::Array(array)) if array.len() == 1 => Some(array[0].as_name().ok()?),
+            Ok(_) => return None,
+            Err(_) => None,
+        };
+
+        let samples = match filter {
+            None => stream.content.clone(),
+            Some(b"FlateDecode") => {
+                let data = inflate(&stream.content).ok()?;
+                let predictor = dict
+                    .get(b"DecodeParms")
+                    .and_then(|o| o.as_dict())
+                    .and_then(|parms| parms.get(b"Predictor"))
+                    .and_then(|o| o.as_i64())
+                    .unwrap_or(1);
+                match predictor {
+                    1 => data,
+                    10..=15 => {
+                        let parms = FlateDecodeParms {
+                            predictor: PngPredictor::Optimum,
+                            colors: components,
+                            bits_per_component: ColorBits::Bit8,
+                            columns: width,
+                        };
+                        png_unpredict(&data, parms)
+                    }
+                    _ => return None,
+                }
+            }
+            // CMYK JPEGs are converted to RGB by the decoder
+            Some(b"DCTDecode") if components != 4 => {
+                let image = image::load_from_memory_with_format(&stream.content, image::ImageFormat::Jpeg).ok()?;
+                match components {
+                    1 => image.to_luma8().into_raw(),
+                    _ => image.to_rgb8().into_raw(),
+                }
+            }
+            _ => return None,
+        };
+
+        let expected_len = width.checked_mul(height)?.checked_mul(components)?;
+        if width == 0 || height == 0 || samples.len() != expected_len {
+            return None;
+        }
+
+        Some(Self {
+            width,
+            height,
+            components,
+            samples,
+            encoded_size: stream.content.len(),
+            greyscale_converted: false,
+        })
+    }
+
+    /// Converts the image to greyscale and downsamples it, if the policy says so
+    fn process(self, scale: f64, policy: &ImagePolicy) -> Self {
+        let image = match (policy.greyscale, self.components) {
+            (true, 3) | (true, 4) => self.into_greyscale(),
+            _ => self,
+        };
+
+        if scale >= 1.0 {
+            return image;
+        }
+
+        let width = ((image.width as f64 * scale).round() as usize).max(1);
+        let height = ((image.height as f64 * scale).round() as usize).max(1);
+        image.resize(Px(width), Px(height), policy.filter)
+    }
+
+    fn into_greyscale(self) -> Self {
+        let samples = self
+            .samples
+            .chunks_exact(self.components)
+            .map(|pixel| match pixel {
+                [r, g, b] => (0.299 * f64::from(*r) + 0.587 * f64::from(*g) + 0.114 * f64::from(*b)).round() as u8,
+                [c, m, y, k] => {
+                    let ink = 0.3 * f64::from(*c) + 0.59 * f64::from(*m) + 0.11 * f64::from(*y) + f64::from(*k);
+                    (255.0 - ink.min(255.0)).round() as u8
+                }
+                _ => pixel[0],
+            })
+            .collect();
+
+        Self {
+            components: 1,
+            samples,
+            greyscale_converted: true,
+            ..self
+        }
+    }
+
+    fn resize(self, width: Px, height: Px, filter: ResampleFilter) -> Self {
+        use image::imageops::{resize, thumbnail, FilterType};
+        use image::{ImageBuffer, Luma, Rgb, Rgba};
+
+        let (w, h) = (self.width as u32, self.height as u32);
+        let (new_w, new_h) = (width.0 as u32, height.0 as u32);
+
+        macro_rules! resample {
+            ($pixel:ty) => {{
+                let buffer = ImageBuffer::<$pixel, Vec<u8>>::from_raw(w, h, self.samples).unwrap_or_default();
+                match filter {
+                    ResampleFilter::Bicubic => resize(&buffer, new_w, new_h, FilterType::CatmullRom).into_raw(),
+                    ResampleFilter::Average => thumbnail(&buffer, new_w, new_h).into_raw(),
+                }
+            }};
+        }
+
+        // CMYK samples are resampled like RGBA, every channel is independent
+        let samples = match self.components {
+            1 => resample!(Luma<u8>),
+            3 => resample!(Rgb<u8>),
+            _ => resample!(Rgba<u8>),
+        };
+
+        Self {
+            width: width.0,
+            height: height.0,
+            samples,
+            ..self
+        }
+    }
+
+    /// Compresses the samples, returns the content and the changed entries of the image dictionary
+    fn encode(self, jpeg_quality: Option<u8>) -> Result<(Vec<u8>, lopdf::Dictionary), Error> {
+        use super::flate::{compress_image, FlateDecodeParms, PngPredictor};
+        use image::codecs::jpeg::JpegEncoder;
+        use image::ExtendedColorType;
+        use lopdf::Object::*;
+        use {ColorBits, PdfError};
+
+        let mut entries = lopdf::Dictionary::new();
+        entries.set("Width", Integer(self.width as i64));
+        entries.set("Height", Integer(self.height as i64));
+
+        // gray images keep their color space (e.g. an ICC profile)
+        if self.greyscale_converted {
+            entries.set("ColorSpace", Name(b"DeviceGray".to_vec()));
+        }
+
+        let content = match (jpeg_quality, self.components) {
+            (Some(quality), 1) | (Some(quality), 3) => {
+                let color_type = match self.components {
+                    1 => ExtendedColorType::L8,
+                    _ => ExtendedColorType::Rgb8,
+                };
+                let mut jpeg = Vec::new();
+                JpegEncoder::new_with_quality(&mut jpeg, quality)
+                    .encode(&self.samples, self.width as u32, self.height as u32, color_type)
+                    .map_err(|_| PdfError::InvalidImage)?;
+                entries.set("Filter", Name(b"DCTDecode".to_vec()));
+                jpeg
+            }
+            _ => {
+                let parms = FlateDecodeParms {
+                    predictor: PngPredictor::Optimum,
+                    colors: self.components,
+                    bits_per_component: ColorBits::Bit8,
+                    columns: self.width,
+                };
+                entries.set("Filter", Name(b"FlateDecode".to_vec()));
+                entries.set("DecodeParms", Dictionary(parms.into()));
+                compress_image(&self.samples, parms, 6)
+            }
+        };
+
+        Ok((content, entries))
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+    use lopdf::Object::*;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_downsample_placed_image() {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let samples: Vec<u8> = (0..100 * 100 * 3).map(|i| (i % 251) as u8).collect();
+        let dict = lopdf::Dictionary::from_iter(vec![
+            ("Type", Name(b"XObject".to_vec())),
+            ("Subtype", Name(b"Image".to_vec())),
+            ("Width", Integer(100)),
+            ("Height", Integer(100)),
+            ("ColorSpace", Name(b"DeviceRGB".to_vec())),
+            ("BitsPerComponent", Integer(8)),
+        ]);
+        let id = doc.add_object(lopdf::Stream::new(dict, samples));
+
+        // 100 px placed at one inch = 100 DPI, and at half an inch = 200 DPI
+        let placements = vec![(id, Pt(72.0), Pt(72.0)), (id, Pt(36.0), Pt(36.0))];
+        let dpi = effective_dpi(&doc, &placements);
+        assert_eq!(dpi[&id], 100.0);
+
+        let policy = ImagePolicy::downsample(75.0, 50.0).with_greyscale(true);
+        let mut processed = HashMap::new();
+        let report = apply_image_policy(&mut doc, &dpi, &policy, &mut processed).unwrap();
+        assert_eq!(report.images_processed, 1);
+        assert_eq!(processed[&id].original.content.len(), 30_000);
+        assert_eq!(report.bytes_before, 30_000);
+        assert!(report.bytes_saved() > 0);
+
+        let dict = &doc.get_object(id).and_then(|o| o.as_stream()).unwrap().dict;
+        assert_eq!(dict.get(b"Width").and_then(|o| o.as_i64()).unwrap(), 50);
+        assert_eq!(dict.get(b"ColorSpace").and_then(|o| o.as_name()).unwrap(), b"DeviceGray");
+        assert_eq!(dict.get(b"Filter").and_then(|o| o.as_name()).unwrap(), b"FlateDecode");
+
+        // below the threshold, nothing changes
+        let dpi = effective_dpi(&doc, &placements);
+        let report = apply_image_policy(&mut doc, &dpi, &policy, &mut processed).unwrap();
+        assert_eq!(report, ImagePolicyReport::default());
+    }
+
+    #[test]
+    fn test_downsample_keeps_icc_gray() {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let profile = doc.add_object(lopdf::Stream::new(
+            lopdf::Dictionary::from_iter(vec![("N", Integer(1))]),
+            Vec::new(),
+        ));
+        let dict = lopdf::Dictionary::from_iter(vec![
+            ("Type", Name(b"XObject".to_vec())),
+            ("Subtype", Name(b"Image".to_vec())),
+            ("Width", Integer(100)),
+            ("Height", Integer(100)),
+            ("ColorSpace", Array(vec![Name(b"ICCBased".to_vec()), Reference(profile)])),
+            ("BitsPerComponent", Integer(8)),
+        ]);
+        let id = doc.add_object(lopdf::Stream::new(dict, vec![128; 100 * 100]));
+
+        let dpi = effective_dpi(&doc, &[(id, Pt(36.0), Pt(36.0))]);
+        let policy = ImagePolicy::downsample(150.0, 100.0).with_greyscale(true);
+        let report = apply_image_policy(&mut doc, &dpi, &policy, &mut HashMap::new()).unwrap();
+        assert_eq!(report.images_processed, 1);
+
+        let dict = &doc.get_object(id).and_then(|o| o.as_stream()).unwrap().dict;
+        assert_eq!(dict.get(b"Width").and_then(|o| o.as_i64()).unwrap(), 50);
+        let color_space = dict.get(b"ColorSpace").and_then(|o| o.as_array()).unwrap();
+        assert_eq!(color_space[0].as_name().unwrap(), b"ICCBased");
+    }
+}
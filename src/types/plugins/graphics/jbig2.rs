@@ -0,0 +1,396 @@
+//! JBIG2 generic region encoding of 1-bit images (`JBIG2Decode`)
+
+use lopdf;
+use {Embeddable, Error};
+
+/// Global segments (for example shared symbol dictionaries) of JBIG2 encoded images,
+/// written as the `/JBIG2Globals` stream that is referenced by the `/DecodeParms`
+/// of every image using them
+#[derive(Debug, Clone)]
+pub struct Jbig2Globals {
+    /// The global segments, in the embedded stream organization of JBIG2
+    pub data: Vec<u8>,
+}
+
+impl Jbig2Globals {
+    /// Creates the globals from pre-encoded JBIG2 segments
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+}
+
+impl Embeddable for Jbig2Globals {
+    const KEY: &'static str = "NEVER";
+
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        let stream = lopdf::Stream::new(lopdf::Dictionary::new(), self.data.clone());
+        Ok(doc.add_object(stream))
+    }
+}
+
+// segment types, see section 7.3 of the JBIG2 specification
+const SEGMENT_IMMEDIATE_GENERIC_REGION: u8 = 38;
+const SEGMENT_PAGE_INFORMATION: u8 = 48;
+
+// nominal adaptive template pixels of template 0: (3, -1), (-3, -1), (2, -2), (-2, -2)
+const TEMPLATE_0_AT_PIXELS: [i8; 8] = [3, -1, -3, -1, 2, -2, -2, -2];
+
+/// Encodes packed 1-bit rows (0 = black, 1 = white) as a JBIG2 page in the embedded
+/// stream organization: a page information segment and a lossless generic region
+/// (template 0, arithmetic coding) covering the whole page
+pub(crate) fn encode_generic_region(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut page_info = Vec::with_capacity(19);
+    page_info.extend_from_slice(&(width as u32).to_be_bytes());
+    page_info.extend_from_slice(&(height as u32).to_be_bytes());
+    // unknown resolution, default pixel value 0, no striping
+    page_info.extend_from_slice(&[0; 8]);
+    page_info.push(0);
+    page_info.extend_from_slice(&[0; 2]);
+
+    let mut region = Vec::new();
+    region.extend_from_slice(&(width as u32).to_be_bytes());
+    region.extend_from_slice(&(height as u32).to_be_bytes());
+    // x and y location, external combination operator OR
+    region.extend_from_slice(&[0; 8]);
+    region.push(0);
+    // arithmetic coding, template 0, no typical prediction
+    region.push(0);
+    region.extend(TEMPLATE_0_AT_PIXELS.iter().map(|p| *p as u8));
+    region.extend_from_slice(&encode_template_0(data, width, height));
+
+    let mut output = Vec::with_capacity(page_info.len() + region.len() + 22);
+    write_segment(&mut output, 0, SEGMENT_PAGE_INFORMATION, &page_info);
+    write_segment(&mut output, 1, SEGMENT_IMMEDIATE_GENERIC_REGION, &region);
+    output
+}
+
+fn write_segment(output: &mut Vec<u8>, number: u32, segment_type: u8, data: &[u8]) {
+    output.extend_from_slice(&number.to_be_bytes());
+    output.push(segment_type);
+    // no referred-to segments
+    output.push(0);
+    // associated with page 1
+    output.push(1);
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(data);
+}
+
+// one byte per pixel (1 = black), with a white border so that the
+// template never reads outside of the bitmap
+struct PaddedBitmap {
+    pixels: Vec<u8>,
+    stride: usize,
+}
+
+const PADDING_X: usize = 4;
+const PADDING_Y: usize = 2;
+
+impl PaddedBitmap {
+    fn new(width: usize, height: usize) -> Self {
+        let stride = width + 2 * PADDING_X;
+        Self {
+            pixels: vec![0; stride * (height + PADDING_Y)],
+            stride,
+        }
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        (y + PADDING_Y) * self.stride + x + PADDING_X
+    }
+
+    // context of template 0 with the nominal adaptive template pixels
+    fn context(&self, x: usize, y: usize) -> usize {
+        let i = self.index(x, y);
+        let p = &self.pixels;
+        let row_2 = i - 2 * self.stride;
+        let row_1 = i - self.stride;
+
+        let mut context = 0;
+        for pixel in [
+            p[row_2 - 2], p[row_2 - 1], p[row_2], p[row_2 + 1], p[row_2 + 2],
+            p[row_1 - 3], p[row_1 - 2], p[row_1 - 1], p[row_1], p[row_1 + 1], p[row_1 + 2], p[row_1 + 3],
+            p[i - 4], p[i - 3], p[i - 2], p[i - 1],
+        ] {
+            context = (context << 1) | usize::from(pixel);
+        }
+        context
+    }
+}
+
+fn encode_template_0(data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let row_length = width.div_ceil(8);
+    let mut bitmap = PaddedBitmap::new(width, height);
+    let mut encoder = MqEncoder::new();
+    let mut contexts = vec![0; 1 << 16];
+
+    for y in 0..height {
+        for x in 0..width {
+            let byte = data.get(y * row_length + x / 8).cloned().unwrap_or(0xFF);
+            // JBIG2 uses 1 for black pixels
+            let pixel = u8::from(byte & (0x80 >> (x % 8)) == 0);
+            let context = bitmap.context(x, y);
+            encoder.encode(&mut contexts[context], pixel);
+            let index = bitmap.index(x, y);
+            bitmap.pixels[index] = pixel;
+        }
+    }
+
+    encoder.finish()
+}
+
+// (Qe, NMPS, NLPS, SWITCH), see table E.1 of the JBIG2 specification
+const QE_TABLE: [(u32, u8, u8, bool); 47] = [
+    (0x5601, 1, 1, true), (0x3401, 2, 6, false), (0x1801, 3, 9, false), (0x0AC1, 4, 12, false),
+    (0x0521, 5, 29, false), (0x0221, 38, 33, false), (0x5601, 7, 6, true), (0x5401, 8, 14, false),
+    (0x4801, 9, 14, false), (0x3801, 10, 14, false), (0x3001, 11, 17, false), (0x2401, 12, 18, false),
+    (0x1C01, 13, 20, false), (0x1601, 29, 21, false), (0x5601, 15, 14, true), (0x5401, 16, 14, false),
+    (0x5101, 17, 15, false), (0x4801, 18, 16, false), (0x3801, 19, 17, false), (0x3401, 20, 18, false),
+    (0x3001, 21, 19, false), (0x2801, 22, 19, false), (0x2401, 23, 20, false), (0x2201, 24, 21, false),
+    (0x1C01, 25, 22, false), (0x1801, 26, 23, false), (0x1601, 27, 24, false), (0x1401, 28, 25, false),
+    (0x1201, 29, 26, false), (0x1101, 30, 27, false), (0x0AC1, 31, 28, false), (0x09C1, 32, 29, false),
+    (0x08A1, 33, 30, false), (0x0521, 34, 31, false), (0x0441, 35, 32, false), (0x02A1, 36, 33, false),
+    (0x0221, 37, 34, false), (0x0141, 38, 35, false), (0x0111, 39, 36, false), (0x0085, 40, 37, false),
+    (0x0049, 41, 38, false), (0x0025, 42, 39, false), (0x0015, 43, 40, false), (0x0009, 44, 41, false),
+    (0x0005, 45, 42, false), (0x0001, 45, 43, false), (0x5601, 46, 46, false),
+];
+
+// state of a context: index into the Qe table << 1 | MPS
+type Context = u8;
+
+/// MQ arithmetic encoder, see annex E.2 of the JBIG2 specification
+struct MqEncoder {
+    a: u32,
+    c: u32,
+    ct: u32,
+    // the first byte is a placeholder for the byte before the start of the data
+    output: Vec<u8>,
+}
+
+impl MqEncoder {
+    fn new() -> Self {
+        Self {
+            a: 0x8000,
+            c: 0,
+            ct: 12,
+            output: vec![0],
+        }
+    }
+
+    fn encode(&mut self, context: &mut Context, bit: u8) {
+        let index = usize::from(*context >> 1);
+        let mps = *context & 1;
+        let (qe, nmps, nlps, switch) = QE_TABLE[index];
+
+        self.a -= qe;
+        if bit == mps {
+            if self.a & 0x8000 != 0 {
+                self.c += qe;
+                return;
+            }
+            if self.a < qe {
+                self.a = qe;
+            } else {
+                self.c += qe;
+            }
+            *context = (nmps << 1) | mps;
+        } else {
+            if self.a < qe {
+                self.c += qe;
+            } else {
+                self.a = qe;
+            }
+            let mps = if switch { 1 - mps } else { mps };
+            *context = (nlps << 1) | mps;
+        }
+        self.renormalize();
+    }
+
+    fn renormalize(&mut self) {
+        while self.a & 0x8000 == 0 {
+            self.a <<= 1;
+            self.c <<= 1;
+            self.ct -= 1;
+            if self.ct == 0 {
+                self.byte_out();
+            }
+        }
+    }
+
+    fn byte_out(&mut self) {
+        let last = self.output.len() - 1;
+        if self.output[last] == 0xFF {
+            self.push_bits(20);
+        } else if self.c < 0x0800_0000 {
+            self.push_bits(19);
+        } else {
+            // carry into the last byte
+            self.output[last] += 1;
+            if self.output[last] == 0xFF {
+                self.c &= 0x07FF_FFFF;
+                self.push_bits(20);
+            } else {
+                self.push_bits(19);
+            }
+        }
+    }
+
+    // after a 0xFF byte only 7 bits are written (bit stuffing)
+    fn push_bits(&mut self, shift: u32) {
+        self.output.push((self.c >> shift) as u8);
+        self.c &= (1 << shift) - 1;
+        self.ct = 27 - shift;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        // set as many bits of C to 1 as possible
+        let temp = self.c + self.a;
+        self.c |= 0xFFFF;
+        if self.c >= temp {
+            self.c -= 0x8000;
+        }
+
+        self.c <<= self.ct;
+        self.byte_out();
+        self.c <<= self.ct;
+        self.byte_out();
+
+        // marker 0xFF 0xAC ends the data
+        if self.output[self.output.len() - 1] != 0xFF {
+            self.output.push(0xFF);
+        }
+        self.output.push(0xAC);
+        self.output.remove(0);
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // MQ decoder, see annex E.3 of the JBIG2 specification
+    struct MqDecoder<'a> {
+        data: &'a [u8],
+        pos: usize,
+        a: u32,
+        c: u32,
+        ct: u32,
+    }
+
+    impl<'a> MqDecoder<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            let mut decoder = Self { data, pos: 0, a: 0x8000, c: u32::from(data[0]) << 16, ct: 0 };
+            decoder.byte_in();
+            decoder.c <<= 7;
+            decoder.ct -= 7;
+            decoder
+        }
+
+        fn byte(&self, pos: usize) -> u32 {
+            u32::from(self.data.get(pos).cloned().unwrap_or(0xFF))
+        }
+
+        fn byte_in(&mut self) {
+            if self.byte(self.pos) == 0xFF {
+                if self.byte(self.pos + 1) > 0x8F {
+                    self.c = self.c.wrapping_add(0xFF00);
+                    self.ct = 8;
+                } else {
+                    self.pos += 1;
+                    self.c = self.c.wrapping_add(self.byte(self.pos) << 9);
+                    self.ct = 7;
+                }
+            } else {
+                self.pos += 1;
+                self.c = self.c.wrapping_add(self.byte(self.pos) << 8);
+                self.ct = 8;
+            }
+        }
+
+        fn decode(&mut self, context: &mut Context) -> u8 {
+            let index = usize::from(*context >> 1);
+            let mps = *context & 1;
+            let (qe, nmps, nlps, switch) = QE_TABLE[index];
+
+            self.a -= qe;
+            let lps = |context: &mut Context| {
+                *context = (nlps << 1) | if switch { 1 - mps } else { mps };
+                1 - mps
+            };
+            let bit = if (self.c >> 16) < qe {
+                // LPS exchange
+                let bit = if self.a < qe { *context = (nmps << 1) | mps; mps } else { lps(context) };
+                self.a = qe;
+                bit
+            } else {
+                self.c -= qe << 16;
+                if self.a & 0x8000 != 0 {
+                    return mps;
+                }
+                // MPS exchange
+                if self.a < qe { lps(context) } else { *context = (nmps << 1) | mps; mps }
+            };
+
+            while self.a & 0x8000 == 0 {
+                if self.ct == 0 {
+                    self.byte_in();
+                }
+                self.a <<= 1;
+                self.c <<= 1;
+                self.ct -= 1;
+            }
+            bit
+        }
+    }
+
+    fn read_u32(data: &[u8], pos: usize) -> usize {
+        u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize
+    }
+
+    #[test]
+    fn test_generic_region_roundtrip() {
+        let (width, height) = (67usize, 45);
+        let row_length = width.div_ceil(8);
+        let mut data = vec![0xFF; row_length * height];
+        for y in 0..height {
+            for x in 0..width {
+                if (x * x + y * 3) % 7 < 2 || (x > 10 && x < 30 && y > 5 && y < 20) {
+                    data[y * row_length + x / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+
+        let encoded = encode_generic_region(&data, width, height);
+
+        // page information segment
+        assert_eq!(encoded[4], SEGMENT_PAGE_INFORMATION);
+        assert_eq!(read_u32(&encoded, 7), 19);
+        assert_eq!(read_u32(&encoded, 11), width);
+        let region = &encoded[11 + 19..];
+        assert_eq!(region[4], SEGMENT_IMMEDIATE_GENERIC_REGION);
+        let length = read_u32(region, 7);
+        let region = &region[11..];
+        assert_eq!(region.len(), length);
+        assert_eq!(read_u32(region, 4), height);
+        assert_eq!(&region[18..26], &[3, 0xFF, 0xFD, 0xFF, 2, 0xFE, 0xFE, 0xFE]);
+        assert_eq!(&region[region.len() - 2..], &[0xFF, 0xAC]);
+
+        let mut decoder = MqDecoder::new(&region[26..]);
+        let mut contexts = vec![0; 1 << 16];
+        let mut bitmap = PaddedBitmap::new(width, height);
+        let mut decoded = vec![0xFF; row_length * height];
+        for y in 0..height {
+            for x in 0..width {
+                let context = bitmap.context(x, y);
+                let pixel = decoder.decode(&mut contexts[context]);
+                let index = bitmap.index(x, y);
+                bitmap.pixels[index] = pixel;
+                if pixel == 1 {
+                    decoded[y * row_length + x / 8] &= !(0x80 >> (x % 8));
+                }
+            }
+        }
+
+        assert!(decoded == data);
+    }
+}
@@ -4,19 +4,35 @@ pub mod two_dimensional;
 
 pub use self::two_dimensional::*;
 
+pub mod ccitt;
 pub mod color;
+pub mod color_conversion;
+pub mod color_space;
 pub mod ctm;
 pub mod extgstate;
+pub mod flate;
+pub mod function;
+pub(crate) mod icc_cmm;
 pub mod icc_profile;
+pub(crate) mod image_header;
+pub mod image_policy;
+pub mod jbig2;
 pub mod ocg;
 pub mod pattern;
 pub mod pdf_resources;
 pub mod xobject;
 
+pub use self::ccitt::*;
 pub use self::color::*;
+pub use self::color_conversion::*;
+pub use self::color_space::*;
 pub use self::ctm::*;
 pub use self::extgstate::*;
+pub use self::flate::*;
+pub use self::function::*;
 pub use self::icc_profile::*;
+pub use self::image_policy::*;
+pub use self::jbig2::*;
 pub use self::ocg::*;
 pub use self::pattern::*;
 pub use self::pdf_resources::*;
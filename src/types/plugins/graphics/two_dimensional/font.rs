@@ -7,7 +7,7 @@ use lopdf::{Dictionary as LoDictionary, Stream as LoStream};
 use std::collections::BTreeMap;
 use std::iter::FromIterator;
 use types::pdf_resources::Embeddable;
-use Error;
+use {Error, PdfError};
 
 use rusttype::Codepoint as Cp;
 use rusttype::FontCollection;
@@ -25,9 +25,9 @@ pub enum Font {
 impl Embeddable for Font {
     const KEY: &'static str = "Font";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         let o = match self {
-            Font::ExternalFont(font) => font.into_with_document(doc),
+            Font::ExternalFont(font) => font.into_with_document(doc)?,
             Font::BuiltinFont(font) => (*font).into(),
         };
         Ok(doc.add_object(o))
@@ -104,7 +104,7 @@ impl Into<LoDictionary> for BuiltinFont {
 impl Embeddable for BuiltinFont {
     const KEY: &'static str = "Font";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         let o: LoDictionary = (*self).into();
         Ok(doc.add_object(o))
     }
@@ -179,18 +179,24 @@ impl ExternalFont {
         })
     }
 
+    /// Parses the font data. For font collections, the first font is used
+    pub(crate) fn parse(&self) -> Result<rusttype::Font<'_>, Error> {
+        let collection =
+            FontCollection::from_bytes(&self.font_bytes).map_err(|_| PdfError::FontParsing)?;
+        match collection.clone().into_font() {
+            Ok(font) => Ok(font),
+            Err(_) => collection.font_at(0).map_err(|_| PdfError::FontParsing.into()),
+        }
+    }
+
     /// Takes the font and adds it to the document and consumes the font
-    pub(crate) fn into_with_document(&self, doc: &mut lopdf::Document) -> LoDictionary {
+    pub(crate) fn into_with_document(&self, doc: &mut lopdf::Document) -> Result<LoDictionary, Error> {
         use lopdf::Object;
         use lopdf::Object::*;
 
         let face_name = format!("Fo{}", doc.objects.len());
 
-        let collection = FontCollection::from_bytes(&self.font_bytes).unwrap();
-        let font = collection
-            .clone()
-            .into_font()
-            .unwrap_or_else(|_| collection.font_at(0).unwrap());
+        let font = self.parse()?;
 
         // Extract basic font information
         let face_metrics = font.v_metrics_unscaled();
@@ -391,7 +397,7 @@ impl ExternalFont {
         ));
         font_vec.push(("ToUnicode".into(), Reference(cid_to_unicode_map_stream_id)));
 
-        LoDictionary::from_iter(font_vec)
+        Ok(LoDictionary::from_iter(font_vec))
     }
 }
 
@@ -404,8 +410,8 @@ impl Into<Font> for ExternalFont {
 impl Embeddable for ExternalFont {
     const KEY: &'static str = "Font";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
-        let o = self.into_with_document(doc);
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        let o = self.into_with_document(doc)?;
         Ok(doc.add_object(o))
     }
 }
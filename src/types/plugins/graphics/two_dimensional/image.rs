@@ -3,7 +3,7 @@
 
 use image::{self, DynamicImage, ImageDecoder};
 use std::borrow::Borrow;
-use {Embeddable, ImageXObject, Registered};
+use {Embeddable, Error, ImageXObject, Mm, Pt, Px, Registered};
 
 /// Image - wrapper around an `ImageXObject` to allow for more control
 /// within the library
@@ -33,10 +33,24 @@ impl Image {
     }
 }
 
+impl Image {
+    /// Embeds a JPEG file without re-encoding it, see `ImageXObject::from_jpeg`
+    pub fn from_jpeg(data: Vec<u8>) -> Result<Self, Error> {
+        let image = ImageXObject::from_jpeg(data)?;
+        Ok(Self { image })
+    }
+
+    /// Embeds a JPEG 2000 file without re-encoding it, see `ImageXObject::from_jpx`
+    pub fn from_jpx(data: Vec<u8>) -> Result<Self, Error> {
+        let image = ImageXObject::from_jpx(data)?;
+        Ok(Self { image })
+    }
+}
+
 impl Embeddable for Image {
     const KEY: &'static str = "XObject";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         self.image.embed(doc)
     }
 }
@@ -47,3 +61,227 @@ impl<I: Borrow<Image>> crate::types::RegisteredXObject for &Registered<I> {
         name.as_bytes().to_vec()
     }
 }
+
+/// How an image is fitted into the target rectangle of an `ImagePlacement`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ImageFit {
+    /// Scales the image to fit into the rectangle, keeping the aspect ratio
+    #[default]
+    Contain,
+    /// Scales the image to cover the rectangle, keeping the aspect ratio.
+    /// The parts outside of the rectangle are clipped
+    Cover,
+    /// Stretches the image to the size of the rectangle
+    Stretch,
+    /// Keeps the natural size of the image (at the `dpi` of the placement)
+    None,
+}
+
+/// Alignment of an image inside the target rectangle of an `ImagePlacement`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ImageAlignment {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl ImageAlignment {
+    // share of the free space left of and below the image
+    fn factors(self) -> (f64, f64) {
+        use self::ImageAlignment::*;
+        let x = match self {
+            TopLeft | Left | BottomLeft => 0.0,
+            Top | Center | Bottom => 0.5,
+            TopRight | Right | BottomRight => 1.0,
+        };
+        let y = match self {
+            BottomLeft | Bottom | BottomRight => 0.0,
+            Left | Center | Right => 0.5,
+            TopLeft | Top | TopRight => 1.0,
+        };
+        (x, y)
+    }
+}
+
+/// Position and size of an image on a page, see `PdfLayer::place_image`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ImagePlacement {
+    /// X coordinate of the lower left corner of the target rectangle
+    pub x: Mm,
+    /// Y coordinate of the lower left corner of the target rectangle
+    pub y: Mm,
+    /// Width and height of the target rectangle. If `None`, the rectangle
+    /// has the natural size of the image at the `dpi`
+    pub size: Option<(Mm, Mm)>,
+    /// Resolution that determines the natural size of the image (default: 300 DPI).
+    /// A resolution that is not a positive number is replaced by the default
+    pub dpi: f64,
+    /// How the image is fitted into the target rectangle
+    pub fit: ImageFit,
+    /// Alignment of the image inside the target rectangle
+    pub alignment: ImageAlignment,
+    /// Clockwise rotation in degrees, around the center of the placed image
+    pub rotate_cw: f64,
+}
+
+/// Default resolution of an `ImagePlacement`
+const DEFAULT_DPI: f64 = 300.0;
+
+impl ImagePlacement {
+    /// Places the image at its natural size (at 300 DPI), with the lower left corner at `x`, `y`
+    pub fn at(x: Mm, y: Mm) -> Self {
+        Self {
+            x,
+            y,
+            size: None,
+            dpi: DEFAULT_DPI,
+            fit: ImageFit::default(),
+            alignment: ImageAlignment::default(),
+            rotate_cw: 0.0,
+        }
+    }
+
+    /// Fits the image into a rectangle (centered, keeping the aspect ratio by default)
+    pub fn in_rect(x: Mm, y: Mm, width: Mm, height: Mm) -> Self {
+        Self {
+            size: Some((width, height)),
+            ..Self::at(x, y)
+        }
+    }
+
+    /// Sets the resolution that determines the natural size of the image
+    #[inline]
+    pub fn with_dpi(mut self, dpi: f64) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    /// Sets how the image is fitted into the target rectangle
+    #[inline]
+    pub fn with_fit(mut self, fit: ImageFit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// Sets the alignment of the image inside the target rectangle
+    #[inline]
+    pub fn with_alignment(mut self, alignment: ImageAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Rotates the image clockwise around its center, in degrees
+    #[inline]
+    pub fn with_rotation(mut self, rotate_cw: f64) -> Self {
+        self.rotate_cw = rotate_cw;
+        self
+    }
+
+    /// Transformation matrix that maps the unit square of an image with the given size
+    /// to the page (in points), and the rectangle to clip to (x, y, width, height)
+    pub(crate) fn matrix(&self, width: Px, height: Px) -> ([f64; 6], Option<[f64; 4]>) {
+        // a zero, negative or NaN resolution would give an infinite or NaN size
+        let dpi = if self.dpi.is_finite() && self.dpi > 0.0 { self.dpi } else { DEFAULT_DPI };
+        let natural_w = width.into_pt(dpi).0;
+        let natural_h = height.into_pt(dpi).0;
+        let x = Pt::from(self.x).0;
+        let y = Pt::from(self.y).0;
+
+        let (rect_w, rect_h) = match self.size {
+            Some((w, h)) => (Pt::from(w).0, Pt::from(h).0),
+            None => (natural_w, natural_h),
+        };
+
+        // an empty image can't be scaled, it is stretched to the rectangle
+        let is_empty = natural_w <= 0.0 || natural_h <= 0.0;
+        let (w, h) = match self.fit {
+            _ if is_empty => (rect_w, rect_h),
+            ImageFit::Stretch => (rect_w, rect_h),
+            ImageFit::None => (natural_w, natural_h),
+            ImageFit::Contain => {
+                let scale = (rect_w / natural_w).min(rect_h / natural_h);
+                (natural_w * scale, natural_h * scale)
+            }
+            ImageFit::Cover => {
+                let scale = (rect_w / natural_w).max(rect_h / natural_h);
+                (natural_w * scale, natural_h * scale)
+            }
+        };
+
+        let (align_x, align_y) = self.alignment.factors();
+        let center_x = x + (rect_w - w) * align_x + w / 2.0;
+        let center_y = y + (rect_h - h) * align_y + h / 2.0;
+
+        // scale to the size, move the center to the origin, rotate clockwise and move to the center
+        let (sin, cos) = self.rotate_cw.to_radians().sin_cos();
+        let matrix = [
+            w * cos,
+            -w * sin,
+            h * sin,
+            h * cos,
+            center_x - w / 2.0 * cos - h / 2.0 * sin,
+            center_y + w / 2.0 * sin - h / 2.0 * cos,
+        ];
+
+        let clip = match self.fit {
+            ImageFit::Cover => Some([x, y, rect_w, rect_h]),
+            _ => None,
+        };
+
+        (matrix, clip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the conversion between mm and pt is not exact
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-3, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_image_placement_matrix() {
+        // 300 x 150 px at 72 DPI = 300 x 150 pt
+        let (w, h) = (Px(300), Px(150));
+        let mm = |pt: f64| Mm::from(Pt(pt));
+        let rect = |fit| ImagePlacement::in_rect(mm(10.0), mm(20.0), mm(100.0), mm(100.0)).with_dpi(72.0).with_fit(fit);
+
+        let (matrix, clip) = rect(ImageFit::Contain).matrix(w, h);
+        assert_close(&matrix, &[100.0, 0.0, 0.0, 50.0, 10.0, 45.0]);
+        assert!(clip.is_none());
+
+        let (matrix, clip) = rect(ImageFit::Cover).with_alignment(ImageAlignment::Left).matrix(w, h);
+        assert_close(&matrix, &[200.0, 0.0, 0.0, 100.0, 10.0, 20.0]);
+        assert_close(&clip.unwrap(), &[10.0, 20.0, 100.0, 100.0]);
+
+        let (matrix, _) = rect(ImageFit::Stretch).matrix(w, h);
+        assert_close(&matrix, &[100.0, 0.0, 0.0, 100.0, 10.0, 20.0]);
+
+        let (matrix, _) = ImagePlacement::at(Mm(0.0), Mm(0.0)).with_dpi(72.0).matrix(w, h);
+        assert_close(&matrix, &[300.0, 0.0, 0.0, 150.0, 0.0, 0.0]);
+
+        // rotated by 90 degrees around the center (150, 75): the lower left corner moves to the upper left
+        let (matrix, _) = ImagePlacement::at(Mm(0.0), Mm(0.0)).with_dpi(72.0).with_rotation(90.0).matrix(w, h);
+        assert_close(&matrix, &[0.0, -300.0, 150.0, 0.0, 75.0, 225.0]);
+
+        // invalid resolutions fall back to 300 DPI
+        let (expected, _) = ImagePlacement::at(Mm(0.0), Mm(0.0)).matrix(w, h);
+        for dpi in &[0.0, -72.0, f64::NAN, f64::INFINITY] {
+            let (matrix, _) = ImagePlacement::at(Mm(0.0), Mm(0.0)).with_dpi(*dpi).matrix(w, h);
+            assert_close(&matrix, &expected);
+        }
+    }
+}
+
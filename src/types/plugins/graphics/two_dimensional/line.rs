@@ -1,10 +1,10 @@
 use lopdf;
 use glob_defines::{
     OP_PATH_CONST_MOVE_TO, OP_PATH_CONST_3BEZIER_V1, OP_PATH_CONST_3BEZIER_V2, OP_PATH_CONST_4BEZIER,
-    OP_PATH_CONST_LINE_TO, OP_PATH_PAINT_FILL_STROKE_CLOSE_NZ, OP_PATH_PAINT_FILL_NZ,
-    OP_PATH_PAINT_STROKE_CLOSE, OP_PATH_PAINT_STROKE, OP_PATH_PAINT_END, OP_PATH_CONST_CLIP_NZ,
+    OP_PATH_CONST_LINE_TO,
 };
-use Point;
+use super::path::paint_operations;
+use {FillRule, Point};
 use std::iter::{FromIterator, IntoIterator};
 
 #[derive(Debug, Clone)]
@@ -13,12 +13,14 @@ pub struct Line {
     pub points: Vec<(Point, bool)>,
     /// Is the line closed or open?
     pub is_closed: bool,
-    /// Should the line be filled (via winding-number rule), for polygons
+    /// Should the line be filled (via the fill rule), for polygons
     pub has_fill: bool,
     /// Should the line have an outline (stroke)?
     pub has_stroke: bool,
-    /// Is this line a clipping path?
+    /// Is this line a clipping path? A clipping path can also be filled and stroked
     pub is_clipping_path: bool,
+    /// Fill rule for filling and clipping
+    pub fill_rule: FillRule,
 }
 
 impl Default for Line {
@@ -29,6 +31,7 @@ impl Default for Line {
             has_fill: false,
             has_stroke: false,
             is_clipping_path: false,
+            fill_rule: FillRule::NonZero,
         }
     }
 }
@@ -48,6 +51,19 @@ impl FromIterator<(Point, bool)> for Line {
 
 impl Line {
 
+    /// Creates a line from its points, which is not a clipping path and is filled
+    /// with the nonzero winding rule. Prefer this (or `..Default::default()`) over
+    /// a struct literal, so that new fields don't break the construction
+    pub fn new(points: Vec<(Point, bool)>, is_closed: bool, has_fill: bool, has_stroke: bool) -> Self {
+        Self {
+            points,
+            is_closed,
+            has_fill,
+            has_stroke,
+            ..Default::default()
+        }
+    }
+
     /// Sets if the line is closed or not
     #[inline]
     pub fn set_closed(&mut self, is_closed: bool) {
@@ -72,6 +88,12 @@ impl Line {
         self.is_clipping_path = is_clipping_path;
     }
 
+    /// Sets the fill rule (nonzero winding or even-odd)
+    #[inline]
+    pub fn set_fill_rule(&mut self, fill_rule: FillRule) {
+        self.fill_rule = fill_rule;
+    }
+
     pub fn into_stream_op(self)
     -> Vec<lopdf::content::Operation>
     {
@@ -122,34 +144,13 @@ impl Line {
         }
 
         // how to paint the path
-        if self.has_stroke {
-            if self.has_fill {
-                if self.is_closed {
-                    // is filled and stroked and closed
-                    operations.push(Operation::new(OP_PATH_PAINT_FILL_STROKE_CLOSE_NZ, vec![]));
-                } else {
-                    // is filled and stroked but not closed
-                    operations.push(Operation::new(OP_PATH_PAINT_FILL_NZ, vec![]));
-                }
-            } else if self.is_closed {
-                // not filled, but stroked and closed
-                operations.push(Operation::new(OP_PATH_PAINT_STROKE_CLOSE, vec![]));
-            } else {
-                // not filled, not closed but only stroked (regular path)
-                operations.push(Operation::new(OP_PATH_PAINT_STROKE, vec![]));
-            }
-        } else if self.has_fill {
-            // is not stroked, only filled
-            // closed-ness doesn't matter in this case, an area is always closed
-            operations.push(Operation::new(OP_PATH_PAINT_FILL_NZ, vec![]));
-        } else if self.is_clipping_path {
-            // set the path as a clipping path
-            operations.push(Operation::new(OP_PATH_CONST_CLIP_NZ, vec![]));
-            operations.push(Operation::new(OP_PATH_PAINT_END, vec![]));
-        } else {
-            // no painting operation nothing, path is invisible, only end the path
-            operations.push(Operation::new(OP_PATH_PAINT_END, vec![]));
-        }
+        operations.extend(paint_operations(
+            self.has_fill,
+            self.has_stroke,
+            self.is_closed,
+            self.is_clipping_path,
+            self.fill_rule,
+        ));
 
         operations
     }
@@ -2,12 +2,14 @@
 
 pub mod point;
 pub mod line;
+pub mod path;
 pub mod font;
 // pub mod svg;
 pub mod image;
 
 pub use self::point::Point;
 pub use self::line::Line;
+pub use self::path::{FillRule, Path, PathSegment};
 pub use self::font::*;
 // pub use self::svg::Svg;
-pub use self::image::Image;
\ No newline at end of file
+pub use self::image::{Image, ImageAlignment, ImageFit, ImagePlacement};
\ No newline at end of file
@@ -0,0 +1,515 @@
+//! Path builder for shapes with several subpaths, arcs and curves
+
+use glob_defines::{
+    OP_PATH_CONST_4BEZIER, OP_PATH_CONST_CLIP_EO, OP_PATH_CONST_CLIP_NZ, OP_PATH_CONST_CLOSE_SUBPATH,
+    OP_PATH_CONST_LINE_TO, OP_PATH_CONST_MOVE_TO, OP_PATH_CONST_RECT, OP_PATH_PAINT_END,
+    OP_PATH_PAINT_FILL_EO, OP_PATH_PAINT_FILL_NZ, OP_PATH_PAINT_FILL_STROKE_CLOSE_EO,
+    OP_PATH_PAINT_FILL_STROKE_CLOSE_NZ, OP_PATH_PAINT_FILL_STROKE_EO, OP_PATH_PAINT_FILL_STROKE_NZ,
+    OP_PATH_PAINT_STROKE, OP_PATH_PAINT_STROKE_CLOSE,
+};
+use lopdf;
+use {Point, Pt};
+
+/// Rule that decides which areas of a path are inside, for filling and clipping
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FillRule {
+    /// Nonzero winding number rule (`f`, `B`, `W`): subpaths running in the same direction
+    /// add up, a hole has to run in the opposite direction
+    #[default]
+    NonZero,
+    /// Even-odd rule (`f*`, `B*`, `W*`): every subpath toggles between inside and outside,
+    /// independent of its direction
+    EvenOdd,
+}
+
+/// Painting operators for a constructed path. A clipping path is also painted if it has a
+/// fill or a stroke. `close` closes the last subpath before stroking (`s`, `b`)
+pub(crate) fn paint_operations(
+    has_fill: bool,
+    has_stroke: bool,
+    close: bool,
+    is_clipping_path: bool,
+    fill_rule: FillRule,
+) -> Vec<lopdf::content::Operation> {
+    use lopdf::content::Operation;
+
+    let even_odd = fill_rule == FillRule::EvenOdd;
+    let mut operations = Vec::new();
+
+    if is_clipping_path {
+        let clip = if even_odd { OP_PATH_CONST_CLIP_EO } else { OP_PATH_CONST_CLIP_NZ };
+        operations.push(Operation::new(clip, vec![]));
+    }
+
+    let paint = match (has_fill, has_stroke, close, even_odd) {
+        (true, true, true, false) => OP_PATH_PAINT_FILL_STROKE_CLOSE_NZ,
+        (true, true, true, true) => OP_PATH_PAINT_FILL_STROKE_CLOSE_EO,
+        (true, true, false, false) => OP_PATH_PAINT_FILL_STROKE_NZ,
+        (true, true, false, true) => OP_PATH_PAINT_FILL_STROKE_EO,
+        (true, false, _, false) => OP_PATH_PAINT_FILL_NZ,
+        (true, false, _, true) => OP_PATH_PAINT_FILL_EO,
+        (false, true, true, _) => OP_PATH_PAINT_STROKE_CLOSE,
+        (false, true, false, _) => OP_PATH_PAINT_STROKE,
+        (false, false, _, _) => OP_PATH_PAINT_END,
+    };
+    operations.push(Operation::new(paint, vec![]));
+
+    operations
+}
+
+/// One segment of a `Path`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PathSegment {
+    /// Begins a new subpath (`m`)
+    MoveTo(Point),
+    /// Straight line to the point (`l`)
+    LineTo(Point),
+    /// Cubic Bézier curve with two control points to the end point (`c`)
+    CubicTo(Point, Point, Point),
+    /// Rectangle as a complete subpath, from the lower left corner with width and height (`re`)
+    Rect(Point, Pt, Pt),
+    /// Closes the current subpath with a straight line to its start (`h`)
+    ClosePath,
+}
+
+/// Path that consists of several subpaths. Subpaths can be used to cut holes into a filled
+/// shape: with the nonzero winding rule, the hole has to run in the opposite direction, with
+/// the even-odd rule the direction doesn't matter.
+///
+/// ```rust
+/// use pdf_rs::{FillRule, Mm, Path, Point};
+///
+/// // square with a round hole
+/// let path = Path::new()
+///     .rect(Point::new(Mm(10.0), Mm(10.0)), Mm(50.0), Mm(50.0))
+///     .ellipse(Point::new(Mm(35.0), Mm(35.0)), Mm(10.0), Mm(10.0))
+///     .with_fill_rule(FillRule::EvenOdd)
+///     .with_fill(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Path {
+    /// Segments of the path
+    pub segments: Vec<PathSegment>,
+    /// Should the path be filled?
+    pub has_fill: bool,
+    /// Should the path have an outline (stroke)?
+    pub has_stroke: bool,
+    /// Is this path a clipping path? A clipping path can also be filled and stroked
+    pub is_clipping_path: bool,
+    /// Fill rule for filling and clipping
+    pub fill_rule: FillRule,
+    /// Start of the current subpath
+    subpath_start: Option<Point>,
+    /// Current point
+    current_point: Option<Point>,
+}
+
+/// Control point distance of a cubic Bézier curve approximating a quarter circle
+const KAPPA: f64 = 0.552_284_749_830_793_4;
+
+impl Path {
+    /// Creates an empty path, which is invisible unless it is filled, stroked or used for clipping
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begins a new subpath at the point
+    pub fn move_to(mut self, point: Point) -> Self {
+        self.segments.push(PathSegment::MoveTo(point));
+        self.subpath_start = Some(point);
+        self.current_point = Some(point);
+        self
+    }
+
+    /// Straight line to the point. Begins a new subpath if there is no current point
+    pub fn line_to(mut self, point: Point) -> Self {
+        if self.current_point.is_none() {
+            return self.move_to(point);
+        }
+        self.segments.push(PathSegment::LineTo(point));
+        self.current_point = Some(point);
+        self
+    }
+
+    /// Cubic Bézier curve from the current point to `end`, with two control points
+    pub fn cubic_to(mut self, control_1: Point, control_2: Point, end: Point) -> Self {
+        if self.current_point.is_none() {
+            self = self.move_to(control_1);
+        }
+        self.segments.push(PathSegment::CubicTo(control_1, control_2, end));
+        self.current_point = Some(end);
+        self
+    }
+
+    /// Quadratic Bézier curve from the current point to `end`. PDF has no quadratic curves,
+    /// so the curve is converted to the equivalent cubic curve
+    pub fn quad_to(self, control: Point, end: Point) -> Self {
+        let start = self.current_point.unwrap_or(control);
+        let two_thirds = |from: Point| Point {
+            x: from.x + (control.x - from.x) * (2.0 / 3.0),
+            y: from.y + (control.y - from.y) * (2.0 / 3.0),
+        };
+        self.cubic_to(two_thirds(start), two_thirds(end), end)
+    }
+
+    /// Circular arc around `center`, from `start_angle` over `sweep_angle` (in degrees,
+    /// counterclockwise from the positive x axis, negative angles go clockwise). The arc is
+    /// connected to the current point with a straight line, or begins a new subpath
+    pub fn arc_to<P: Into<Pt>>(self, center: Point, radius: P, start_angle: f64, sweep_angle: f64) -> Self {
+        let radius = radius.into();
+        self.elliptical_arc(center, radius, radius, start_angle, sweep_angle)
+    }
+
+    /// Elliptical arc around `center` with the radii `radius_x` and `radius_y`, see `arc_to`
+    pub fn elliptical_arc<P: Into<Pt>>(
+        mut self,
+        center: Point,
+        radius_x: P,
+        radius_y: P,
+        start_angle: f64,
+        sweep_angle: f64,
+    ) -> Self {
+        let (rx, ry) = (radius_x.into().0, radius_y.into().0);
+        let point_at = |angle: f64| Point {
+            x: Pt(center.x.0 + rx * angle.cos()),
+            y: Pt(center.y.0 + ry * angle.sin()),
+        };
+
+        let start = start_angle.to_radians();
+        let start_point = point_at(start);
+        let is_current = self.current_point.is_some_and(|p| {
+            (p.x.0 - start_point.x.0).abs() < 1e-9 && (p.y.0 - start_point.y.0).abs() < 1e-9
+        });
+        if !is_current {
+            self = self.line_to(start_point);
+        }
+
+        // every curve spans 90 degrees at most
+        let count = (sweep_angle.abs() / 90.0).ceil().max(1.0) as usize;
+        let step = sweep_angle.to_radians() / count as f64;
+        let k = 4.0 / 3.0 * (step / 4.0).tan();
+
+        for i in 0..count {
+            let a0 = start + step * i as f64;
+            let a1 = a0 + step;
+            let (p0, p3) = (point_at(a0), point_at(a1));
+            let control_1 = Point {
+                x: Pt(p0.x.0 - k * rx * a0.sin()),
+                y: Pt(p0.y.0 + k * ry * a0.cos()),
+            };
+            let control_2 = Point {
+                x: Pt(p3.x.0 + k * rx * a1.sin()),
+                y: Pt(p3.y.0 - k * ry * a1.cos()),
+            };
+            self = self.cubic_to(control_1, control_2, p3);
+        }
+
+        self
+    }
+
+    /// Closes the current subpath with a straight line to its start
+    pub fn close_path(mut self) -> Self {
+        if self.current_point.is_some() {
+            self.segments.push(PathSegment::ClosePath);
+            self.current_point = self.subpath_start;
+        }
+        self
+    }
+
+    /// Adds a rectangle as a closed subpath (`re`), from the lower left corner
+    pub fn rect<P: Into<Pt>>(mut self, lower_left: Point, width: P, height: P) -> Self {
+        self.segments.push(PathSegment::Rect(lower_left, width.into(), height.into()));
+        self.subpath_start = Some(lower_left);
+        self.current_point = Some(lower_left);
+        self
+    }
+
+    /// Adds a rectangle with rounded corners as a closed subpath (counterclockwise). The radius
+    /// is limited to half of the shorter side
+    pub fn rounded_rect<P: Into<Pt>>(self, lower_left: Point, width: P, height: P, radius: P) -> Self {
+        let (width, height) = (width.into(), height.into());
+        let radius = radius.into().0.min(width.0.abs() / 2.0).min(height.0.abs() / 2.0).max(0.0);
+        if radius == 0.0 {
+            return self.rect(lower_left, width, height);
+        }
+
+        let (left, bottom) = (lower_left.x.0, lower_left.y.0);
+        let (right, top) = (left + width.0, bottom + height.0);
+        let corner = |x: f64, y: f64| Point { x: Pt(x), y: Pt(y) };
+
+        self.move_to(corner(left + radius, bottom))
+            .line_to(corner(right - radius, bottom))
+            .arc_to(corner(right - radius, bottom + radius), Pt(radius), 270.0, 90.0)
+            .line_to(corner(right, top - radius))
+            .arc_to(corner(right - radius, top - radius), Pt(radius), 0.0, 90.0)
+            .line_to(corner(left + radius, top))
+            .arc_to(corner(left + radius, top - radius), Pt(radius), 90.0, 90.0)
+            .line_to(corner(left, bottom + radius))
+            .arc_to(corner(left + radius, bottom + radius), Pt(radius), 180.0, 90.0)
+            .close_path()
+    }
+
+    /// Adds an ellipse as a closed subpath (counterclockwise, four Bézier curves)
+    pub fn ellipse<P: Into<Pt>>(self, center: Point, radius_x: P, radius_y: P) -> Self {
+        let (rx, ry) = (radius_x.into().0, radius_y.into().0);
+        let point = |x: f64, y: f64| Point {
+            x: Pt(center.x.0 + x),
+            y: Pt(center.y.0 + y),
+        };
+
+        self.move_to(point(rx, 0.0))
+            .cubic_to(point(rx, KAPPA * ry), point(KAPPA * rx, ry), point(0.0, ry))
+            .cubic_to(point(-KAPPA * rx, ry), point(-rx, KAPPA * ry), point(-rx, 0.0))
+            .cubic_to(point(-rx, -KAPPA * ry), point(-KAPPA * rx, -ry), point(0.0, -ry))
+            .cubic_to(point(KAPPA * rx, -ry), point(rx, -KAPPA * ry), point(rx, 0.0))
+            .close_path()
+    }
+
+    /// Adds a circle as a closed subpath (counterclockwise)
+    #[inline]
+    pub fn circle<P: Into<Pt>>(self, center: Point, radius: P) -> Self {
+        let radius = radius.into();
+        self.ellipse(center, radius, radius)
+    }
+
+    /// Adds a closed polygon through the points as a subpath
+    pub fn polygon<I: IntoIterator<Item = Point>>(mut self, points: I) -> Self {
+        let mut points = points.into_iter();
+        if let Some(first) = points.next() {
+            self = self.move_to(first);
+            for point in points {
+                self = self.line_to(point);
+            }
+            self = self.close_path();
+        }
+        self
+    }
+
+    /// Reverses the direction of the last subpath, e.g. to cut a hole with the nonzero
+    /// winding rule. A reversed rectangle is written with lines instead of `re`
+    pub fn reverse_last_subpath(mut self) -> Self {
+        let start = self
+            .segments
+            .iter()
+            .rposition(|s| matches!(s, PathSegment::MoveTo(_) | PathSegment::Rect(..)))
+            .unwrap_or(0);
+        let subpath = self.segments.split_off(start);
+
+        let mut reversed = Vec::with_capacity(subpath.len() + 1);
+        let mut current = None;
+        let mut closed = false;
+        for segment in subpath {
+            match segment {
+                PathSegment::MoveTo(p) => current = Some(p),
+                PathSegment::LineTo(p) => {
+                    reversed.push((current, None));
+                    current = Some(p);
+                }
+                PathSegment::CubicTo(c1, c2, p) => {
+                    reversed.push((current, Some((c2, c1))));
+                    current = Some(p);
+                }
+                PathSegment::Rect(p, w, h) => {
+                    let corner = |dx: Pt, dy: Pt| Point { x: p.x + dx, y: p.y + dy };
+                    current = Some(p);
+                    for &(dx, dy) in &[(w, Pt(0.0)), (w, h), (Pt(0.0), h)] {
+                        reversed.push((current, None));
+                        current = Some(corner(dx, dy));
+                    }
+                    closed = true;
+                }
+                PathSegment::ClosePath => closed = true,
+            }
+        }
+
+        // each entry holds the start point of a segment, which is its end point when reversed
+        self.subpath_start = None;
+        self.current_point = None;
+        if let Some(end) = current {
+            self = self.move_to(end);
+            for (point, controls) in reversed.into_iter().rev() {
+                let point = point.unwrap_or(end);
+                self = match controls {
+                    Some((c1, c2)) => self.cubic_to(c1, c2, point),
+                    None => self.line_to(point),
+                };
+            }
+            if closed {
+                self = self.close_path();
+            }
+        }
+
+        self
+    }
+
+    /// Sets if the path is filled
+    #[inline]
+    pub fn with_fill(mut self, has_fill: bool) -> Self {
+        self.has_fill = has_fill;
+        self
+    }
+
+    /// Sets if the path is stroked (has an outline)
+    #[inline]
+    pub fn with_stroke(mut self, has_stroke: bool) -> Self {
+        self.has_stroke = has_stroke;
+        self
+    }
+
+    /// Sets if the path is a clipping path
+    #[inline]
+    pub fn with_clipping_path(mut self, is_clipping_path: bool) -> Self {
+        self.is_clipping_path = is_clipping_path;
+        self
+    }
+
+    /// Sets the fill rule for filling and clipping
+    #[inline]
+    pub fn with_fill_rule(mut self, fill_rule: FillRule) -> Self {
+        self.fill_rule = fill_rule;
+        self
+    }
+
+    /// Returns the operations that construct and paint the path
+    pub fn into_stream_op(self) -> Vec<lopdf::content::Operation> {
+        use lopdf::content::Operation;
+
+        if self.segments.is_empty() {
+            return Vec::new();
+        }
+
+        let mut operations: Vec<Operation> = self
+            .segments
+            .into_iter()
+            .map(|segment| match segment {
+                PathSegment::MoveTo(p) => Operation::new(OP_PATH_CONST_MOVE_TO, vec![p.x.into(), p.y.into()]),
+                PathSegment::LineTo(p) => Operation::new(OP_PATH_CONST_LINE_TO, vec![p.x.into(), p.y.into()]),
+                PathSegment::CubicTo(c1, c2, p) => Operation::new(
+                    OP_PATH_CONST_4BEZIER,
+                    vec![c1.x.into(), c1.y.into(), c2.x.into(), c2.y.into(), p.x.into(), p.y.into()],
+                ),
+                PathSegment::Rect(p, w, h) => {
+                    Operation::new(OP_PATH_CONST_RECT, vec![p.x.into(), p.y.into(), w.into(), h.into()])
+                }
+                PathSegment::ClosePath => Operation::new(OP_PATH_CONST_CLOSE_SUBPATH, vec![]),
+            })
+            .collect();
+
+        // subpaths are closed explicitly, so the painting operators don't close them
+        operations.extend(paint_operations(
+            self.has_fill,
+            self.has_stroke,
+            false,
+            self.is_clipping_path,
+            self.fill_rule,
+        ));
+
+        operations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `Point` equality needs non-zero coordinates
+    fn pt(x: f64, y: f64) -> Point {
+        Point { x: Pt(x), y: Pt(y) }
+    }
+
+    fn operators(path: Path) -> Vec<::std::string::String> {
+        path.into_stream_op().into_iter().map(|op| op.operator).collect()
+    }
+
+    #[test]
+    fn test_path_operators() {
+        let path = Path::new()
+            .rect(pt(0.0, 0.0), Pt(100.0), Pt(100.0))
+            .polygon(vec![pt(10.0, 10.0), pt(20.0, 10.0), pt(15.0, 20.0)])
+            .move_to(pt(30.0, 30.0))
+            .quad_to(pt(40.0, 60.0), pt(50.0, 30.0))
+            .with_fill(true)
+            .with_stroke(true);
+        assert_eq!(operators(path), vec!["re", "m", "l", "l", "h", "m", "c", "B"]);
+
+        let clip = Path::new().circle(pt(50.0, 50.0), Pt(10.0)).with_clipping_path(true);
+        assert_eq!(operators(clip), vec!["m", "c", "c", "c", "c", "h", "W", "n"]);
+
+        // donut with the even-odd rule, clipped and painted
+        let donut = Path::new()
+            .circle(pt(50.0, 50.0), Pt(20.0))
+            .circle(pt(50.0, 50.0), Pt(10.0))
+            .with_fill_rule(FillRule::EvenOdd)
+            .with_clipping_path(true)
+            .with_fill(true)
+            .with_stroke(true);
+        let ops = operators(donut);
+        assert_eq!(ops[ops.len() - 2..], ["W*", "B*"]);
+
+        // the quadratic control point is converted
+        let quad = Path::new().move_to(pt(3.0, 3.0)).quad_to(pt(33.0, 33.0), pt(63.0, 3.0));
+        assert_eq!(quad.segments[1], PathSegment::CubicTo(pt(23.0, 23.0), pt(43.0, 23.0), pt(63.0, 3.0)));
+    }
+
+    #[test]
+    fn test_paint_operations() {
+        let paint = |fill, stroke, close, clip, rule| -> Vec<::std::string::String> {
+            paint_operations(fill, stroke, close, clip, rule).into_iter().map(|op| op.operator).collect()
+        };
+        assert_eq!(paint(true, true, false, false, FillRule::NonZero), ["B"]);
+        assert_eq!(paint(true, true, true, false, FillRule::EvenOdd), ["b*"]);
+        assert_eq!(paint(true, false, true, false, FillRule::EvenOdd), ["f*"]);
+        assert_eq!(paint(false, true, true, true, FillRule::EvenOdd), ["W*", "s"]);
+        assert_eq!(paint(false, false, false, true, FillRule::NonZero), ["W", "n"]);
+        assert_eq!(paint(false, false, false, false, FillRule::NonZero), ["n"]);
+    }
+
+    #[test]
+    fn test_arcs() {
+        // a half circle is split into two curves that end on the circle
+        let arc = Path::new().arc_to(pt(0.0, 0.0), Pt(10.0), 0.0, 180.0);
+        assert_eq!(arc.segments.len(), 3);
+        match arc.segments[2] {
+            PathSegment::CubicTo(_, _, end) => {
+                assert!((end.x.0 + 10.0).abs() < 1e-9 && end.y.0.abs() < 1e-9);
+            }
+            ref other => panic!("unexpected segment {:?}", other),
+        }
+
+        // a quarter arc has the same control points as the ellipse
+        let quarter = Path::new().arc_to(pt(0.0, 0.0), Pt(10.0), 0.0, 90.0);
+        match quarter.segments[1] {
+            PathSegment::CubicTo(c1, _, _) => assert!((c1.y.0 - KAPPA * 10.0).abs() < 1e-6),
+            ref other => panic!("unexpected segment {:?}", other),
+        }
+
+        let rounded = Path::new().rounded_rect(pt(5.0, 5.0), Pt(100.0), Pt(20.0), Pt(50.0));
+        assert_eq!(rounded.segments.iter().filter(|s| matches!(s, PathSegment::CubicTo(..))).count(), 4);
+        assert_eq!(rounded.segments.len(), 10);
+        assert_eq!(rounded.segments[1], PathSegment::LineTo(pt(95.0, 5.0)));
+    }
+
+    #[test]
+    fn test_reverse_subpath() {
+        let path = Path::new()
+            .rect(pt(1.0, 1.0), Pt(10.0), Pt(10.0))
+            .polygon(vec![pt(1.0, 1.0), pt(5.0, 1.0), pt(5.0, 5.0)])
+            .reverse_last_subpath();
+        assert_eq!(path.segments[1..], [
+            PathSegment::MoveTo(pt(5.0, 5.0)),
+            PathSegment::LineTo(pt(5.0, 1.0)),
+            PathSegment::LineTo(pt(1.0, 1.0)),
+            PathSegment::ClosePath,
+        ]);
+
+        let rect = Path::new().rect(pt(2.0, 2.0), Pt(10.0), Pt(20.0)).reverse_last_subpath();
+        assert_eq!(rect.segments, vec![
+            PathSegment::MoveTo(pt(2.0, 22.0)),
+            PathSegment::LineTo(pt(12.0, 22.0)),
+            PathSegment::LineTo(pt(12.0, 2.0)),
+            PathSegment::LineTo(pt(2.0, 2.0)),
+            PathSegment::ClosePath,
+        ]);
+    }
+}
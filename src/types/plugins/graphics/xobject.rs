@@ -5,7 +5,18 @@ use crate::OffsetDateTime;
 #[cfg(feature = "image")]
 use image::{DynamicImage, GenericImageView, ImageDecoder, ImageError};
 use lopdf;
-use {ColorBits, ColorSpace, CurTransMat, Embeddable, Px};
+use lopdf::content::Operation;
+use super::ccitt::{decode_g4_lines, encode_g4, encode_g4_lines, CcittFaxDecodeParms};
+use super::flate::{compress_image, FlateDecodeParms, ImageCompression, PngPredictor};
+use super::image_header::{
+    parse_jpeg_header, parse_jpx_header, parse_png, parse_tiff_g4, ImageHeader, JpxColorSpace,
+};
+use super::jbig2::{encode_generic_region, Jbig2Globals};
+use {
+    ColorBits, ColorSpace, CurTransMat, Embeddable, Embedded, Error, IccProfile, IccProfileType,
+    ImageColorSpace, PdfError, Px,
+};
+use utils::ascii85_encode;
 
 /* Parent: Resources dictionary of the page */
 /// External object that gets reference outside the PDF content stream
@@ -16,7 +27,7 @@ use {ColorBits, ColorSpace, CurTransMat, Embeddable, Px};
 pub enum XObject {
     /* /Subtype /Image */
     /// Image XObject, for images
-    Image(ImageXObject),
+    Image(Box<ImageXObject>),
     /* /Subtype /Form */
     /// Form XObject, for PDF forms
     Form(Box<FormXObject>),
@@ -41,21 +52,29 @@ impl XObject {
     }
 }
 
-impl Into<lopdf::Object> for XObject {
-    fn into(self) -> lopdf::Object {
+impl Embeddable for XObject {
+    const KEY: &'static str = "XObject";
+
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         match self {
-            XObject::Image(image) => lopdf::Object::Stream(Self::compress_stream(image.into())),
+            // images can consist of several objects (soft mask, ICC profile, color space)
+            XObject::Image(image) => image.embed(doc),
             XObject::Form(form) => {
-                let cur_form: FormXObject = *form;
-                lopdf::Object::Stream(Self::compress_stream(cur_form.into()))
+                let cur_form: FormXObject = (**form).clone();
+                Ok(doc.add_object(Self::compress_stream(cur_form.into())))
             }
-            XObject::PostScript(ps) => lopdf::Object::Stream(Self::compress_stream(ps.into())),
+            XObject::PostScript(ps) => Ok(doc.add_object(Self::compress_stream(ps.clone().into()))),
         }
     }
 }
 
-/* todo: inline images? (icons, logos, etc.) */
-/* todo: JPXDecode filter */
+/// Maximum size of the (compressed and ASCII85 encoded) data of an inline image, as recommended by the PDF spec
+pub const INLINE_IMAGE_MAX_SIZE: usize = 4096;
+
+/// Operator of the single operation that holds an inline image: the operands are the abbreviated
+/// image dictionary, followed by the encoded data as a string. Written as `BI` ... `ID` ... `EI`
+/// by `PdfLayer`, lopdf can't encode inline images
+pub(crate) const OP_INLINE_IMAGE: &str = "BI";
 
 #[derive(Debug, Clone)]
 pub struct ImageXObject {
@@ -74,6 +93,10 @@ pub struct ImageXObject {
     pub image_data: Vec<u8>,
     /// Decompression filter for `image_data`, if `None` assumes uncompressed raw pixels in the expected color format.
     pub image_filter: Option<ImageFilter>,
+    /// `/DecodeParms` of the `image_filter`
+    pub decode_parms: Option<ImageDecodeParms>,
+    /// Compression of uncompressed image data (`image_filter` is `None`) when the image is embedded
+    pub compression: ImageCompression,
     /* /BBox << dictionary >> */
     /* todo: find out if this is really required */
     /// Required bounds to clip the image, in unit space
@@ -81,6 +104,16 @@ pub struct ImageXObject {
     pub clipping_bbox: Option<CurTransMat>,
     /// Soft mask
     pub soft_mask: Option<SMask>,
+    /// ICC profile of the image. If set, the color space is written as `[/ICCBased ...]`
+    pub icc_profile: Option<IccProfile>,
+    /// Decode array, maps the samples to the range of the color space
+    /// (for example `[1 0 1 0 1 0 1 0]` to invert CMYK samples)
+    pub decode: Option<Vec<f64>>,
+    /// Indexed, Separation, DeviceN or ICCBased color space, overrides `color_space` and `icc_profile`.
+    /// An `icc_profile` is used as the base of an indexed color space with a device base
+    pub special_color_space: Option<ImageColorSpace>,
+    /// Stencil mask, color-key masking or explicit mask image
+    pub masking: Option<ImageMasking>,
 }
 
 impl<'a> ImageXObject {
@@ -105,120 +138,589 @@ impl<'a> ImageXObject {
             interpolate,
             image_data: data,
             image_filter,
+            decode_parms: None,
+            compression: ImageCompression::default(),
             clipping_bbox: bbox,
             soft_mask: None,
+            icc_profile: None,
+            decode: None,
+            special_color_space: None,
+            masking: None,
         }
     }
 
-    #[cfg(feature = "image")]
-    pub fn try_from<T: ImageDecoder>(image: T) -> Result<Self, ImageError> {
-        use image::error::{LimitError, LimitErrorKind};
-        use std::usize;
+    /// Embeds a JPEG file as it is (`DCTDecode`), without decoding and re-encoding it.
+    /// The size and color space are read from the JPEG headers, an embedded ICC profile
+    /// is used as the color space of the image. CMYK JPEGs written by Adobe applications
+    /// have inverted samples, these get a matching `/Decode` array.
+    pub fn from_jpeg(data: Vec<u8>) -> Result<Self, Error> {
+        let header = parse_jpeg_header(&data)?;
+
+        let (color_space, icc_type) = match header.components {
+            1 => (ColorSpace::Greyscale, IccProfileType::Greyscale),
+            3 => (ColorSpace::Rgb, IccProfileType::Rgb),
+            4 => (ColorSpace::Cmyk, IccProfileType::Cmyk),
+            _ => return Err(PdfError::InvalidImage.into()),
+        };
+
+        let decode = if header.inverted {
+            Some(vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0])
+        } else {
+            None
+        };
+
+        Ok(Self::from_encoded(header, color_space, icc_type, ImageFilter::DCT, decode, data))
+    }
 
-        let dim = image.dimensions();
-        let color_type = image.color_type();
-        let num_image_bytes = image.total_bytes();
+    /// Embeds a JPEG 2000 file (JP2 or raw codestream) as it is (`JPXDecode`, PDF 1.5).
+    /// The size and color space are read from the headers, an embedded ICC profile
+    /// is used as the color space of the image.
+    pub fn from_jpx(data: Vec<u8>) -> Result<Self, Error> {
+        let (header, jpx_color_space) = parse_jpx_header(&data)?;
+
+        let jpx_color_space = match (jpx_color_space, header.components) {
+            (Some(color_space), _) => color_space,
+            (None, 1) | (None, 2) => JpxColorSpace::Greyscale,
+            (None, 3) => JpxColorSpace::Rgb,
+            (None, 4) => JpxColorSpace::Cmyk,
+            _ => return Err(PdfError::InvalidImage.into()),
+        };
+
+        let (color_space, icc_type) = match jpx_color_space {
+            JpxColorSpace::Greyscale => (ColorSpace::Greyscale, IccProfileType::Greyscale),
+            JpxColorSpace::Rgb => (ColorSpace::Rgb, IccProfileType::Rgb),
+            JpxColorSpace::Cmyk => (ColorSpace::Cmyk, IccProfileType::Cmyk),
+        };
+
+        Ok(Self::from_encoded(header, color_space, icc_type, ImageFilter::JPX, None, data))
+    }
 
-        if num_image_bytes > usize::MAX as u64 {
-            return Err(ImageError::Limits(LimitError::from_kind(
-                LimitErrorKind::InsufficientMemory,
-            )));
-        }
+    /// Embeds a PNG file. Greyscale, RGB and palette images that are not interlaced are embedded
+    /// without recompressing them (the IDAT data is a valid `FlateDecode` stream with a PNG
+    /// predictor), the palette is kept as an indexed color space and the transparent color of
+    /// greyscale and RGB images as a color key mask (`ImageMasking::ColorKey`). Other PNG files
+    /// (and palette images with transparent colors) are decoded and compressed again, which
+    /// needs the `image` feature.
+    pub fn from_png(data: Vec<u8>) -> Result<Self, Error> {
+        let png = parse_png(&data)?;
+
+        let bits_per_component = match png.bit_depth {
+            1 => ColorBits::Bit1,
+            2 => ColorBits::Bit2,
+            4 => ColorBits::Bit4,
+            8 => ColorBits::Bit8,
+            16 => ColorBits::Bit16,
+            _ => return Err(PdfError::InvalidImage.into()),
+        };
+
+        let (color_space, icc_type) = match (png.color_type, png.interlaced, png.transparency.is_some()) {
+            (0, false, _) => (ColorSpace::Greyscale, IccProfileType::Greyscale),
+            (2, false, _) => (ColorSpace::Rgb, IccProfileType::Rgb),
+            (3, false, false) => (ColorSpace::Palette, IccProfileType::Rgb),
+            _ => return Self::decode_png(&data),
+        };
+
+        // the transparent gray / RGB color, as 16-bit values that have to fit the bit depth
+        let masking = match png.transparency {
+            Some(ref transparency) if color_space != ColorSpace::Palette => {
+                let components = color_space.num_components();
+                let samples: Vec<u32> = transparency
+                    .chunks(2)
+                    .take(components)
+                    .filter(|sample| sample.len() == 2)
+                    .map(|sample| u32::from(u16::from_be_bytes([sample[0], sample[1]])))
+                    .collect();
+                if samples.len() != components || samples.iter().any(|s| *s >> png.bit_depth != 0) {
+                    return Err(PdfError::InvalidImage.into());
+                }
+                Some(ImageMasking::ColorKey(samples.iter().flat_map(|s| vec![*s, *s]).collect()))
+            }
+            _ => None,
+        };
 
-        let mut image_data = vec![0; num_image_bytes as usize];
-        image.read_image(&mut image_data)?;
+        let special_color_space = match (color_space, png.palette) {
+            (ColorSpace::Palette, Some(palette)) => {
+                Some(ImageColorSpace::indexed(ImageColorSpace::Device(ColorSpace::Rgb), palette)?)
+            }
+            (ColorSpace::Palette, None) => return Err(PdfError::InvalidImage.into()),
+            _ => None,
+        };
 
-        let color_bits = ColorBits::from(color_type);
-        let color_space = ColorSpace::from(color_type);
+        let decode_parms = FlateDecodeParms {
+            predictor: PngPredictor::Optimum,
+            colors: color_space.num_components(),
+            bits_per_component,
+            columns: png.width,
+        };
 
         Ok(Self {
-            width: Px(dim.0 as usize),
-            height: Px(dim.1 as usize),
-            color_space: color_space,
-            bits_per_component: color_bits,
-            image_data,
+            width: Px(png.width),
+            height: Px(png.height),
+            color_space,
+            bits_per_component,
             interpolate: true,
-            image_filter: None,
+            image_data: png.idat,
+            image_filter: Some(ImageFilter::Flate),
+            decode_parms: Some(ImageDecodeParms::Flate(decode_parms)),
+            compression: ImageCompression::None,
             clipping_bbox: None,
             soft_mask: None,
+            icc_profile: png.icc_profile.map(|icc| IccProfile::new(icc, icc_type)),
+            decode: None,
+            special_color_space,
+            masking,
         })
     }
 
+    /// Embeds CCITT Group 4 (T.6) compressed data of a 1-bit image as it is (`CCITTFaxDecode`).
+    /// If `black_is_1` is `false`, black pixels are decoded as 0 bits, as in fax
+    /// and TIFF images that use `WhiteIsZero`.
+    pub fn from_ccitt_g4(width: Px, height: Px, data: Vec<u8>, black_is_1: bool) -> Self {
+        let decode_parms = CcittFaxDecodeParms {
+            black_is_1,
+            end_of_block: false,
+            ..CcittFaxDecodeParms::group_4(width.0, height.0)
+        };
+
+        Self::from_bilevel(width, height, ImageFilter::CCITTFax, ImageDecodeParms::CcittFax(decode_parms), data)
+    }
+
+    /// Embeds the first image of a TIFF file that is compressed with CCITT Group 4, as written
+    /// by most scanners. Images stored in one strip are embedded without decoding them,
+    /// images with several strips are decoded and encoded again as one strip.
+    pub fn from_tiff_g4(data: &[u8]) -> Result<Self, Error> {
+        let tiff = parse_tiff_g4(data)?;
+        let (width, height) = (Px(tiff.width), Px(tiff.height));
+
+        if tiff.strips.len() == 1 {
+            let strip = tiff.strips.into_iter().next().unwrap_or_default();
+            return Ok(Self::from_ccitt_g4(width, height, strip, tiff.black_is_1));
+        }
+
+        // every strip starts with an all-white reference line
+        let mut lines = Vec::with_capacity(tiff.height);
+        for strip in &tiff.strips {
+            let rows = tiff.rows_per_strip.min(tiff.height - lines.len());
+            lines.extend(decode_g4_lines(strip, tiff.width, rows)?);
+            if lines.len() == tiff.height {
+                break;
+            }
+        }
+
+        if lines.len() != tiff.height {
+            return Err(PdfError::InvalidImage.into());
+        }
+
+        let mut image = Self::from_ccitt_g4(width, height, encode_g4_lines(lines, tiff.width), tiff.black_is_1);
+        if let Some(ImageDecodeParms::CcittFax(ref mut decode_parms)) = image.decode_parms {
+            decode_parms.end_of_block = true;
+        }
+        Ok(image)
+    }
+
+    /// Embeds a JBIG2 encoded 1-bit image as it is (`JBIG2Decode`, PDF 1.4). The data has to
+    /// be in the embedded stream organization (without file header and end-of-page segments),
+    /// global segments shared by several images are embedded once as `Jbig2Globals`.
+    pub fn from_jbig2(width: Px, height: Px, data: Vec<u8>, globals: Option<&Embedded<Jbig2Globals>>) -> Self {
+        let decode_parms = ImageDecodeParms::Jbig2 {
+            globals: globals.map(|globals| globals.object_id),
+        };
+
+        Self::from_bilevel(width, height, ImageFilter::JBIG2, decode_parms, data)
+    }
+
+    fn from_bilevel(width: Px, height: Px, filter: ImageFilter, decode_parms: ImageDecodeParms, data: Vec<u8>) -> Self {
+        Self {
+            width,
+            height,
+            color_space: ColorSpace::Greyscale,
+            bits_per_component: ColorBits::Bit1,
+            interpolate: false,
+            image_data: data,
+            image_filter: Some(filter),
+            decode_parms: Some(decode_parms),
+            compression: ImageCompression::None,
+            clipping_bbox: None,
+            soft_mask: None,
+            icc_profile: None,
+            decode: None,
+            special_color_space: None,
+            masking: None,
+        }
+    }
+
+    #[cfg(feature = "image")]
+    fn decode_png(data: &[u8]) -> Result<Self, Error> {
+        image::load_from_memory_with_format(data, image::ImageFormat::Png)
+            .map(Self::from_dynamic_image)
+            .map_err(|_| PdfError::InvalidImage.into())
+    }
+
+    #[cfg(not(feature = "image"))]
+    fn decode_png(_: &[u8]) -> Result<Self, Error> {
+        Err(PdfError::InvalidImage.into())
+    }
+
+    /// Sets an indexed, Separation, DeviceN or ICCBased color space. The samples have to
+    /// match the number of components of the color space
+    #[inline]
+    pub fn with_special_color_space(mut self, color_space: ImageColorSpace) -> Self {
+        self.special_color_space = Some(color_space);
+        self
+    }
+
+    /// Creates a stencil mask from packed 1-bit rows (`/ImageMask true`). The mask is painted
+    /// in the current fill color where the samples are 0, samples that are 1 are transparent.
+    /// Use `with_decode(vec![1.0, 0.0])` to paint the 1 samples instead
+    pub fn stencil_mask(width: Px, height: Px, data: Vec<u8>) -> Self {
+        Self {
+            interpolate: false,
+            masking: Some(ImageMasking::Stencil),
+            ..Self::new(width, height, ColorSpace::Greyscale, ColorBits::Bit1, false, None, None, data)
+        }
+    }
+
+    /// Sets the masking of the image
+    #[inline]
+    pub fn with_masking(mut self, masking: ImageMasking) -> Self {
+        self.masking = Some(masking);
+        self
+    }
+
+    /// Sets the `/Decode` array, two values per color component (or `[1 0]` to invert a stencil mask)
+    #[inline]
+    pub fn with_decode(mut self, decode: Vec<f64>) -> Self {
+        self.decode = Some(decode);
+        self
+    }
+
+    /// Sets the compression of the raw image data, default: `FlateDecode` with
+    /// compression level 6 and the optimum PNG predictor for every row.
+    /// Scanned 1-bit images get much smaller with `CcittG4` or `Jbig2`.
+    #[inline]
+    pub fn with_compression(mut self, compression: ImageCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    fn from_encoded(
+        header: ImageHeader,
+        color_space: ColorSpace,
+        icc_type: IccProfileType,
+        filter: ImageFilter,
+        decode: Option<Vec<f64>>,
+        data: Vec<u8>,
+    ) -> Self {
+        let bits_per_component = match header.bits_per_component {
+            1 => ColorBits::Bit1,
+            2 => ColorBits::Bit2,
+            3..=4 => ColorBits::Bit4,
+            5..=8 => ColorBits::Bit8,
+            _ => ColorBits::Bit16,
+        };
+
+        Self {
+            width: Px(header.width),
+            height: Px(header.height),
+            color_space,
+            bits_per_component,
+            interpolate: true,
+            image_data: data,
+            image_filter: Some(filter),
+            decode_parms: None,
+            compression: ImageCompression::None,
+            clipping_bbox: None,
+            soft_mask: None,
+            icc_profile: header.icc_profile.map(|icc| IccProfile::new(icc, icc_type)),
+            decode,
+            special_color_space: None,
+            masking: None,
+        }
+    }
+
+    /// Decodes an image. Images with an alpha channel are split into
+    /// the color image and a soft mask, see `from_dynamic_image`
+    #[cfg(feature = "image")]
+    pub fn try_from<T: ImageDecoder>(image: T) -> Result<Self, ImageError> {
+        DynamicImage::from_decoder(image).map(Self::from_dynamic_image)
+    }
+
+    /// Creates an image from a `DynamicImage`. RGBA and grey + alpha images are split
+    /// into a DeviceRGB / DeviceGray image and an `SMask` containing the alpha channel
+    /// (the mask is left out if the image is fully opaque). 16-bit images stay 16-bit,
+    /// floating point images are converted to 16-bit.
     #[cfg(feature = "image")]
     pub fn from_dynamic_image(image: DynamicImage) -> Self {
+        let image = match image {
+            DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(image.to_rgb16()),
+            DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba16(image.to_rgba16()),
+            other => other,
+        };
+
         let dim = image.dimensions();
         let color_type = image.color();
-        let data = image.into_bytes();
+        let mut data = image.into_bytes();
         let color_bits = ColorBits::from(color_type);
         let color_space = ColorSpace::from(color_type);
 
+        // the image crate stores 16-bit samples in native byte order, PDF needs big endian
+        if let ColorBits::Bit16 = color_bits {
+            for sample in data.chunks_exact_mut(2) {
+                let value = u16::from_ne_bytes([sample[0], sample[1]]);
+                sample.copy_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        let (color_space, image_data, alpha) = match color_space {
+            ColorSpace::Rgba => {
+                let (color, alpha) = split_alpha_channel(&data, 3, color_bits);
+                (ColorSpace::Rgb, color, Some(alpha))
+            }
+            ColorSpace::GreyscaleAlpha => {
+                let (color, alpha) = split_alpha_channel(&data, 1, color_bits);
+                (ColorSpace::Greyscale, color, Some(alpha))
+            }
+            other => (other, data, None),
+        };
+
+        let soft_mask = alpha
+            .filter(|alpha| alpha.iter().any(|a| *a != 0xff))
+            .map(|alpha| SMask {
+                width: Px(dim.0 as usize),
+                height: Px(dim.1 as usize),
+                interpolate: true,
+                bits_per_component: color_bits,
+                matte: Vec::new(),
+                image_data: alpha,
+                image_filter: None,
+                decode_parms: None,
+            });
+
         Self {
             width: Px(dim.0 as usize),
             height: Px(dim.1 as usize),
-            color_space: color_space,
+            color_space,
             bits_per_component: color_bits,
-            image_data: data,
+            image_data,
             interpolate: true,
             image_filter: None,
+            decode_parms: None,
+            compression: ImageCompression::default(),
             clipping_bbox: None,
-            soft_mask: None,
+            soft_mask,
+            icc_profile: None,
+            decode: None,
+            special_color_space: None,
+            masking: None,
         }
     }
-}
 
-impl Into<lopdf::Stream> for ImageXObject {
-    fn into(self) -> lopdf::Stream {
+    /// Premultiplies the color components with the alpha values of the soft mask,
+    /// blended against the `matte` color (one value between 0.0 and 1.0 per color component),
+    /// and sets the `/Matte` entry of the soft mask, so that viewers can undo the
+    /// premultiplication. Premultiplied images look better when they are resampled.
+    ///
+    /// Does nothing if the image has no uncompressed soft mask with the same size and
+    /// bit depth as the image, or if the image is compressed
+    pub fn with_premultiplied_alpha(mut self, matte: Vec<f64>) -> Self {
+        let soft_mask = match self.soft_mask {
+            Some(ref mut soft_mask) => soft_mask,
+            None => return self,
+        };
+
+        let bytes_per_sample = match (self.bits_per_component, soft_mask.bits_per_component) {
+            (ColorBits::Bit8, ColorBits::Bit8) => 1,
+            (ColorBits::Bit16, ColorBits::Bit16) => 2,
+            _ => return self,
+        };
+
+        let components = matte.len();
+        let pixels = self.width.0 * self.height.0;
+        let is_valid = self.image_filter.is_none()
+            && soft_mask.image_filter.is_none()
+            && soft_mask.width.0 == self.width.0
+            && soft_mask.height.0 == self.height.0
+            && self.image_data.len() == pixels * components * bytes_per_sample
+            && soft_mask.image_data.len() == pixels * bytes_per_sample;
+
+        if !is_valid {
+            return self;
+        }
+
+        let max = if bytes_per_sample == 1 { 255.0 } else { 65535.0 };
+        let read = |bytes: &[u8]| match bytes_per_sample {
+            1 => f64::from(bytes[0]),
+            _ => f64::from(u16::from_be_bytes([bytes[0], bytes[1]])),
+        };
+
+        let alpha_samples = soft_mask.image_data.chunks_exact(bytes_per_sample);
+        let pixel_samples = self.image_data.chunks_exact_mut(components * bytes_per_sample);
+
+        for (pixel, alpha) in pixel_samples.zip(alpha_samples) {
+            let alpha = read(alpha) / max;
+            for (sample, m) in pixel.chunks_exact_mut(bytes_per_sample).zip(matte.iter()) {
+                let m = m.clamp(0.0, 1.0) * max;
+                // c' = m + alpha * (c - m), see "Soft-Mask Images" in the PDF reference
+                let value = (m + alpha * (read(sample) - m)).round();
+                match bytes_per_sample {
+                    1 => sample[0] = value as u8,
+                    _ => sample.copy_from_slice(&(value as u16).to_be_bytes()),
+                }
+            }
+        }
+
+        soft_mask.matte = matte;
+        self
+    }
+
+    // number of color components of a pixel, without the soft mask
+    fn num_color_components(&self) -> usize {
+        match &self.special_color_space {
+            Some(color_space) => color_space.num_components(),
+            None => self.color_space.num_components(),
+        }
+    }
+
+    /// Writes the image as an inline image (`BI` ... `ID` ... `EI`) with abbreviated keys and
+    /// filter names. The data is ASCII85 encoded, so that the content stream stays text.
+    /// Inline images can't reference resources, so images with soft masks, mask images,
+    /// color-key masking, ICC profiles or special color spaces other than `Indexed` are rejected,
+    /// as well as images whose encoded data is larger than `INLINE_IMAGE_MAX_SIZE`
+    pub(crate) fn to_inline_image(&self) -> Result<Operation, Error> {
         use lopdf::Object::*;
-        use std::iter::FromIterator;
 
-        let cs: &'static str = self.color_space.into();
-        let bbox: lopdf::Object = self.clipping_bbox.unwrap_or(CurTransMat::Identity).into();
+        let colors = self.num_color_components();
+        check_filter(self.image_filter, colors, self.bits_per_component)?;
 
-        let mut dict = lopdf::Dictionary::from_iter(vec![
-            ("Type", Name("XObject".as_bytes().to_vec())),
-            ("Subtype", Name("Image".as_bytes().to_vec())),
-            ("Width", Integer(self.width.0 as i64)),
-            ("Height", Integer(self.height.0 as i64)),
-            ("Interpolate", self.interpolate.into()),
-            ("BitsPerComponent", Integer(self.bits_per_component.into())),
-            ("ColorSpace", Name(cs.as_bytes().to_vec())),
-            ("BBox", bbox),
-        ]);
+        let is_stencil = matches!(self.masking, Some(ImageMasking::Stencil));
+        if self.soft_mask.is_some() || self.icc_profile.is_some() || (self.masking.is_some() && !is_stencil) {
+            return Err(PdfError::InvalidInlineImage.into());
+        }
 
-        if let Some(filter) = self.image_filter {
-            let params = match filter {
-                // TODO technically we could use multiple filters,
-                // DCT as an exception!
-                ImageFilter::DCT => {
-                    vec![
-                        ("Filter", Array(vec![Name("DCTDecode".as_bytes().to_vec())])),
-                        // not necessary, unless missing in the jpeg header
-                        (
-                            "DecodeParams",
-                            Dictionary(lopdf::dictionary!("ColorTransform" => Integer(0))),
-                        ),
-                    ]
-                }
-                _ => unimplemented!("Encountered filter type is not supported"),
-            };
+        // JBIG2 is not allowed for inline images
+        let compression = match self.compression {
+            ImageCompression::Jbig2 => ImageCompression::default(),
+            other => other,
+        };
+
+        let (image_data, image_filter, decode_parms) = encode_samples(
+            &self.image_data,
+            self.image_filter,
+            self.decode_parms,
+            compression,
+            colors,
+            self.bits_per_component,
+            self.width.0,
+        );
+
+        let image_data = ascii85_encode(&image_data).into_bytes();
+        if image_data.len() > INLINE_IMAGE_MAX_SIZE {
+            return Err(PdfError::InvalidInlineImage.into());
+        }
 
-            params
-                .into_iter()
-                .for_each(|param| dict.set(param.0, param.1));
+        let mut dict = vec![
+            Name(b"W".to_vec()),
+            Integer(self.width.0 as i64),
+            Name(b"H".to_vec()),
+            Integer(self.height.0 as i64),
+            Name(b"BPC".to_vec()),
+            Integer(self.bits_per_component.into()),
+        ];
+
+        if is_stencil {
+            if colors != 1 || self.bits_per_component != ColorBits::Bit1 {
+                return Err(PdfError::InvalidImage.into());
+            }
+            dict.extend(vec![Name(b"IM".to_vec()), Boolean(true)]);
+        } else {
+            dict.extend(vec![Name(b"CS".to_vec()), self.inline_color_space()?]);
         }
 
-        lopdf::Stream::new(dict, self.image_data)
+        if let Some(decode) = &self.decode {
+            dict.extend(vec![Name(b"D".to_vec()), Array(decode.iter().map(|d| Real(*d)).collect())]);
+        }
+
+        if self.interpolate {
+            dict.extend(vec![Name(b"I".to_vec()), Boolean(true)]);
+        }
+
+        // the ASCII85 encoding is decoded first
+        let mut filters = vec![Name(b"A85".to_vec())];
+        if let Some(filter) = image_filter {
+            let name = filter.abbreviated_name().ok_or(PdfError::InvalidInlineImage)?;
+            filters.push(Name(name.as_bytes().to_vec()));
+        }
+        dict.extend(vec![Name(b"F".to_vec()), Array(filters)]);
+
+        if let Some(decode_parms) = decode_parms.map(lopdf::Dictionary::from).filter(|d| !d.is_empty()) {
+            dict.extend(vec![Name(b"DP".to_vec()), Array(vec![Null, Dictionary(decode_parms)])]);
+        }
+
+        dict.push(String(image_data, lopdf::StringFormat::Literal));
+        Ok(Operation::new(OP_INLINE_IMAGE, dict))
+    }
+
+    // abbreviated color space of an inline image, only device and indexed color spaces are possible
+    fn inline_color_space(&self) -> Result<lopdf::Object, Error> {
+        use lopdf::Object::*;
+
+        let device_name = |color_space: ColorSpace| match color_space {
+            ColorSpace::Greyscale => Ok(Name(b"G".to_vec())),
+            ColorSpace::Rgb => Ok(Name(b"RGB".to_vec())),
+            ColorSpace::Cmyk => Ok(Name(b"CMYK".to_vec())),
+            _ => Err(Error::from(PdfError::InvalidInlineImage)),
+        };
+
+        match &self.special_color_space {
+            None => device_name(self.color_space),
+            Some(ImageColorSpace::Device(color_space)) => device_name(*color_space),
+            Some(ImageColorSpace::Indexed { base, hival, lookup }) => match **base {
+                ImageColorSpace::Device(base) => Ok(Array(vec![
+                    Name(b"I".to_vec()),
+                    device_name(base)?,
+                    Integer(i64::from(*hival)),
+                    String(lookup.clone(), lopdf::StringFormat::Hexadecimal),
+                ])),
+                _ => Err(PdfError::InvalidInlineImage.into()),
+            },
+            Some(_) => Err(PdfError::InvalidInlineImage.into()),
+        }
+    }
+}
+
+// splits interleaved samples (with big endian 16-bit samples) into the color and alpha samples
+fn split_alpha_channel(data: &[u8], color_components: usize, bits: ColorBits) -> (Vec<u8>, Vec<u8>) {
+    let bytes_per_sample = match bits {
+        ColorBits::Bit16 => 2,
+        _ => 1,
+    };
+
+    let color_bytes = color_components * bytes_per_sample;
+    let pixel_bytes = color_bytes + bytes_per_sample;
+    let pixels = data.len() / pixel_bytes;
+
+    let mut color = Vec::with_capacity(pixels * color_bytes);
+    let mut alpha = Vec::with_capacity(pixels * bytes_per_sample);
+
+    for pixel in data.chunks_exact(pixel_bytes) {
+        color.extend_from_slice(&pixel[..color_bytes]);
+        alpha.extend_from_slice(&pixel[color_bytes..]);
     }
+
+    (color, alpha)
 }
 
 impl Embeddable for ImageXObject {
     const KEY: &'static str = "XObject";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         // TODO: All of this can be precalculated.
         use lopdf::Object::*;
         use std::iter::FromIterator;
 
+        let colors = self.num_color_components();
+        check_filter(self.image_filter, colors, self.bits_per_component)?;
+
         let cs: &'static str = self.color_space.into();
         let bbox: lopdf::Object = self.clipping_bbox.unwrap_or(CurTransMat::Identity).into();
 
@@ -233,65 +735,178 @@ impl Embeddable for ImageXObject {
             ("BBox", bbox),
         ]);
 
-        if let Some(soft_mask) = &self.soft_mask {
-            let soft_mask = crate::types::pdf_resources::embed(doc, soft_mask)?;
-            dict.set("SMask", soft_mask.object_id);
+        match (&self.special_color_space, &self.icc_profile) {
+            (Some(ImageColorSpace::Indexed { base, hival, lookup }), Some(icc_profile))
+                if matches!(**base, ImageColorSpace::Device(_)) =>
+            {
+                let icc_profile = crate::types::pdf_resources::embed(doc, icc_profile.clone())?;
+                let indexed = ImageColorSpace::Indexed {
+                    base: Box::new(ImageColorSpace::icc_based(&icc_profile)),
+                    hival: *hival,
+                    lookup: lookup.clone(),
+                };
+                dict.set("ColorSpace", indexed.to_object(doc)?);
+            }
+            (Some(special_color_space), _) => {
+                dict.set("ColorSpace", special_color_space.to_object(doc)?);
+            }
+            (None, Some(icc_profile)) => {
+                let icc_profile_id = icc_profile.embed(doc)?;
+                dict.set("ColorSpace", Array(vec![Name("ICCBased".into()), Reference(icc_profile_id)]));
+            }
+            (None, None) => {}
         }
 
-        if let Some(filter) = self.image_filter {
-            let params = match filter {
-                // TODO technically we could use multiple filters,
-                // DCT as an exception!
-                ImageFilter::DCT => {
-                    vec![
-                        ("Filter", Array(vec![Name("DCTDecode".as_bytes().to_vec())])),
-                        // not necessary, unless missing in the jpeg header
-                        (
-                            "DecodeParams",
-                            Dictionary(lopdf::dictionary!("ColorTransform" => Integer(0))),
-                        ),
-                    ]
-                }
-                ImageFilter::Lzw => {
-                    vec![
-                        ("Filter", Array(vec![Name("LZWDecode".as_bytes().to_vec())])),
-                        // not necessary, unless missing in the jpeg header
-                        // (
-                        //     "DecodeParams",
-                        //     Dictionary(lopdf::dictionary!("EarlyChange" => Integer(0))),
-                        // ),
-                    ]
+        if let Some(decode) = &self.decode {
+            dict.set("Decode", Array(decode.iter().map(|d| Real(*d)).collect()));
+        }
+
+        match &self.masking {
+            Some(ImageMasking::Stencil) => {
+                if colors != 1 || self.bits_per_component != ColorBits::Bit1 {
+                    return Err(PdfError::InvalidImage.into());
                 }
-                ImageFilter::Flate => {
-                    vec![
-                        (
-                            "Filter",
-                            Array(vec![Name("FlateDecode".as_bytes().to_vec())]),
-                        ),
-                        // not necessary, unless missing in the jpeg header
-                        // (
-                        //     "DecodeParams",
-                        //     Dictionary(lopdf::dictionary!("EarlyChange" => Integer(0))),
-                        // ),
-                    ]
+                // stencil masks have no color space, they are painted in the fill color
+                dict.remove(b"ColorSpace");
+                dict.set("ImageMask", true);
+            }
+            Some(ImageMasking::ColorKey(ranges)) => {
+                let bits: i64 = self.bits_per_component.into();
+                let max = (1u32 << bits) - 1;
+                let is_valid = ranges.len() == 2 * colors
+                    && ranges.chunks_exact(2).all(|range| range[0] <= range[1] && range[1] <= max);
+                if !is_valid {
+                    return Err(PdfError::InvalidImage.into());
                 }
-                _ => unimplemented!("Encountered filter type is not supported"),
+                dict.set("Mask", Array(ranges.iter().map(|v| Integer(i64::from(*v))).collect()));
+            }
+            Some(ImageMasking::Explicit(mask)) => {
+                let mask = ImageXObject {
+                    masking: Some(ImageMasking::Stencil),
+                    soft_mask: None,
+                    ..(**mask).clone()
+                };
+                dict.set("Mask", mask.embed(doc)?);
+            }
+            None => {}
+        }
+
+        // a stencil mask is only a shape, it can't have a soft mask
+        if let (Some(soft_mask), false) = (&self.soft_mask, matches!(self.masking, Some(ImageMasking::Stencil))) {
+            // the soft mask is compressed like the image
+            let (image_data, image_filter, decode_parms) = encode_samples(
+                &soft_mask.image_data,
+                soft_mask.image_filter,
+                soft_mask.decode_parms,
+                self.compression,
+                1,
+                soft_mask.bits_per_component,
+                soft_mask.width.0,
+            );
+
+            let soft_mask = SMask {
+                width: soft_mask.width,
+                height: soft_mask.height,
+                interpolate: soft_mask.interpolate,
+                bits_per_component: soft_mask.bits_per_component,
+                matte: soft_mask.matte.clone(),
+                image_data,
+                image_filter,
+                decode_parms,
             };
 
-            params
-                .into_iter()
-                .for_each(|param| dict.set(param.0, param.1));
+            let soft_mask = crate::types::pdf_resources::embed(doc, soft_mask)?;
+            dict.set("SMask", soft_mask.object_id);
         }
 
-        let stream = lopdf::Stream::new(dict, self.image_data.clone());
+        let (image_data, image_filter, decode_parms) = encode_samples(
+            &self.image_data,
+            self.image_filter,
+            self.decode_parms,
+            self.compression,
+            colors,
+            self.bits_per_component,
+            self.width.0,
+        );
+
+        if let Some(filter) = image_filter {
+            dict.set("Filter", Name(filter.name().as_bytes().to_vec()));
+        }
+
+        if let Some(decode_parms) = decode_parms.map(lopdf::Dictionary::from).filter(|d| !d.is_empty()) {
+            dict.set("DecodeParms", Dictionary(decode_parms));
+        }
+
+        let stream = lopdf::Stream::new(dict, image_data);
         Ok(doc.add_object(stream))
     }
 }
 
+// the bilevel filters can only be used for 1-bit images with one color component
+fn check_filter(filter: Option<ImageFilter>, colors: usize, bits_per_component: ColorBits) -> Result<(), Error> {
+    match filter {
+        Some(ImageFilter::CCITTFax) | Some(ImageFilter::JBIG2)
+            if colors != 1 || bits_per_component != ColorBits::Bit1 =>
+        {
+            Err(PdfError::UnsupportedFilter.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+// compresses raw samples (if `filter` is `None`), returns the data, filter and decode parameters
+fn encode_samples(
+    data: &[u8],
+    filter: Option<ImageFilter>,
+    decode_parms: Option<ImageDecodeParms>,
+    compression: ImageCompression,
+    colors: usize,
+    bits_per_component: ColorBits,
+    columns: usize,
+) -> (Vec<u8>, Option<ImageFilter>, Option<ImageDecodeParms>) {
+    let is_bilevel = colors == 1 && bits_per_component == ColorBits::Bit1;
+    let rows = match columns {
+        0 => 0,
+        _ => data.len() / columns.div_ceil(8),
+    };
+
+    match (filter, compression) {
+        (Some(_), _) | (None, ImageCompression::None) => (data.to_vec(), filter, decode_parms),
+        (None, ImageCompression::CcittG4) if is_bilevel => (
+            encode_g4(data, columns, rows),
+            Some(ImageFilter::CCITTFax),
+            Some(ImageDecodeParms::CcittFax(CcittFaxDecodeParms::group_4(columns, rows))),
+        ),
+        (None, ImageCompression::Jbig2) if is_bilevel => (
+            encode_generic_region(data, columns, rows),
+            Some(ImageFilter::JBIG2),
+            Some(ImageDecodeParms::Jbig2 { globals: None }),
+        ),
+        (None, compression) => {
+            // images that are not bilevel fall back to the default compression
+            let (level, predictor) = match compression {
+                ImageCompression::Flate { level, predictor } => (level, predictor),
+                _ => (6, PngPredictor::Optimum),
+            };
+            let parms = FlateDecodeParms {
+                predictor,
+                colors,
+                bits_per_component,
+                columns,
+            };
+            (
+                compress_image(data, parms, level),
+                Some(ImageFilter::Flate),
+                Some(ImageDecodeParms::Flate(parms)),
+            )
+        }
+    }
+}
+
 /// Describes the format the image bytes are compressed with.
 #[derive(Debug, Copy, Clone)]
 pub enum ImageFilter {
-    /// ???
+    /// ASCII base-85 encoding
     Ascii85,
     /// Lempel Ziv Welch compression, i.e. zip
     Lzw,
@@ -301,6 +916,81 @@ pub enum ImageFilter {
     JPX,
     /// DEFLATE
     Flate,
+    /// CCITT Group 3 or Group 4 fax compression of 1-bit images
+    CCITTFax,
+    /// JBIG2 compression of 1-bit images (PDF 1.4)
+    JBIG2,
+}
+
+impl ImageFilter {
+    /// Name of the decode filter, as written into the `/Filter` entry
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageFilter::Ascii85 => "ASCII85Decode",
+            ImageFilter::Lzw => "LZWDecode",
+            ImageFilter::DCT => "DCTDecode",
+            ImageFilter::JPX => "JPXDecode",
+            ImageFilter::Flate => "FlateDecode",
+            ImageFilter::CCITTFax => "CCITTFaxDecode",
+            ImageFilter::JBIG2 => "JBIG2Decode",
+        }
+    }
+
+    /// Abbreviated name of the filter in inline images, `None` if the
+    /// filter can't be used by inline images
+    pub fn abbreviated_name(self) -> Option<&'static str> {
+        match self {
+            ImageFilter::Ascii85 => Some("A85"),
+            ImageFilter::Lzw => Some("LZW"),
+            ImageFilter::DCT => Some("DCT"),
+            ImageFilter::Flate => Some("Fl"),
+            ImageFilter::CCITTFax => Some("CCF"),
+            ImageFilter::JPX | ImageFilter::JBIG2 => None,
+        }
+    }
+}
+
+/// Parts of an image that are not painted
+#[derive(Debug, Clone)]
+pub enum ImageMasking {
+    /// The image is a 1-bit stencil mask (`/ImageMask true`) that is painted in the current fill color
+    Stencil,
+    /// Color-key masking (`/Mask [min max ...]`): pixels whose components are all within the ranges
+    /// are not painted. One range (minimum and maximum sample value) per color component
+    ColorKey(Vec<u32>),
+    /// Explicit masking with a 1-bit mask image (`/Mask` stream), which is embedded as a stencil mask.
+    /// The mask can have a different size than the image, it is stretched over the same area
+    Explicit(Box<ImageXObject>),
+}
+
+/// `/DecodeParms` of an image stream, depending on the filter
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageDecodeParms {
+    /// `FlateDecode` compressed image data that uses a PNG predictor
+    Flate(FlateDecodeParms),
+    /// `CCITTFaxDecode` compressed image data
+    CcittFax(CcittFaxDecodeParms),
+    /// `JBIG2Decode` compressed image data
+    Jbig2 {
+        /// Embedded `Jbig2Globals` stream, if the image uses global segments
+        globals: Option<lopdf::ObjectId>,
+    },
+}
+
+impl From<ImageDecodeParms> for lopdf::Dictionary {
+    fn from(parms: ImageDecodeParms) -> Self {
+        match parms {
+            ImageDecodeParms::Flate(parms) => parms.into(),
+            ImageDecodeParms::CcittFax(parms) => parms.into(),
+            ImageDecodeParms::Jbig2 { globals } => {
+                let mut dict = lopdf::Dictionary::new();
+                if let Some(globals) = globals {
+                    dict.set("JBIG2Globals", globals);
+                }
+                dict
+            }
+        }
+    }
 }
 
 /// __THIS IS NOT A PDF FORM!__ A form `XObject` can be nearly everything.
@@ -511,22 +1201,27 @@ pub struct SMask {
     pub interpolate: bool,
     /// Bits per component, required (warning: this is a grayscale image)
     pub bits_per_component: ColorBits,
-    /// Vec of component values
-    pub matte: Vec<i64>,
+    /// Matte color of a premultiplied parent image, one value per color component
+    /// of the parent image (`/Matte`). Empty if the parent image is not premultiplied
+    pub matte: Vec<f64>,
     /// The actual data from the image
     pub image_data: Vec<u8>,
     /// Decompression filter for `image_data`, if `None` assumes uncompressed raw pixels in the expected color format.
     pub image_filter: Option<ImageFilter>,
+    /// `/DecodeParms` of the `image_filter`
+    pub decode_parms: Option<ImageDecodeParms>,
 }
 
 impl Embeddable for SMask {
     const KEY: &'static str = "NEVER";
 
-    fn embed(&self, doc: &mut lopdf::Document) -> lopdf::Result<lopdf::ObjectId> {
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
         // TODO: All of this can be precalculated.
         use lopdf::Object::*;
         use std::iter::FromIterator;
 
+        check_filter(self.image_filter, 1, self.bits_per_component)?;
+
         let cs: &'static str = ColorSpace::Greyscale.into();
 
         let mut dict = lopdf::Dictionary::from_iter(vec![
@@ -539,49 +1234,16 @@ impl Embeddable for SMask {
             ("ColorSpace", Name(cs.as_bytes().to_vec())),
         ]);
 
+        if !self.matte.is_empty() {
+            dict.set("Matte", Array(self.matte.iter().map(|m| Real(*m)).collect()));
+        }
+
         if let Some(filter) = self.image_filter {
-            let params = match filter {
-                // TODO technically we could use multiple filters,
-                // DCT as an exception!
-                ImageFilter::DCT => {
-                    vec![
-                        ("Filter", Array(vec![Name("DCTDecode".as_bytes().to_vec())])),
-                        // not necessary, unless missing in the jpeg header
-                        (
-                            "DecodeParams",
-                            Dictionary(lopdf::dictionary!("ColorTransform" => Integer(0))),
-                        ),
-                    ]
-                }
-                ImageFilter::Lzw => {
-                    vec![
-                        ("Filter", Array(vec![Name("LZWDecode".as_bytes().to_vec())])),
-                        // not necessary, unless missing in the jpeg header
-                        // (
-                        //     "DecodeParams",
-                        //     Dictionary(lopdf::dictionary!("EarlyChange" => Integer(0))),
-                        // ),
-                    ]
-                }
-                ImageFilter::Flate => {
-                    vec![
-                        (
-                            "Filter",
-                            Array(vec![Name("FlateDecode".as_bytes().to_vec())]),
-                        ),
-                        // not necessary, unless missing in the jpeg header
-                        // (
-                        //     "DecodeParams",
-                        //     Dictionary(lopdf::dictionary!("EarlyChange" => Integer(0))),
-                        // ),
-                    ]
-                }
-                _ => unimplemented!("Encountered filter type is not supported"),
-            };
+            dict.set("Filter", Name(filter.name().as_bytes().to_vec()));
+        }
 
-            params
-                .into_iter()
-                .for_each(|param| dict.set(param.0, param.1));
+        if let Some(decode_parms) = self.decode_parms.map(lopdf::Dictionary::from).filter(|d| !d.is_empty()) {
+            dict.set("DecodeParms", Dictionary(decode_parms));
         }
 
         let stream = lopdf::Stream::new(dict, self.image_data.clone());
@@ -674,3 +1336,203 @@ impl Into<lopdf::Stream> for PostScriptXObject {
         lopdf::Stream::new(lopdf::Dictionary::new(), Vec::new())
     }
 }
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use types::pdf_layer::encode_content;
+
+    #[test]
+    fn test_rgba_image_soft_mask() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 255, 128]));
+
+        let xobject = ImageXObject::from_dynamic_image(DynamicImage::ImageRgba8(image));
+        assert_eq!(xobject.image_data, vec![255, 0, 0, 0, 0, 255]);
+        let soft_mask = xobject.soft_mask.as_ref().unwrap();
+        assert_eq!(soft_mask.image_data, vec![255, 128]);
+
+        let premultiplied = xobject.with_premultiplied_alpha(vec![1.0, 1.0, 1.0]);
+        assert_eq!(premultiplied.image_data, vec![255, 0, 0, 127, 127, 255]);
+
+        let opaque = ImageXObject::from_dynamic_image(DynamicImage::ImageRgba16(
+            image::ImageBuffer::from_pixel(1, 1, Rgba([0x0102, 0, 0, 0xffff])),
+        ));
+        assert!(opaque.soft_mask.is_none());
+        assert_eq!(opaque.image_data, vec![1, 2, 0, 0, 0, 0]);
+    }
+
+    fn png_file(chunks: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        for (chunk_type, contents) in chunks {
+            let mut crc = flate2::Crc::new();
+            crc.update(chunk_type);
+            crc.update(contents);
+            png.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+            png.extend_from_slice(chunk_type);
+            png.extend_from_slice(contents);
+            png.extend_from_slice(&crc.sum().to_be_bytes());
+        }
+        png
+    }
+
+    #[test]
+    fn test_png_palette_image() {
+        use lopdf::Object;
+
+        // 2x1 pixels, 8-bit palette image
+        let ihdr = vec![0, 0, 0, 2, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+        let palette = vec![255, 0, 0, 0, 0, 255];
+        let idat = super::super::flate::deflate(&[0, 1, 0], 6);
+        let png = png_file(&[
+            (b"IHDR", ihdr.clone()),
+            (b"PLTE", palette.clone()),
+            (b"IDAT", idat.clone()),
+            (b"IEND", Vec::new()),
+        ]);
+
+        let image = ImageXObject::from_png(png).unwrap();
+        assert_eq!(image.image_data, idat);
+        assert_eq!(
+            image.special_color_space,
+            Some(ImageColorSpace::Indexed {
+                base: Box::new(ImageColorSpace::Device(ColorSpace::Rgb)),
+                hival: 1,
+                lookup: palette.clone(),
+            })
+        );
+
+        let mut doc = lopdf::Document::with_version("1.5");
+        let id = image.embed(&mut doc).unwrap();
+        let stream = doc.get_object(id).and_then(Object::as_stream).unwrap();
+        let color_space = stream.dict.get(b"ColorSpace").and_then(Object::as_array).unwrap();
+        assert_eq!(color_space[0].as_name().unwrap(), b"Indexed");
+        let decode_parms = stream.dict.get(b"DecodeParms").and_then(Object::as_dict).unwrap();
+        assert_eq!(decode_parms.get(b"Colors").and_then(Object::as_i64).unwrap(), 1);
+
+        // transparent palette entries need a soft mask, the image is decoded
+        let transparent = png_file(&[
+            (b"IHDR", ihdr),
+            (b"PLTE", palette),
+            (b"tRNS", vec![0]),
+            (b"IDAT", idat),
+            (b"IEND", Vec::new()),
+        ]);
+        let image = ImageXObject::from_png(transparent).unwrap();
+        assert!(image.special_color_space.is_none());
+    }
+
+    #[test]
+    fn test_png_transparent_color() {
+        use lopdf::Object;
+
+        // 2x1 pixels, 8-bit greyscale and RGB images with a transparent color
+        let idat = super::super::flate::deflate(&[0, 0, 255], 6);
+        let grey = png_file(&[
+            (b"IHDR", vec![0, 0, 0, 2, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            (b"tRNS", vec![0, 255]),
+            (b"IDAT", idat.clone()),
+            (b"IEND", Vec::new()),
+        ]);
+        let image = ImageXObject::from_png(grey).unwrap();
+        assert_eq!(image.image_data, idat);
+        match image.masking {
+            Some(ImageMasking::ColorKey(ref ranges)) => assert_eq!(ranges, &vec![255, 255]),
+            _ => panic!("expected a color key mask"),
+        }
+
+        let mut doc = lopdf::Document::with_version("1.5");
+        let id = image.embed(&mut doc).unwrap();
+        let stream = doc.get_object(id).and_then(Object::as_stream).unwrap();
+        assert_eq!(stream.dict.get(b"Mask").and_then(Object::as_array).unwrap().len(), 2);
+
+        let rgb = |transparency: Vec<u8>| {
+            png_file(&[
+                (b"IHDR", vec![0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0]),
+                (b"tRNS", transparency),
+                (b"IDAT", super::super::flate::deflate(&[0, 0, 0, 0, 1, 2, 3], 6)),
+                (b"IEND", Vec::new()),
+            ])
+        };
+        match ImageXObject::from_png(rgb(vec![0, 1, 0, 2, 0, 3])).unwrap().masking {
+            Some(ImageMasking::ColorKey(ref ranges)) => assert_eq!(ranges, &vec![1, 1, 2, 2, 3, 3]),
+            _ => panic!("expected a color key mask"),
+        }
+
+        // the transparent color has to fit the bit depth
+        assert!(ImageXObject::from_png(rgb(vec![1, 0, 0, 0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_image_masking() {
+        use lopdf::Object;
+
+        let mut doc = lopdf::Document::with_version("1.5");
+        let get_dict = |doc: &lopdf::Document, id| {
+            doc.get_object(id).and_then(Object::as_stream).unwrap().dict.clone()
+        };
+
+        let stencil = ImageXObject::stencil_mask(Px(8), Px(2), vec![0x0f, 0xf0]).with_decode(vec![1.0, 0.0]);
+        let id = stencil.embed(&mut doc).unwrap();
+        let dict = get_dict(&doc, id);
+        assert!(matches!(dict.get(b"ImageMask").ok(), Some(&Object::Boolean(true))));
+        assert!(dict.get(b"ColorSpace").is_err());
+        assert_eq!(dict.get(b"Decode").and_then(Object::as_array).unwrap().len(), 2);
+
+        let image = ImageXObject::new(Px(1), Px(1), ColorSpace::Rgb, ColorBits::Bit8, false, None, None, vec![0; 3]);
+        let color_key = image.clone().with_masking(ImageMasking::ColorKey(vec![250, 255, 250, 255, 250, 255]));
+        let id = color_key.embed(&mut doc).unwrap();
+        let dict = get_dict(&doc, id);
+        assert_eq!(dict.get(b"Mask").and_then(Object::as_array).unwrap().len(), 6);
+
+        let invalid = image.clone().with_masking(ImageMasking::ColorKey(vec![0, 256, 0, 255, 0, 255]));
+        assert!(invalid.embed(&mut doc).is_err());
+        let invalid = image.clone().with_masking(ImageMasking::Stencil);
+        assert!(invalid.embed(&mut doc).is_err());
+
+        let mask = ImageXObject::new(Px(8), Px(1), ColorSpace::Greyscale, ColorBits::Bit1, false, None, None, vec![0xaa]);
+        let explicit = image.with_masking(ImageMasking::Explicit(Box::new(mask)));
+        let id = explicit.embed(&mut doc).unwrap();
+        let dict = get_dict(&doc, id);
+        let mask_id = dict.get(b"Mask").and_then(Object::as_reference).unwrap();
+        assert!(matches!(get_dict(&doc, mask_id).get(b"ImageMask").ok(), Some(&Object::Boolean(true))));
+    }
+
+    #[test]
+    fn test_inline_image() {
+        let image = ImageXObject::new(Px(2), Px(1), ColorSpace::Greyscale, ColorBits::Bit8, false, None, None, vec![0, 255])
+            .with_compression(ImageCompression::None)
+            .with_decode(vec![1.0, 0.0]);
+        let content = encode_content(vec![
+            Operation::new("q", vec![]),
+            image.to_inline_image().unwrap(),
+            Operation::new("Q", vec![]),
+        ]);
+        let content = ::std::string::String::from_utf8(content.unwrap()).unwrap();
+        assert_eq!(content, "q\nBI\n/W 2 /H 1 /BPC 8 /CS /G /D [1.00 0.00] /F [/A85] ID\n!<3~>\nEI\nQ\n");
+
+        let stencil = ImageXObject::stencil_mask(Px(8), Px(1), vec![0xaa]).with_compression(ImageCompression::CcittG4);
+        let operation = stencil.to_inline_image().unwrap();
+        let dict = &operation.operands;
+        let position = |key: &[u8]| dict.iter().position(|o| o.as_name().ok() == Some(key));
+        assert!(position(b"IM").is_some());
+        assert!(position(b"CS").is_none());
+        let filters = dict[position(b"F").unwrap() + 1].as_array().unwrap();
+        assert_eq!(filters[1].as_name().unwrap(), b"CCF");
+
+        let mut state = 0x1234_5678u32;
+        let noise = (0..10_000)
+            .map(|_| {
+                // xorshift, incompressible data
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let large = ImageXObject::new(Px(100), Px(100), ColorSpace::Greyscale, ColorBits::Bit8, false, None, None, noise);
+        assert!(large.to_inline_image().is_err());
+    }
+}
@@ -0,0 +1,165 @@
+//! Page annotations
+
+use lopdf;
+
+use utils::to_pdf_text_string;
+use {Mm, Pt};
+
+/// Icon that the PDF viewer shows for a file attachment annotation
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FileAttachmentIcon {
+    /// Graph icon
+    Graph,
+    /// Push pin icon (default)
+    #[default]
+    PushPin,
+    /// Paperclip icon
+    Paperclip,
+    /// Tag icon
+    Tag,
+}
+
+impl From<FileAttachmentIcon> for &'static str {
+    fn from(value: FileAttachmentIcon) -> Self {
+        use self::FileAttachmentIcon::*;
+        match value {
+            Graph => "Graph",
+            PushPin => "PushPin",
+            Paperclip => "Paperclip",
+            Tag => "Tag",
+        }
+    }
+}
+
+/// Annotation that shows an embedded file at a position on the page.
+/// Added to a page via `PdfPage::add_file_attachment`
+#[derive(Debug, Clone)]
+pub struct FileAttachmentAnnotation {
+    /// Position of the lower left corner of the annotation, from the left edge of the page
+    pub x: Mm,
+    /// Position of the lower left corner of the annotation, from the bottom edge of the page
+    pub y: Mm,
+    /// Width of the annotation
+    pub width: Mm,
+    /// Height of the annotation
+    pub height: Mm,
+    /// Icon shown by the PDF viewer
+    pub icon: FileAttachmentIcon,
+    /// Text shown when hovering over the annotation
+    pub contents: Option<String>,
+}
+
+impl FileAttachmentAnnotation {
+    /// Creates a new file attachment annotation at the given rectangle
+    pub fn new(x: Mm, y: Mm, width: Mm, height: Mm) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            icon: FileAttachmentIcon::default(),
+            contents: None,
+        }
+    }
+
+    /// Sets the icon of the annotation
+    #[inline]
+    pub fn with_icon(mut self, icon: FileAttachmentIcon) -> Self {
+        self.icon = icon;
+        self
+    }
+
+    /// Sets the text that is shown when hovering over the annotation
+    #[inline]
+    pub fn with_contents<S>(mut self, contents: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.contents = Some(contents.into());
+        self
+    }
+
+    /// Adds the annotation (and its appearance stream) to the document.
+    /// `file_spec` is the file specification of the attached file
+    pub(crate) fn into_obj(
+        self,
+        doc: &mut lopdf::Document,
+        file_spec: lopdf::ObjectId,
+    ) -> lopdf::ObjectId {
+        use lopdf::content::{Content, Operation};
+        use lopdf::Object::*;
+        use lopdf::{Dictionary as LoDictionary, Stream as LoStream};
+        use std::iter::FromIterator;
+
+        let x: Pt = self.x.into();
+        let y: Pt = self.y.into();
+        let width: Pt = self.width.into();
+        let height: Pt = self.height.into();
+
+        // PDF/A requires an appearance stream for every visible annotation,
+        // so draw a simple framed box instead of relying on the viewers icon
+        let appearance = Content {
+            operations: vec![
+                Operation::new("w", vec![Real(0.5)]),
+                Operation::new("G", vec![Real(0.0)]),
+                Operation::new("g", vec![Real(0.9)]),
+                Operation::new(
+                    "re",
+                    vec![
+                        Real(0.25),
+                        Real(0.25),
+                        Real((width.0 - 0.5).max(0.0)),
+                        Real((height.0 - 0.5).max(0.0)),
+                    ],
+                ),
+                Operation::new("B", vec![]),
+            ],
+        };
+
+        let appearance_stream = LoStream::new(
+            LoDictionary::from_iter(vec![
+                ("Type", Name("XObject".into())),
+                ("Subtype", Name("Form".into())),
+                (
+                    "BBox",
+                    Array(vec![Real(0.0), Real(0.0), width.into(), height.into()]),
+                ),
+            ]),
+            appearance.encode().unwrap_or_default(),
+        );
+
+        let appearance_id = doc.add_object(appearance_stream);
+        let icon: &'static str = self.icon.into();
+
+        let mut annotation = LoDictionary::from_iter(vec![
+            ("Type", Name("Annot".into())),
+            ("Subtype", Name("FileAttachment".into())),
+            (
+                "Rect",
+                Array(vec![
+                    x.into(),
+                    y.into(),
+                    Pt(x.0 + width.0).into(),
+                    Pt(y.0 + height.0).into(),
+                ]),
+            ),
+            // print flag, required for PDF/A
+            ("F", Integer(4)),
+            ("FS", Reference(file_spec)),
+            ("Name", Name(icon.into())),
+            (
+                "AP",
+                Dictionary(LoDictionary::from_iter(vec![(
+                    "N",
+                    Reference(appearance_id),
+                )])),
+            ),
+        ]);
+
+        if let Some(contents) = self.contents {
+            annotation.set("Contents", to_pdf_text_string(&contents));
+        }
+
+        doc.add_object(annotation)
+    }
+}
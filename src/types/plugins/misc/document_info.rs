@@ -1,57 +1,51 @@
 //! Info dictionary of a PDF document
 
-use crate::OffsetDateTime;
 use lopdf;
 
-use PdfConformance;
+use date::to_pdf_date;
+
+use utils::to_pdf_text_string;
+use PdfMetadata;
+
+/// Keys of the Info dictionary that are written from the `PdfMetadata` fields,
+/// custom entries with these keys are ignored
+pub(crate) const RESERVED_INFO_KEYS: &[&str] = &[
+    "Title", "Author", "Subject", "Keywords", "Creator", "Producer",
+    "CreationDate", "ModDate", "Trapped", "GTS_PDFXVersion",
+];
+
+/// Returns whether the key is a standard Info dictionary key (see `RESERVED_INFO_KEYS`)
+pub(crate) fn is_reserved_info_key(key: &str) -> bool {
+    RESERVED_INFO_KEYS.contains(&key)
+}
+
+/// Returns whether the key can be used for a custom entry: custom entries are also written
+/// as XMP properties, so the key has to be an XML name (an ASCII letter or `_`, followed by
+/// letters, digits, `_`, `-` or `.`)
+pub(crate) fn is_valid_custom_info_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
 
 /// "Info" dictionary of a PDF document.
-/// Actual data is contained in `DocumentMetadata`, to keep it in sync with the `XmpMetadata`
+/// Actual data is contained in `PdfMetadata`, to keep it in sync with the `XmpMetadata`
 /// (if the timestamps / settings are not in sync, Preflight will complain)
 #[derive(Default, Debug, Copy, Clone)]
 pub struct DocumentInfo {
-    // DocumentInfo is older than XmpMetadata
-    // The following is a list of things available to the DocumentInfo dictionary.
-    // These keys don't have to be set:
-
-    /*
-    /Author ( Craig J. Hogan )
-    /Subject ( doi:10.1038/445037a )
-    /Keywords ( cosmology infrared protogalaxy starlight )
-    /Identifier ( doi:10.1038/445037a )
-    /Creator ( … )
-    /Producer ( … )
-    */
-
-    // The more modern approach is to put them into the XmpMetadata struct:
-    // This struct is merely a wrapper around those types that HAVE to be in a PDF/X-conform
-    // document.
-
-    /*
-    <rdf:Description rdf:about=“” xmlns:dc=“http://purl.org/dc/elements/1.1/">
-            <dc:creator>Craig J. Hogan</dc:creator>
-            <dc:title>Cosmology: Ripples of early starlight</dc:title>
-            <dc:identifier>doi:10.1038/445037a</dc:identifier>
-            <dc:source>Nature 445, 37 (2007)</dc:source>
-            <dc:date>2007-01-04</dc:date>
-            <dc:format>application/pdf</dc:format>
-            <dc:publisher>Nature Publishing Group</dc:publisher>
-            <dc:language>en<dc:language>
-            <dc:rights>© 2007 Nature Publishing Group</dc:rights>
-    </rdf:Description>
-    <rdf:Description rdf:about=“” xmlns:prism=“http://prismstandard.org/namespaces/1.2/basic/">
-        <prism:publicationName>Nature</prism:publicationName>
-        <prism:issn>0028-0836</prism:issn>
-        <prism:eIssn>1476-4679</prism:eIssn>
-        <prism:publicationDate>2007-01-04</prism:publicationDate>
-        <prism:copyright>© 2007 Nature Publishing Group</prism:copyright>
-        <prism:rightsAgent>permissions@nature.com</prism:rightsAgent>
-        <prism:volume>445</prism:volume> <prism:number>7123</prism:number>
-        <prism:startingPage>37</prism:startingPage>
-        <prism:endingPage>37</prism:endingPage>
-        <prism:section>News and Views</prism:section>
-    </rdf:Description>
-    */
+    // DocumentInfo is older than XmpMetadata. The keys written into the
+    // Info dictionary and their XMP equivalents (PDF/A requires them to match):
+    //
+    // /Title          dc:title
+    // /Author         dc:creator
+    // /Subject        dc:description
+    // /Keywords       pdf:Keywords (and dc:subject)
+    // /Creator        xmp:CreatorTool
+    // /Producer       pdf:Producer
+    // /CreationDate   xmp:CreateDate
+    // /ModDate        xmp:ModifyDate
+    // /Trapped        pdf:Trapped
+    // custom keys     pdfx:<key>
 }
 
 impl DocumentInfo {
@@ -64,48 +58,59 @@ impl DocumentInfo {
     }
 
     /// This functions is similar to the IntoPdfObject trait method,
-    /// but takes additional arguments in order to delay the setting
-    pub(in types) fn into_obj<S>(self,
-                                 document_title: S,
-                                 trapping: bool,
-                                 conformance: PdfConformance,
-                                 creation_date: OffsetDateTime,
-                                 modification_date: OffsetDateTime)
-    -> lopdf::Object where S: Into<String>
+    /// but takes the shared metadata as an additional argument
+    pub(in types) fn into_obj(self, metadata: &PdfMetadata)
+    -> lopdf::Object
     {
         use lopdf::Dictionary as LoDictionary;
         use lopdf::Object::*;
         use lopdf::StringFormat::Literal;
         use std::iter::FromIterator;
 
-        let trapping = if trapping { "True" } else { "False" };
-        let gts_pdfx_version = conformance.get_identifier_string();
+        let trapping = if metadata.trapping { "True" } else { "False" };
 
-        let info_mod_date = to_pdf_time_stamp_metadata(modification_date);
-        let info_create_date = to_pdf_time_stamp_metadata(creation_date);
+        let info_mod_date = to_pdf_date(&metadata.modification_date);
+        let info_create_date = to_pdf_date(&metadata.creation_date);
 
-        Dictionary(LoDictionary::from_iter(vec![
+        let mut info = LoDictionary::from_iter(vec![
             ("Trapped", trapping.into()),
             ("CreationDate", String(info_create_date.into_bytes(), Literal)),
             ("ModDate", String(info_mod_date.into_bytes(), Literal)),
-            ("GTS_PDFXVersion", String(gts_pdfx_version.into(), Literal)),
-            ("Title", String(document_title.into().as_bytes().to_vec(), Literal))
-        ]))
-    }
-}
+        ]);
+
+        if metadata.conformance.is_pdf_x() {
+            let gts_pdfx_version = metadata.conformance.get_identifier_string();
+            info.set("GTS_PDFXVersion", String(gts_pdfx_version.into(), Literal));
+        }
 
-// D:20170505150224+02'00'
-fn to_pdf_time_stamp_metadata(date: OffsetDateTime)
--> String
-{
-    // Since the time is in UTC, we know that the time zone
-    // difference to UTC is 0 min, 0 sec, hence the 00'00
-    format!("D:{:04}{:02}{:02}{:02}{:02}{:02}+00'00'",
-        date.year(),
-        date.month(),
-        date.day(),
-        date.hour(),
-        date.minute(),
-        date.second(),
-    )
+        info.set("Title", to_pdf_text_string(&metadata.document_title));
+
+        if let Some(ref author) = metadata.author {
+            info.set("Author", to_pdf_text_string(author));
+        }
+
+        if let Some(ref subject) = metadata.subject {
+            info.set("Subject", to_pdf_text_string(subject));
+        }
+
+        if !metadata.keywords.is_empty() {
+            info.set("Keywords", to_pdf_text_string(&metadata.keywords_string()));
+        }
+
+        if let Some(ref creator) = metadata.creator {
+            info.set("Creator", to_pdf_text_string(creator));
+        }
+
+        if let Some(ref producer) = metadata.producer {
+            info.set("Producer", to_pdf_text_string(producer));
+        }
+
+        let custom_info = metadata.custom_info.iter()
+            .filter(|e| !is_reserved_info_key(&e.0) && is_valid_custom_info_key(&e.0));
+        for (key, value) in custom_info {
+            info.set(key.as_bytes().to_vec(), to_pdf_text_string(value));
+        }
+
+        Dictionary(info)
+    }
 }
@@ -0,0 +1,196 @@
+//! Embedded files (attachments) and PDF/A-3 associated files
+
+use crate::OffsetDateTime;
+use lopdf;
+
+use date::to_pdf_date;
+use utils::{md5, to_pdf_text_string};
+use {Embeddable, Error};
+
+/// A file that is embedded into the PDF, for example the XML invoice of a
+/// ZUGFeRD / Factur-X document or the source spreadsheet of a report.
+///
+/// Embedding the file creates an `/EmbeddedFile` stream and a file specification
+/// dictionary pointing to it. Use `PdfDocument::attach_file` to also list the file in the
+/// `/EmbeddedFiles` name tree and in the associated files (`/AF`) of the document catalog.
+#[derive(Debug, Clone)]
+pub struct EmbeddedFile {
+    /// Name of the file, as shown in the attachment panel of the PDF viewer
+    pub file_name: String,
+    /// Content of the file
+    pub data: Vec<u8>,
+    /// MIME type of the file, such as `"text/xml"`. Written as the `/Subtype` of the
+    /// embedded file stream, which is required for PDF/A-3 (PDF/A-2 only allows `"application/pdf"`)
+    pub mime_type: Option<String>,
+    /// Description of the file
+    pub description: Option<String>,
+    /// Relationship between the file and the document (or the page) it is associated with
+    pub relationship: AFRelationship,
+    /// Creation date of the file
+    pub creation_date: Option<OffsetDateTime>,
+    /// Modification date of the file. Required for PDF/A-3 attachments, the modification
+    /// date of the document is used if it is not set
+    pub modification_date: Option<OffsetDateTime>,
+}
+
+/// Relationship of an associated file to the PDF content, see ISO 19005-3, Annex E
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum AFRelationship {
+    /// Original source material for the associated content
+    Source,
+    /// Information used to derive a visual presentation, such as the data of a table or graph.
+    /// Used by Factur-X for the `BASIC WL` and `MINIMUM` profiles
+    Data,
+    /// Alternative representation of the content, for example the XML invoice of a
+    /// ZUGFeRD / Factur-X document
+    Alternative,
+    /// Supplemental representation of the original source or data that may be more easily consumable
+    Supplement,
+    /// Encrypted payload document that should be displayed to the user if the PDF
+    /// processor has the cryptographic filter needed to decrypt the document
+    EncryptedPayload,
+    /// Data associated with an `AcroForm` of the PDF
+    FormData,
+    /// A schema definition for the associated object
+    Schema,
+    /// The relationship is not known or cannot be described using one of the other values
+    #[default]
+    Unspecified,
+}
+
+impl From<AFRelationship> for &'static str {
+    fn from(value: AFRelationship) -> Self {
+        use self::AFRelationship::*;
+        match value {
+            Source => "Source",
+            Data => "Data",
+            Alternative => "Alternative",
+            Supplement => "Supplement",
+            EncryptedPayload => "EncryptedPayload",
+            FormData => "FormData",
+            Schema => "Schema",
+            Unspecified => "Unspecified",
+        }
+    }
+}
+
+impl EmbeddedFile {
+    /// Creates a new embedded file from a file name and the file contents
+    pub fn new<S>(file_name: S, data: Vec<u8>) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            file_name: file_name.into(),
+            data,
+            mime_type: None,
+            description: None,
+            relationship: AFRelationship::default(),
+            creation_date: None,
+            modification_date: None,
+        }
+    }
+
+    /// Sets the MIME type of the file, for example `"text/xml"` or `"application/pdf"`
+    #[inline]
+    pub fn with_mime_type<S>(mut self, mime_type: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Sets the description of the file
+    #[inline]
+    pub fn with_description<S>(mut self, description: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets the relationship between the file and the document
+    #[inline]
+    pub fn with_relationship(mut self, relationship: AFRelationship) -> Self {
+        self.relationship = relationship;
+        self
+    }
+
+    /// Sets the creation date of the file
+    #[inline]
+    pub fn with_creation_date(mut self, creation_date: OffsetDateTime) -> Self {
+        self.creation_date = Some(creation_date);
+        self
+    }
+
+    /// Sets the modification date of the file
+    #[inline]
+    pub fn with_modification_date(mut self, modification_date: OffsetDateTime) -> Self {
+        self.modification_date = Some(modification_date);
+        self
+    }
+}
+
+impl Embeddable for EmbeddedFile {
+    // file specifications are referenced from the catalog, annotations or
+    // `/AF` arrays, never from a resource dictionary
+    const KEY: &'static str = "NEVER";
+
+    /// Embeds the file stream and returns the ID of the file specification dictionary
+    fn embed(&self, doc: &mut lopdf::Document) -> Result<lopdf::ObjectId, Error> {
+        use lopdf::Object::*;
+        use lopdf::StringFormat::{Hexadecimal, Literal};
+        use lopdf::{Dictionary as LoDictionary, Stream as LoStream};
+        use std::iter::FromIterator;
+
+        let mut params = LoDictionary::from_iter(vec![
+            ("Size", Integer(self.data.len() as i64)),
+            ("CheckSum", String(md5(&self.data).to_vec(), Hexadecimal)),
+        ]);
+
+        if let Some(ref date) = self.creation_date {
+            let date = to_pdf_date(date);
+            params.set("CreationDate", String(date.into_bytes(), Literal));
+        }
+
+        if let Some(ref date) = self.modification_date {
+            let date = to_pdf_date(date);
+            params.set("ModDate", String(date.into_bytes(), Literal));
+        }
+
+        let mut stream_dict = LoDictionary::from_iter(vec![
+            ("Type", Name("EmbeddedFile".into())),
+            ("Params", Dictionary(params)),
+        ]);
+
+        if let Some(ref mime_type) = self.mime_type {
+            stream_dict.set("Subtype", Name(mime_type.as_bytes().to_vec()));
+        }
+
+        let stream_id = doc.add_object(LoStream::new(stream_dict, self.data.clone()));
+
+        let relationship: &'static str = self.relationship.into();
+
+        let mut file_spec = LoDictionary::from_iter(vec![
+            ("Type", Name("Filespec".into())),
+            ("F", String(self.file_name.as_bytes().to_vec(), Literal)),
+            ("UF", to_pdf_text_string(&self.file_name)),
+            ("AFRelationship", Name(relationship.into())),
+            (
+                "EF",
+                Dictionary(LoDictionary::from_iter(vec![
+                    ("F", Reference(stream_id)),
+                    ("UF", Reference(stream_id)),
+                ])),
+            ),
+        ]);
+
+        if let Some(ref description) = self.description {
+            file_spec.set("Desc", to_pdf_text_string(description));
+        }
+
+        Ok(doc.add_object(file_spec))
+    }
+}
@@ -1,2 +1,3 @@
 //! Other PDF objects that should have their own module, but don't belong into any other category
-pub mod document_info;
\ No newline at end of file
+pub mod document_info;
+pub mod embedded_file;
@@ -1 +1,4 @@
 pub mod xmp_metadata;
+pub mod xmp_extension;
+pub mod xmp_packet;
+pub mod xmp_schemas;
@@ -0,0 +1,159 @@
+//! PDF/A extension schemas for custom XMP properties
+//!
+//! PDF/A only allows XMP properties from the predefined schemas, every other property
+//! has to be described by an extension schema in the `pdfaExtension` namespace.
+//! This is used for example by ZUGFeRD / Factur-X invoices, which store the name
+//! and the profile of the attached XML invoice in the XMP metadata.
+
+use super::xmp_packet::{XmpSchema, XmpValue};
+
+/// Custom XMP schema, written into the XMP metadata together with its PDF/A
+/// extension schema description
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmpExtensionSchema {
+    /// Human-readable description of the schema, such as `"Factur-X PDFA Extension Schema"`
+    pub schema: String,
+    /// Namespace URI of the schema
+    pub namespace_uri: String,
+    /// Preferred namespace prefix, such as `"fx"`
+    pub prefix: String,
+    /// Properties of the schema, including their values
+    pub properties: Vec<XmpExtensionProperty>,
+}
+
+/// A property of an `XmpExtensionSchema`, together with its value
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmpExtensionProperty {
+    /// Name of the property (without the namespace prefix)
+    pub name: String,
+    /// XMP value type, usually `"Text"`
+    pub value_type: String,
+    /// Either `"external"` (the value is visible to the user) or `"internal"`
+    pub category: String,
+    /// Human-readable description of the property
+    pub description: String,
+    /// Value of the property
+    pub value: String,
+}
+
+impl XmpExtensionProperty {
+    /// Creates a new external text property
+    pub fn new<S, T, U>(name: S, description: T, value: U) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+        U: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            value_type: "Text".into(),
+            category: "external".into(),
+            description: description.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl XmpExtensionSchema {
+    /// Creates a new, empty extension schema
+    pub fn new<S, T, U>(schema: S, namespace_uri: T, prefix: U) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+        U: Into<String>,
+    {
+        Self {
+            schema: schema.into(),
+            namespace_uri: namespace_uri.into(),
+            prefix: prefix.into(),
+            properties: Vec::new(),
+        }
+    }
+
+    /// Adds a property to the schema
+    #[inline]
+    pub fn with_property(mut self, property: XmpExtensionProperty) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    /// Extension schema required for Factur-X (and ZUGFeRD 2.x) invoices.
+    ///
+    /// `document_file_name` is the name of the attached XML file (usually `"factur-x.xml"`),
+    /// `conformance_level` the invoice profile, such as `"MINIMUM"`, `"BASIC WL"`, `"BASIC"`,
+    /// `"EN 16931"` or `"EXTENDED"`
+    pub fn factur_x<S, T>(document_file_name: S, conformance_level: T) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        Self::new(
+            "Factur-X PDFA Extension Schema",
+            "urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#",
+            "fx",
+        )
+        .with_property(XmpExtensionProperty::new(
+            "DocumentFileName",
+            "name of the embedded XML invoice file",
+            document_file_name,
+        ))
+        .with_property(XmpExtensionProperty::new(
+            "DocumentType",
+            "INVOICE",
+            "INVOICE",
+        ))
+        .with_property(XmpExtensionProperty::new(
+            "Version",
+            "The actual version of the Factur-X XML schema",
+            "1.0",
+        ))
+        .with_property(XmpExtensionProperty::new(
+            "ConformanceLevel",
+            "The conformance level of the embedded Factur-X data",
+            conformance_level,
+        ))
+    }
+
+    /// Description of the schema, an entry of the `pdfaExtension:schemas` bag
+    pub(crate) fn to_schema_description(&self) -> XmpValue {
+        let properties = self
+            .properties
+            .iter()
+            .map(|p| {
+                XmpValue::Struct(vec![
+                    ("pdfaProperty:name".into(), p.name.clone().into()),
+                    ("pdfaProperty:valueType".into(), p.value_type.clone().into()),
+                    ("pdfaProperty:category".into(), p.category.clone().into()),
+                    ("pdfaProperty:description".into(), p.description.clone().into()),
+                ])
+            })
+            .collect();
+
+        XmpValue::Struct(vec![
+            ("pdfaSchema:schema".into(), self.schema.clone().into()),
+            ("pdfaSchema:namespaceURI".into(), self.namespace_uri.clone().into()),
+            ("pdfaSchema:prefix".into(), self.prefix.clone().into()),
+            ("pdfaSchema:property".into(), XmpValue::Seq(properties)),
+        ])
+    }
+
+    /// Schema containing the values of the properties
+    pub(crate) fn to_value_schema(&self) -> XmpSchema {
+        let mut schema = XmpSchema::new(self.prefix.clone(), self.namespace_uri.clone());
+        for property in &self.properties {
+            schema.set(property.name.clone(), property.value.clone());
+        }
+        schema
+    }
+}
+
+/// Writes the `pdfaExtension` schema, which describes all custom schemas
+pub(crate) fn pdfa_extension_schema(schemas: &[XmpExtensionSchema]) -> XmpSchema {
+    XmpSchema::new("pdfaExtension", "http://www.aiim.org/pdfa/ns/extension/")
+        .with_namespace("pdfaSchema", "http://www.aiim.org/pdfa/ns/schema#")
+        .with_namespace("pdfaProperty", "http://www.aiim.org/pdfa/ns/property#")
+        .with_property(
+            "schemas",
+            XmpValue::Bag(schemas.iter().map(|s| s.to_schema_description()).collect()),
+        )
+}
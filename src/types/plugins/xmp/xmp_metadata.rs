@@ -1,21 +1,36 @@
-//! Stub plugin for XMP Metadata streams, to be expanded later
+//! XMP Metadata stream of a PDF document
 
-use crate::OffsetDateTime;
 use lopdf;
 
-use PdfConformance;
+use date::to_xmp_date;
+
+use super::xmp_extension::{pdfa_extension_schema, XmpExtensionProperty, XmpExtensionSchema};
+use super::xmp_packet::{XmpPacket, XmpSchema};
+use super::xmp_schemas::{
+    AdobePdf, DublinCore, PdfAIdentification, PdfXIdentification, XmpBasic, XmpMediaManagement,
+};
+use PdfMetadata;
+use super::super::misc::document_info::{is_reserved_info_key, is_valid_custom_info_key};
 use utils::random_character_string_32;
 
-/// Initial struct for Xmp metatdata. This should be expanded later for XML handling, etc.
-/// Right now it just fills out the necessary fields
+/// XMP metadata of the document. The shared fields (title, dates, etc.) are
+/// stored in the `PdfMetadata`, this struct contains only the XMP-specific settings
 #[derive(Debug, Clone)]
 pub struct XmpMetadata {
     /// Document ID
     pub document_id: String,
     /// Web-viewable or "default" or to be left empty. Usually "default".
     pub rendition_class: Option<String>,
+    /// Instance ID of this version of the document. `None` generates
+    /// a new random ID every time the document is saved
+    pub instance_id: Option<String>,
     /// Document version
     pub document_version: u32,
+    /// Custom schemas, written together with their PDF/A extension schema description
+    pub extension_schemas: Vec<XmpExtensionSchema>,
+    /// Additional schemas (custom namespaces), written as-is.
+    /// Note that PDF/A documents need an extension schema for custom namespaces
+    pub custom_schemas: Vec<XmpSchema>,
 }
 
 impl XmpMetadata {
@@ -26,68 +41,153 @@ impl XmpMetadata {
     {
         let document_id: String = random_character_string_32();
         Self {
-            document_id: document_id,
-            rendition_class: rendition_class,
-            document_version: document_version,
+            document_id,
+            instance_id: None,
+            rendition_class,
+            document_version,
+            extension_schemas: Vec::new(),
+            custom_schemas: Vec::new(),
         }
     }
 
-    /// Consumes the XmpMetadata and turns it into a PDF Object.
-    /// This is similar to the
-    pub(in types) fn into_obj<S>(self,
-                           conformance: PdfConformance,
-                           trapping: bool,
-                           creation_date: OffsetDateTime,
-                           modification_date: OffsetDateTime,
-                           metadata_date: OffsetDateTime,
-                           document_title: S)
-    -> lopdf::Object where S: Into<String> + ::std::fmt::Display
+    /// Reads the XMP-specific settings from a parsed packet. Schemas that are
+    /// not handled by `PdfMetadata` end up in `custom_schemas`.
+    pub fn from_packet(packet: &XmpPacket)
+    -> Self
     {
-        use lopdf::{Stream as LoStream, Dictionary as LoDictionary};
-        use lopdf::Object::*;
-        use std::iter::FromIterator;
+        let mm = XmpMediaManagement::from_packet(packet).unwrap_or_default();
+
+        let known_namespaces = [
+            DublinCore::NAMESPACE,
+            XmpBasic::NAMESPACE,
+            AdobePdf::NAMESPACE,
+            PdfAIdentification::NAMESPACE,
+            PdfXIdentification::NAMESPACE,
+            XmpMediaManagement::NAMESPACE,
+            PDFX_NAMESPACE,
+        ];
+
+        Self {
+            document_id: mm.document_id
+                .map(|id| id.trim_start_matches("uuid:").to_string())
+                .unwrap_or_else(random_character_string_32),
+            instance_id: None,
+            rendition_class: mm.rendition_class,
+            document_version: mm.version_id.and_then(|v| v.trim().parse().ok()).unwrap_or(1),
+            extension_schemas: Vec::new(),
+            custom_schemas: packet.schemas.iter()
+                .filter(|s| !known_namespaces.contains(&s.namespace_uri.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Builds the XMP packet from the XMP-specific settings and the shared metadata
+    /// (which is also used for the Info dictionary, so both stay in sync)
+    pub(in types) fn to_packet(&self, metadata: &PdfMetadata)
+    -> XmpPacket
+    {
+        let conformance = &metadata.conformance;
+        let instance_id = self.instance_id.clone().unwrap_or_else(random_character_string_32);
 
-        // Shared between XmpMetadata and DocumentInfo
-        let trapping = if trapping { "True" } else { "False" };
+        let mut packet = XmpPacket::new()
+            .with_schema(XmpBasic {
+                create_date: Some(to_xmp_date(&metadata.creation_date)),
+                modify_date: Some(to_xmp_date(&metadata.modification_date)),
+                metadata_date: Some(to_xmp_date(&metadata.metadata_date)),
+                creator_tool: metadata.creator.clone(),
+            }.into())
+            .with_schema(DublinCore {
+                format: Some("application/pdf".into()),
+                title: Some(metadata.document_title.clone()),
+                creator: metadata.author.iter().cloned().collect(),
+                description: metadata.subject.clone(),
+                subject: metadata.keywords.clone(),
+                .. Default::default()
+            }.into())
+            .with_schema(XmpMediaManagement {
+                document_id: Some(format!("uuid:{}", self.document_id)),
+                instance_id: Some(format!("uuid:{}", instance_id)),
+                rendition_class: Some(self.rendition_class.clone().unwrap_or_default()),
+                version_id: Some(self.document_version.to_string()),
+            }.into())
+            .with_schema(AdobePdf {
+                keywords: if metadata.keywords.is_empty() { None } else { Some(metadata.keywords_string()) },
+                producer: metadata.producer.clone(),
+                trapped: Some(metadata.trapping),
+                .. Default::default()
+            }.into());
 
-        // let xmp_instance_id = "2898d852-f86f-4479-955b-804d81046b19";
-        let instance_id = random_character_string_32();
-        let create_date = to_pdf_xmp_date(creation_date);
-        let modification_date = to_pdf_xmp_date(modification_date);
-        let metadata_date = to_pdf_xmp_date(metadata_date);
+        if conformance.is_pdf_x() {
+            let pdf_x_version = conformance.get_identifier_string();
+            packet.add_schema(XmpSchema::new("pdfx", PDFX_NAMESPACE)
+                .with_property("GTS_PDFXVersion", pdf_x_version.clone()));
+            packet.add_schema(PdfXIdentification { gts_pdfx_version: pdf_x_version }.into());
+        }
 
-        let pdf_x_version = conformance.get_identifier_string();
-        let document_version = self.document_version.to_string();
-        let document_id = self.document_id.to_string();
+        if let Some((part, conformance_level)) = conformance.get_pdfa_part() {
+            packet.add_schema(PdfAIdentification {
+                part,
+                conformance: conformance_level.into(),
+            }.into());
+        }
 
-        let rendition_class = match self.rendition_class {
-            Some(class) => class,
-            None => "".to_string(),
-        };
+        let mut extension_schemas = self.extension_schemas.clone();
 
-        let xmp_metadata = format!(include_str!("../../../templates/catalog_xmp_metadata.txt"),
-                           create_date, modification_date, metadata_date, document_title, document_id,
-                           instance_id, rendition_class, document_version, pdf_x_version, trapping);
+        // custom Info entries go into the Adobe PDF/X namespace, like Acrobat does it
+        let custom_info: Vec<_> = metadata.custom_info.iter()
+            .filter(|e| !is_reserved_info_key(&e.0) && is_valid_custom_info_key(&e.0))
+            .collect();
+        if !custom_info.is_empty() {
+            let mut custom_schema = XmpExtensionSchema::new(
+                "Custom document information", PDFX_NAMESPACE, "pdfx");
+            for (key, value) in custom_info {
+                custom_schema = custom_schema.with_property(XmpExtensionProperty::new(
+                    key.clone(), "Custom document information entry", value.clone()));
+            }
 
+            if conformance.is_pdf_a() {
+                extension_schemas.push(custom_schema);
+            } else {
+                packet.add_schema(custom_schema.to_value_schema());
+            }
+        }
+
+        if !extension_schemas.is_empty() {
+            packet.add_schema(pdfa_extension_schema(&extension_schemas));
+            for schema in &extension_schemas {
+                packet.add_schema(schema.to_value_schema());
+            }
+        }
+
+        for schema in &self.custom_schemas {
+            packet.add_schema(schema.clone());
+        }
+
+        packet
+    }
+
+    /// Consumes the XmpMetadata and turns it into a PDF Object.
+    /// This is similar to the IntoPdfObject trait method, but takes
+    /// the shared metadata as an additional argument
+    pub(in types) fn into_obj(self, metadata: &PdfMetadata)
+    -> lopdf::Object
+    {
+        use lopdf::{Stream as LoStream, Dictionary as LoDictionary};
+        use lopdf::Object::*;
+        use std::iter::FromIterator;
+
+        let packet = self.to_packet(metadata);
+
+        // the metadata stream must not be compressed, so that
+        // non-PDF-aware tools can find the XMP packet
         Stream(LoStream::new(LoDictionary::from_iter(vec![
             ("Type", "Metadata".into()),
             ("Subtype", "XML".into()), ]),
-            xmp_metadata.as_bytes().to_vec() ))
+            packet.to_xml().into_bytes()).with_compression(false))
     }
 }
 
-// D:2018-09-19T10:05:05+00'00'
-fn to_pdf_xmp_date(date: OffsetDateTime)
--> String
-{
-    // Since the time is in UTC, we know that the time zone
-    // difference to UTC is 0 min, 0 sec, hence the 00'00
-    format!("D:{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00'00'",
-        date.year(),
-        date.month(),
-        date.day(),
-        date.hour(),
-        date.minute(),
-        date.second(),
-    )
-}
+/// Adobe namespace for custom PDF/X properties, written in addition to the
+/// `pdfxid` schema for older PDF/X readers
+pub(crate) const PDFX_NAMESPACE: &str = "http://ns.adobe.com/pdfx/1.3/";
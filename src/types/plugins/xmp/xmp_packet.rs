@@ -0,0 +1,887 @@
+//! Generic XMP data model: a packet consists of schemas (namespaces),
+//! each schema holds a list of properties. The packet can be serialized to
+//! RDF/XML and parsed back from existing documents.
+
+use {Error, PdfError};
+
+/// Namespace of the RDF syntax
+pub const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// Value of an XMP property
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmpValue {
+    /// Simple text value (also used for dates, numbers, booleans, etc.)
+    Text(String),
+    /// Ordered array (`rdf:Seq`), for example the authors of a document
+    Seq(Vec<XmpValue>),
+    /// Unordered array (`rdf:Bag`), for example keywords
+    Bag(Vec<XmpValue>),
+    /// Language alternatives (`rdf:Alt` with `xml:lang` qualifiers), as (language, text).
+    /// The default language is called `"x-default"`
+    LangAlt(Vec<(String, String)>),
+    /// Structure (`rdf:parseType="Resource"`), as (qualified field name, value).
+    /// The namespaces of the field names have to be declared on the schema
+    Struct(Vec<(String, XmpValue)>),
+}
+
+impl XmpValue {
+    /// Creates a language alternative with only the `x-default` language
+    pub fn lang_alt_default<S>(text: S) -> Self
+    where
+        S: Into<String>,
+    {
+        XmpValue::LangAlt(vec![("x-default".into(), text.into())])
+    }
+
+    /// Creates an ordered array of text values
+    pub fn text_seq<I, S>(items: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        XmpValue::Seq(items.into_iter().map(|s| XmpValue::Text(s.into())).collect())
+    }
+
+    /// Creates an unordered array of text values
+    pub fn text_bag<I, S>(items: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        XmpValue::Bag(items.into_iter().map(|s| XmpValue::Text(s.into())).collect())
+    }
+
+    /// Returns the text of a simple value or the default language of a
+    /// language alternative (or the first language, if there is no default)
+    pub fn as_text(&self) -> Option<&str> {
+        match *self {
+            XmpValue::Text(ref s) => Some(s),
+            XmpValue::LangAlt(ref alts) => alts
+                .iter()
+                .find(|(lang, _)| lang == "x-default")
+                .or_else(|| alts.first())
+                .map(|(_, text)| text.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Returns the text items of an array (`Seq` or `Bag`)
+    pub fn as_text_list(&self) -> Vec<String> {
+        match *self {
+            XmpValue::Seq(ref items) | XmpValue::Bag(ref items) => items
+                .iter()
+                .filter_map(|item| item.as_text().map(|s| s.to_string()))
+                .collect(),
+            XmpValue::Text(ref s) => vec![s.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the value of a field of a structure, by its local name (without prefix)
+    pub fn field(&self, local_name: &str) -> Option<&XmpValue> {
+        match *self {
+            XmpValue::Struct(ref fields) => fields
+                .iter()
+                .find(|(name, _)| local_part(name) == local_name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for XmpValue {
+    fn from(text: &'a str) -> Self {
+        XmpValue::Text(text.to_string())
+    }
+}
+
+impl From<String> for XmpValue {
+    fn from(text: String) -> Self {
+        XmpValue::Text(text)
+    }
+}
+
+/// All properties of one namespace, written as one `rdf:Description`
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmpSchema {
+    /// Namespace prefix, such as `"dc"`
+    pub prefix: String,
+    /// Namespace URI, such as `"http://purl.org/dc/elements/1.1/"`
+    pub namespace_uri: String,
+    /// Additional namespaces used by the field names of structures, as (prefix, URI)
+    pub additional_namespaces: Vec<(String, String)>,
+    /// Properties as (local name, value), in the order they are written
+    pub properties: Vec<(String, XmpValue)>,
+}
+
+impl XmpSchema {
+    /// Creates a new, empty schema
+    pub fn new<S, T>(prefix: S, namespace_uri: T) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        Self {
+            prefix: prefix.into(),
+            namespace_uri: namespace_uri.into(),
+            additional_namespaces: Vec::new(),
+            properties: Vec::new(),
+        }
+    }
+
+    /// Declares an additional namespace that is used by the fields of structures
+    #[inline]
+    pub fn with_namespace<S, T>(mut self, prefix: S, namespace_uri: T) -> Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        self.additional_namespaces
+            .push((prefix.into(), namespace_uri.into()));
+        self
+    }
+
+    /// Sets a property, see `set`
+    #[inline]
+    pub fn with_property<S, V>(mut self, name: S, value: V) -> Self
+    where
+        S: Into<String>,
+        V: Into<XmpValue>,
+    {
+        self.set(name, value);
+        self
+    }
+
+    /// Sets a property, replacing an existing property with the same name
+    pub fn set<S, V>(&mut self, name: S, value: V)
+    where
+        S: Into<String>,
+        V: Into<XmpValue>,
+    {
+        let name = name.into();
+        let value = value.into();
+        match self.properties.iter_mut().find(|p| p.0 == name) {
+            Some(property) => property.1 = value,
+            None => self.properties.push((name, value)),
+        }
+    }
+
+    /// Returns the value of a property
+    pub fn get(&self, name: &str) -> Option<&XmpValue> {
+        self.properties
+            .iter()
+            .find(|p| p.0 == name)
+            .map(|p| &p.1)
+    }
+
+    /// Returns the text of a property, see `XmpValue::as_text`
+    pub fn get_text(&self, name: &str) -> Option<&str> {
+        self.get(name).and_then(|v| v.as_text())
+    }
+
+    fn write_description(&self, out: &mut String) {
+        out.push_str("      <rdf:Description rdf:about=\"\"\n");
+        out.push_str(&format!(
+            "            xmlns:{}=\"{}\"",
+            self.prefix,
+            escape_xml(&self.namespace_uri)
+        ));
+        for (prefix, uri) in &self.additional_namespaces {
+            out.push_str(&format!("\n            xmlns:{}=\"{}\"", prefix, escape_xml(uri)));
+        }
+        out.push_str(">\n");
+
+        for (name, value) in &self.properties {
+            let qualified_name = format!("{}:{}", self.prefix, name);
+            write_property(out, &qualified_name, value, 3);
+        }
+
+        out.push_str("      </rdf:Description>\n");
+    }
+}
+
+/// An XMP packet, the content of the `/Metadata` stream of a PDF
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct XmpPacket {
+    /// Schemas of this packet. Every namespace should only appear once
+    pub schemas: Vec<XmpSchema>,
+}
+
+impl XmpPacket {
+    /// Creates a new, empty XMP packet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a schema, see `add_schema`
+    #[inline]
+    pub fn with_schema(mut self, schema: XmpSchema) -> Self {
+        self.add_schema(schema);
+        self
+    }
+
+    /// Adds a schema. If a schema with the same namespace already exists,
+    /// the properties are merged into the existing schema.
+    pub fn add_schema(&mut self, schema: XmpSchema) {
+        match self
+            .schemas
+            .iter_mut()
+            .find(|s| s.namespace_uri == schema.namespace_uri)
+        {
+            Some(existing) => {
+                for namespace in schema.additional_namespaces {
+                    if !existing.additional_namespaces.contains(&namespace) {
+                        existing.additional_namespaces.push(namespace);
+                    }
+                }
+                for (name, value) in schema.properties {
+                    existing.set(name, value);
+                }
+            }
+            None => self.schemas.push(schema),
+        }
+    }
+
+    /// Returns the schema with the given namespace URI
+    pub fn schema(&self, namespace_uri: &str) -> Option<&XmpSchema> {
+        self.schemas
+            .iter()
+            .find(|s| s.namespace_uri == namespace_uri)
+    }
+
+    /// Returns the value of a property
+    pub fn get(&self, namespace_uri: &str, name: &str) -> Option<&XmpValue> {
+        self.schema(namespace_uri).and_then(|s| s.get(name))
+    }
+
+    /// Returns the text of a property, see `XmpValue::as_text`
+    pub fn get_text(&self, namespace_uri: &str, name: &str) -> Option<&str> {
+        self.get(namespace_uri, name).and_then(|v| v.as_text())
+    }
+
+    /// Serializes the packet, including the `<?xpacket?>` wrapper and padding,
+    /// so that the metadata can be updated in place later
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+        out.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+        out.push_str(&format!("   <rdf:RDF xmlns:rdf=\"{}\">\n", RDF_NAMESPACE));
+
+        for schema in &self.schemas {
+            schema.write_description(&mut out);
+        }
+
+        out.push_str("   </rdf:RDF>\n");
+        out.push_str("</x:xmpmeta>\n");
+
+        // recommended padding of ~2KB, for in-place editing
+        for _ in 0..20 {
+            out.push_str(&" ".repeat(100));
+            out.push('\n');
+        }
+
+        out.push_str("<?xpacket end=\"w\"?>");
+        out
+    }
+
+    /// Parses an XMP packet, for example the `/Metadata` stream of an existing document.
+    ///
+    /// Supports the common RDF/XML forms: properties as elements or as attributes of
+    /// `rdf:Description`, arrays, language alternatives and structures.
+    pub fn parse(xml: &str) -> Result<Self, Error> {
+        let root = xml::parse(xml)?;
+        let mut packet = XmpPacket::new();
+
+        let rdf = root
+            .find(RDF_NAMESPACE, "RDF")
+            .ok_or(PdfError::InvalidXmp)?;
+
+        for description in rdf
+            .children()
+            .filter(|e| e.is(RDF_NAMESPACE, "Description"))
+        {
+            // short form: simple properties as attributes
+            for attribute in &description.attributes {
+                if attribute.namespace.is_empty()
+                    || attribute.namespace == RDF_NAMESPACE
+                    || attribute.namespace == xml::XML_NAMESPACE
+                {
+                    continue;
+                }
+                packet.add_schema(
+                    XmpSchema::new(attribute.prefix.clone(), attribute.namespace.clone())
+                        .with_property(attribute.local_name.clone(), attribute.value.clone()),
+                );
+            }
+
+            for property in description.children() {
+                let mut schema = XmpSchema::new(property.prefix.clone(), property.namespace.clone())
+                    .with_property(property.local_name.clone(), parse_value(property));
+                collect_field_namespaces(property, &mut schema);
+                packet.add_schema(schema);
+            }
+        }
+
+        Ok(packet)
+    }
+}
+
+fn parse_value(element: &xml::Element) -> XmpValue {
+    let is_resource = element
+        .attribute(RDF_NAMESPACE, "parseType")
+        .map(|t| t == "Resource")
+        .unwrap_or(false);
+
+    if is_resource {
+        return parse_struct(element);
+    }
+
+    if let Some(inner) = element.children().next() {
+        if inner.is(RDF_NAMESPACE, "Seq") {
+            return XmpValue::Seq(list_items(inner).map(parse_value).collect());
+        } else if inner.is(RDF_NAMESPACE, "Bag") {
+            return XmpValue::Bag(list_items(inner).map(parse_value).collect());
+        } else if inner.is(RDF_NAMESPACE, "Alt") {
+            return XmpValue::LangAlt(
+                list_items(inner)
+                    .map(|li| {
+                        let lang = li
+                            .attribute(xml::XML_NAMESPACE, "lang")
+                            .unwrap_or("x-default");
+                        (lang.to_string(), li.text())
+                    })
+                    .collect(),
+            );
+        } else if inner.is(RDF_NAMESPACE, "Description") {
+            return parse_struct(inner);
+        }
+    }
+
+    XmpValue::Text(element.text())
+}
+
+fn parse_struct(element: &xml::Element) -> XmpValue {
+    let mut fields: Vec<(String, XmpValue)> = element
+        .attributes
+        .iter()
+        .filter(|a| {
+            !a.namespace.is_empty()
+                && a.namespace != RDF_NAMESPACE
+                && a.namespace != xml::XML_NAMESPACE
+        })
+        .map(|a| (a.qualified_name(), XmpValue::Text(a.value.clone())))
+        .collect();
+
+    fields.extend(
+        element
+            .children()
+            .map(|field| (field.qualified_name(), parse_value(field))),
+    );
+
+    XmpValue::Struct(fields)
+}
+
+// declares the namespaces of structure fields on the schema
+fn collect_field_namespaces(element: &xml::Element, schema: &mut XmpSchema) {
+    let fields = element
+        .children()
+        .map(|e| (&e.prefix, &e.namespace))
+        .chain(element.attributes.iter().map(|a| (&a.prefix, &a.namespace)));
+
+    for (prefix, namespace) in fields {
+        let is_known = namespace.is_empty()
+            || *namespace == RDF_NAMESPACE
+            || *namespace == xml::XML_NAMESPACE
+            || *namespace == schema.namespace_uri
+            || schema.additional_namespaces.iter().any(|n| n.1 == *namespace);
+
+        if !is_known {
+            schema
+                .additional_namespaces
+                .push((prefix.clone(), namespace.clone()));
+        }
+    }
+
+    for child in element.children() {
+        collect_field_namespaces(child, schema);
+    }
+}
+
+fn list_items<'a>(list: &'a xml::Element) -> impl Iterator<Item = &'a xml::Element> + 'a {
+    list.children().filter(|e| e.is(RDF_NAMESPACE, "li"))
+}
+
+fn write_property(out: &mut String, name: &str, value: &XmpValue, depth: usize) {
+    let indent = "   ".repeat(depth);
+    match *value {
+        XmpValue::Text(ref text) => {
+            out.push_str(&format!("{}<{}>{}</{}>\n", indent, name, escape_xml(text), name));
+        }
+        XmpValue::Seq(ref items) | XmpValue::Bag(ref items) => {
+            let array = if let XmpValue::Seq(_) = *value { "rdf:Seq" } else { "rdf:Bag" };
+            out.push_str(&format!("{}<{}>\n{}   <{}>\n", indent, name, indent, array));
+            for item in items {
+                write_property(out, "rdf:li", item, depth + 2);
+            }
+            out.push_str(&format!("{}   </{}>\n{}</{}>\n", indent, array, indent, name));
+        }
+        XmpValue::LangAlt(ref alternatives) => {
+            out.push_str(&format!("{}<{}>\n{}   <rdf:Alt>\n", indent, name, indent));
+            for (lang, text) in alternatives {
+                out.push_str(&format!(
+                    "{}      <rdf:li xml:lang=\"{}\">{}</rdf:li>\n",
+                    indent,
+                    escape_xml(lang),
+                    escape_xml(text)
+                ));
+            }
+            out.push_str(&format!("{}   </rdf:Alt>\n{}</{}>\n", indent, indent, name));
+        }
+        XmpValue::Struct(ref fields) => {
+            out.push_str(&format!("{}<{} rdf:parseType=\"Resource\">\n", indent, name));
+            for (field_name, field_value) in fields {
+                write_property(out, field_name, field_value, depth + 1);
+            }
+            out.push_str(&format!("{}</{}>\n", indent, name));
+        }
+    }
+}
+
+fn local_part(qualified_name: &str) -> &str {
+    qualified_name
+        .rfind(':')
+        .map(|pos| &qualified_name[pos + 1..])
+        .unwrap_or(qualified_name)
+}
+
+/// Escapes the characters that are not allowed in XML text and attribute values
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Minimal namespace-aware XML reader, just enough for XMP packets
+/// (no DTDs, no external entities)
+mod xml {
+
+    use {Error, PdfError};
+
+    pub const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+    #[derive(Debug, Clone)]
+    pub struct Attribute {
+        pub prefix: String,
+        pub local_name: String,
+        pub namespace: String,
+        pub value: String,
+    }
+
+    impl Attribute {
+        pub fn qualified_name(&self) -> String {
+            format!("{}:{}", self.prefix, self.local_name)
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub enum Node {
+        Element(Element),
+        Text(String),
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Element {
+        pub prefix: String,
+        pub local_name: String,
+        pub namespace: String,
+        pub attributes: Vec<Attribute>,
+        pub nodes: Vec<Node>,
+    }
+
+    impl Element {
+        pub fn is(&self, namespace: &str, local_name: &str) -> bool {
+            self.namespace == namespace && self.local_name == local_name
+        }
+
+        pub fn qualified_name(&self) -> String {
+            if self.prefix.is_empty() {
+                self.local_name.clone()
+            } else {
+                format!("{}:{}", self.prefix, self.local_name)
+            }
+        }
+
+        pub fn children(&self) -> impl Iterator<Item = &Element> {
+            self.nodes.iter().filter_map(|n| match *n {
+                Node::Element(ref e) => Some(e),
+                Node::Text(_) => None,
+            })
+        }
+
+        pub fn attribute(&self, namespace: &str, local_name: &str) -> Option<&str> {
+            self.attributes
+                .iter()
+                .find(|a| a.namespace == namespace && a.local_name == local_name)
+                .map(|a| a.value.as_str())
+        }
+
+        /// Concatenated text content of this element (not of the child elements)
+        pub fn text(&self) -> String {
+            self.nodes
+                .iter()
+                .filter_map(|n| match *n {
+                    Node::Text(ref t) => Some(t.as_str()),
+                    Node::Element(_) => None,
+                })
+                .collect()
+        }
+
+        /// Depth-first search for an element
+        pub fn find(&self, namespace: &str, local_name: &str) -> Option<&Element> {
+            if self.is(namespace, local_name) {
+                return Some(self);
+            }
+            self.children().filter_map(|c| c.find(namespace, local_name)).next()
+        }
+    }
+
+    /// Maximum element nesting depth, the XMP data model is walked
+    /// recursively, so deeper (malicious) documents are rejected
+    const MAX_DEPTH: usize = 64;
+
+    struct RawElement {
+        name: String,
+        attributes: Vec<(String, String)>,
+        nodes: Vec<Node>,
+        namespaces: Vec<(String, String)>,
+    }
+
+    /// Parses the document, returns a virtual root element containing all top-level elements
+    pub fn parse(input: &str) -> Result<Element, Error> {
+        let mut stack = vec![RawElement {
+            name: String::new(),
+            attributes: Vec::new(),
+            nodes: Vec::new(),
+            namespaces: vec![("xml".into(), XML_NAMESPACE.into())],
+        }];
+
+        let mut rest = input;
+
+        while !rest.is_empty() {
+            if rest.starts_with("<?") {
+                rest = skip_past(rest, "?>")?;
+            } else if rest.starts_with("<!--") {
+                rest = skip_past(rest, "-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                let end = rest.find("]]>").ok_or(PdfError::InvalidXmp)?;
+                push_text(&mut stack, rest[9..end].to_string());
+                rest = &rest[end + 3..];
+            } else if rest.starts_with("<!") {
+                rest = skip_past(rest, ">")?;
+            } else if rest.starts_with("</") {
+                let end = rest.find('>').ok_or(PdfError::InvalidXmp)?;
+                let name = rest[2..end].trim();
+                rest = &rest[end + 1..];
+
+                if stack.len() < 2 || stack[stack.len() - 1].name != name {
+                    return Err(PdfError::InvalidXmp.into());
+                }
+                close_element(&mut stack)?;
+            } else if rest.starts_with('<') {
+                let end = find_tag_end(rest).ok_or(PdfError::InvalidXmp)?;
+                let tag = &rest[1..end];
+                rest = &rest[end + 1..];
+
+                let self_closing = tag.ends_with('/');
+                let tag = if self_closing { &tag[..tag.len() - 1] } else { tag };
+                let (name, attributes) = parse_tag(tag)?;
+
+                let namespaces = attributes
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        if key == "xmlns" {
+                            Some((String::new(), value.clone()))
+                        } else {
+                            key.strip_prefix("xmlns:")
+                                .map(|prefix| (prefix.to_string(), value.clone()))
+                        }
+                    })
+                    .collect();
+
+                if stack.len() > MAX_DEPTH {
+                    return Err(PdfError::InvalidXmp.into());
+                }
+
+                stack.push(RawElement {
+                    name,
+                    attributes,
+                    nodes: Vec::new(),
+                    namespaces,
+                });
+
+                if self_closing {
+                    close_element(&mut stack)?;
+                }
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = unescape(&rest[..end])?;
+                push_text(&mut stack, text);
+                rest = &rest[end..];
+            }
+        }
+
+        if stack.len() != 1 {
+            return Err(PdfError::InvalidXmp.into());
+        }
+
+        let root = stack.pop().ok_or(PdfError::InvalidXmp)?;
+        Ok(Element {
+            prefix: String::new(),
+            local_name: String::new(),
+            namespace: String::new(),
+            attributes: Vec::new(),
+            nodes: without_whitespace(root.nodes),
+        })
+    }
+
+    // whitespace between elements is not significant in XMP (RDF doesn't allow mixed content)
+    fn without_whitespace(nodes: Vec<Node>) -> Vec<Node> {
+        if !nodes.iter().any(|n| matches!(*n, Node::Element(_))) {
+            return nodes;
+        }
+        nodes
+            .into_iter()
+            .filter(|n| !matches!(*n, Node::Text(ref t) if t.trim().is_empty()))
+            .collect()
+    }
+
+    fn skip_past<'a>(input: &'a str, pattern: &str) -> Result<&'a str, Error> {
+        match input.find(pattern) {
+            Some(pos) => Ok(&input[pos + pattern.len()..]),
+            None => Err(PdfError::InvalidXmp.into()),
+        }
+    }
+
+    // finds the closing '>' of a tag, ignoring '>' inside of quoted attribute values
+    fn find_tag_end(input: &str) -> Option<usize> {
+        let mut quote = None;
+        for (pos, c) in input.char_indices() {
+            match (quote, c) {
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (Some(q), c) if q == c => quote = None,
+                (None, '>') => return Some(pos),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn parse_tag(tag: &str) -> Result<(String, Vec<(String, String)>), Error> {
+        let tag = tag.trim();
+        let name_end = tag
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_string();
+        let mut rest = tag[name_end..].trim_start();
+        let mut attributes = Vec::new();
+
+        while !rest.is_empty() {
+            let eq = rest.find('=').ok_or(PdfError::InvalidXmp)?;
+            let key = rest[..eq].trim().to_string();
+            rest = rest[eq + 1..].trim_start();
+
+            let quote = rest.chars().next().ok_or(PdfError::InvalidXmp)?;
+            if quote != '"' && quote != '\'' {
+                return Err(PdfError::InvalidXmp.into());
+            }
+            let value_end = rest[1..].find(quote).ok_or(PdfError::InvalidXmp)? + 1;
+            let value = unescape(&rest[1..value_end])?;
+            rest = rest[value_end + 1..].trim_start();
+
+            attributes.push((key, value));
+        }
+
+        Ok((name, attributes))
+    }
+
+    // whitespace-only text is removed when the element turns out to have child elements
+    // (see `without_whitespace`), the text of leaf elements is kept as it is
+    fn push_text(stack: &mut [RawElement], text: String) {
+        if let Some(current) = stack.last_mut() {
+            current.nodes.push(Node::Text(text));
+        }
+    }
+
+    fn resolve(stack: &[RawElement], prefix: &str) -> Option<String> {
+        stack
+            .iter()
+            .rev()
+            .flat_map(|e| e.namespaces.iter())
+            .find(|(p, _)| p == prefix)
+            .map(|(_, uri)| uri.clone())
+    }
+
+    fn split_name(name: &str) -> (&str, &str) {
+        match name.find(':') {
+            Some(pos) => (&name[..pos], &name[pos + 1..]),
+            None => ("", name),
+        }
+    }
+
+    // pops the last element from the stack, resolves its namespaces and
+    // appends it to its parent
+    fn close_element(stack: &mut Vec<RawElement>) -> Result<(), Error> {
+        let (prefix, local_name) = {
+            let raw = stack.last().ok_or(PdfError::InvalidXmp)?;
+            let (prefix, local_name) = split_name(&raw.name);
+            (prefix.to_string(), local_name.to_string())
+        };
+
+        let namespace = resolve(stack, &prefix).unwrap_or_default();
+
+        let attributes = {
+            let raw = stack.last().ok_or(PdfError::InvalidXmp)?;
+            raw.attributes
+                .iter()
+                .filter(|(key, _)| key != "xmlns" && !key.starts_with("xmlns:"))
+                .map(|(key, value)| {
+                    let (prefix, local_name) = split_name(key);
+                    // unprefixed attributes have no namespace
+                    let namespace = if prefix.is_empty() {
+                        String::new()
+                    } else {
+                        resolve(stack, prefix).unwrap_or_default()
+                    };
+                    Attribute {
+                        prefix: prefix.to_string(),
+                        local_name: local_name.to_string(),
+                        namespace,
+                        value: value.clone(),
+                    }
+                })
+                .collect()
+        };
+
+        let raw = stack.pop().ok_or(PdfError::InvalidXmp)?;
+        let element = Element {
+            prefix,
+            local_name,
+            namespace,
+            attributes,
+            nodes: without_whitespace(raw.nodes),
+        };
+
+        let parent = stack.last_mut().ok_or(PdfError::InvalidXmp)?;
+        parent.nodes.push(Node::Element(element));
+        Ok(())
+    }
+
+    fn unescape(text: &str) -> Result<String, Error> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(pos) = rest.find('&') {
+            out.push_str(&rest[..pos]);
+            let end = rest[pos..].find(';').ok_or(PdfError::InvalidXmp)? + pos;
+            let entity = &rest[pos + 1..end];
+
+            match entity {
+                "amp" => out.push('&'),
+                "lt" => out.push('<'),
+                "gt" => out.push('>'),
+                "quot" => out.push('"'),
+                "apos" => out.push('\''),
+                _ => {
+                    let code = match entity.strip_prefix('#') {
+                        Some(hex) if hex.starts_with('x') || hex.starts_with('X') => {
+                            u32::from_str_radix(&hex[1..], 16).ok()
+                        }
+                        Some(decimal) => decimal.parse::<u32>().ok(),
+                        None => None,
+                    };
+                    let c = code
+                        .and_then(::std::char::from_u32)
+                        .ok_or(PdfError::InvalidXmp)?;
+                    out.push(c);
+                }
+            }
+
+            rest = &rest[end + 1..];
+        }
+
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xmp_packet_roundtrip() {
+        let packet = XmpPacket::new()
+            .with_schema(
+                XmpSchema::new("dc", "http://purl.org/dc/elements/1.1/")
+                    .with_property("title", XmpValue::lang_alt_default("Fish & <Chips>"))
+                    .with_property("creator", XmpValue::text_seq(vec!["Jane", "John"]))
+                    .with_property("subject", XmpValue::text_bag(vec!["food"])),
+            )
+            .with_schema(
+                XmpSchema::new("ex", "http://example.com/ns/")
+                    .with_namespace("exf", "http://example.com/ns/field#")
+                    .with_property(
+                        "info",
+                        XmpValue::Struct(vec![("exf:name".into(), "it's \"quoted\"".into())]),
+                    ),
+            );
+
+        let parsed = XmpPacket::parse(&packet.to_xml()).unwrap();
+        assert_eq!(parsed, packet);
+        assert_eq!(
+            parsed.get_text("http://purl.org/dc/elements/1.1/", "title"),
+            Some("Fish & <Chips>")
+        );
+    }
+
+    #[test]
+    fn test_xmp_whitespace_value() {
+        let packet = XmpPacket::new().with_schema(
+            XmpSchema::new("pdf", "http://ns.adobe.com/pdf/1.3/").with_property("Keywords", " "),
+        );
+
+        let parsed = XmpPacket::parse(&packet.to_xml()).unwrap();
+        assert_eq!(parsed, packet);
+        assert_eq!(parsed.get_text("http://ns.adobe.com/pdf/1.3/", "Keywords"), Some(" "));
+    }
+
+    #[test]
+    fn test_xmp_parse_attribute_form() {
+        let xml = "<x:xmpmeta xmlns:x='adobe:ns:meta/'><rdf:RDF \
+                   xmlns:rdf='http://www.w3.org/1999/02/22-rdf-syntax-ns#'>\
+                   <rdf:Description rdf:about='' xmlns:a='http://ns.adobe.com/pdf/1.3/' \
+                   a:Producer='printpdf &amp; co'/></rdf:RDF></x:xmpmeta>";
+
+        let parsed = XmpPacket::parse(xml).unwrap();
+        assert_eq!(
+            parsed.get_text("http://ns.adobe.com/pdf/1.3/", "Producer"),
+            Some("printpdf & co")
+        );
+        assert!(XmpPacket::parse("<rdf:RDF>").is_err());
+    }
+
+    #[test]
+    fn test_xmp_parse_nesting_limit() {
+        let nested = format!("{}{}", "<a>".repeat(100_000), "</a>".repeat(100_000));
+        assert!(XmpPacket::parse(&nested).is_err());
+    }
+}
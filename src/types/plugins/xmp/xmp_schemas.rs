@@ -0,0 +1,282 @@
+//! Typed versions of the predefined XMP schemas that are used in PDF documents.
+//!
+//! Every schema converts into a generic `XmpSchema` and can be read back from a
+//! parsed `XmpPacket`. Dates are stored in their ISO 8601 text form.
+
+use super::xmp_packet::{XmpPacket, XmpSchema, XmpValue};
+
+/// Dublin Core schema (`dc`)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DublinCore {
+    /// Title of the document (`dc:title`)
+    pub title: Option<String>,
+    /// Authors of the document, in order of precedence (`dc:creator`)
+    pub creator: Vec<String>,
+    /// Description of the document, the "Subject" in the Info dictionary (`dc:description`)
+    pub description: Option<String>,
+    /// Keywords of the document (`dc:subject`)
+    pub subject: Vec<String>,
+    /// Languages used in the document, such as `"en-US"` (`dc:language`)
+    pub language: Vec<String>,
+    /// Publishers of the document (`dc:publisher`)
+    pub publisher: Vec<String>,
+    /// Copyright statement (`dc:rights`)
+    pub rights: Option<String>,
+    /// Unique identifier of the document, such as a DOI (`dc:identifier`)
+    pub identifier: Option<String>,
+    /// MIME type of the document, `"application/pdf"` (`dc:format`)
+    pub format: Option<String>,
+}
+
+impl DublinCore {
+    /// Namespace of the Dublin Core schema
+    pub const NAMESPACE: &'static str = "http://purl.org/dc/elements/1.1/";
+
+    /// Reads the schema from a packet, returns `None` if the packet doesn't contain it
+    pub fn from_packet(packet: &XmpPacket) -> Option<Self> {
+        let schema = packet.schema(Self::NAMESPACE)?;
+        Some(Self {
+            title: text(schema, "title"),
+            creator: list(schema, "creator"),
+            description: text(schema, "description"),
+            subject: list(schema, "subject"),
+            language: list(schema, "language"),
+            publisher: list(schema, "publisher"),
+            rights: text(schema, "rights"),
+            identifier: text(schema, "identifier"),
+            format: text(schema, "format"),
+        })
+    }
+}
+
+impl From<DublinCore> for XmpSchema {
+    fn from(dc: DublinCore) -> Self {
+        let mut schema = XmpSchema::new("dc", DublinCore::NAMESPACE);
+        if let Some(format) = dc.format {
+            schema.set("format", format);
+        }
+        if let Some(title) = dc.title {
+            schema.set("title", XmpValue::lang_alt_default(title));
+        }
+        if !dc.creator.is_empty() {
+            schema.set("creator", XmpValue::text_seq(dc.creator));
+        }
+        if let Some(description) = dc.description {
+            schema.set("description", XmpValue::lang_alt_default(description));
+        }
+        if !dc.subject.is_empty() {
+            schema.set("subject", XmpValue::text_bag(dc.subject));
+        }
+        if !dc.language.is_empty() {
+            schema.set("language", XmpValue::text_bag(dc.language));
+        }
+        if !dc.publisher.is_empty() {
+            schema.set("publisher", XmpValue::text_bag(dc.publisher));
+        }
+        if let Some(rights) = dc.rights {
+            schema.set("rights", XmpValue::lang_alt_default(rights));
+        }
+        if let Some(identifier) = dc.identifier {
+            schema.set("identifier", identifier);
+        }
+        schema
+    }
+}
+
+/// XMP basic schema (`xmp`)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct XmpBasic {
+    /// Creation date (`xmp:CreateDate`)
+    pub create_date: Option<String>,
+    /// Modification date (`xmp:ModifyDate`)
+    pub modify_date: Option<String>,
+    /// Date of the last metadata change (`xmp:MetadataDate`)
+    pub metadata_date: Option<String>,
+    /// Application that created the original document, the "Creator"
+    /// in the Info dictionary (`xmp:CreatorTool`)
+    pub creator_tool: Option<String>,
+}
+
+impl XmpBasic {
+    /// Namespace of the XMP basic schema
+    pub const NAMESPACE: &'static str = "http://ns.adobe.com/xap/1.0/";
+
+    /// Reads the schema from a packet, returns `None` if the packet doesn't contain it
+    pub fn from_packet(packet: &XmpPacket) -> Option<Self> {
+        let schema = packet.schema(Self::NAMESPACE)?;
+        Some(Self {
+            create_date: text(schema, "CreateDate"),
+            modify_date: text(schema, "ModifyDate"),
+            metadata_date: text(schema, "MetadataDate"),
+            creator_tool: text(schema, "CreatorTool"),
+        })
+    }
+}
+
+impl From<XmpBasic> for XmpSchema {
+    fn from(xmp: XmpBasic) -> Self {
+        let mut schema = XmpSchema::new("xmp", XmpBasic::NAMESPACE);
+        set_opt(&mut schema, "CreateDate", xmp.create_date);
+        set_opt(&mut schema, "ModifyDate", xmp.modify_date);
+        set_opt(&mut schema, "MetadataDate", xmp.metadata_date);
+        set_opt(&mut schema, "CreatorTool", xmp.creator_tool);
+        schema
+    }
+}
+
+/// Adobe PDF schema (`pdf`)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AdobePdf {
+    /// Keywords, as in the Info dictionary (`pdf:Keywords`)
+    pub keywords: Option<String>,
+    /// PDF version, such as `"1.7"` (`pdf:PDFVersion`)
+    pub pdf_version: Option<String>,
+    /// Application that converted the document to PDF (`pdf:Producer`)
+    pub producer: Option<String>,
+    /// Whether the document has been trapped (`pdf:Trapped`)
+    pub trapped: Option<bool>,
+}
+
+impl AdobePdf {
+    /// Namespace of the Adobe PDF schema
+    pub const NAMESPACE: &'static str = "http://ns.adobe.com/pdf/1.3/";
+
+    /// Reads the schema from a packet, returns `None` if the packet doesn't contain it
+    pub fn from_packet(packet: &XmpPacket) -> Option<Self> {
+        let schema = packet.schema(Self::NAMESPACE)?;
+        Some(Self {
+            keywords: text(schema, "Keywords"),
+            pdf_version: text(schema, "PDFVersion"),
+            producer: text(schema, "Producer"),
+            trapped: schema.get_text("Trapped").map(|t| t == "True"),
+        })
+    }
+}
+
+impl From<AdobePdf> for XmpSchema {
+    fn from(pdf: AdobePdf) -> Self {
+        let mut schema = XmpSchema::new("pdf", AdobePdf::NAMESPACE);
+        set_opt(&mut schema, "Keywords", pdf.keywords);
+        set_opt(&mut schema, "PDFVersion", pdf.pdf_version);
+        set_opt(&mut schema, "Producer", pdf.producer);
+        if let Some(trapped) = pdf.trapped {
+            schema.set("Trapped", if trapped { "True" } else { "False" });
+        }
+        schema
+    }
+}
+
+/// PDF/A identification schema (`pdfaid`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfAIdentification {
+    /// PDF/A part: 1, 2 or 3 (`pdfaid:part`)
+    pub part: u8,
+    /// Conformance level: "A", "B" or "U" (`pdfaid:conformance`)
+    pub conformance: String,
+}
+
+impl PdfAIdentification {
+    /// Namespace of the PDF/A identification schema
+    pub const NAMESPACE: &'static str = "http://www.aiim.org/pdfa/ns/id/";
+
+    /// Reads the schema from a packet, returns `None` if the packet doesn't contain it
+    pub fn from_packet(packet: &XmpPacket) -> Option<Self> {
+        let schema = packet.schema(Self::NAMESPACE)?;
+        Some(Self {
+            part: schema.get_text("part")?.trim().parse().ok()?,
+            conformance: text(schema, "conformance").unwrap_or_default(),
+        })
+    }
+}
+
+impl From<PdfAIdentification> for XmpSchema {
+    fn from(id: PdfAIdentification) -> Self {
+        XmpSchema::new("pdfaid", PdfAIdentification::NAMESPACE)
+            .with_property("part", id.part.to_string())
+            .with_property("conformance", id.conformance)
+    }
+}
+
+/// PDF/X identification schema (`pdfxid`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfXIdentification {
+    /// PDF/X version, such as `"PDF/X-3:2002"` (`pdfxid:GTS_PDFXVersion`)
+    pub gts_pdfx_version: String,
+}
+
+impl PdfXIdentification {
+    /// Namespace of the PDF/X identification schema
+    pub const NAMESPACE: &'static str = "http://www.npes.org/pdfx/ns/id/";
+
+    /// Reads the schema from a packet, returns `None` if the packet doesn't contain it
+    pub fn from_packet(packet: &XmpPacket) -> Option<Self> {
+        let schema = packet.schema(Self::NAMESPACE)?;
+        Some(Self {
+            gts_pdfx_version: text(schema, "GTS_PDFXVersion")?,
+        })
+    }
+}
+
+impl From<PdfXIdentification> for XmpSchema {
+    fn from(id: PdfXIdentification) -> Self {
+        XmpSchema::new("pdfxid", PdfXIdentification::NAMESPACE)
+            .with_property("GTS_PDFXVersion", id.gts_pdfx_version)
+    }
+}
+
+/// XMP media management schema (`xmpMM`)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct XmpMediaManagement {
+    /// Identifier of all versions of the document (`xmpMM:DocumentID`)
+    pub document_id: Option<String>,
+    /// Identifier of this specific version of the document (`xmpMM:InstanceID`)
+    pub instance_id: Option<String>,
+    /// Rendition class, usually "default" (`xmpMM:RenditionClass`)
+    pub rendition_class: Option<String>,
+    /// Version of the document (`xmpMM:VersionID`)
+    pub version_id: Option<String>,
+}
+
+impl XmpMediaManagement {
+    /// Namespace of the XMP media management schema
+    pub const NAMESPACE: &'static str = "http://ns.adobe.com/xap/1.0/mm/";
+
+    /// Reads the schema from a packet, returns `None` if the packet doesn't contain it
+    pub fn from_packet(packet: &XmpPacket) -> Option<Self> {
+        let schema = packet.schema(Self::NAMESPACE)?;
+        Some(Self {
+            document_id: text(schema, "DocumentID"),
+            instance_id: text(schema, "InstanceID"),
+            rendition_class: text(schema, "RenditionClass"),
+            version_id: text(schema, "VersionID"),
+        })
+    }
+}
+
+impl From<XmpMediaManagement> for XmpSchema {
+    fn from(mm: XmpMediaManagement) -> Self {
+        let mut schema = XmpSchema::new("xmpMM", XmpMediaManagement::NAMESPACE);
+        set_opt(&mut schema, "DocumentID", mm.document_id);
+        set_opt(&mut schema, "InstanceID", mm.instance_id);
+        set_opt(&mut schema, "RenditionClass", mm.rendition_class);
+        set_opt(&mut schema, "VersionID", mm.version_id);
+        schema
+    }
+}
+
+fn set_opt(schema: &mut XmpSchema, name: &str, value: Option<String>) {
+    if let Some(value) = value {
+        schema.set(name, value);
+    }
+}
+
+fn text(schema: &XmpSchema, name: &str) -> Option<String> {
+    schema.get_text(name).map(|s| s.to_string())
+}
+
+fn list(schema: &XmpSchema, name: &str) -> Vec<String> {
+    schema
+        .get(name)
+        .map(|v| v.as_text_list())
+        .unwrap_or_default()
+}
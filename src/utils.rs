@@ -141,7 +141,7 @@ use std::{
   sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::{Font, Registered};
+use crate::{Error, Font, Registered};
 
 /// Since the random number generator doesn't have to be cryptographically secure
 /// it doesn't make sense to import the entire rand library, so this is just a
@@ -195,17 +195,16 @@ fn u8_to_char(input: u8) -> char {
 }
 
 #[inline]
-pub fn measure_text<S, F>(text: S, font: &Registered<F>, font_size: f64) -> (Pt, Pt)
+pub fn measure_text<S, F>(text: S, font: &Registered<F>, font_size: f64) -> Result<(Pt, Pt), Error>
 where
   S: AsRef<str>,
   F: Borrow<Font>,
 {
   let Font::ExternalFont(face_direct_ref) = font.object.borrow() else {
-    return (Pt(0.0), Pt(0.0));
+    return Ok((Pt(0.0), Pt(0.0)));
   };
 
-  let collection = rusttype::FontCollection::from_bytes(&*face_direct_ref.font_bytes).unwrap();
-  let font = collection.clone().into_font().unwrap_or(collection.font_at(0).unwrap());
+  let font = face_direct_ref.parse()?;
 
   let scale = rusttype::Scale::uniform(font_size as f32);
   let text = text.as_ref();
@@ -220,5 +219,184 @@ where
   // let height = (v_metrics.ascent - v_metrics.descent + v_metrics.line_gap) as f64;
   let height = v_metrics.ascent as f64;
 
-  (Pt(width), Pt(height))
+  Ok((Pt(width), Pt(height)))
+}
+
+/// MD5 digest of `data`. PDF uses MD5 for the checksums of embedded files and
+/// for the file identifiers in the trailer - this is not used for anything
+/// security-related, so a small implementation is enough
+pub(crate) fn md5(data: &[u8]) -> [u8; 16] {
+  const SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+  ];
+
+  const K: [u32; 64] = [
+    0xd76a_a478, 0xe8c7_b756, 0x2420_70db, 0xc1bd_ceee, 0xf57c_0faf, 0x4787_c62a, 0xa830_4613, 0xfd46_9501,
+    0x6980_98d8, 0x8b44_f7af, 0xffff_5bb1, 0x895c_d7be, 0x6b90_1122, 0xfd98_7193, 0xa679_438e, 0x49b4_0821,
+    0xf61e_2562, 0xc040_b340, 0x265e_5a51, 0xe9b6_c7aa, 0xd62f_105d, 0x0244_1453, 0xd8a1_e681, 0xe7d3_fbc8,
+    0x21e1_cde6, 0xc337_07d6, 0xf4d5_0d87, 0x455a_14ed, 0xa9e3_e905, 0xfcef_a3f8, 0x676f_02d9, 0x8d2a_4c8a,
+    0xfffa_3942, 0x8771_f681, 0x6d9d_6122, 0xfde5_380c, 0xa4be_ea44, 0x4bde_cfa9, 0xf6bb_4b60, 0xbebf_bc70,
+    0x289b_7ec6, 0xeaa1_27fa, 0xd4ef_3085, 0x0488_1d05, 0xd9d4_d039, 0xe6db_99e5, 0x1fa2_7cf8, 0xc4ac_5665,
+    0xf429_2244, 0x432a_ff97, 0xab94_23a7, 0xfc93_a039, 0x655b_59c3, 0x8f0c_cc92, 0xffef_f47d, 0x8584_5dd1,
+    0x6fa8_7e4f, 0xfe2c_e6e0, 0xa301_4314, 0x4e08_11a1, 0xf753_7e82, 0xbd3a_f235, 0x2ad7_d2bb, 0xeb86_d391,
+  ];
+
+  let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+
+  // pad the message to a multiple of 64 bytes, ending with the bit length
+  let mut message = data.to_vec();
+  message.push(0x80);
+  while message.len() % 64 != 56 {
+    message.push(0);
+  }
+  message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+  for chunk in message.chunks(64) {
+    let mut words = [0_u32; 16];
+    for (i, word) in words.iter_mut().enumerate() {
+      *word = u32::from_le_bytes([chunk[i * 4], chunk[i * 4 + 1], chunk[i * 4 + 2], chunk[i * 4 + 3]]);
+    }
+
+    let [mut a, mut b, mut c, mut d] = state;
+
+    for i in 0..64 {
+      let (f, g) = match i / 16 {
+        0 => ((b & c) | (!b & d), i),
+        1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+        2 => (b ^ c ^ d, (3 * i + 5) % 16),
+        _ => (c ^ (b | !d), (7 * i) % 16),
+      };
+
+      let rotated = a
+        .wrapping_add(f)
+        .wrapping_add(K[i])
+        .wrapping_add(words[g])
+        .rotate_left(SHIFTS[i]);
+
+      a = d;
+      d = c;
+      c = b;
+      b = b.wrapping_add(rotated);
+    }
+
+    state[0] = state[0].wrapping_add(a);
+    state[1] = state[1].wrapping_add(b);
+    state[2] = state[2].wrapping_add(c);
+    state[3] = state[3].wrapping_add(d);
+  }
+
+  let mut digest = [0_u8; 16];
+  for (i, word) in state.iter().enumerate() {
+    digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+  }
+  digest
+}
+
+#[test]
+fn test_md5() {
+  fn hex(digest: [u8; 16]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+  }
+
+  // test suite of RFC 1321, appendix A.5
+  assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+  assert_eq!(hex(md5(b"a")), "0cc175b9c0f1b6a831c399e269772661");
+  assert_eq!(hex(md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+  assert_eq!(hex(md5(b"message digest")), "f96b697d7cb7938d525a2f31aaf161d0");
+  assert_eq!(hex(md5(b"abcdefghijklmnopqrstuvwxyz")), "c3fcd3d76192e4007dfb496cca67e13b");
+  assert_eq!(
+    hex(md5(b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789")),
+    "d174ab98d277d9f5a5611c2c9f419d9f"
+  );
+  assert_eq!(
+    hex(md5(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890")),
+    "57edf4a22be3c955ac49da2e2107b67a"
+  );
+
+  // padding edge cases: 55, 56 and 64 bytes
+  assert_eq!(hex(md5(b"The quick brown fox jumps over the lazy dog")), "9e107d9d372bb6826bd81d3542a419d6");
+  assert_eq!(hex(md5(&[b'a'; 55])), "ef1772b6dff9a122358552954ad0df65");
+  assert_eq!(hex(md5(&[b'a'; 56])), "3b0c8ac703f828b04c6c197006d17218");
+  assert_eq!(hex(md5(&[b'a'; 64])), "014842d480b571495a4a0363793f7367");
+  assert_eq!(hex(md5(&[b'a'; 100])), "36a92cc94a9e0fa21f625f8bfb007adf");
+}
+
+/// Encodes a "text string" (titles, author, annotation contents, etc.): PDFDocEncoding if
+/// possible, otherwise UTF-16BE with a byte order mark (PDF 1.7, section 7.9.2.2)
+pub(crate) fn to_pdf_text_string(text: &str) -> lopdf::Object {
+  // PDFDocEncoding is identical to Latin-1 for these characters
+  let is_pdf_doc_encoding =
+    |c: char| matches!(c as u32, 0x09 | 0x0A | 0x0D | 0x20..=0x7E | 0xA1..=0xAC | 0xAE..=0xFF);
+
+  let bytes = if text.chars().all(is_pdf_doc_encoding) {
+    text.chars().map(|c| c as u8).collect()
+  } else {
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+      bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    bytes
+  };
+
+  lopdf::Object::String(bytes, lopdf::StringFormat::Literal)
+}
+
+#[test]
+fn test_pdf_text_string() {
+  use lopdf::Object::String;
+
+  match to_pdf_text_string("Grüße (1)") {
+    String(bytes, _) => assert_eq!(bytes, b"Gr\xFC\xDFe (1)".to_vec()),
+    _ => panic!(),
+  }
+
+  match to_pdf_text_string("日本") {
+    String(bytes, _) => assert_eq!(bytes, vec![0xFE, 0xFF, 0x65, 0xE5, 0x67, 0x2C]),
+    _ => panic!(),
+  }
+}
+
+/// Array of real numbers, e.g. the `/Domain` of a function or the `/WhitePoint` of a color space
+pub(crate) fn to_pdf_reals(values: &[f64]) -> lopdf::Object {
+  lopdf::Object::Array(values.iter().map(|v| lopdf::Object::Real(*v)).collect())
+}
+
+/// ASCII base-85 encoding (`ASCII85Decode`), including the `~>` end-of-data marker
+pub(crate) fn ascii85_encode(data: &[u8]) -> String {
+  let mut encoded = String::with_capacity(data.len() * 5 / 4 + 2);
+
+  for chunk in data.chunks(4) {
+    let mut group = [0_u8; 4];
+    group[..chunk.len()].copy_from_slice(chunk);
+    let mut value = u32::from_be_bytes(group);
+
+    // a complete group of zeros is abbreviated as "z"
+    if chunk.len() == 4 && value == 0 {
+      encoded.push('z');
+      continue;
+    }
+
+    let mut digits = [0_u8; 5];
+    for digit in digits.iter_mut().rev() {
+      *digit = (value % 85) as u8 + b'!';
+      value /= 85;
+    }
+
+    // an incomplete last group of n bytes is written as n + 1 characters
+    encoded.extend(digits[..chunk.len() + 1].iter().map(|d| *d as char));
+  }
+
+  encoded.push_str("~>");
+  encoded
+}
+
+#[test]
+fn test_ascii85_encode() {
+  assert_eq!(ascii85_encode(b"Man "), "9jqo^~>");
+  assert_eq!(ascii85_encode(b"sure."), "F*2M7/c~>");
+  assert_eq!(ascii85_encode(&[0, 0, 0, 0, 1]), "z!<~>");
+  assert_eq!(ascii85_encode(b""), "~>");
 }